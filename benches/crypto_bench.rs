@@ -2,7 +2,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 
 use aegis::crypto::{
-    kyber::{KeyPair, PublicKey},
+    kyber::KeyPair,
     symmetric::{SymmetricKey, encrypt_simple, decrypt_simple},
     kdf::{derive_master_key, derive_message_key, blake3_keyed_hash},
     ratchet::RatchetState,
@@ -162,7 +162,7 @@ fn bench_full_decryption_flow(c: &mut Criterion) {
 
         // Pre-encrypt messages
         let mut encrypted_messages = Vec::new();
-        for i in 0..100 {
+        for _ in 0..100 {
             let (key, _) = ratchet.next_send_key().unwrap();
             encrypted_messages.push(encrypt_simple(&key, plaintext).unwrap());
         }
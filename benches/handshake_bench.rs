@@ -0,0 +1,44 @@
+// Handshake benchmarks for Aegis
+// Measures the full Session::connect/accept round trip over an in-memory transport
+use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
+use zeroize::Zeroizing;
+
+use aegis::crypto::kyber::KyberLevel;
+use aegis::network::connection::memory_pair;
+use aegis::session::{Session, SessionConfig};
+
+/// Kyber parameter sets a session can be configured with
+const KYBER_LEVELS: [KyberLevel; 3] = [KyberLevel::Kyber512, KyberLevel::Kyber768, KyberLevel::Kyber1024];
+
+/// Key schedules: Kyber alone, or hybrid with a pre-shared key mixed in
+fn key_modes() -> [(&'static str, Option<Zeroizing<Vec<u8>>>); 2] {
+    [("pure", None), ("hybrid_psk", Some(Zeroizing::new(vec![0x42; 32])))]
+}
+
+fn bench_full_handshake(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("full_handshake");
+
+    for (mode, psk) in key_modes() {
+        for level in KYBER_LEVELS {
+            let config = SessionConfig { kem: level, psk: psk.clone(), ..SessionConfig::default() };
+            group.bench_with_input(BenchmarkId::new(mode, format!("{:?}", level)), &config, |b, config| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let (client_conn, server_conn) = memory_pair();
+                        let (client, server) = tokio::join!(
+                            Session::connect_with_config(client_conn, config),
+                            Session::accept_with_config(server_conn, config),
+                        );
+                        black_box((client.unwrap(), server.unwrap()))
+                    })
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(handshake_benches, bench_full_handshake);
+
+criterion_main!(handshake_benches);
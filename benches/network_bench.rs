@@ -1,7 +1,9 @@
 // Network benchmarks for Aegis
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 
+use aegis::network::connection::{Listener, connect};
 use aegis::network::protocol::{Message, frame_message, parse_framed_message};
+use std::time::Duration;
 
 fn bench_message_serialization(c: &mut Criterion) {
     let msg = Message::heartbeat();
@@ -34,6 +36,21 @@ fn bench_message_framing(c: &mut Criterion) {
     });
 }
 
+fn bench_encrypted_message_framing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encrypted_message_framing");
+
+    for size in [64, 1024, 16384].iter() {
+        let msg = Message::encrypted([0u8; 24], vec![0u8; *size], 0, 0);
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, _| {
+            b.iter(|| {
+                black_box(frame_message(&msg).unwrap())
+            })
+        });
+    }
+    group.finish();
+}
+
 fn bench_frame_parsing(c: &mut Criterion) {
     let msg = Message::heartbeat();
     let framed = frame_message(&msg).unwrap();
@@ -67,7 +84,8 @@ fn bench_message_validation(c: &mut Criterion) {
 
     c.bench_function("message_validation", |b| {
         b.iter(|| {
-            black_box(msg.validate().unwrap())
+            msg.validate().unwrap();
+            black_box(&msg)
         })
     });
 }
@@ -84,15 +102,51 @@ fn bench_full_message_roundtrip(c: &mut Criterion) {
     });
 }
 
+fn bench_paste_burst(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("paste_100_lines");
+
+    for linger_ms in [0u64, 2] {
+        let mut client = rt.block_on(async {
+            let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+            let client = connect(&addr.to_string()).await.unwrap();
+
+            // Drain the server side so the socket never backs up
+            let mut server = accept.await.unwrap();
+            tokio::spawn(async move { while server.recv_message().await.is_ok() {} });
+
+            client
+        });
+        client.set_flush_linger(Duration::from_millis(linger_ms));
+
+        let line = Message::encrypted([0u8; 24], vec![0x42u8; 80], 0, 0);
+        group.bench_with_input(BenchmarkId::new("linger_ms", linger_ms), &linger_ms, |b, _| {
+            b.iter(|| {
+                rt.block_on(async {
+                    for _ in 0..100 {
+                        client.send_message(&line).await.unwrap();
+                    }
+                    client.flush().await.unwrap();
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     network_benches,
     bench_message_serialization,
     bench_message_deserialization,
     bench_message_framing,
+    bench_encrypted_message_framing,
     bench_frame_parsing,
     bench_encrypted_message_serialization,
     bench_message_validation,
-    bench_full_message_roundtrip
+    bench_full_message_roundtrip,
+    bench_paste_burst
 );
 
 criterion_main!(network_benches);
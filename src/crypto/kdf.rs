@@ -5,6 +5,7 @@ use hkdf::Hkdf;
 use sha2::Sha256;
 use blake3::Hasher as Blake3Hasher;
 use hmac::{Hmac, Mac};
+use zeroize::Zeroizing;
 
 use super::{CryptoError, symmetric::SymmetricKey};
 
@@ -49,6 +50,50 @@ pub fn derive_master_key(shared_secret: &[u8], salt: &[u8]) -> Result<SymmetricK
     Ok(SymmetricKey::new(key_bytes))
 }
 
+/// Derive the master key from the shared secret plus a transcript hash and PSK
+///
+/// Each input goes into HKDF-extract as a presence byte, then (if present)
+/// a 4-byte big-endian length and the bytes, so the key depends on all of
+/// them and no split of the same bytes between fields gives the same key.
+/// With neither extra input, this equals `derive_master_key`.
+pub fn derive_master_key_bound(
+    shared_secret: &[u8],
+    transcript_hash: Option<&[u8; 32]>,
+    psk: Option<&[u8]>,
+    salt: &[u8],
+) -> Result<SymmetricKey, CryptoError> {
+    if transcript_hash.is_none() && psk.is_none() {
+        return derive_master_key(shared_secret, salt);
+    }
+
+    let mut ikm = Zeroizing::new(Vec::new());
+    for field in [Some(shared_secret), transcript_hash.map(|hash| hash.as_slice()), psk] {
+        let Some(field) = field else {
+            ikm.push(0);
+            continue;
+        };
+        let len = u32::try_from(field.len())
+            .map_err(|_| CryptoError::KeyExchangeError("Key derivation input too long".to_string()))?;
+        ikm.push(1);
+        ikm.extend_from_slice(&len.to_be_bytes());
+        ikm.extend_from_slice(field);
+    }
+
+    derive_master_key(&ikm, salt)
+}
+
+/// Derive the shared key of a broadcast room from a secret all members hold
+///
+/// Deterministic: every member with `master_key` gets the same key for
+/// `room_id`, and different rooms get unrelated keys.
+pub fn derive_room_key(master_key: &[u8], room_id: &str) -> Result<SymmetricKey, CryptoError> {
+    let mut info = b"aegis-room-key-v1:".to_vec();
+    info.extend_from_slice(room_id.as_bytes());
+    let derived = Zeroizing::new(derive_keys(master_key, b"aegis-room-v1-salt", &info, 32)?);
+
+    SymmetricKey::from_slice(&derived)
+}
+
 /// Derive chain key from previous chain key
 pub fn derive_chain_key(previous_chain_key: &[u8; 32], context: &[u8]) -> Result<[u8; 32], CryptoError> {
     let derived = derive_keys(
@@ -127,6 +172,110 @@ pub fn derive_key_bundle(
     Ok(keys)
 }
 
+/// Derive one key per leaf label through a balanced binary HKDF tree
+///
+/// The root node comes from `master_key`, each internal node from its
+/// parent ("left"/"right"), and each leaf key from its parent and its label:
+/// 3N-1 HKDF calls for N leaves (the root, 2N-2 child nodes and N leaf keys). Leaves are split at the midpoint (left half
+/// rounded up), so whoever holds an internal node can regenerate every leaf
+/// beneath it without the master key.
+pub fn tree_kdf(master_key: &[u8; 32], leaves: &[&[u8]]) -> Result<Vec<[u8; 32]>, CryptoError> {
+    let mut keys = Vec::with_capacity(leaves.len());
+    if !leaves.is_empty() {
+        let root = tree_kdf_node(master_key, b"aegis-tree-kdf-v1:root")?;
+        tree_kdf_subtree(&root, leaves, &mut keys)?;
+    }
+    Ok(keys)
+}
+
+fn tree_kdf_subtree(node: &Zeroizing<[u8; 32]>, leaves: &[&[u8]], keys: &mut Vec<[u8; 32]>) -> Result<(), CryptoError> {
+    if let [label] = leaves {
+        let mut info = b"aegis-tree-kdf-v1:leaf:".to_vec();
+        info.extend_from_slice(label);
+        keys.push(*tree_kdf_node(node, &info)?);
+        return Ok(());
+    }
+
+    let (left, right) = leaves.split_at(leaves.len().div_ceil(2));
+    tree_kdf_subtree(&tree_kdf_node(node, b"aegis-tree-kdf-v1:left")?, left, keys)?;
+    tree_kdf_subtree(&tree_kdf_node(node, b"aegis-tree-kdf-v1:right")?, right, keys)
+}
+
+fn tree_kdf_node(parent: &[u8; 32], info: &[u8]) -> Result<Zeroizing<[u8; 32]>, CryptoError> {
+    let derived = Zeroizing::new(derive_keys(parent, &[], info, 32)?);
+    let mut node = Zeroizing::new([0u8; 32]);
+    node.copy_from_slice(&derived);
+    Ok(node)
+}
+
+/// Derive key material from a password with PBKDF2-HMAC-SHA256
+/// (for FIPS-constrained environments)
+pub fn derive_key_pbkdf2(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    output_len: usize,
+) -> Result<Vec<u8>, CryptoError> {
+    if iterations == 0 || output_len == 0 {
+        return Err(CryptoError::InvalidKey);
+    }
+
+    let mut output = vec![0u8; output_len];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut output);
+
+    Ok(output)
+}
+
+/// Password-based KDF choice, tagged so stored material records how it was derived
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordKdf {
+    Pbkdf2Sha256 { iterations: u32 },
+}
+
+impl PasswordKdf {
+    const PBKDF2_SHA256_TAG: u8 = 0x01;
+
+    /// Derive `output_len` bytes from a password
+    pub fn derive(&self, password: &[u8], salt: &[u8], output_len: usize) -> Result<Vec<u8>, CryptoError> {
+        match *self {
+            PasswordKdf::Pbkdf2Sha256 { iterations } => {
+                derive_key_pbkdf2(password, salt, iterations, output_len)
+            }
+        }
+    }
+
+    /// Encode as a header: tag byte followed by parameters
+    pub fn to_header(&self) -> Vec<u8> {
+        match *self {
+            PasswordKdf::Pbkdf2Sha256 { iterations } => {
+                let mut header = vec![Self::PBKDF2_SHA256_TAG];
+                header.extend_from_slice(&iterations.to_be_bytes());
+                header
+            }
+        }
+    }
+
+    /// Decode a header written by `to_header`, returning the KDF and bytes consumed
+    pub fn from_header(header: &[u8]) -> Result<(Self, usize), CryptoError> {
+        match header.first() {
+            Some(&Self::PBKDF2_SHA256_TAG) if header.len() >= 5 => {
+                let iterations = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+                Ok((PasswordKdf::Pbkdf2Sha256 { iterations }, 5))
+            }
+            _ => Err(CryptoError::InvalidKey),
+        }
+    }
+}
+
+/// Check an HMAC-SHA256 tag over `data` in constant time
+pub fn verify_mac(key: &[u8; 32], data: &[u8], tag: &[u8]) -> bool {
+    let Ok(mut mac) = HmacSha256::new_from_slice(key) else {
+        return false;
+    };
+    mac.update(data);
+    mac.verify_slice(tag).is_ok()
+}
+
 /// Zero-knowledge proof of key knowledge (simplified version)
 /// Used for authentication without revealing the key
 pub fn prove_key_knowledge(key: &[u8; 32], challenge: &[u8]) -> [u8; 32] {
@@ -159,6 +308,55 @@ mod tests {
         assert_eq!(key.as_bytes().len(), 32);
     }
 
+    #[test]
+    fn test_derive_master_key_bound() {
+        let secret = [42u8; 32];
+        let transcript = [7u8; 32];
+        let base = derive_master_key_bound(&secret, Some(&transcript), Some(b"psk"), b"salt").unwrap();
+
+        // No extra inputs is the plain derivation
+        let plain = derive_master_key_bound(&secret, None, None, b"salt").unwrap();
+        assert_eq!(plain.as_bytes(), derive_master_key(&secret, b"salt").unwrap().as_bytes());
+        assert_ne!(plain.as_bytes(), base.as_bytes());
+
+        let mut altered_transcript = transcript;
+        altered_transcript[31] ^= 1;
+        let variants = [
+            derive_master_key_bound(&[43u8; 32], Some(&transcript), Some(b"psk"), b"salt").unwrap(),
+            derive_master_key_bound(&secret, Some(&altered_transcript), Some(b"psk"), b"salt").unwrap(),
+            derive_master_key_bound(&secret, None, Some(b"psk"), b"salt").unwrap(),
+            derive_master_key_bound(&secret, Some(&transcript), Some(b"psK"), b"salt").unwrap(),
+            derive_master_key_bound(&secret, Some(&transcript), None, b"salt").unwrap(),
+        ];
+        for variant in &variants {
+            assert_ne!(variant.as_bytes(), base.as_bytes());
+        }
+
+        // The same bytes split differently between the fields don't collide
+        let mut joined = secret.to_vec();
+        joined.extend_from_slice(&transcript);
+        assert_ne!(
+            derive_master_key_bound(&secret, Some(&transcript), None, b"salt").unwrap().as_bytes(),
+            derive_master_key_bound(&secret, None, Some(&transcript), b"salt").unwrap().as_bytes(),
+        );
+        assert_ne!(
+            derive_master_key_bound(&secret, None, Some(&transcript), b"salt").unwrap().as_bytes(),
+            derive_master_key_bound(&joined, None, Some(b""), b"salt").unwrap().as_bytes(),
+        );
+        assert_ne!(
+            derive_master_key_bound(&secret, Some(&transcript), None, b"salt").unwrap().as_bytes(),
+            derive_master_key_bound(&secret, Some(&transcript), Some(b""), b"salt").unwrap().as_bytes(),
+        );
+    }
+
+    #[test]
+    fn test_derive_room_key() {
+        let key = derive_room_key(b"room secret", "lobby").unwrap();
+        assert_eq!(key.as_bytes(), derive_room_key(b"room secret", "lobby").unwrap().as_bytes());
+        assert_ne!(key.as_bytes(), derive_room_key(b"room secret", "lobby2").unwrap().as_bytes());
+        assert_ne!(key.as_bytes(), derive_room_key(b"other secret", "lobby").unwrap().as_bytes());
+    }
+
     #[test]
     fn test_derive_chain_key() {
         let previous_key = [1u8; 32];
@@ -234,6 +432,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_verify_mac() {
+        let key = [9u8; 32];
+        let tag = ratchet_key_hmac(&key, b"payload").unwrap();
+
+        assert!(verify_mac(&key, b"payload", &tag));
+        assert!(!verify_mac(&key, b"payloae", &tag));
+        assert!(!verify_mac(&key, b"payload", &tag[..16]));
+    }
+
     #[test]
     fn test_prove_key_knowledge() {
         let key = [7u8; 32];
@@ -252,6 +460,29 @@ mod tests {
         assert_ne!(proof, proof3);
     }
 
+    #[test]
+    fn test_pbkdf2_known_vector() {
+        let derived = derive_key_pbkdf2(b"password", b"salt", 1, 32).unwrap();
+        assert_eq!(
+            hex::encode(derived),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+
+        assert!(derive_key_pbkdf2(b"password", b"salt", 0, 32).is_err());
+    }
+
+    #[test]
+    fn test_password_kdf_header_roundtrip() {
+        let kdf = PasswordKdf::Pbkdf2Sha256 { iterations: 600_000 };
+        let header = kdf.to_header();
+
+        let (parsed, consumed) = PasswordKdf::from_header(&header).unwrap();
+        assert_eq!(parsed, kdf);
+        assert_eq!(consumed, header.len());
+
+        assert!(PasswordKdf::from_header(&[0xEE]).is_err());
+    }
+
     #[test]
     fn test_different_salts() {
         let ikm = b"secret";
@@ -264,4 +495,24 @@ mod tests {
 
         assert_ne!(derived1, derived2);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_tree_kdf() {
+        let master = [3u8; 32];
+        let labels: Vec<&[u8]> = vec![b"alice", b"bob", b"carol", b"dave", b"erin"];
+
+        let keys = tree_kdf(&master, &labels).unwrap();
+        assert_eq!(keys.len(), labels.len());
+        assert_eq!(keys, tree_kdf(&master, &labels).unwrap());
+        let distinct: std::collections::HashSet<_> = keys.iter().collect();
+        assert_eq!(distinct.len(), keys.len());
+
+        // A leaf depends on its own label, not its neighbours'
+        let renamed: Vec<&[u8]> = vec![b"alice", b"bob", b"carol", b"dave", b"frank"];
+        let changed = tree_kdf(&master, &renamed).unwrap();
+        assert_eq!(keys[..4], changed[..4]);
+        assert_ne!(keys[4], changed[4]);
+
+        assert_ne!(tree_kdf(&[4u8; 32], &labels).unwrap()[0], keys[0]);
+        assert!(tree_kdf(&master, &[]).unwrap().is_empty());
+    }
+}
@@ -1,14 +1,84 @@
-// Post-quantum key exchange using Kyber-1024
+// Post-quantum key exchange using Kyber (512/768/1024, default 1024)
 // Provides quantum-resistant key encapsulation mechanism
 
-use pqcrypto_kyber::kyber1024;
+use pqcrypto_kyber::{kyber512, kyber768, kyber1024};
 use pqcrypto_traits::kem::{PublicKey as PQPublicKey, SecretKey as PQSecretKey, SharedSecret as PQSharedSecret, Ciphertext as PQCiphertext};
-use zeroize::ZeroizeOnDrop;
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use super::CryptoError;
 
-/// Kyber-1024 keypair for quantum-resistant key exchange
+/// Cached handshakes a `SemiStaticKeyPair` remembers per key; past this it
+/// refuses them until the key rotates, and clients fall back to a full handshake
+const MAX_SEEN_CIPHERTEXTS: usize = 1 << 16;
+
+/// Kyber parameter set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum KyberLevel {
+    Kyber512,
+    Kyber768,
+    #[default]
+    Kyber1024,
+}
+
+/// Run `$body` with `$kem` bound to the pqcrypto module for `$level`
+macro_rules! with_kyber {
+    ($level:expr, $kem:ident => $body:expr) => {
+        match $level {
+            KyberLevel::Kyber512 => { use kyber512 as $kem; $body }
+            KyberLevel::Kyber768 => { use kyber768 as $kem; $body }
+            KyberLevel::Kyber1024 => { use kyber1024 as $kem; $body }
+        }
+    };
+}
+
+impl KyberLevel {
+    /// Exact public key length for this level
+    pub fn public_key_bytes(self) -> usize {
+        with_kyber!(self, kem => kem::public_key_bytes())
+    }
+
+    /// Exact ciphertext length for this level
+    pub fn ciphertext_bytes(self) -> usize {
+        with_kyber!(self, kem => kem::ciphertext_bytes())
+    }
+
+    fn secret_key_bytes(self) -> usize {
+        with_kyber!(self, kem => kem::secret_key_bytes())
+    }
+
+    /// Name used in PEM labels, e.g. `KYBER1024 PUBLIC KEY`
+    fn pem_name(self) -> &'static str {
+        match self {
+            KyberLevel::Kyber512 => "KYBER512",
+            KyberLevel::Kyber768 => "KYBER768",
+            KyberLevel::Kyber1024 => "KYBER1024",
+        }
+    }
+}
+
+const PEM_PUBLIC_KEY: &str = "PUBLIC KEY";
+const PEM_SECRET_KEY: &str = "SECRET KEY";
+
+fn encode_pem(level: KyberLevel, kind: &str, contents: &[u8]) -> String {
+    let pem = pem::Pem::new(format!("{} {}", level.pem_name(), kind), contents);
+    pem::encode_config(&pem, pem::EncodeConfig::new().set_line_ending(pem::LineEnding::LF))
+}
+
+/// Parse a `KYBER<n> <kind>` block, returning its level and contents
+fn decode_pem(input: &str, kind: &str) -> Result<(KyberLevel, Zeroizing<Vec<u8>>), CryptoError> {
+    let pem = pem::parse(input).map_err(|e| CryptoError::InvalidPem(e.to_string()))?;
+    let level = [KyberLevel::Kyber512, KyberLevel::Kyber768, KyberLevel::Kyber1024]
+        .into_iter()
+        .find(|level| pem.tag() == format!("{} {}", level.pem_name(), kind))
+        .ok_or_else(|| CryptoError::InvalidPem(format!("Expected a Kyber {}, found {}", kind.to_lowercase(), pem.tag())))?;
+    Ok((level, Zeroizing::new(pem.into_contents())))
+}
+
+/// Kyber keypair for quantum-resistant key exchange
 #[derive(ZeroizeOnDrop)]
 pub struct KeyPair {
     #[zeroize(skip)]
@@ -20,6 +90,7 @@ pub struct KeyPair {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PublicKey {
     bytes: Vec<u8>,
+    level: KyberLevel,
 }
 
 /// Secret key wrapper (zeroized on drop)
@@ -32,6 +103,7 @@ struct SecretKey {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Ciphertext {
     bytes: Vec<u8>,
+    level: KyberLevel,
 }
 
 /// Shared secret result (zeroized on drop)
@@ -43,31 +115,46 @@ pub struct SharedSecret {
 impl KeyPair {
     /// Generate a new Kyber-1024 keypair
     pub fn generate() -> Result<Self, CryptoError> {
-        let (pk, sk) = kyber1024::keypair();
+        Self::generate_with_level(KyberLevel::Kyber1024)
+    }
+
+    /// Generate a new keypair at the given level
+    pub fn generate_with_level(level: KyberLevel) -> Result<Self, CryptoError> {
+        let (pk, sk) = with_kyber!(level, kem => {
+            let (pk, sk) = kem::keypair();
+            (pk.as_bytes().to_vec(), sk.as_bytes().to_vec())
+        });
 
         Ok(Self {
             public: PublicKey {
-                bytes: pk.as_bytes().to_vec(),
+                bytes: pk,
+                level,
             },
             secret: SecretKey {
-                bytes: sk.as_bytes().to_vec(),
+                bytes: sk,
             },
         })
     }
 
     /// Decapsulate a ciphertext to obtain the shared secret
     pub fn decapsulate(&self, ciphertext: &Ciphertext) -> Result<SharedSecret, CryptoError> {
-        let sk = kyber1024::SecretKey::from_bytes(&self.secret.bytes)
-            .map_err(|_| CryptoError::KeyExchangeError("Invalid secret key".to_string()))?;
+        if ciphertext.level != self.public.level {
+            return Err(CryptoError::KeyExchangeError("Ciphertext level does not match keypair".to_string()));
+        }
 
-        let ct = kyber1024::Ciphertext::from_bytes(&ciphertext.bytes)
-            .map_err(|_| CryptoError::KeyExchangeError("Invalid ciphertext".to_string()))?;
+        let ss = with_kyber!(self.public.level, kem => {
+            let sk = kem::SecretKey::from_bytes(&self.secret.bytes)
+                .map_err(|_| CryptoError::KeyExchangeError("Invalid secret key".to_string()))?;
 
-        let ss = kyber1024::decapsulate(&ct, &sk);
+            let ct = kem::Ciphertext::from_bytes(&ciphertext.bytes)
+                .map_err(|_| CryptoError::KeyExchangeError("Invalid ciphertext".to_string()))?;
+
+            kem::decapsulate(&ct, &sk).as_bytes().to_vec()
+        });
 
         // Convert to 32-byte shared secret
         let mut bytes = [0u8; 32];
-        bytes.copy_from_slice(&ss.as_bytes()[..32]);
+        bytes.copy_from_slice(&ss[..32]);
 
         Ok(SharedSecret { bytes })
     }
@@ -75,24 +162,67 @@ impl KeyPair {
     pub fn public_key(&self) -> &PublicKey {
         &self.public
     }
+
+    /// Public key as a `KYBER<n> PUBLIC KEY` PEM block
+    pub fn export_public_key_to_pem(&self) -> String {
+        self.public.to_pem()
+    }
+
+    /// Secret key as a `KYBER<n> SECRET KEY` PEM block
+    ///
+    /// Only built with the `key-export` feature, so binaries that never need
+    /// to write keys out can't be made to.
+    #[cfg(feature = "key-export")]
+    pub fn export_secret_key_to_pem(&self) -> Zeroizing<String> {
+        Zeroizing::new(encode_pem(self.public.level, PEM_SECRET_KEY, &self.secret.bytes))
+    }
+
+    /// Load a keypair from a `KYBER<n> SECRET KEY` PEM block
+    ///
+    /// The public key is the copy embedded in the secret key; a test
+    /// encapsulation checks that the two belong together.
+    pub fn import_secret_key_from_pem(pem: &str) -> Result<Self, CryptoError> {
+        let (level, contents) = decode_pem(pem, PEM_SECRET_KEY)?;
+        if contents.len() != level.secret_key_bytes() {
+            return Err(CryptoError::InvalidKey);
+        }
+
+        // Secret key layout: IND-CPA secret key || public key || H(pk) || z
+        let public_end = contents.len() - 64;
+        let public_start = public_end - level.public_key_bytes();
+        let keypair = Self {
+            public: PublicKey::from_bytes(contents[public_start..public_end].to_vec(), level)?,
+            secret: SecretKey { bytes: contents.to_vec() },
+        };
+
+        let (expected, ciphertext) = keypair.public.encapsulate()?;
+        if keypair.decapsulate(&ciphertext)?.as_bytes() != expected.as_bytes() {
+            return Err(CryptoError::InvalidKey);
+        }
+        Ok(keypair)
+    }
 }
 
 impl PublicKey {
     /// Encapsulate a shared secret for this public key
     pub fn encapsulate(&self) -> Result<(SharedSecret, Ciphertext), CryptoError> {
-        let pk = kyber1024::PublicKey::from_bytes(&self.bytes)
-            .map_err(|_| CryptoError::KeyExchangeError("Invalid public key".to_string()))?;
+        let (ss, ct) = with_kyber!(self.level, kem => {
+            let pk = kem::PublicKey::from_bytes(&self.bytes)
+                .map_err(|_| CryptoError::KeyExchangeError("Invalid public key".to_string()))?;
 
-        let (ss, ct) = kyber1024::encapsulate(&pk);
+            let (ss, ct) = kem::encapsulate(&pk);
+            (ss.as_bytes().to_vec(), ct.as_bytes().to_vec())
+        });
 
         // Convert to 32-byte shared secret
         let mut bytes = [0u8; 32];
-        bytes.copy_from_slice(&ss.as_bytes()[..32]);
+        bytes.copy_from_slice(&ss[..32]);
 
         Ok((
             SharedSecret { bytes },
             Ciphertext {
-                bytes: ct.as_bytes().to_vec(),
+                bytes: ct,
+                level: self.level,
             },
         ))
     }
@@ -101,12 +231,98 @@ impl PublicKey {
         &self.bytes
     }
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, CryptoError> {
-        // Validate the public key length
-        if bytes.len() != kyber1024::public_key_bytes() {
+    pub fn level(&self) -> KyberLevel {
+        self.level
+    }
+
+    /// BLAKE3 hash identifying this key
+    pub fn fingerprint(&self) -> [u8; 32] {
+        *blake3::hash(&self.bytes).as_bytes()
+    }
+
+    /// `KYBER<n> PUBLIC KEY` PEM block, for configuration files and sharing
+    pub fn to_pem(&self) -> String {
+        encode_pem(self.level, PEM_PUBLIC_KEY, &self.bytes)
+    }
+
+    /// Parse a `KYBER<n> PUBLIC KEY` PEM block
+    ///
+    /// The level comes from the label, and the key must have exactly that
+    /// level's length.
+    pub fn import_from_pem(pem: &str) -> Result<Self, CryptoError> {
+        let (level, contents) = decode_pem(pem, PEM_PUBLIC_KEY)?;
+        Self::from_bytes(contents.to_vec(), level)
+    }
+
+    /// Parse a public key, requiring exactly the expected level's length
+    pub fn from_bytes(bytes: Vec<u8>, level: KyberLevel) -> Result<Self, CryptoError> {
+        // A key of another level's size must not be reinterpreted
+        if bytes.len() != level.public_key_bytes() {
             return Err(CryptoError::InvalidKey);
         }
-        Ok(Self { bytes })
+        Ok(Self { bytes, level })
+    }
+}
+
+/// Long-lived responder keypair for cached (0-RTT) handshakes
+///
+/// Unlike per-handshake ephemeral keys, every session encapsulated to this
+/// key is exposed if its secret leaks before it is replaced, so it should be
+/// rotated on a short schedule.
+///
+/// It also remembers the hash of every ciphertext it accepts, so a recorded
+/// 0-RTT handshake (and the early data after it) can't be replayed while
+/// the key lives. Rotating the key starts a fresh cache.
+pub struct SemiStaticKeyPair {
+    keypair: KeyPair,
+    created_at: Instant,
+    lifetime: Duration,
+    seen_ciphertexts: Mutex<HashSet<[u8; 32]>>,
+}
+
+impl SemiStaticKeyPair {
+    /// Generate a key that should be replaced after `lifetime`
+    pub fn generate(level: KyberLevel, lifetime: Duration) -> Result<Self, CryptoError> {
+        Ok(Self {
+            keypair: KeyPair::generate_with_level(level)?,
+            created_at: Instant::now(),
+            lifetime,
+            seen_ciphertexts: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn keypair(&self) -> &KeyPair {
+        &self.keypair
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        self.keypair.public_key()
+    }
+
+    /// Whether the key has outlived its rotation schedule
+    pub fn is_expired(&self) -> bool {
+        self.created_at.elapsed() >= self.lifetime
+    }
+
+    /// Record a ciphertext encapsulated to this key; false if it was seen before
+    /// (or the cache is full, which fails closed)
+    pub fn first_use(&self, ciphertext: &Ciphertext) -> bool {
+        let hash = *blake3::hash(ciphertext.as_bytes()).as_bytes();
+        let mut seen = self.seen_ciphertexts.lock().unwrap_or_else(|e| e.into_inner());
+        if seen.len() >= MAX_SEEN_CIPHERTEXTS {
+            return false;
+        }
+        seen.insert(hash)
+    }
+
+    /// Replace the key if it has expired; returns true if it was rotated
+    pub fn rotate_if_due(&mut self) -> Result<bool, CryptoError> {
+        if !self.is_expired() {
+            return Ok(false);
+        }
+
+        *self = Self::generate(self.keypair.public.level, self.lifetime)?;
+        Ok(true)
     }
 }
 
@@ -127,12 +343,13 @@ impl Ciphertext {
         &self.bytes
     }
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, CryptoError> {
+    /// Parse a ciphertext, requiring exactly the expected level's length
+    pub fn from_bytes(bytes: Vec<u8>, level: KyberLevel) -> Result<Self, CryptoError> {
         // Validate the ciphertext length
-        if bytes.len() != kyber1024::ciphertext_bytes() {
+        if bytes.len() != level.ciphertext_bytes() {
             return Err(CryptoError::InvalidKey);
         }
-        Ok(Self { bytes })
+        Ok(Self { bytes, level })
     }
 }
 
@@ -166,7 +383,7 @@ mod tests {
         let keypair = KeyPair::generate().unwrap();
         let pk_bytes = keypair.public_key().as_bytes().to_vec();
 
-        let pk_restored = PublicKey::from_bytes(pk_bytes).unwrap();
+        let pk_restored = PublicKey::from_bytes(pk_bytes, KyberLevel::Kyber1024).unwrap();
         assert_eq!(keypair.public_key().as_bytes(), pk_restored.as_bytes());
     }
 
@@ -176,20 +393,98 @@ mod tests {
         let (_, ciphertext) = keypair.public_key().encapsulate().unwrap();
 
         let ct_bytes = ciphertext.as_bytes().to_vec();
-        let ct_restored = Ciphertext::from_bytes(ct_bytes).unwrap();
+        let ct_restored = Ciphertext::from_bytes(ct_bytes, KyberLevel::Kyber1024).unwrap();
 
         assert_eq!(ciphertext.as_bytes(), ct_restored.as_bytes());
     }
 
+    #[test]
+    fn test_pem_round_trip() {
+        for level in [KyberLevel::Kyber512, KyberLevel::Kyber1024] {
+            let keypair = KeyPair::generate_with_level(level).unwrap();
+            let pem = keypair.export_public_key_to_pem();
+            assert!(pem.starts_with(&format!("-----BEGIN {} PUBLIC KEY-----\n", level.pem_name())));
+
+            let restored = PublicKey::import_from_pem(&pem).unwrap();
+            assert_eq!(restored.level(), level);
+            assert_eq!(restored.as_bytes(), keypair.public_key().as_bytes());
+        }
+
+        // A label naming another level or key type is rejected
+        let pem = KeyPair::generate().unwrap().export_public_key_to_pem();
+        let relabeled = pem.replace("KYBER1024", "KYBER768");
+        assert!(PublicKey::import_from_pem(&relabeled).is_err());
+        assert!(PublicKey::import_from_pem(&pem.replace("PUBLIC", "SECRET")).is_err());
+    }
+
+    #[test]
+    fn test_import_secret_key_from_pem() {
+        let keypair = KeyPair::generate().unwrap();
+        let pem = encode_pem(KyberLevel::Kyber1024, PEM_SECRET_KEY, &keypair.secret.bytes);
+
+        let restored = KeyPair::import_secret_key_from_pem(&pem).unwrap();
+        assert_eq!(restored.public_key().as_bytes(), keypair.public_key().as_bytes());
+
+        let (ss, ciphertext) = keypair.public_key().encapsulate().unwrap();
+        assert_eq!(restored.decapsulate(&ciphertext).unwrap().as_bytes(), ss.as_bytes());
+
+        assert!(KeyPair::import_secret_key_from_pem(&keypair.export_public_key_to_pem()).is_err());
+    }
+
     #[test]
     fn test_invalid_public_key() {
         let invalid_bytes = vec![0u8; 10]; // Wrong length
-        assert!(PublicKey::from_bytes(invalid_bytes).is_err());
+        assert!(PublicKey::from_bytes(invalid_bytes, KyberLevel::Kyber1024).is_err());
     }
 
     #[test]
     fn test_invalid_ciphertext() {
         let invalid_bytes = vec![0u8; 10]; // Wrong length
-        assert!(Ciphertext::from_bytes(invalid_bytes).is_err());
+        assert!(Ciphertext::from_bytes(invalid_bytes, KyberLevel::Kyber1024).is_err());
+    }
+
+    #[test]
+    fn test_cross_level_public_key_rejected() {
+        let kp768 = KeyPair::generate_with_level(KyberLevel::Kyber768).unwrap();
+        let kp1024 = KeyPair::generate().unwrap();
+
+        let bytes768 = kp768.public_key().as_bytes().to_vec();
+        let bytes1024 = kp1024.public_key().as_bytes().to_vec();
+
+        assert!(PublicKey::from_bytes(bytes768.clone(), KyberLevel::Kyber1024).is_err());
+        assert!(PublicKey::from_bytes(bytes1024.clone(), KyberLevel::Kyber768).is_err());
+        assert!(PublicKey::from_bytes(bytes768, KyberLevel::Kyber768).is_ok());
+        assert!(PublicKey::from_bytes(bytes1024, KyberLevel::Kyber1024).is_ok());
+    }
+
+    #[test]
+    fn test_all_levels_roundtrip() {
+        for level in [KyberLevel::Kyber512, KyberLevel::Kyber768, KyberLevel::Kyber1024] {
+            let keypair = KeyPair::generate_with_level(level).unwrap();
+            let (ss_encap, ciphertext) = keypair.public_key().encapsulate().unwrap();
+            assert_eq!(ciphertext.as_bytes().len(), level.ciphertext_bytes());
+
+            let ss_decap = keypair.decapsulate(&ciphertext).unwrap();
+            assert_eq!(ss_encap.as_bytes(), ss_decap.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_semi_static_rotation() {
+        let mut key = SemiStaticKeyPair::generate(KyberLevel::Kyber512, Duration::ZERO).unwrap();
+        let old = key.public_key().fingerprint();
+
+        assert!(key.is_expired());
+        assert!(key.rotate_if_due().unwrap());
+        assert_ne!(key.public_key().fingerprint(), old);
+        assert_eq!(key.public_key().level(), KyberLevel::Kyber512);
+
+        let mut key = SemiStaticKeyPair::generate(KyberLevel::Kyber512, Duration::from_secs(3600)).unwrap();
+        assert!(!key.rotate_if_due().unwrap());
+
+        // Each ciphertext is accepted once per key
+        let (_, ciphertext) = key.public_key().encapsulate().unwrap();
+        assert!(key.first_use(&ciphertext));
+        assert!(!key.first_use(&ciphertext));
     }
 }
@@ -2,9 +2,12 @@
 // Provides quantum-resistant encryption, key exchange, and key derivation
 
 pub mod kyber;
+pub mod signing;
 pub mod symmetric;
 pub mod kdf;
 pub mod ratchet;
+pub mod sealed;
+pub mod prekey;
 pub mod random;
 pub mod timing;
 
@@ -26,6 +29,9 @@ pub enum CryptoError {
     #[error("Invalid key material")]
     InvalidKey,
 
+    #[error("Invalid PEM: {0}")]
+    InvalidPem(String),
+
     #[error("Authentication failed")]
     AuthenticationFailed,
 
@@ -34,6 +40,9 @@ pub enum CryptoError {
 
     #[error("Ratchet error: {0}")]
     RatchetError(#[from] RatchetError),
+
+    #[error("Audit log error: {0}")]
+    AuditError(String),
 }
 
 pub type Result<T> = std::result::Result<T, CryptoError>;
@@ -0,0 +1,289 @@
+// Pre-key bundles for starting sessions with offline peers (X3DH-style)
+// Key agreement is KEM-only: the sender encapsulates to published Kyber pre-keys
+
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroizing;
+
+use super::{
+    CryptoError,
+    kdf::derive_keys,
+    kyber::{Ciphertext, KeyPair, KyberLevel, PublicKey},
+    signing::{SigningKeyPair, VerifyingKey},
+};
+
+const SIGNED_PREKEY_CONTEXT: &[u8] = b"aegis-signed-prekey-v1";
+const PREKEY_SALT: &[u8] = b"aegis-prekey-v1-salt";
+
+/// Medium-term Kyber key signed by the owner's identity key
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SignedPreKey {
+    pub public_key: PublicKey,
+    pub signature: Vec<u8>,
+}
+
+/// Single-use Kyber key; the owner deletes its secret once used
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OneTimePreKey {
+    pub id: u32,
+    pub public_key: PublicKey,
+}
+
+/// Everything a sender needs to start a session with an offline peer
+///
+/// Published to a directory. One-time pre-keys are not signed (as in X3DH);
+/// a directory that swaps them only loses the extra forward secrecy they
+/// give, since the signed pre-key is always used. One that withholds them
+/// leaves senders with signed-only initiations, which owners reject unless
+/// they allow them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PreKeyBundle {
+    pub identity: VerifyingKey,
+    pub signed_prekey: SignedPreKey,
+    pub one_time_prekeys: Vec<OneTimePreKey>,
+}
+
+impl PreKeyBundle {
+    /// Check the signed pre-key against the identity key and all key sizes
+    pub fn verify(&self) -> Result<(), CryptoError> {
+        let signed = &self.signed_prekey.public_key;
+        check_key_length(signed)?;
+        for one_time in &self.one_time_prekeys {
+            if one_time.public_key.level() != signed.level() {
+                return Err(CryptoError::InvalidKey);
+            }
+            check_key_length(&one_time.public_key)?;
+        }
+
+        self.identity.verify(&signed_prekey_data(signed), &self.signed_prekey.signature)
+    }
+}
+
+/// What the sender transmits so the owner can derive the same root key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreKeyInitiation {
+    pub signed_prekey_fingerprint: [u8; 32],
+    pub signed_ciphertext: Vec<u8>,
+    /// One-time pre-key id and ciphertext, if the bundle had any left
+    pub one_time: Option<(u32, Vec<u8>)>,
+}
+
+/// Secret half of a published bundle, kept by its owner
+pub struct PreKeyStore {
+    identity: SigningKeyPair,
+    signed_prekey: KeyPair,
+    signature: Vec<u8>,
+    one_time_prekeys: HashMap<u32, KeyPair>,
+    next_id: u32,
+    /// Accept initiations that used no one-time pre-key (replayable)
+    allow_signed_only: bool,
+}
+
+impl PreKeyStore {
+    /// Generate a signed pre-key and `one_time_count` one-time pre-keys
+    pub fn generate(identity: SigningKeyPair, level: KyberLevel, one_time_count: usize) -> Result<Self, CryptoError> {
+        let signed_prekey = KeyPair::generate_with_level(level)?;
+        let signature = identity.sign(&signed_prekey_data(signed_prekey.public_key()))?;
+
+        let mut store = Self {
+            identity,
+            signed_prekey,
+            signature,
+            one_time_prekeys: HashMap::new(),
+            next_id: 0,
+            allow_signed_only: false,
+        };
+        store.replenish(one_time_count)?;
+        Ok(store)
+    }
+
+    /// Add `count` fresh one-time pre-keys (republish the bundle afterwards)
+    pub fn replenish(&mut self, count: usize) -> Result<(), CryptoError> {
+        let level = self.signed_prekey.public_key().level();
+        for _ in 0..count {
+            self.one_time_prekeys.insert(self.next_id, KeyPair::generate_with_level(level)?);
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// Public bundle to publish
+    pub fn bundle(&self) -> PreKeyBundle {
+        let mut one_time_prekeys: Vec<OneTimePreKey> = self.one_time_prekeys.iter()
+            .map(|(&id, keypair)| OneTimePreKey { id, public_key: keypair.public_key().clone() })
+            .collect();
+        one_time_prekeys.sort_by_key(|prekey| prekey.id);
+
+        PreKeyBundle {
+            identity: self.identity.verifying_key().clone(),
+            signed_prekey: SignedPreKey {
+                public_key: self.signed_prekey.public_key().clone(),
+                signature: self.signature.clone(),
+            },
+            one_time_prekeys,
+        }
+    }
+
+    /// One-time pre-keys not yet used
+    pub fn one_time_remaining(&self) -> usize {
+        self.one_time_prekeys.len()
+    }
+
+    /// Accept initiations that used only the signed pre-key (off by default)
+    ///
+    /// Such an initiation consumes nothing, so `respond` derives the same
+    /// root key every time it is replayed; the application has to detect
+    /// replays itself.
+    pub fn set_allow_signed_only(&mut self, allow: bool) {
+        self.allow_signed_only = allow;
+    }
+}
+
+/// Sender side: encapsulate to a bundle, returning the root key and the initiation to send
+///
+/// `one_time_id` names the one-time pre-key to use. The directory should
+/// hand each one out to a single sender, or two senders picking the same
+/// key collide and the owner rejects the second. With `None` only the
+/// signed pre-key is used: the owner rejects that unless it opted in with
+/// `PreKeyStore::set_allow_signed_only`, since nothing stops the initiation
+/// being replayed.
+pub fn initiate(bundle: &PreKeyBundle, one_time_id: Option<u32>) -> Result<([u8; 32], PreKeyInitiation), CryptoError> {
+    bundle.verify()?;
+
+    let one_time_prekey = match one_time_id {
+        Some(id) => Some(bundle.one_time_prekeys.iter().find(|prekey| prekey.id == id)
+            .ok_or_else(|| CryptoError::KeyExchangeError(format!("One-time pre-key {} not in bundle", id)))?),
+        None => None,
+    };
+
+    let signed = &bundle.signed_prekey.public_key;
+    let (signed_secret, signed_ciphertext) = signed.encapsulate()?;
+
+    let mut ikm = Zeroizing::new(signed_secret.as_bytes().to_vec());
+    let one_time = match one_time_prekey {
+        Some(prekey) => {
+            let (secret, ciphertext) = prekey.public_key.encapsulate()?;
+            ikm.extend_from_slice(secret.as_bytes());
+            Some((prekey.id, ciphertext.as_bytes().to_vec()))
+        }
+        None => None,
+    };
+
+    let initiation = PreKeyInitiation {
+        signed_prekey_fingerprint: signed.fingerprint(),
+        signed_ciphertext: signed_ciphertext.as_bytes().to_vec(),
+        one_time,
+    };
+    let root_key = derive_prekey_root(&ikm, &bundle.identity, &initiation)?;
+    Ok((root_key, initiation))
+}
+
+/// Owner side: derive the sender's root key, consuming the one-time pre-key it used
+///
+/// A second initiation naming the same one-time pre-key fails, as does one
+/// naming none unless `set_allow_signed_only` was called.
+pub fn respond(store: &mut PreKeyStore, initiation: &PreKeyInitiation) -> Result<[u8; 32], CryptoError> {
+    let level = store.signed_prekey.public_key().level();
+    if initiation.signed_prekey_fingerprint != store.signed_prekey.public_key().fingerprint() {
+        return Err(CryptoError::KeyExchangeError("Unknown signed pre-key".to_string()));
+    }
+    if initiation.one_time.is_none() && !store.allow_signed_only {
+        return Err(CryptoError::KeyExchangeError("Initiation used no one-time pre-key".to_string()));
+    }
+
+    let signed_ciphertext = Ciphertext::from_bytes(initiation.signed_ciphertext.clone(), level)?;
+    let signed_secret = store.signed_prekey.decapsulate(&signed_ciphertext)?;
+    let mut ikm = Zeroizing::new(signed_secret.as_bytes().to_vec());
+
+    if let Some((id, ciphertext)) = &initiation.one_time {
+        let ciphertext = Ciphertext::from_bytes(ciphertext.clone(), level)?;
+        let keypair = store.one_time_prekeys.get(id)
+            .ok_or_else(|| CryptoError::KeyExchangeError(format!("One-time pre-key {} already used or unknown", id)))?;
+        let secret = keypair.decapsulate(&ciphertext)?;
+        ikm.extend_from_slice(secret.as_bytes());
+
+        // Only burn the key once the initiation has checked out
+        store.one_time_prekeys.remove(id);
+    }
+
+    derive_prekey_root(&ikm, store.identity.verifying_key(), initiation)
+}
+
+/// Root key bound to the owner's identity and the exact initiation
+fn derive_prekey_root(
+    ikm: &[u8],
+    identity: &VerifyingKey,
+    initiation: &PreKeyInitiation,
+) -> Result<[u8; 32], CryptoError> {
+    let mut info = b"aegis-prekey-root-v1".to_vec();
+    info.extend_from_slice(blake3::hash(identity.as_bytes()).as_bytes());
+    info.extend_from_slice(&initiation.signed_prekey_fingerprint);
+    if let Some((id, _)) = &initiation.one_time {
+        info.extend_from_slice(&id.to_be_bytes());
+    }
+
+    let derived = derive_keys(ikm, PREKEY_SALT, &info, 32)?;
+    let mut root_key = [0u8; 32];
+    root_key.copy_from_slice(&derived);
+    Ok(root_key)
+}
+
+/// Bytes covered by the signed pre-key's signature
+fn signed_prekey_data(public_key: &PublicKey) -> Vec<u8> {
+    let mut data = SIGNED_PREKEY_CONTEXT.to_vec();
+    data.extend_from_slice(public_key.as_bytes());
+    data
+}
+
+/// Deserialized keys skip `PublicKey::from_bytes`, so check their size here
+fn check_key_length(public_key: &PublicKey) -> Result<(), CryptoError> {
+    if public_key.as_bytes().len() != public_key.level().public_key_bytes() {
+        return Err(CryptoError::InvalidKey);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(one_time_count: usize) -> PreKeyStore {
+        PreKeyStore::generate(SigningKeyPair::generate().unwrap(), KyberLevel::Kyber768, one_time_count).unwrap()
+    }
+
+    #[test]
+    fn test_prekey_agreement() {
+        let mut store = store(2);
+        let bundle = store.bundle();
+
+        let (sender_root, initiation) = initiate(&bundle, Some(1)).unwrap();
+        assert_eq!(initiation.one_time.as_ref().unwrap().0, 1);
+        assert_eq!(respond(&mut store, &initiation).unwrap(), sender_root);
+        assert_eq!(store.one_time_remaining(), 1);
+
+        // The one-time key is gone, so the same initiation can't be replayed
+        assert!(respond(&mut store, &initiation).is_err());
+        assert!(initiate(&bundle, Some(7)).is_err());
+    }
+
+    #[test]
+    fn test_prekey_agreement_without_one_time_keys() {
+        let mut store = store(0);
+        let (sender_root, initiation) = initiate(&store.bundle(), None).unwrap();
+        assert!(initiation.one_time.is_none());
+
+        // Replayable, so only accepted once the owner opts in
+        assert!(respond(&mut store, &initiation).is_err());
+        store.set_allow_signed_only(true);
+        assert_eq!(respond(&mut store, &initiation).unwrap(), sender_root);
+    }
+
+    #[test]
+    fn test_substituted_signed_prekey_rejected() {
+        let mut bundle = store(1).bundle();
+        bundle.signed_prekey.public_key = store(0).bundle().signed_prekey.public_key;
+
+        assert!(bundle.verify().is_err());
+        assert!(initiate(&bundle, Some(0)).is_err());
+    }
+}
@@ -1,8 +1,9 @@
 // Cryptographically secure random number generation
 // Provides a safe wrapper around the system CSPRNG
 
-use rand::RngCore;
+use rand::{Rng, RngCore};
 use rand::rngs::OsRng;
+use std::time::Duration;
 use zeroize::Zeroize;
 
 use super::CryptoError;
@@ -28,6 +29,37 @@ pub fn generate_nonce() -> Result<[u8; 24], CryptoError> {
     Ok(nonce)
 }
 
+/// `base` shifted by a uniformly random amount within ±`spread`
+///
+/// The spread is capped at half of `base`, so the result stays positive.
+pub fn jittered_duration(base: Duration, spread: Duration) -> Duration {
+    let spread = spread.min(base / 2);
+    let spread_ns = spread.as_nanos().min(u64::MAX as u128 / 2) as u64;
+    if spread_ns == 0 {
+        return base;
+    }
+    base - spread + Duration::from_nanos(OsRng.gen_range(0..=spread_ns * 2))
+}
+
+/// Fill `dest` by repeating `pattern` (deterministic test data, not random)
+#[cfg(any(test, feature = "test_helpers"))]
+pub fn fill_with_pattern(dest: &mut [u8], pattern: &[u8]) {
+    assert!(!pattern.is_empty() || dest.is_empty(), "fill pattern must not be empty");
+    for (byte, value) in dest.iter_mut().zip(pattern.iter().cycle()) {
+        *byte = *value;
+    }
+}
+
+/// Panic if `data` is exactly what `fill_with_pattern` would produce
+///
+/// Guards tests against random output that was never actually filled in.
+#[cfg(any(test, feature = "test_helpers"))]
+pub fn assert_no_pattern(data: &[u8], pattern: &[u8]) {
+    let mut filled = vec![0u8; data.len()];
+    fill_with_pattern(&mut filled, pattern);
+    assert!(data.is_empty() || data != filled.as_slice(), "data matches the test pattern {:02x?}", pattern);
+}
+
 /// Secure random number generator that zeroizes on drop
 pub struct SecureRng {
     seed: Option<[u8; 32]>,
@@ -93,4 +125,34 @@ mod tests {
         rng.fill_bytes(&mut buffer);
         assert_ne!(buffer, [0u8; 32]);
     }
+
+    #[test]
+    fn test_jittered_duration() {
+        let base = Duration::from_secs(60);
+        for _ in 0..100 {
+            let d = jittered_duration(base, Duration::from_secs(5));
+            assert!(d >= Duration::from_secs(55) && d <= Duration::from_secs(65));
+        }
+        assert_eq!(jittered_duration(base, Duration::ZERO), base);
+        // Spread wider than the base is capped
+        assert!(jittered_duration(Duration::from_secs(2), Duration::from_secs(10)) >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_fill_with_pattern() {
+        let mut buffer = [0u8; 7];
+        fill_with_pattern(&mut buffer, b"abc");
+        assert_eq!(&buffer, b"abcabca");
+
+        assert_no_pattern(&secure_random_bytes(64).unwrap(), &[0x42]);
+        assert_no_pattern(&generate_key().unwrap(), b"abc");
+    }
+
+    #[test]
+    #[should_panic(expected = "matches the test pattern")]
+    fn test_assert_no_pattern_catches_pattern() {
+        let mut buffer = [0u8; 16];
+        fill_with_pattern(&mut buffer, &[0x42]);
+        assert_no_pattern(&buffer, &[0x42]);
+    }
 }
@@ -1,20 +1,25 @@
 // Double Ratchet Algorithm for forward secrecy
-// Automatically rotates keys every 60 seconds and per message
+// Advances keys per message; each direction's chain is rotated independently
 
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
-use zeroize::ZeroizeOnDrop;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 use thiserror::Error;
 
+use crate::security::audit::{AuditEvent, AuditLog};
+
 use super::{
     CryptoError,
     kdf::{derive_chain_key, derive_message_key, ratchet_key_hmac},
-    symmetric::SymmetricKey,
+    symmetric::{decrypt, encrypt, EncryptedMessage, SymmetricKey, AEAD},
 };
 
 const ROTATION_INTERVAL_SECS: u64 = 60;
 const MAX_SKIP: usize = 1000; // Maximum skipped messages
 const CHAIN_ADVANCE_CONTEXT: &[u8] = b"chain-advance";
+const RECV_STATE_AAD: &[u8] = b"aegis-recv-state-v1";
+const CHAIN_COMMITMENT_CONTEXT: &str = "aegis-chain-commitment-v1";
 
 #[derive(Error, Debug)]
 pub enum RatchetError {
@@ -29,6 +34,28 @@ pub enum RatchetError {
 
     #[error("Time error: {0}")]
     TimeError(String),
+
+    #[error("Message counter exhausted, session must be rekeyed")]
+    CounterExhausted,
+
+    #[error("Invalid ratchet transition: {event:?} while {state:?}")]
+    InvalidTransition {
+        state: State,
+        event: RatchetEvent,
+    },
+}
+
+/// Audit record of one `rotate_audited` call
+///
+/// Holds hashes of the chain keys, never the keys themselves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotationRecord {
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+    pub pre_rotation_commitment: [u8; 32],
+    pub post_rotation_commitment: [u8; 32],
+    /// Send epoch after the rotation
+    pub rotation_count: u32,
 }
 
 /// Ratchet state for one direction of communication
@@ -52,12 +79,27 @@ pub struct RatchetState {
     /// Receive message counter
     recv_counter: u64,
 
-    /// Last rotation timestamp
+    /// Last send-chain rotation timestamp
     last_rotation: u64,
 
+    /// Seconds between scheduled rotations of the sending chain
+    rotation_interval_secs: u64,
+
+    /// Rotations applied to the sending chain
+    send_epoch: u16,
+
+    /// Rotations of the sending chain since the last `rekey`, without wrapping
+    rotation_count: u32,
+
+    /// Rotations applied to the receiving chain (follows the peer's send epoch)
+    recv_epoch: u16,
+
     /// Skipped message keys for out-of-order messages
     #[zeroize(skip)]
     skipped_message_keys: HashMap<u64, SymmetricKey>,
+
+    /// Most skipped keys kept at once (oldest are pruned first)
+    max_skipped_keys: usize,
 }
 
 impl RatchetState {
@@ -75,7 +117,12 @@ impl RatchetState {
             send_counter: 0,
             recv_counter: 0,
             last_rotation: current_timestamp(),
+            rotation_interval_secs: ROTATION_INTERVAL_SECS,
+            send_epoch: 0,
+            rotation_count: 0,
+            recv_epoch: 0,
             skipped_message_keys: HashMap::new(),
+            max_skipped_keys: MAX_SKIP,
         }
     }
 
@@ -93,94 +140,189 @@ impl RatchetState {
             send_counter: 0,
             recv_counter: 0,
             last_rotation: current_timestamp(),
+            rotation_interval_secs: ROTATION_INTERVAL_SECS,
+            send_epoch: 0,
+            rotation_count: 0,
+            recv_epoch: 0,
             skipped_message_keys: HashMap::new(),
+            max_skipped_keys: MAX_SKIP,
         }
     }
 
     /// Get the next sending message key and advance the chain
     pub fn next_send_key(&mut self) -> Result<(SymmetricKey, u64), CryptoError> {
-        // Check if rotation is needed
-        self.check_and_rotate()?;
+        // The counter after this one must still be representable
+        let next_counter = self.send_counter.checked_add(1)
+            .ok_or(CryptoError::RatchetError(RatchetError::CounterExhausted))?;
 
         let message_key = derive_message_key(&self.send_chain_key, self.send_counter)?;
         let counter = self.send_counter;
 
         // Advance the chain
         self.send_chain_key = derive_chain_key(&self.send_chain_key, CHAIN_ADVANCE_CONTEXT)?;
-        self.send_counter += 1;
+        self.send_counter = next_counter;
 
         Ok((message_key, counter))
     }
 
     /// Get the receiving message key for a given counter
     pub fn get_recv_key(&mut self, message_counter: u64) -> Result<SymmetricKey, CryptoError> {
-        // Check if this is a skipped message
-        if let Some(key) = self.skipped_message_keys.remove(&message_counter) {
-            return Ok(key);
+        recv_key_from_chain(
+            &mut self.recv_chain_key,
+            &mut self.recv_counter,
+            &mut self.skipped_message_keys,
+            self.max_skipped_keys,
+            message_counter,
+        )
+    }
+
+    /// Export only the receiving chain, encrypted under `wrap_key`
+    ///
+    /// Lets a trusted archiver decrypt what this side receives (see
+    /// `RecvOnlyRatchet`) without learning the sending chain. The blob is
+    /// `nonce || ciphertext`.
+    pub fn export_recv_state_encrypted(&self, wrap_key: &SymmetricKey) -> Result<Vec<u8>, CryptoError> {
+        let mut state = Zeroizing::new(Vec::with_capacity(44 + self.skipped_message_keys.len() * 40));
+        state.extend_from_slice(&self.recv_chain_key);
+        state.extend_from_slice(&self.recv_counter.to_be_bytes());
+        state.extend_from_slice(&(self.skipped_message_keys.len() as u32).to_be_bytes());
+        for (counter, key) in &self.skipped_message_keys {
+            state.extend_from_slice(&counter.to_be_bytes());
+            state.extend_from_slice(key.as_bytes());
         }
 
-        // If message is in the future, store skipped keys
-        if message_counter > self.recv_counter {
-            let skip_count = (message_counter - self.recv_counter) as usize;
-            if skip_count > MAX_SKIP {
-                return Err(CryptoError::RatchetError(RatchetError::TooManySkippedMessages));
-            }
+        let encrypted = encrypt(wrap_key, &state, RECV_STATE_AAD)?;
+        let mut blob = Vec::with_capacity(24 + encrypted.ciphertext.len());
+        blob.extend_from_slice(&encrypted.nonce);
+        blob.extend_from_slice(&encrypted.ciphertext);
+        Ok(blob)
+    }
 
-            // Store keys for skipped messages
-            for i in self.recv_counter..message_counter {
-                let skipped_key = derive_message_key(&self.recv_chain_key, i)?;
-                self.skipped_message_keys.insert(i, skipped_key);
-                self.recv_chain_key = derive_chain_key(&self.recv_chain_key, CHAIN_ADVANCE_CONTEXT)?;
-            }
+    /// Rotate both chains at once
+    ///
+    /// Only stays in sync if the peer does the same at the same point in the
+    /// message stream; prefer `rotate_send` plus an announcement.
+    pub fn rotate(&mut self) -> Result<(), CryptoError> {
+        self.rotate_send()?;
+        self.rotate_recv(self.recv_epoch.wrapping_add(1))
+    }
 
-            self.recv_counter = message_counter;
-        }
+    /// `rotate`, recording commitments to the chain keys before and after
+    ///
+    /// Each record's pre-rotation commitment matches the previous record's
+    /// post-rotation one, so an auditor holding the final chain keys can check
+    /// the last commitment (`chain_commitment`) and the sequence leading to it.
+    /// If the log fails, the rotation has still happened.
+    pub fn rotate_audited(&mut self, audit_log: &mut AuditLog) -> Result<RotationRecord, CryptoError> {
+        let pre_rotation_commitment = self.chain_commitment();
+        self.rotate()?;
+
+        let record = RotationRecord {
+            timestamp: self.last_rotation,
+            pre_rotation_commitment,
+            post_rotation_commitment: self.chain_commitment(),
+            rotation_count: self.rotation_count,
+        };
+        audit_log.record(AuditEvent::RatchetRotated(record.clone()))
+            .map_err(|e| CryptoError::AuditError(e.to_string()))?;
+        Ok(record)
+    }
 
-        // Derive the message key
-        let message_key = derive_message_key(&self.recv_chain_key, message_counter)?;
+    /// BLAKE3 commitment to the current send and receive chain keys and the epoch
+    pub fn chain_commitment(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new_derive_key(CHAIN_COMMITMENT_CONTEXT);
+        hasher.update(&self.send_chain_key);
+        hasher.update(&self.recv_chain_key);
+        hasher.update(&self.rotation_count.to_be_bytes());
+        *hasher.finalize().as_bytes()
+    }
 
-        // Advance the chain if this is the next expected message
-        if message_counter == self.recv_counter {
-            self.recv_chain_key = derive_chain_key(&self.recv_chain_key, CHAIN_ADVANCE_CONTEXT)?;
-            self.recv_counter += 1;
-        }
+    /// Rotate the sending chain on the local schedule
+    ///
+    /// Returns the new send epoch, which must be announced to the peer so it
+    /// can follow with `rotate_recv` before it sees the next message.
+    pub fn rotate_send(&mut self) -> Result<u16, CryptoError> {
+        self.rotate_send_mixing(&[])
+    }
 
-        Ok(message_key)
+    /// Rotate the sending chain, mixing in a fresh shared secret (DH-style rekey)
+    ///
+    /// Unlike `rotate_send`, a compromised chain key alone doesn't reveal the
+    /// new chain. The peer follows with `rotate_recv_with_secret`.
+    pub fn rotate_send_with_secret(&mut self, shared_secret: &[u8; 32]) -> Result<u16, CryptoError> {
+        self.rotate_send_mixing(shared_secret)
     }
 
-    /// Force a key rotation (called automatically every 60 seconds)
-    pub fn rotate(&mut self) -> Result<(), CryptoError> {
-        let timestamp = current_timestamp();
+    /// Follow a rotation the peer announced for its sending chain
+    pub fn rotate_recv(&mut self, epoch: u16) -> Result<(), CryptoError> {
+        self.rotate_recv_mixing(epoch, &[])
+    }
 
-        // Ratchet both chains with timestamp as context
-        let mut context = b"rotation-v1-".to_vec();
-        context.extend_from_slice(&timestamp.to_le_bytes());
+    /// Follow a peer's `rotate_send_with_secret`
+    pub fn rotate_recv_with_secret(&mut self, epoch: u16, shared_secret: &[u8; 32]) -> Result<(), CryptoError> {
+        self.rotate_recv_mixing(epoch, shared_secret)
+    }
 
-        self.send_chain_key = ratchet_key_hmac(&self.send_chain_key, &context)?;
-        self.recv_chain_key = ratchet_key_hmac(&self.recv_chain_key, &context)?;
+    fn rotate_send_mixing(&mut self, secret: &[u8]) -> Result<u16, CryptoError> {
+        let epoch = self.send_epoch.wrapping_add(1);
+        self.send_chain_key = ratchet_key_hmac(&self.send_chain_key, &rotation_context_mixing(epoch, secret))?;
+        self.send_epoch = epoch;
+        self.rotation_count = self.rotation_count.wrapping_add(1);
+        self.last_rotation = current_timestamp();
+        Ok(epoch)
+    }
 
-        self.last_rotation = timestamp;
+    fn rotate_recv_mixing(&mut self, epoch: u16, secret: &[u8]) -> Result<(), CryptoError> {
+        if epoch != self.recv_epoch.wrapping_add(1) {
+            return Err(CryptoError::RatchetError(RatchetError::InvalidState));
+        }
 
-        // Reset counters (optional, for additional security)
-        // Uncomment if you want to reset message counters on rotation
-        // self.send_counter = 0;
-        // self.recv_counter = 0;
+        self.recv_chain_key = ratchet_key_hmac(&self.recv_chain_key, &rotation_context_mixing(epoch, secret))?;
+        self.recv_epoch = epoch;
 
-        // Clear old skipped keys to prevent memory buildup
-        if self.skipped_message_keys.len() > 100 {
-            self.skipped_message_keys.clear();
-        }
+        // Skipped keys are finished message keys, not chain keys, so they
+        // stay valid across the rotation; only the size cap applies
+        self.prune_skipped_keys();
 
         Ok(())
     }
 
-    /// Check if rotation is needed and perform it
-    fn check_and_rotate(&mut self) -> Result<(), CryptoError> {
-        let now = current_timestamp();
-        if now >= self.last_rotation + ROTATION_INTERVAL_SECS {
-            self.rotate()?;
-        }
-        Ok(())
+    /// Current send-chain epoch
+    pub fn send_epoch(&self) -> u16 {
+        self.send_epoch
+    }
+
+    /// Current recv-chain epoch
+    pub fn recv_epoch(&self) -> u16 {
+        self.recv_epoch
+    }
+
+    /// Rotations of our sending chain since creation or the last `rekey`
+    ///
+    /// `send_epoch` is this truncated to the 16 bits a message's `key_id` holds.
+    pub fn epoch(&self) -> u32 {
+        self.rotation_count
+    }
+
+    /// Set how many skipped message keys are retained (across rotations too)
+    pub fn set_max_skipped_keys(&mut self, max: usize) {
+        self.max_skipped_keys = max;
+        self.prune_skipped_keys();
+    }
+
+    /// Get the skipped message key cap
+    pub fn max_skipped_keys(&self) -> usize {
+        self.max_skipped_keys
+    }
+
+    /// Number of skipped message keys currently held
+    pub fn skipped_key_count(&self) -> usize {
+        self.skipped_message_keys.len()
+    }
+
+    /// Drop the oldest skipped keys beyond the configured cap
+    fn prune_skipped_keys(&mut self) {
+        prune_skipped_keys(&mut self.skipped_message_keys, self.max_skipped_keys);
     }
 
     /// Get current send counter
@@ -195,9 +337,35 @@ impl RatchetState {
 
     /// Get seconds until next rotation
     pub fn seconds_until_rotation(&self) -> u64 {
-        let now = current_timestamp();
-        let elapsed = now.saturating_sub(self.last_rotation);
-        ROTATION_INTERVAL_SECS.saturating_sub(elapsed)
+        self.rotation_interval_secs.saturating_sub(self.seconds_since_rotation())
+    }
+
+    /// Seconds since the sending chain last rotated
+    pub fn seconds_since_rotation(&self) -> u64 {
+        current_timestamp().saturating_sub(self.last_rotation)
+    }
+
+    /// Time between scheduled rotations (60 seconds by default)
+    pub fn rotation_interval(&self) -> Duration {
+        Duration::from_secs(self.rotation_interval_secs)
+    }
+
+    /// Change the rotation interval (whole seconds, at least one)
+    pub fn set_rotation_interval(&mut self, interval: Duration) {
+        self.rotation_interval_secs = interval.as_secs().max(1);
+    }
+
+    /// Key for a purpose outside the message chains, e.g. side-channel MACs
+    ///
+    /// Derived from the root key, so it stays the same across rotations.
+    pub fn derive_subkey(&self, label: &[u8]) -> Result<[u8; 32], CryptoError> {
+        ratchet_key_hmac(&self.root_key, label)
+    }
+
+    /// The root key itself, for `Session::dump_keys` only
+    #[cfg(debug_assertions)]
+    pub(crate) fn root_key(&self) -> &[u8; 32] {
+        &self.root_key
     }
 
     /// Reset the ratchet with a new root key (for rekeying)
@@ -208,11 +376,241 @@ impl RatchetState {
         self.send_counter = 0;
         self.recv_counter = 0;
         self.last_rotation = current_timestamp();
+        self.send_epoch = 0;
+        self.rotation_count = 0;
+        self.recv_epoch = 0;
         self.skipped_message_keys.clear();
         Ok(())
     }
 }
 
+/// Decrypt-only view of one ratchet's receiving chain
+///
+/// Built from `RatchetState::export_recv_state_encrypted`; it has no
+/// sending chain and cannot follow key rotations.
+#[derive(ZeroizeOnDrop)]
+pub struct RecvOnlyRatchet {
+    recv_chain_key: [u8; 32],
+
+    recv_counter: u64,
+
+    #[zeroize(skip)]
+    skipped_message_keys: HashMap<u64, SymmetricKey>,
+}
+
+impl RecvOnlyRatchet {
+    /// Decrypt an exported receive state
+    pub fn from_encrypted(data: &[u8], wrap_key: &SymmetricKey) -> Result<Self, CryptoError> {
+        let invalid = || CryptoError::DecryptionError("Invalid receive state".to_string());
+
+        if data.len() < 24 {
+            return Err(invalid());
+        }
+        let (nonce, ciphertext) = data.split_at(24);
+        let encrypted = EncryptedMessage {
+            nonce: nonce.try_into().map_err(|_| invalid())?,
+            ciphertext: ciphertext.to_vec(),
+            aead: AEAD::XChaCha20Poly1305,
+        };
+        let state = Zeroizing::new(decrypt(wrap_key, &encrypted, RECV_STATE_AAD)?);
+
+        if state.len() < 44 {
+            return Err(invalid());
+        }
+        let mut ratchet = Self {
+            recv_chain_key: state[..32].try_into().map_err(|_| invalid())?,
+            recv_counter: u64::from_be_bytes(state[32..40].try_into().map_err(|_| invalid())?),
+            skipped_message_keys: HashMap::new(),
+        };
+
+        let count = u32::from_be_bytes(state[40..44].try_into().map_err(|_| invalid())?) as usize;
+        let entries = &state[44..];
+        if count > MAX_SKIP || entries.len() != count * 40 {
+            return Err(invalid());
+        }
+        for entry in entries.chunks_exact(40) {
+            let counter = u64::from_be_bytes(entry[..8].try_into().map_err(|_| invalid())?);
+            ratchet.skipped_message_keys.insert(counter, SymmetricKey::from_slice(&entry[8..])?);
+        }
+
+        Ok(ratchet)
+    }
+
+    /// Get the receiving message key for a given counter
+    pub fn get_recv_key(&mut self, message_counter: u64) -> Result<SymmetricKey, CryptoError> {
+        recv_key_from_chain(
+            &mut self.recv_chain_key,
+            &mut self.recv_counter,
+            &mut self.skipped_message_keys,
+            MAX_SKIP,
+            message_counter,
+        )
+    }
+
+    /// Get current receive counter
+    pub fn recv_counter(&self) -> u64 {
+        self.recv_counter
+    }
+}
+
+/// Phase of a `DoubleRatchet`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum State {
+    #[default]
+    Uninitialized,
+    /// Initialized as sender; nothing received yet
+    SendChainActive,
+    /// Initialized as receiver; may not send until the first message arrives
+    RecvChainActive,
+    BothActive,
+    /// A rotation is under way; receiving continues, sending waits
+    RotationPending,
+}
+
+/// Step that moves a `DoubleRatchet` between states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatchetEvent {
+    InitAsSender,
+    InitAsReceiver,
+    MessageSent,
+    MessageReceived,
+    RotationStarted,
+    RotationCompleted,
+}
+
+/// State machine of the double ratchet protocol's allowed orderings
+///
+/// Holds no keys; drive it alongside a `RatchetState` to catch send, receive
+/// and rotation calls made out of order, such as sending before setup or
+/// rotating before both chains are in use. The rules:
+///
+/// - Initialization happens once, as sender or receiver.
+/// - A receiver sends only after its first message arrives.
+/// - Rotation starts only once both chains are active, and nothing is sent
+///   until it completes (messages on the old chain may still arrive).
+#[derive(Debug, Clone, Default)]
+pub struct DoubleRatchet {
+    state: State,
+}
+
+impl DoubleRatchet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Apply an event; an invalid one fails and leaves the state unchanged
+    pub fn transition(&mut self, event: RatchetEvent) -> Result<(), RatchetError> {
+        use RatchetEvent::*;
+
+        self.state = match (self.state, event) {
+            (State::Uninitialized, InitAsSender) => State::SendChainActive,
+            (State::Uninitialized, InitAsReceiver) => State::RecvChainActive,
+
+            (State::SendChainActive, MessageSent) => State::SendChainActive,
+            (State::SendChainActive | State::RecvChainActive, MessageReceived) => State::BothActive,
+
+            (State::BothActive, MessageSent | MessageReceived) => State::BothActive,
+            (State::BothActive, RotationStarted) => State::RotationPending,
+
+            (State::RotationPending, MessageReceived) => State::RotationPending,
+            (State::RotationPending, RotationCompleted) => State::BothActive,
+
+            (state, event) => return Err(RatchetError::InvalidTransition { state, event }),
+        };
+        Ok(())
+    }
+}
+
+/// Look up or derive the key for `message_counter`, advancing the receiving chain
+fn recv_key_from_chain(
+    chain_key: &mut [u8; 32],
+    recv_counter: &mut u64,
+    skipped: &mut HashMap<u64, SymmetricKey>,
+    max_skipped: usize,
+    message_counter: u64,
+) -> Result<SymmetricKey, CryptoError> {
+    // Check if this is a skipped message
+    if let Some(key) = skipped.remove(&message_counter) {
+        return Ok(key);
+    }
+
+    // Anything older without a stored key was already consumed (a replay) or pruned
+    if message_counter < *recv_counter {
+        return Err(CryptoError::RatchetError(RatchetError::MessageKeyNotFound));
+    }
+
+    // If message is in the future, store skipped keys
+    if message_counter > *recv_counter {
+        // Bound the skip before doing any arithmetic on the attacker-chosen counter
+        if message_counter - *recv_counter > MAX_SKIP as u64 {
+            return Err(CryptoError::RatchetError(RatchetError::TooManySkippedMessages));
+        }
+        if message_counter == u64::MAX {
+            return Err(CryptoError::RatchetError(RatchetError::CounterExhausted));
+        }
+
+        // Store keys for skipped messages
+        for i in *recv_counter..message_counter {
+            let skipped_key = derive_message_key(chain_key, i)?;
+            skipped.insert(i, skipped_key);
+            *chain_key = derive_chain_key(chain_key, CHAIN_ADVANCE_CONTEXT)?;
+        }
+
+        *recv_counter = message_counter;
+
+        // Repeated jumps must not grow the map without bound
+        prune_skipped_keys(skipped, max_skipped);
+    }
+
+    // Derive the message key
+    let message_key = derive_message_key(chain_key, message_counter)?;
+
+    // Advance the chain if this is the next expected message
+    if message_counter == *recv_counter {
+        let next_counter = recv_counter.checked_add(1)
+            .ok_or(CryptoError::RatchetError(RatchetError::CounterExhausted))?;
+        *chain_key = derive_chain_key(chain_key, CHAIN_ADVANCE_CONTEXT)?;
+        *recv_counter = next_counter;
+    }
+
+    Ok(message_key)
+}
+
+/// Drop the oldest skipped keys beyond `max`
+fn prune_skipped_keys(skipped: &mut HashMap<u64, SymmetricKey>, max: usize) {
+    let excess = skipped.len().saturating_sub(max);
+    if excess == 0 {
+        return;
+    }
+
+    let mut counters: Vec<u64> = skipped.keys().copied().collect();
+    counters.sort_unstable();
+    for counter in &counters[..excess] {
+        skipped.remove(counter);
+    }
+}
+
+/// HMAC context for the rotation into `epoch`; both peers derive it identically
+fn rotation_context(epoch: u16) -> Vec<u8> {
+    let mut context = b"rotation-v1-".to_vec();
+    context.extend_from_slice(&epoch.to_le_bytes());
+    context
+}
+
+/// Rotation context with a shared secret appended (empty for plain rotations)
+fn rotation_context_mixing(epoch: u16, secret: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut context = Zeroizing::new(rotation_context(epoch));
+    if !secret.is_empty() {
+        context.extend_from_slice(b"-dh-");
+        context.extend_from_slice(secret);
+    }
+    context
+}
+
 /// Get current Unix timestamp in seconds
 fn current_timestamp() -> u64 {
     SystemTime::now()
@@ -224,6 +622,38 @@ fn current_timestamp() -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_double_ratchet_transitions() {
+        use RatchetEvent::*;
+
+        let mut sender = DoubleRatchet::new();
+        for event in [InitAsSender, MessageSent, MessageSent, MessageReceived, RotationStarted, MessageReceived, RotationCompleted, MessageSent] {
+            sender.transition(event).unwrap();
+        }
+        assert_eq!(sender.state(), State::BothActive);
+
+        let mut receiver = DoubleRatchet::new();
+        assert!(matches!(
+            receiver.transition(MessageSent),
+            Err(RatchetError::InvalidTransition { state: State::Uninitialized, event: MessageSent })
+        ));
+        receiver.transition(InitAsReceiver).unwrap();
+        assert!(receiver.transition(MessageSent).is_err());
+        assert!(receiver.transition(RotationStarted).is_err());
+        assert_eq!(receiver.state(), State::RecvChainActive);
+        receiver.transition(MessageReceived).unwrap();
+        receiver.transition(MessageSent).unwrap();
+
+        receiver.transition(RotationStarted).unwrap();
+        assert!(receiver.transition(MessageSent).is_err());
+        assert!(receiver.transition(RotationStarted).is_err());
+        assert!(receiver.transition(InitAsSender).is_err());
+        assert_eq!(receiver.state(), State::RotationPending);
+        receiver.transition(RotationCompleted).unwrap();
+        assert!(receiver.transition(RotationCompleted).is_err());
+    }
 
     #[test]
     fn test_ratchet_initialization() {
@@ -280,6 +710,45 @@ mod tests {
         assert_ne!(key0.as_bytes(), key2.as_bytes());
     }
 
+    #[test]
+    fn test_replayed_counter_rejected() {
+        let mut ratchet = RatchetState::new([4u8; 32]);
+
+        ratchet.get_recv_key(0).unwrap();
+        ratchet.get_recv_key(2).unwrap();
+        ratchet.get_recv_key(1).unwrap();
+
+        for counter in 0..3 {
+            assert!(matches!(
+                ratchet.get_recv_key(counter),
+                Err(CryptoError::RatchetError(RatchetError::MessageKeyNotFound))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_recv_only_ratchet_export() {
+        let mut sender = RatchetState::new([8u8; 32]);
+        let mut receiver = RatchetState::new_responder([8u8; 32]);
+        let sent: Vec<_> = (0..4).map(|_| sender.next_send_key().unwrap().0).collect();
+
+        // Message 1 is still outstanding when the state is exported
+        receiver.get_recv_key(0).unwrap();
+        receiver.get_recv_key(2).unwrap();
+
+        let wrap_key = SymmetricKey::new([0x5au8; 32]);
+        let blob = receiver.export_recv_state_encrypted(&wrap_key).unwrap();
+        let mut archiver = RecvOnlyRatchet::from_encrypted(&blob, &wrap_key).unwrap();
+
+        assert_eq!(archiver.recv_counter(), 3);
+        assert_eq!(archiver.get_recv_key(1).unwrap().as_bytes(), sent[1].as_bytes());
+        assert_eq!(archiver.get_recv_key(3).unwrap().as_bytes(), sent[3].as_bytes());
+        assert!(archiver.get_recv_key(0).is_err());
+
+        let wrong_key = SymmetricKey::new([0xa5u8; 32]);
+        assert!(RecvOnlyRatchet::from_encrypted(&blob, &wrong_key).is_err());
+    }
+
     #[test]
     fn test_too_many_skipped() {
         let root_key = [5u8; 32];
@@ -290,6 +759,88 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_counters_near_u64_max() {
+        let mut ratchet = RatchetState::new([12u8; 32]);
+
+        ratchet.send_counter = u64::MAX - 1;
+        let (_, counter) = ratchet.next_send_key().unwrap();
+        assert_eq!(counter, u64::MAX - 1);
+        assert!(matches!(
+            ratchet.next_send_key(),
+            Err(CryptoError::RatchetError(RatchetError::CounterExhausted))
+        ));
+        assert_eq!(ratchet.send_counter(), u64::MAX);
+
+        ratchet.recv_counter = u64::MAX - 2;
+        ratchet.get_recv_key(u64::MAX - 2).unwrap();
+        assert!(matches!(
+            ratchet.get_recv_key(u64::MAX),
+            Err(CryptoError::RatchetError(RatchetError::CounterExhausted))
+        ));
+        assert_eq!(ratchet.recv_counter(), u64::MAX - 1);
+    }
+
+    #[test]
+    fn test_skipped_keys_survive_rotation() {
+        use crate::crypto::symmetric::{encrypt_simple, decrypt_simple};
+
+        let mut sender = RatchetState::new([13u8; 32]);
+        let mut receiver = RatchetState::new_responder([13u8; 32]);
+
+        let mut sent = Vec::new();
+        for i in 0..6u8 {
+            let (key, counter) = sender.next_send_key().unwrap();
+            sent.push((counter, encrypt_simple(&key, &[i]).unwrap()));
+        }
+
+        // Message 5 arrives first, so 0..5 are skipped
+        receiver.get_recv_key(5).unwrap();
+        assert_eq!(receiver.skipped_key_count(), 5);
+
+        receiver.rotate().unwrap();
+
+        for (counter, encrypted) in &sent[..5] {
+            let key = receiver.get_recv_key(*counter).unwrap();
+            assert_eq!(decrypt_simple(&key, encrypted).unwrap(), vec![*counter as u8]);
+        }
+        assert_eq!(receiver.skipped_key_count(), 0);
+    }
+
+    #[test]
+    fn test_max_skipped_keys_cap() {
+        let mut ratchet = RatchetState::new([14u8; 32]);
+        ratchet.set_max_skipped_keys(3);
+
+        ratchet.get_recv_key(10).unwrap();
+        assert_eq!(ratchet.skipped_key_count(), 3);
+
+        // The newest skipped keys are the ones kept
+        assert!(ratchet.skipped_message_keys.contains_key(&9));
+        assert!(!ratchet.skipped_message_keys.contains_key(&6));
+    }
+
+    #[test]
+    fn test_rotation_followed_by_peer() {
+        let mut alice = RatchetState::new([15u8; 32]);
+        let mut bob = RatchetState::new_responder([15u8; 32]);
+
+        let epoch = alice.rotate_send().unwrap();
+        bob.rotate_recv(epoch).unwrap();
+
+        // Alice -> Bob uses the rotated chain on both ends
+        let (sent, counter) = alice.next_send_key().unwrap();
+        assert_eq!(bob.get_recv_key(counter).unwrap().as_bytes(), sent.as_bytes());
+
+        // Bob -> Alice is untouched
+        let (sent, counter) = bob.next_send_key().unwrap();
+        assert_eq!(alice.get_recv_key(counter).unwrap().as_bytes(), sent.as_bytes());
+
+        // Epochs can't be skipped or replayed
+        assert!(bob.rotate_recv(epoch).is_err());
+        assert!(bob.rotate_recv(epoch + 2).is_err());
+    }
+
     #[test]
     fn test_manual_rotation() {
         let root_key = [6u8; 32];
@@ -306,6 +857,20 @@ mod tests {
         assert_ne!(key1.as_bytes(), key2.as_bytes());
     }
 
+    #[test]
+    fn test_epoch_counts_rotations() {
+        let mut ratchet = RatchetState::new([6u8; 32]);
+        assert_eq!(ratchet.epoch(), 0);
+
+        ratchet.rotate().unwrap();
+        ratchet.rotate_send().unwrap();
+        assert_eq!(ratchet.epoch(), 2);
+        assert_eq!(ratchet.send_epoch(), 2);
+
+        ratchet.rekey([8u8; 32]).unwrap();
+        assert_eq!(ratchet.epoch(), 0);
+    }
+
     #[test]
     fn test_rekey() {
         let root_key1 = [7u8; 32];
@@ -326,6 +891,72 @@ mod tests {
         assert_ne!(key1.as_bytes(), key2.as_bytes());
     }
 
+    #[test]
+    fn test_rotate_audited() {
+        use crate::security::audit::{verify_chain, MemoryAuditSink};
+
+        let sink = MemoryAuditSink::new();
+        let mut log = AuditLog::new(sink.clone());
+        let mut ratchet = RatchetState::new([9u8; 32]);
+
+        let first = ratchet.rotate_audited(&mut log).unwrap();
+        let second = ratchet.rotate_audited(&mut log).unwrap();
+
+        assert_eq!(first.rotation_count, 1);
+        assert_eq!(second.rotation_count, 2);
+        assert_eq!(second.pre_rotation_commitment, first.post_rotation_commitment);
+        assert_eq!(second.post_rotation_commitment, ratchet.chain_commitment());
+        assert_ne!(first.pre_rotation_commitment, first.post_rotation_commitment);
+
+        let entries = sink.entries();
+        assert!(verify_chain(&entries).is_ok());
+        assert_eq!(entries[1].event, AuditEvent::RatchetRotated(second));
+    }
+
+    fn adversarial_counter() -> impl Strategy<Value = u64> {
+        prop_oneof![
+            0..(3 * MAX_SKIP as u64),
+            (u64::MAX - 4)..=u64::MAX,
+            any::<u64>(),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_recv_counters_never_panic(counters in prop::collection::vec(adversarial_counter(), 1..48)) {
+            let mut ratchet = RatchetState::new([10u8; 32]);
+
+            for counter in counters {
+                let before = ratchet.recv_counter();
+                let result = ratchet.get_recv_key(counter);
+
+                if counter > before && counter - before > MAX_SKIP as u64 {
+                    prop_assert!(matches!(
+                        result,
+                        Err(CryptoError::RatchetError(RatchetError::TooManySkippedMessages))
+                    ));
+                    prop_assert_eq!(ratchet.recv_counter(), before);
+                }
+
+                prop_assert!(ratchet.recv_counter() >= before);
+                prop_assert!(ratchet.skipped_message_keys.len() <= MAX_SKIP);
+            }
+        }
+    }
+
+    #[test]
+    fn test_skipped_keys_bounded_across_jumps() {
+        let mut ratchet = RatchetState::new([11u8; 32]);
+
+        for step in 1..=5u64 {
+            ratchet.get_recv_key(step * MAX_SKIP as u64).unwrap();
+        }
+
+        assert!(ratchet.skipped_message_keys.len() <= MAX_SKIP);
+    }
+
     #[test]
     fn test_seconds_until_rotation() {
         let root_key = [9u8; 32];
@@ -334,4 +965,17 @@ mod tests {
         let seconds = ratchet.seconds_until_rotation();
         assert!(seconds <= ROTATION_INTERVAL_SECS);
     }
+
+    #[test]
+    fn test_rotation_interval_change() {
+        let mut ratchet = RatchetState::new([9u8; 32]);
+        assert_eq!(ratchet.rotation_interval(), Duration::from_secs(ROTATION_INTERVAL_SECS));
+
+        ratchet.set_rotation_interval(Duration::from_secs(5));
+        assert!(ratchet.seconds_until_rotation() <= 5);
+
+        // Sub-second intervals round up to one second
+        ratchet.set_rotation_interval(Duration::from_millis(10));
+        assert_eq!(ratchet.rotation_interval(), Duration::from_secs(1));
+    }
 }
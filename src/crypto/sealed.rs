@@ -0,0 +1,195 @@
+// Anonymous sealed boxes: one-shot encryption to a recipient's Kyber public key
+// No handshake runs, so the AEAD suite is carried in the sealed message itself
+
+use aes_gcm::{Aes256Gcm, Nonce as GcmNonce};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+
+use super::{
+    CryptoError,
+    kdf::derive_keys,
+    kyber::{Ciphertext, KeyPair, PublicKey},
+    random::secure_random_bytes,
+    symmetric::SymmetricKey,
+};
+
+/// AEAD used for a sealed message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum SealSuite {
+    #[default]
+    XChaCha20Poly1305 = 0x01,
+    Aes256Gcm = 0x02,
+}
+
+impl SealSuite {
+    /// Nonce length in bytes
+    pub fn nonce_len(self) -> usize {
+        match self {
+            SealSuite::XChaCha20Poly1305 => 24,
+            SealSuite::Aes256Gcm => 12,
+        }
+    }
+}
+
+impl TryFrom<u8> for SealSuite {
+    type Error = CryptoError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(SealSuite::XChaCha20Poly1305),
+            0x02 => Ok(SealSuite::Aes256Gcm),
+            _ => Err(CryptoError::DecryptionError(format!("Unknown sealed box suite: {:#04x}", value))),
+        }
+    }
+}
+
+/// Parsed sealed message: suite tag, KEM ciphertext, nonce and AEAD ciphertext
+pub struct SealedMessage {
+    pub suite: SealSuite,
+    pub kem_ciphertext: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl SealedMessage {
+    /// Serialize as `suite || kem_ciphertext || nonce || ciphertext`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + self.kem_ciphertext.len() + self.nonce.len() + self.ciphertext.len());
+        bytes.push(self.suite as u8);
+        bytes.extend_from_slice(&self.kem_ciphertext);
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+
+    /// Parse a sealed message whose KEM ciphertext is `kem_ciphertext_len` bytes
+    pub fn from_bytes(bytes: &[u8], kem_ciphertext_len: usize) -> Result<Self, CryptoError> {
+        let (&tag, rest) = bytes.split_first()
+            .ok_or_else(|| CryptoError::DecryptionError("Empty sealed message".to_string()))?;
+        let suite = SealSuite::try_from(tag)?;
+
+        if rest.len() < kem_ciphertext_len + suite.nonce_len() {
+            return Err(CryptoError::DecryptionError("Sealed message too short".to_string()));
+        }
+
+        let (kem_ciphertext, rest) = rest.split_at(kem_ciphertext_len);
+        let (nonce, ciphertext) = rest.split_at(suite.nonce_len());
+
+        Ok(Self {
+            suite,
+            kem_ciphertext: kem_ciphertext.to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+
+    /// Header bound into the AEAD so the suite tag can't be swapped
+    fn associated_data(&self) -> Vec<u8> {
+        let mut aad = vec![self.suite as u8];
+        aad.extend_from_slice(&self.kem_ciphertext);
+        aad
+    }
+}
+
+/// Encrypt `plaintext` so only the holder of `recipient`'s secret key can read it
+pub fn seal_anonymous(recipient: &PublicKey, plaintext: &[u8], suite: SealSuite) -> Result<Vec<u8>, CryptoError> {
+    let (shared_secret, kem_ciphertext) = recipient.encapsulate()?;
+    let key = derive_seal_key(shared_secret.as_bytes(), suite)?;
+
+    let mut sealed = SealedMessage {
+        suite,
+        kem_ciphertext: kem_ciphertext.as_bytes().to_vec(),
+        nonce: secure_random_bytes(suite.nonce_len())?,
+        ciphertext: Vec::new(),
+    };
+    let aad = sealed.associated_data();
+    let payload = Payload { msg: plaintext, aad: &aad };
+
+    sealed.ciphertext = match suite {
+        SealSuite::XChaCha20Poly1305 => XChaCha20Poly1305::new(key.as_bytes().into())
+            .encrypt(XNonce::from_slice(&sealed.nonce), payload),
+        SealSuite::Aes256Gcm => Aes256Gcm::new(key.as_bytes().into())
+            .encrypt(GcmNonce::from_slice(&sealed.nonce), payload),
+    }
+    .map_err(|e| CryptoError::EncryptionError(format!("Sealing failed: {}", e)))?;
+
+    Ok(sealed.to_bytes())
+}
+
+/// Open a sealed message with the recipient's keypair, using whichever suite it names
+pub fn open_anonymous(keypair: &KeyPair, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let level = keypair.public_key().level();
+    let sealed = SealedMessage::from_bytes(sealed, level.ciphertext_bytes())?;
+
+    let kem_ciphertext = Ciphertext::from_bytes(sealed.kem_ciphertext.clone(), level)?;
+    let shared_secret = keypair.decapsulate(&kem_ciphertext)?;
+    let key = derive_seal_key(shared_secret.as_bytes(), sealed.suite)?;
+
+    let aad = sealed.associated_data();
+    let payload = Payload { msg: &sealed.ciphertext, aad: &aad };
+
+    match sealed.suite {
+        SealSuite::XChaCha20Poly1305 => XChaCha20Poly1305::new(key.as_bytes().into())
+            .decrypt(XNonce::from_slice(&sealed.nonce), payload),
+        SealSuite::Aes256Gcm => Aes256Gcm::new(key.as_bytes().into())
+            .decrypt(GcmNonce::from_slice(&sealed.nonce), payload),
+    }
+    .map_err(|_| CryptoError::DecryptionError("Authentication failed or invalid ciphertext".to_string()))
+}
+
+/// Per-suite key so one shared secret never keys two different ciphers
+fn derive_seal_key(shared_secret: &[u8; 32], suite: SealSuite) -> Result<SymmetricKey, CryptoError> {
+    let mut info = b"aegis-sealed-box-v1-".to_vec();
+    info.push(suite as u8);
+
+    let derived = derive_keys(shared_secret, &[], &info, 32)?;
+    SymmetricKey::from_slice(&derived)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_each_suite() {
+        let recipient = KeyPair::generate().unwrap();
+
+        for suite in [SealSuite::XChaCha20Poly1305, SealSuite::Aes256Gcm] {
+            let sealed = seal_anonymous(recipient.public_key(), b"drop box", suite).unwrap();
+            assert_eq!(sealed[0], suite as u8);
+            assert_eq!(open_anonymous(&recipient, &sealed).unwrap(), b"drop box");
+        }
+    }
+
+    #[test]
+    fn test_unknown_suite_rejected() {
+        let recipient = KeyPair::generate().unwrap();
+        let mut sealed = seal_anonymous(recipient.public_key(), b"drop box", SealSuite::default()).unwrap();
+
+        sealed[0] = 0x7f;
+        assert!(open_anonymous(&recipient, &sealed).is_err());
+    }
+
+    #[test]
+    fn test_suite_tag_is_authenticated() {
+        let recipient = KeyPair::generate().unwrap();
+        let sealed = seal_anonymous(recipient.public_key(), b"drop box", SealSuite::Aes256Gcm).unwrap();
+
+        // Relabelling as XChaCha shifts the nonce boundary and changes the AAD
+        let mut relabelled = sealed.clone();
+        relabelled[0] = SealSuite::XChaCha20Poly1305 as u8;
+        assert!(open_anonymous(&recipient, &relabelled).is_err());
+    }
+
+    #[test]
+    fn test_wrong_recipient_rejected() {
+        let recipient = KeyPair::generate().unwrap();
+        let other = KeyPair::generate().unwrap();
+        let sealed = seal_anonymous(recipient.public_key(), b"drop box", SealSuite::default()).unwrap();
+
+        assert!(open_anonymous(&other, &sealed).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+// Post-quantum digital signatures using Dilithium5
+// Provides quantum-resistant message authentication independent of encryption
+
+use pqcrypto_dilithium::dilithium5;
+use pqcrypto_traits::sign::{PublicKey as PQPublicKey, SecretKey as PQSecretKey, DetachedSignature as PQDetachedSignature};
+use zeroize::ZeroizeOnDrop;
+use serde::{Serialize, Deserialize};
+
+use super::CryptoError;
+
+/// Dilithium5 keypair for signing messages
+#[derive(ZeroizeOnDrop)]
+pub struct SigningKeyPair {
+    #[zeroize(skip)]
+    pub public: VerifyingKey,
+    secret: SigningSecretKey,
+}
+
+/// Public verification key wrapper
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VerifyingKey {
+    bytes: Vec<u8>,
+}
+
+/// Secret signing key wrapper (zeroized on drop)
+#[derive(ZeroizeOnDrop)]
+struct SigningSecretKey {
+    bytes: Vec<u8>,
+}
+
+impl SigningKeyPair {
+    /// Generate a new Dilithium5 keypair
+    pub fn generate() -> Result<Self, CryptoError> {
+        let (pk, sk) = dilithium5::keypair();
+
+        Ok(Self {
+            public: VerifyingKey {
+                bytes: pk.as_bytes().to_vec(),
+            },
+            secret: SigningSecretKey {
+                bytes: sk.as_bytes().to_vec(),
+            },
+        })
+    }
+
+    /// Produce a detached signature over `data`
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let sk = dilithium5::SecretKey::from_bytes(&self.secret.bytes)
+            .map_err(|_| CryptoError::InvalidKey)?;
+
+        Ok(dilithium5::detached_sign(data, &sk).as_bytes().to_vec())
+    }
+
+    pub fn verifying_key(&self) -> &VerifyingKey {
+        &self.public
+    }
+}
+
+/// Length in bytes of every detached signature
+pub fn signature_len() -> usize {
+    dilithium5::signature_bytes()
+}
+
+impl VerifyingKey {
+    /// Verify a detached signature over `data`
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> Result<(), CryptoError> {
+        let pk = dilithium5::PublicKey::from_bytes(&self.bytes)
+            .map_err(|_| CryptoError::InvalidKey)?;
+
+        let sig = dilithium5::DetachedSignature::from_bytes(signature)
+            .map_err(|_| CryptoError::AuthenticationFailed)?;
+
+        dilithium5::verify_detached_signature(&sig, data, &pk)
+            .map_err(|_| CryptoError::AuthenticationFailed)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// BLAKE3 hash identifying this key
+    pub fn fingerprint(&self) -> [u8; 32] {
+        *blake3::hash(&self.bytes).as_bytes()
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, CryptoError> {
+        // Validate the public key length
+        if bytes.len() != dilithium5::public_key_bytes() {
+            return Err(CryptoError::InvalidKey);
+        }
+        Ok(Self { bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let keypair = SigningKeyPair::generate().unwrap();
+        let signature = keypair.sign(b"hello").unwrap();
+
+        assert!(keypair.verifying_key().verify(b"hello", &signature).is_ok());
+        assert!(keypair.verifying_key().verify(b"hellp", &signature).is_err());
+    }
+
+    #[test]
+    fn test_wrong_key_rejected() {
+        let keypair = SigningKeyPair::generate().unwrap();
+        let other = SigningKeyPair::generate().unwrap();
+        let signature = keypair.sign(b"hello").unwrap();
+
+        assert!(matches!(
+            other.verifying_key().verify(b"hello", &signature),
+            Err(CryptoError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_verifying_key() {
+        assert!(VerifyingKey::from_bytes(vec![0u8; 10]).is_err());
+    }
+}
@@ -1,98 +1,159 @@
-// Symmetric encryption using ChaCha20-Poly1305 AEAD
+// Symmetric encryption using XChaCha20-Poly1305 or AES-256-GCM-SIV AEAD
 // Provides fast, authenticated encryption with 256-bit keys
 
+use aes_gcm_siv::Aes256GcmSiv;
 use chacha20poly1305::{
     aead::{Aead, KeyInit, Payload},
     XChaCha20Poly1305, XNonce,
 };
-use zeroize::ZeroizeOnDrop;
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 use serde::{Serialize, Deserialize};
 
 use super::{CryptoError, random::generate_nonce};
+use crate::storage::ephemeral::{AccessCounter, AccessReport};
+
+const GCM_SIV_SUBKEY_CONTEXT: &str = "aegis-gcm-siv-subkey-v1";
+
+/// AEAD cipher a message was sealed with
+///
+/// Both take 24-byte nonces. AES-256-GCM-SIV natively takes 12, so the
+/// first 12 bytes select a per-message subkey (as XChaCha does for ChaCha)
+/// and the last 12 are its nonce.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AEAD {
+    #[default]
+    XChaCha20Poly1305,
+    Aes256GcmSiv,
+}
+
+impl AEAD {
+    /// AES-256-GCM-SIV when the CPU has AES-NI and carry-less multiply, else XChaCha20-Poly1305
+    pub fn fastest_for_platform() -> AEAD {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if std::arch::is_x86_feature_detected!("aes") && std::arch::is_x86_feature_detected!("pclmulqdq") {
+                return AEAD::Aes256GcmSiv;
+            }
+        }
+        AEAD::XChaCha20Poly1305
+    }
+
+    /// Encrypt under a fresh random nonce; the result is tagged with this cipher
+    pub fn encrypt(&self, key: &SymmetricKey, plaintext: &[u8], associated_data: &[u8]) -> Result<EncryptedMessage, CryptoError> {
+        if null_cipher_active() {
+            return Ok(EncryptedMessage { nonce: [0u8; 24], ciphertext: plaintext.to_vec(), aead: *self });
+        }
+
+        let nonce = generate_nonce()
+            .map_err(|_| CryptoError::EncryptionError("Failed to generate nonce".to_string()))?;
+        let payload = Payload { msg: plaintext, aad: associated_data };
+
+        let ciphertext = match self {
+            AEAD::XChaCha20Poly1305 => XChaCha20Poly1305::new(key.as_bytes().into())
+                .encrypt(XNonce::from_slice(&nonce), payload),
+            AEAD::Aes256GcmSiv => Aes256GcmSiv::new(gcm_siv_subkey(key, &nonce).as_slice().into())
+                .encrypt(aes_gcm_siv::Nonce::from_slice(&nonce[12..]), payload),
+        }
+        .map_err(|e| CryptoError::EncryptionError(format!("Encryption failed: {}", e)))?;
+
+        Ok(EncryptedMessage { nonce, ciphertext, aead: *self })
+    }
+
+    /// Decrypt with this cipher, whatever the message is tagged with
+    pub fn decrypt(&self, key: &SymmetricKey, encrypted: &EncryptedMessage, associated_data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if null_cipher_active() {
+            return Ok(encrypted.ciphertext.clone());
+        }
+
+        let payload = Payload { msg: &encrypted.ciphertext, aad: associated_data };
+        match self {
+            AEAD::XChaCha20Poly1305 => XChaCha20Poly1305::new(key.as_bytes().into())
+                .decrypt(XNonce::from_slice(&encrypted.nonce), payload),
+            AEAD::Aes256GcmSiv => Aes256GcmSiv::new(gcm_siv_subkey(key, &encrypted.nonce).as_slice().into())
+                .decrypt(aes_gcm_siv::Nonce::from_slice(&encrypted.nonce[12..]), payload),
+        }
+        .map_err(|_| CryptoError::DecryptionError("Authentication failed or invalid ciphertext".to_string()))
+    }
+}
 
-const TAG_SIZE: usize = 16;
+/// Per-message AES key from the first half of the 24-byte nonce
+fn gcm_siv_subkey(key: &SymmetricKey, nonce: &[u8; 24]) -> Zeroizing<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new_derive_key(GCM_SIV_SUBKEY_CONTEXT);
+    hasher.update(key.as_bytes());
+    hasher.update(&nonce[..12]);
+    Zeroizing::new(*hasher.finalize().as_bytes())
+}
 
 /// Encrypted message with nonce and authentication tag
 #[derive(Clone, Serialize, Deserialize)]
 pub struct EncryptedMessage {
     pub nonce: [u8; 24],
     pub ciphertext: Vec<u8>,
+    /// Cipher it was sealed with, so `decrypt` can handle a mix
+    #[serde(default)]
+    pub aead: AEAD,
 }
 
-/// Symmetric key for ChaCha20-Poly1305 (zeroized on drop)
-#[derive(Clone, ZeroizeOnDrop)]
+/// Symmetric key for either AEAD (zeroized on drop)
+///
+/// Message keys are created and dropped per message, so they aren't given a
+/// locked page each; reads are still counted for the audit log.
 pub struct SymmetricKey {
-    key: [u8; 32],
+    key: Zeroizing<[u8; 32]>,
+    access: AccessCounter,
+}
+
+/// `Zeroizing` wipes the key when dropped
+impl ZeroizeOnDrop for SymmetricKey {}
+
+/// The copy starts with no recorded accesses
+impl Clone for SymmetricKey {
+    fn clone(&self) -> Self {
+        Self::new(*self.key)
+    }
 }
 
 impl SymmetricKey {
     /// Create a new symmetric key from bytes
     pub fn new(key: [u8; 32]) -> Self {
-        Self { key }
+        Self { key: Zeroizing::new(key), access: AccessCounter::new() }
     }
 
-    /// Get the key as a byte slice
+    /// Get the key as a byte slice; each call counts as an access
     pub fn as_bytes(&self) -> &[u8; 32] {
+        self.access.record();
         &self.key
     }
 
+    /// How often the key bytes have been read
+    pub fn access_report(&self) -> AccessReport {
+        self.access.report()
+    }
+
     /// Create from a slice (must be exactly 32 bytes)
     pub fn from_slice(slice: &[u8]) -> Result<Self, CryptoError> {
-        if slice.len() != 32 {
-            return Err(CryptoError::InvalidKey);
-        }
-        let mut key = [0u8; 32];
-        key.copy_from_slice(slice);
-        Ok(Self { key })
+        let key: [u8; 32] = slice.try_into().map_err(|_| CryptoError::InvalidKey)?;
+        Ok(Self::new(key))
     }
 }
 
-/// Encrypt plaintext with associated data
+/// Encrypt plaintext with associated data (XChaCha20-Poly1305)
 pub fn encrypt(
     key: &SymmetricKey,
     plaintext: &[u8],
     associated_data: &[u8],
 ) -> Result<EncryptedMessage, CryptoError> {
-    let nonce_bytes = generate_nonce()
-        .map_err(|_| CryptoError::EncryptionError("Failed to generate nonce".to_string()))?;
-
-    let cipher = XChaCha20Poly1305::new(key.as_bytes().into());
-    let nonce = XNonce::from_slice(&nonce_bytes);
-
-    let payload = Payload {
-        msg: plaintext,
-        aad: associated_data,
-    };
-
-    let ciphertext = cipher
-        .encrypt(nonce, payload)
-        .map_err(|e| CryptoError::EncryptionError(format!("Encryption failed: {}", e)))?;
-
-    Ok(EncryptedMessage {
-        nonce: nonce_bytes,
-        ciphertext,
-    })
+    AEAD::XChaCha20Poly1305.encrypt(key, plaintext, associated_data)
 }
 
-/// Decrypt ciphertext with associated data
+/// Decrypt ciphertext with associated data, using the cipher it is tagged with
 pub fn decrypt(
     key: &SymmetricKey,
     encrypted: &EncryptedMessage,
     associated_data: &[u8],
 ) -> Result<Vec<u8>, CryptoError> {
-    let cipher = XChaCha20Poly1305::new(key.as_bytes().into());
-    let nonce = XNonce::from_slice(&encrypted.nonce);
-
-    let payload = Payload {
-        msg: &encrypted.ciphertext,
-        aad: associated_data,
-    };
-
-    let plaintext = cipher
-        .decrypt(nonce, payload)
-        .map_err(|_| CryptoError::DecryptionError("Authentication failed or invalid ciphertext".to_string()))?;
-
-    Ok(plaintext)
+    encrypted.aead.decrypt(key, encrypted, associated_data)
 }
 
 /// Encrypt without associated data
@@ -105,6 +166,48 @@ pub fn decrypt_simple(key: &SymmetricKey, encrypted: &EncryptedMessage) -> Resul
     decrypt(key, encrypted, &[])
 }
 
+#[cfg(test)]
+thread_local! {
+    static NULL_CIPHER_ACTIVE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Unit-test-only suite that turns `encrypt`/`decrypt` (and their `_simple`
+/// forms) into identity functions while the guard is alive
+///
+/// Isolates ratchet desyncs from AEAD failures: with the cipher stubbed out,
+/// plaintext shows up on the wire and keys are ignored. The switch is
+/// per-thread, and none of this is compiled outside `cfg(test)`.
+#[cfg(test)]
+pub(crate) struct NullCipher {
+    _private: (),
+}
+
+#[cfg(test)]
+impl NullCipher {
+    pub(crate) fn enable() -> Self {
+        NULL_CIPHER_ACTIVE.with(|active| active.set(true));
+        Self { _private: () }
+    }
+}
+
+#[cfg(test)]
+impl Drop for NullCipher {
+    fn drop(&mut self) {
+        NULL_CIPHER_ACTIVE.with(|active| active.set(false));
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn null_cipher_active() -> bool {
+    NULL_CIPHER_ACTIVE.with(|active| active.get())
+}
+
+#[cfg(not(test))]
+#[inline(always)]
+pub(crate) fn null_cipher_active() -> bool {
+    false
+}
+
 /// Constant-time comparison to prevent timing attacks
 pub fn constant_time_compare(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
@@ -182,6 +285,41 @@ mod tests {
         assert!(decrypt_simple(&key2, &encrypted).is_err());
     }
 
+    #[test]
+    fn test_aead_dispatch() {
+        let key = SymmetricKey::new(generate_key().unwrap());
+
+        let chacha = encrypt(&key, b"before upgrade", b"aad").unwrap();
+        let siv = AEAD::Aes256GcmSiv.encrypt(&key, b"after upgrade", b"aad").unwrap();
+        assert_eq!(siv.aead, AEAD::Aes256GcmSiv);
+
+        // Each message decrypts by its own tag
+        assert_eq!(decrypt(&key, &chacha, b"aad").unwrap(), b"before upgrade");
+        assert_eq!(decrypt(&key, &siv, b"aad").unwrap(), b"after upgrade");
+        assert!(decrypt(&key, &siv, b"other").is_err());
+
+        let mut mislabeled = siv.clone();
+        mislabeled.aead = AEAD::XChaCha20Poly1305;
+        assert!(decrypt(&key, &mislabeled, b"aad").is_err());
+    }
+
+    #[test]
+    fn test_null_cipher_is_identity() {
+        let key = SymmetricKey::new(generate_key().unwrap());
+        let other = SymmetricKey::new(generate_key().unwrap());
+
+        {
+            let _null = NullCipher::enable();
+            let encrypted = encrypt_simple(&key, b"visible").unwrap();
+            assert_eq!(encrypted.ciphertext, b"visible");
+            // Keys are ignored, so a key desync still decrypts
+            assert_eq!(decrypt_simple(&other, &encrypted).unwrap(), b"visible");
+        }
+
+        let encrypted = encrypt_simple(&key, b"hidden").unwrap();
+        assert_ne!(encrypted.ciphertext, b"hidden");
+    }
+
     #[test]
     fn test_tampered_aad() {
         let key_bytes = generate_key().unwrap();
@@ -38,7 +38,7 @@ pub fn pad_to_block_size(data: &[u8], block_size: usize) -> Vec<u8> {
     let unpadded_size = 2 + data.len();
 
     // Round up to next multiple of block_size
-    let padding_needed = if unpadded_size % block_size == 0 {
+    let padding_needed = if unpadded_size.is_multiple_of(block_size) {
         0
     } else {
         block_size - (unpadded_size % block_size)
@@ -144,6 +144,72 @@ pub fn constant_time_gt_u64(a: u64, b: u64) -> u8 {
     (!underflow as u8) & ((diff != 0) as u8)
 }
 
+/// Calls timed together as one sample, so short functions register on the clock
+const CALLS_PER_SAMPLE: u32 = 64;
+
+/// Timing statistics across inputs, in nanoseconds per call
+#[derive(Debug, Clone, Copy)]
+pub struct TimingReport {
+    pub mean_ns: f64,
+    pub stddev_ns: f64,
+    /// Largest distance of any input's time from the mean
+    pub max_deviation_ns: f64,
+    /// Standard deviation is under 5% of the mean
+    pub is_likely_constant_time: bool,
+}
+
+/// Statistical timing check for code that must not depend on its input
+pub struct BenchmarkHarness {
+    iterations: u32,
+}
+
+impl BenchmarkHarness {
+    /// Take `iterations` samples per input
+    pub fn new(iterations: u32) -> Self {
+        Self { iterations: iterations.max(1) }
+    }
+
+    /// Time `f` on each input and compare the per-input timings
+    ///
+    /// Inputs are sampled round-robin so drift in machine load hits them
+    /// all alike, and each input is summarised by its median sample.
+    pub fn measure_ct<F: Fn(&[u8]) -> bool>(&self, f: F, inputs: Vec<Vec<u8>>) -> TimingReport {
+        let mut samples = vec![Vec::with_capacity(self.iterations as usize); inputs.len()];
+
+        for _ in 0..self.iterations {
+            for (input, samples) in inputs.iter().zip(samples.iter_mut()) {
+                let start = Instant::now();
+                for _ in 0..CALLS_PER_SAMPLE {
+                    std::hint::black_box(f(std::hint::black_box(input)));
+                }
+                samples.push(start.elapsed().as_nanos() as f64 / CALLS_PER_SAMPLE as f64);
+            }
+        }
+
+        let per_input: Vec<f64> = samples.into_iter().map(median).collect();
+        if per_input.is_empty() {
+            return TimingReport { mean_ns: 0.0, stddev_ns: 0.0, max_deviation_ns: 0.0, is_likely_constant_time: true };
+        }
+
+        let count = per_input.len() as f64;
+        let mean_ns = per_input.iter().sum::<f64>() / count;
+        let stddev_ns = (per_input.iter().map(|t| (t - mean_ns).powi(2)).sum::<f64>() / count).sqrt();
+        let max_deviation_ns = per_input.iter().map(|t| (t - mean_ns).abs()).fold(0.0, f64::max);
+
+        TimingReport {
+            mean_ns,
+            stddev_ns,
+            max_deviation_ns,
+            is_likely_constant_time: stddev_ns < 0.05 * mean_ns,
+        }
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    values[values.len() / 2]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,4 +302,77 @@ mod tests {
         let unpadded = unpad(&padded).unwrap();
         assert_eq!(unpadded.as_slice(), data);
     }
+
+    // Timing audits: `cargo test --release -- --ignored ct_audit`
+
+    fn assert_constant_time(report: TimingReport) {
+        assert!(report.is_likely_constant_time, "timing depends on input: {:?}", report);
+    }
+
+    #[test]
+    fn test_harness_flags_early_exit() {
+        let secret = vec![0u8; 4096];
+        let early_exit = |input: &[u8]| input.iter().zip(&secret).all(|(a, b)| a == b);
+
+        let mut differs_first = vec![0u8; 4096];
+        differs_first[0] = 1;
+        let report = BenchmarkHarness::new(50).measure_ct(early_exit, vec![differs_first, vec![0u8; 4096]]);
+
+        assert!(report.mean_ns > 0.0);
+        assert!(report.max_deviation_ns >= report.stddev_ns);
+        assert!(!report.is_likely_constant_time);
+    }
+
+    #[test]
+    #[ignore]
+    fn ct_audit_constant_time_eq() {
+        let secret = [0x5au8; 64];
+        let inputs = (0..64).step_by(9).map(|i| {
+            let mut input = secret.to_vec();
+            input[i] ^= 1;
+            input
+        }).chain(std::iter::once(secret.to_vec())).collect();
+
+        assert_constant_time(BenchmarkHarness::new(2000).measure_ct(|input| constant_time_eq(input, &secret), inputs));
+    }
+
+    #[test]
+    #[ignore]
+    fn ct_audit_decrypt() {
+        use crate::crypto::symmetric::{decrypt_simple, encrypt_simple, EncryptedMessage, SymmetricKey};
+
+        let key = SymmetricKey::new([3u8; 32]);
+        let encrypted = encrypt_simple(&key, &[0u8; 256]).unwrap();
+        // Where a forgery is corrupted must not show; success vs. failure may
+        let last = encrypted.ciphertext.len() - 1;
+        let inputs = [0, 128, last - 16, last].into_iter().map(|i| {
+            let mut ciphertext = encrypted.ciphertext.clone();
+            ciphertext[i] ^= 1;
+            ciphertext
+        }).collect();
+
+        let report = BenchmarkHarness::new(500).measure_ct(|ciphertext| {
+            let msg = EncryptedMessage { nonce: encrypted.nonce, ciphertext: ciphertext.to_vec(), aead: encrypted.aead };
+            decrypt_simple(&key, &msg).is_ok()
+        }, inputs);
+        assert_constant_time(report);
+    }
+
+    #[test]
+    #[ignore]
+    fn ct_audit_verify_mac() {
+        use crate::crypto::kdf::{ratchet_key_hmac, verify_mac};
+
+        let key = [4u8; 32];
+        let tag = ratchet_key_hmac(&key, b"payload").unwrap();
+        let inputs = [None, Some(0), Some(16), Some(31)].into_iter().map(|tamper| {
+            let mut tag = tag.to_vec();
+            if let Some(i) = tamper {
+                tag[i] ^= 1;
+            }
+            tag
+        }).collect();
+
+        assert_constant_time(BenchmarkHarness::new(2000).measure_ct(|tag| verify_mac(&key, b"payload", tag), inputs));
+    }
 }
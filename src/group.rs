@@ -0,0 +1,72 @@
+// Shared-key group sessions for broadcast rooms
+// One ciphertext per message, readable by every member who knows the room secret
+
+use crate::crypto::{
+    CryptoError,
+    kdf::derive_room_key,
+    symmetric::{decrypt, encrypt, EncryptedMessage, SymmetricKey},
+};
+
+/// Room where all members encrypt and decrypt with one derived key
+///
+/// This is much weaker than per-sender keys: the key never changes, so there
+/// is no forward secrecy (a leaked room secret exposes every past and future
+/// message), any member can impersonate any other, and removing a member
+/// means moving everyone to a new room secret. Suited to broadcast channels,
+/// not private conversation.
+pub struct GroupSession {
+    room_id: String,
+    key: SymmetricKey,
+}
+
+impl GroupSession {
+    /// Join `room_id` using the secret shared out of band with its members
+    pub fn new_shared_key(room_secret: &[u8], room_id: &str) -> Result<Self, CryptoError> {
+        Ok(Self {
+            room_id: room_id.to_string(),
+            key: derive_room_key(room_secret, room_id)?,
+        })
+    }
+
+    pub fn room_id(&self) -> &str {
+        &self.room_id
+    }
+
+    /// Encrypt once for every member; bound to this room so it can't be replayed into another
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedMessage, CryptoError> {
+        encrypt(&self.key, plaintext, self.room_id.as_bytes())
+    }
+
+    /// Decrypt a message any member of the room encrypted
+    pub fn decrypt(&self, message: &EncryptedMessage) -> Result<Vec<u8>, CryptoError> {
+        decrypt(&self.key, message, self.room_id.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_members_share_the_room_key() {
+        let members: Vec<GroupSession> = (0..3)
+            .map(|_| GroupSession::new_shared_key(b"room secret", "announcements").unwrap())
+            .collect();
+
+        let message = members[0].encrypt(b"hello everyone").unwrap();
+        for member in &members {
+            assert_eq!(member.decrypt(&message).unwrap(), b"hello everyone");
+        }
+    }
+
+    #[test]
+    fn test_other_rooms_cannot_read() {
+        let room = GroupSession::new_shared_key(b"room secret", "announcements").unwrap();
+        let message = room.encrypt(b"members only").unwrap();
+
+        let other_room = GroupSession::new_shared_key(b"room secret", "random").unwrap();
+        let outsider = GroupSession::new_shared_key(b"guess", "announcements").unwrap();
+        assert!(other_room.decrypt(&message).is_err());
+        assert!(outsider.decrypt(&message).is_err());
+    }
+}
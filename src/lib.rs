@@ -5,3 +5,6 @@ pub mod network;
 pub mod storage;
 pub mod security;
 pub mod session;
+pub mod group;
+pub mod telemetry;
+pub mod ui;
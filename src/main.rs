@@ -1,17 +1,14 @@
 // Aegis - Quantum-Secure Terminal Chat System
 // A post-quantum encrypted messaging system with forward secrecy
 
-mod crypto;
-mod network;
-mod storage;
-mod ui;
-mod security;
-mod session;
-
+use aegis::{network, session};
+use aegis::network::connection::{IpCidr, ListenerOpts};
+use aegis::network::protocol::{PeerInfo, WireVersion};
 use clap::Parser;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::mpsc;
-use tokio::time::{interval, Duration};
+use tokio::time::{interval, sleep_until, Duration};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::io::Write;
 
 #[derive(Parser, Debug)]
@@ -29,35 +26,150 @@ enum Commands {
     /// Start server and listen for connections
     Listen {
         /// Port to listen on
-        #[arg(short, long, default_value = "9999")]
+        #[arg(short, long, env = "AEGIS_PORT", default_value = "9999")]
         port: u16,
 
-        /// Key rotation interval in seconds
-        #[arg(short = 'r', long, default_value = "60")]
-        rotation_interval: u64,
+        /// Local address to listen on
+        #[arg(long, env = "AEGIS_BIND_ADDRESS", default_value = "0.0.0.0")]
+        bind_address: std::net::IpAddr,
 
-        /// Use TLS 1.3 encryption
-        #[arg(short, long)]
-        tls: bool,
+        /// Network interface to bind to (Linux only)
+        #[arg(long, env = "AEGIS_INTERFACE")]
+        interface: Option<String>,
+
+        /// Only accept peers in this CIDR range (repeatable)
+        #[arg(long = "allow", value_name = "CIDR")]
+        allow: Vec<IpCidr>,
+
+        /// Reject peers in this CIDR range (repeatable)
+        #[arg(long = "deny", value_name = "CIDR")]
+        deny: Vec<IpCidr>,
+
+        #[command(flatten)]
+        session: SessionArgs,
     },
 
     /// Connect to a peer
     Connect {
         /// Address to connect to (host:port)
+        #[arg(env = "AEGIS_ADDRESS")]
         address: String,
 
-        /// Key rotation interval in seconds
-        #[arg(short = 'r', long, default_value = "60")]
-        rotation_interval: u64,
+        #[command(flatten)]
+        session: SessionArgs,
+
+        /// Server name for TLS verification
+        #[arg(short = 's', long, env = "AEGIS_SERVER_NAME", default_value = "localhost")]
+        server_name: String,
+
+        /// Require the TLS certificate to be logged by this CT log (base64 DER public key, as in the log lists; repeatable)
+        #[arg(long = "ct-log-key", value_name = "BASE64", value_parser = parse_ct_log_key)]
+        ct_log_keys: Vec<network::ct::CtLog>,
+
+        /// Tunnel stdin/stdout through the session instead of chatting (status goes to stderr)
+        #[arg(long)]
+        stdio: bool,
+    },
+
+    /// Connect to a peer and measure heartbeat round-trip times
+    Benchmark {
+        /// Address to connect to (host:port)
+        #[arg(env = "AEGIS_ADDRESS")]
+        address: String,
+
+        /// Number of heartbeats to send
+        #[arg(short = 'n', long, default_value = "100")]
+        count: u32,
+
+        #[command(flatten)]
+        session: SessionArgs,
+
+        /// Server name for TLS verification
+        #[arg(short = 's', long, env = "AEGIS_SERVER_NAME", default_value = "localhost")]
+        server_name: String,
+    },
+
+    /// Accept plain TCP locally and carry each connection to a reverse proxy over an Aegis session
+    Proxy {
+        /// Local address to accept plain TCP connections on
+        listen_addr: std::net::SocketAddr,
+
+        /// Address of the remote `reverse-proxy` (host:port)
+        backend_addr: String,
+
+        /// File holding the key shared with the reverse proxy (at least 16 bytes)
+        #[arg(long, env = "AEGIS_PSK_FILE")]
+        psk_file: std::path::PathBuf,
 
-        /// Use TLS 1.3 encryption
-        #[arg(short, long)]
+        /// Use TLS 1.3 underneath the sessions
+        #[arg(short, long, env = "AEGIS_TLS")]
         tls: bool,
 
         /// Server name for TLS verification
-        #[arg(short = 's', long, default_value = "localhost")]
+        #[arg(short = 's', long, env = "AEGIS_SERVER_NAME", default_value = "localhost")]
         server_name: String,
     },
+
+    /// Accept Aegis sessions from `proxy` and forward the decrypted data to a TCP backend
+    ReverseProxy {
+        /// Address to accept Aegis sessions on
+        listen_addr: std::net::SocketAddr,
+
+        /// Plain TCP service to forward to (host:port)
+        backend_addr: String,
+
+        /// File holding the key shared with the proxy (at least 16 bytes)
+        #[arg(long, env = "AEGIS_PSK_FILE")]
+        psk_file: std::path::PathBuf,
+
+        /// Use TLS 1.3 underneath the sessions
+        #[arg(short, long, env = "AEGIS_TLS")]
+        tls: bool,
+    },
+
+    /// Print the PEM public key of a stored Kyber secret key file
+    ShowPublicKey {
+        /// PEM file holding a `KYBER<n> SECRET KEY` block
+        key_file: std::path::PathBuf,
+    },
+
+    /// Print effective settings (from arguments or environment) as KEY=VALUE lines
+    PrintEnv {
+        /// Port to listen on
+        #[arg(short, long, env = "AEGIS_PORT", default_value = "9999")]
+        port: u16,
+
+        /// Address to connect to (host:port)
+        #[arg(long, env = "AEGIS_ADDRESS")]
+        address: Option<String>,
+
+        #[command(flatten)]
+        session: SessionArgs,
+
+        /// Server name for TLS verification
+        #[arg(short = 's', long, env = "AEGIS_SERVER_NAME", default_value = "localhost")]
+        server_name: String,
+    },
+}
+
+/// Options shared by every mode that runs a session
+#[derive(clap::Args, Debug)]
+struct SessionArgs {
+    /// Key rotation interval in seconds
+    #[arg(short = 'r', long, env = "AEGIS_ROTATION_INTERVAL", default_value = "60")]
+    rotation_interval: u64,
+
+    /// Use TLS 1.3 encryption
+    #[arg(short, long, env = "AEGIS_TLS")]
+    tls: bool,
+
+    /// Display name announced to the peer
+    #[arg(long, env = "AEGIS_NAME")]
+    name: Option<String>,
+
+    /// File holding a seed shared out of band; rekeys from it every minute
+    #[arg(long, env = "AEGIS_OOB_SEED_FILE")]
+    oob_seed_file: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
@@ -69,17 +181,61 @@ async fn main() {
 
     let args = Args::parse();
 
-    println!("🛡️  Aegis - Quantum-Secure Terminal Chat");
-    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    println!();
+    if let Commands::PrintEnv { port, address, session, server_name } = &args.command {
+        println!("AEGIS_PORT={}", port);
+        println!("AEGIS_ADDRESS={}", address.as_deref().unwrap_or_default());
+        println!("AEGIS_ROTATION_INTERVAL={}", session.rotation_interval);
+        println!("AEGIS_TLS={}", session.tls);
+        println!("AEGIS_NAME={}", session.name.as_deref().unwrap_or_default());
+        println!("AEGIS_OOB_SEED_FILE={}", session.oob_seed_file.as_ref().map(|p| p.display().to_string()).unwrap_or_default());
+        println!("AEGIS_SERVER_NAME={}", server_name);
+        return;
+    }
+
+    if let Commands::ShowPublicKey { key_file } = &args.command {
+        if let Err(e) = show_public_key(key_file) {
+            eprintln!("❌ Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Keep stdout clean for `connect --stdio`
+    if !matches!(args.command, Commands::Connect { stdio: true, .. }) {
+        println!("🛡️  Aegis - Quantum-Secure Terminal Chat");
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        println!();
+    }
 
     let result = match args.command {
-        Commands::Listen { port, rotation_interval, tls } => {
-            run_server(port, rotation_interval, tls).await
+        Commands::Listen { port, bind_address, interface, allow, deny, session } => {
+            match load_oob_seed(session.oob_seed_file.as_deref()) {
+                Ok(oob_seed) => {
+                    let bind = std::net::SocketAddr::new(bind_address, port);
+                    let opts = ListenerOpts { allow, deny, interface };
+                    run_server(bind, opts, session.rotation_interval, session.tls, session.name, oob_seed).await
+                }
+                Err(e) => Err(e),
+            }
         }
-        Commands::Connect { address, rotation_interval, tls, server_name } => {
-            run_client(&address, rotation_interval, tls, &server_name).await
+        Commands::Connect { address, session, server_name, ct_log_keys, stdio } => match load_oob_seed(session.oob_seed_file.as_deref()) {
+            Ok(oob_seed) => {
+                run_client(&address, session.rotation_interval, session.tls, &server_name, ct_log_keys, session.name, oob_seed, stdio).await
+            }
+            Err(e) => Err(e),
+        },
+        Commands::Benchmark { address, count, session, server_name } => {
+            run_benchmark(&address, count, session.tls, &server_name).await
         }
+        Commands::Proxy { listen_addr, backend_addr, psk_file, tls, server_name } => match load_psk(&psk_file) {
+            Ok(psk) => run_proxy(listen_addr, backend_addr, psk, tls, server_name).await,
+            Err(e) => Err(e),
+        },
+        Commands::ReverseProxy { listen_addr, backend_addr, psk_file, tls } => match load_psk(&psk_file) {
+            Ok(psk) => run_reverse_proxy(listen_addr, &backend_addr, psk, tls).await,
+            Err(e) => Err(e),
+        },
+        Commands::PrintEnv { .. } | Commands::ShowPublicKey { .. } => unreachable!("handled above"),
     };
 
     if let Err(e) = result {
@@ -88,20 +244,33 @@ async fn main() {
     }
 }
 
-async fn run_server(port: u16, rotation_interval: u64, use_tls: bool) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_server(
+    bind: std::net::SocketAddr,
+    opts: ListenerOpts,
+    rotation_interval: u64,
+    use_tls: bool,
+    name: Option<String>,
+    oob_seed: Option<Vec<u8>>,
+) -> Result<(), Box<dyn std::error::Error>> {
     use network::connection::Listener;
     use session::Session;
 
-    println!("🔊 Listening on port {}...", port);
+    println!("🔊 Listening on {}...", bind);
+    if let Some(interface) = &opts.interface {
+        println!("🔌 Bound to interface {}", interface);
+    }
+    if !opts.allow.is_empty() || !opts.deny.is_empty() {
+        println!("🚧 Accepting peers from {} allowed range(s), {} denied", opts.allow.len(), opts.deny.len());
+    }
     if use_tls {
         println!("🔐 TLS 1.3 enabled");
     }
     println!("⏳ Waiting for connection...");
 
     let listener = if use_tls {
-        Listener::bind_tls(&format!("0.0.0.0:{}", port)).await?
+        Listener::bind_tls_with_opts(&bind.to_string(), opts).await?
     } else {
-        Listener::bind(&format!("0.0.0.0:{}", port)).await?
+        Listener::bind_with_opts(&bind.to_string(), opts).await?
     };
 
     let connection = listener.accept().await?;
@@ -109,7 +278,8 @@ async fn run_server(port: u16, rotation_interval: u64, use_tls: bool) -> Result<
     println!("✅ Connection established from {}", connection.peer_addr());
     println!("🔐 Performing quantum-safe key exchange...");
 
-    let session = Session::accept(connection).await?;
+    let mut session = Session::accept(connection).await?;
+    session.set_oob_seed(oob_seed);
 
     println!("✅ Secure session established!");
     println!("🔑 Key rotation every {} seconds", rotation_interval);
@@ -117,28 +287,57 @@ async fn run_server(port: u16, rotation_interval: u64, use_tls: bool) -> Result<
     println!("Type messages and press Enter to send. Ctrl+C to quit.");
     println!();
 
-    run_chat_loop(session, rotation_interval).await
+    run_chat_loop(session, rotation_interval, name).await
 }
 
-async fn run_client(address: &str, rotation_interval: u64, use_tls: bool, server_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    use network::connection::{connect, connect_tls};
+#[allow(clippy::too_many_arguments)]
+async fn run_client(
+    address: &str,
+    rotation_interval: u64,
+    use_tls: bool,
+    server_name: &str,
+    ct_log_keys: Vec<network::ct::CtLog>,
+    name: Option<String>,
+    oob_seed: Option<Vec<u8>>,
+    stdio: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use network::connection::{connect, connect_tls, connect_tls_with_verifier};
+    use network::ct::CtVerifiedCertVerifier;
     use session::Session;
 
-    println!("🔌 Connecting to {}...", address);
+    if !ct_log_keys.is_empty() && !use_tls {
+        return Err("--ct-log-key requires --tls".into());
+    }
+
+    // In --stdio mode stdout carries the tunnelled data
+    let status = |line: String| if stdio { eprintln!("{}", line) } else { println!("{}", line) };
+
+    status(format!("🔌 Connecting to {}...", address));
     if use_tls {
-        println!("🔐 TLS 1.3 enabled");
+        status("🔐 TLS 1.3 enabled".to_string());
     }
 
-    let connection = if use_tls {
+    let connection = if use_tls && !ct_log_keys.is_empty() {
+        status(format!("📜 Requiring certificate transparency from {} log(s)", ct_log_keys.len()));
+        let verifier = CtVerifiedCertVerifier::new(ct_log_keys)?;
+        connect_tls_with_verifier(address, server_name, std::sync::Arc::new(verifier)).await?
+    } else if use_tls {
         connect_tls(address, server_name).await?
     } else {
         connect(address).await?
     };
 
-    println!("✅ Connected to {}", connection.peer_addr());
-    println!("🔐 Performing quantum-safe key exchange...");
+    status(format!("✅ Connected to {}", connection.peer_addr()));
+    status("🔐 Performing quantum-safe key exchange...".to_string());
 
-    let session = Session::connect(connection).await?;
+    let mut session = Session::connect(connection).await?;
+    session.set_oob_seed(oob_seed);
+
+    if stdio {
+        status("✅ Secure session established, tunnelling stdin/stdout".to_string());
+        session.set_rekey_policy(Some(chat_rekey_policy(Duration::from_secs(rotation_interval))));
+        return Ok(session.into_stdio_tunnel().await?);
+    }
 
     println!("✅ Secure session established!");
     println!("🔑 Key rotation every {} seconds", rotation_interval);
@@ -146,10 +345,246 @@ async fn run_client(address: &str, rotation_interval: u64, use_tls: bool, server
     println!("Type messages and press Enter to send. Ctrl+C to quit.");
     println!();
 
-    run_chat_loop(session, rotation_interval).await
+    run_chat_loop(session, rotation_interval, name).await
+}
+
+/// First message each end of a proxy tunnel sends, proving it holds the PSK
+const PROXY_HELLO: &[u8] = b"aegis-proxy-v1";
+
+/// How long each end of a proxy tunnel waits for the other's `PROXY_HELLO`
+const PROXY_HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shortest pre-shared key the proxy subcommands accept
+const MIN_PSK_LEN: usize = 16;
+
+/// Exchange `PROXY_HELLO` so neither end forwards data before the peer
+/// shows it derived the same PSK-bound keys
+async fn confirm_proxy_peer(session: &mut session::Session) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    session.send(PROXY_HELLO).await?;
+    let hello = tokio::time::timeout(PROXY_HELLO_TIMEOUT, session.recv()).await?
+        .map_err(|e| format!("peer does not hold the pre-shared key ({})", e))?;
+    if hello != PROXY_HELLO {
+        return Err("unexpected first message from the proxy peer".into());
+    }
+    Ok(())
+}
+
+async fn run_proxy(
+    listen_addr: std::net::SocketAddr,
+    backend_addr: String,
+    psk: zeroize::Zeroizing<Vec<u8>>,
+    use_tls: bool,
+    server_name: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = session::SessionConfig { psk: Some(psk), ..Default::default() };
+    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
+    println!("🔊 Proxying {} to {} over Aegis sessions", listen_addr, backend_addr);
+    if use_tls {
+        println!("🔐 TLS 1.3 enabled");
+    }
+
+    loop {
+        let (mut local, client_addr) = listener.accept().await?;
+        let backend_addr = backend_addr.clone();
+        let server_name = server_name.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let result: Result<(u64, u64), Box<dyn std::error::Error + Send + Sync>> = async {
+                let connection = if use_tls {
+                    network::connection::connect_tls(&backend_addr, &server_name).await?
+                } else {
+                    network::connection::connect(&backend_addr).await?
+                };
+                let mut session = session::Session::connect_with_config(connection, &config).await?;
+                confirm_proxy_peer(&mut session).await?;
+                let mut remote = session.into_stream();
+                Ok(tokio::io::copy_bidirectional(&mut local, &mut remote).await?)
+            }.await;
+            match result {
+                Ok((sent, received)) => tracing::info!("{} closed: {} bytes out, {} bytes in", client_addr, sent, received),
+                Err(e) => eprintln!("❌ Proxy connection from {} failed: {}", client_addr, e),
+            }
+        });
+    }
+}
+
+async fn run_reverse_proxy(
+    listen_addr: std::net::SocketAddr,
+    backend_addr: &str,
+    psk: zeroize::Zeroizing<Vec<u8>>,
+    use_tls: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use network::connection::Listener;
+
+    let listener = if use_tls {
+        Listener::bind_tls(&listen_addr.to_string()).await?
+    } else {
+        Listener::bind(&listen_addr.to_string()).await?
+    };
+    let config = session::SessionPoolConfig {
+        session: session::SessionConfig { psk: Some(psk), ..Default::default() },
+        ..Default::default()
+    };
+    let mut pool = session::SessionPool::with_config(listener, config)?;
+    println!("🔊 Forwarding Aegis sessions on {} to {}", listen_addr, backend_addr);
+    if use_tls {
+        println!("🔐 TLS 1.3 enabled");
+    }
+
+    while let Some(mut session) = pool.next().await {
+        let peer_addr = session.peer_addr;
+        let backend_addr = backend_addr.to_string();
+        tokio::spawn(async move {
+            let result: Result<(u64, u64), Box<dyn std::error::Error + Send + Sync>> = async {
+                confirm_proxy_peer(&mut session).await?;
+                let mut backend = tokio::net::TcpStream::connect(&backend_addr).await?;
+                let mut remote = session.into_stream();
+                Ok(tokio::io::copy_bidirectional(&mut remote, &mut backend).await?)
+            }.await;
+            match result {
+                Ok((received, sent)) => tracing::info!("{} closed: {} bytes in, {} bytes out", peer_addr, received, sent),
+                Err(e) => eprintln!("❌ Forwarding for {} failed: {}", peer_addr, e),
+            }
+        });
+    }
+    Ok(())
+}
+
+fn show_public_key(key_file: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let pem = zeroize::Zeroizing::new(std::fs::read_to_string(key_file)
+        .map_err(|e| format!("cannot read key file {}: {}", key_file.display(), e))?);
+    let keypair = aegis::crypto::kyber::KeyPair::import_secret_key_from_pem(&pem)?;
+    print!("{}", keypair.export_public_key_to_pem());
+    Ok(())
+}
+
+/// Read the pre-shared key file the proxy subcommands require
+fn load_psk(path: &std::path::Path) -> Result<zeroize::Zeroizing<Vec<u8>>, Box<dyn std::error::Error>> {
+    let psk = zeroize::Zeroizing::new(std::fs::read(path)
+        .map_err(|e| format!("cannot read PSK file {}: {}", path.display(), e))?);
+    if psk.len() < MIN_PSK_LEN {
+        return Err(format!("PSK file {} must hold at least {} bytes", path.display(), MIN_PSK_LEN).into());
+    }
+    Ok(psk)
+}
+
+/// Read the out-of-band seed file, if one was given
+fn load_oob_seed(path: Option<&std::path::Path>) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let seed = std::fs::read(path)
+        .map_err(|e| format!("cannot read seed file {}: {}", path.display(), e))?;
+    if seed.is_empty() {
+        return Err(format!("seed file {} is empty", path.display()).into());
+    }
+    Ok(Some(seed))
+}
+
+/// Parse a CT log public key given as base64 DER `SubjectPublicKeyInfo`
+fn parse_ct_log_key(value: &str) -> Result<network::ct::CtLog, String> {
+    use base64::Engine;
+
+    let spki = base64::engine::general_purpose::STANDARD.decode(value).map_err(|e| format!("invalid base64: {}", e))?;
+    network::ct::CtLog::from_spki_der(&spki).map_err(|e| e.to_string())
 }
 
-async fn run_chat_loop(mut session: session::Session, rotation_interval: u64) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_benchmark(address: &str, count: u32, use_tls: bool, server_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use network::connection::{connect, connect_tls};
+    use session::Session;
+
+    println!("🔌 Connecting to {}...", address);
+
+    let connection = if use_tls {
+        connect_tls(address, server_name).await?
+    } else {
+        connect(address).await?
+    };
+
+    let mut session = Session::connect(connection).await?;
+
+    println!("✅ Secure session established!");
+    println!("⏱️  Sending {} heartbeats...", count);
+
+    for _ in 0..count {
+        session.ping().await?;
+    }
+
+    let histogram = session.ping_rtt_histogram();
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("Samples: {}", histogram.len());
+    println!("Mean:    {:.1} µs", session.mean_rtt_us());
+    println!("p50:     {} µs", histogram.value_at_quantile(0.50));
+    println!("p90:     {} µs", histogram.value_at_quantile(0.90));
+    println!("p99:     {} µs", session.p99_rtt_us());
+    println!("Max:     {} µs", session.max_rtt_us());
+
+    session.close().await?;
+    Ok(())
+}
+
+/// Rotation deadlines in the chat loop vary by ± interval / this
+const ROTATION_JITTER_DIVISOR: u32 = 10;
+
+/// Symmetric rotations per Kyber rekey in the chat loop
+const DH_REKEY_EVERY_ROTATIONS: u32 = 10;
+
+/// How often the chat loop rekeys from an out-of-band seed
+const OTP_ROTATION_PERIOD: Duration = Duration::from_secs(60);
+
+/// Chat input handled by `run_security_command` rather than sent
+const CHAT_COMMANDS: [&str; 3] = ["/security", "/rekey", "/rotate"];
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Symmetric rotation every interval, with a Kyber rekey every few rotations
+fn chat_rekey_policy(rotation_interval: Duration) -> session::RekeyPolicy {
+    session::RekeyPolicy {
+        symmetric_every: rotation_interval,
+        dh_every: rotation_interval * DH_REKEY_EVERY_ROTATIONS,
+        jitter: rotation_interval / ROTATION_JITTER_DIVISOR,
+    }
+}
+
+/// Handle `/security`, `/rekey` and `/rotate <seconds>` typed in the chat
+async fn run_security_command(session: &mut session::Session, command: &str) -> Result<(), network::NetworkError> {
+    let mut status = aegis::ui::status::StatusBar::new();
+    status.set_security(Some(session.negotiated_params()));
+
+    match command.split_whitespace().collect::<Vec<_>>()[..] {
+        ["/security"] => {
+            for line in status.details() {
+                println!("\r🔒 {}", line);
+            }
+        }
+        ["/rekey"] => {
+            session.dh_rekey().await?;
+            println!("\r🔑 Kyber rekey started");
+        }
+        ["/rotate", seconds] => match seconds.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => {
+                session.set_rotation_interval(Duration::from_secs(seconds)).await?;
+                println!("\r🔑 Key rotation interval set to {}s", seconds);
+            }
+            _ => println!("\rUsage: /rotate <seconds>"),
+        },
+        _ => println!("\rUsage: /security, /rekey, /rotate <seconds> (start a message with // to send a leading /)"),
+    }
+    Ok(())
+}
+
+async fn run_chat_loop(mut session: session::Session, rotation_interval: u64, name: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    // Introduce ourselves before any chat messages, if the peer understands it
+    if let Some(name) = name {
+        if session.suite().supports(WireVersion::FEATURE_PEER_INFO) {
+            session.send_peer_info(&PeerInfo::new(name, Vec::new())).await?;
+        } else {
+            println!("⚠️  Peer does not support display names; --name ignored");
+        }
+    }
+
     // Create channel for stdin input
     let (stdin_tx, mut stdin_rx) = mpsc::channel::<String>(100);
 
@@ -168,10 +603,8 @@ async fn run_chat_loop(mut session: session::Session, rotation_interval: u64) ->
                 Ok(0) => break, // EOF
                 Ok(_) => {
                     let trimmed = line.trim();
-                    if !trimmed.is_empty() {
-                        if stdin_tx.send(trimmed.to_string()).await.is_err() {
-                            break;
-                        }
+                    if !trimmed.is_empty() && stdin_tx.send(trimmed.to_string()).await.is_err() {
+                        break;
                     }
                 }
                 Err(_) => break,
@@ -179,18 +612,35 @@ async fn run_chat_loop(mut session: session::Session, rotation_interval: u64) ->
         }
     });
 
-    // Create timers for key rotation and heartbeat
-    let mut rotation_timer = interval(Duration::from_secs(rotation_interval));
-    rotation_timer.tick().await; // Skip first immediate tick
+    let rotation_interval = Duration::from_secs(rotation_interval);
+    session.set_rekey_policy(Some(chat_rekey_policy(rotation_interval)));
 
     let mut heartbeat_timer = interval(Duration::from_secs(30));
     heartbeat_timer.tick().await; // Skip first immediate tick
 
+    let mut otp_timer = interval(OTP_ROTATION_PERIOD);
+    otp_timer.tick().await;
+    if session.has_oob_seed() {
+        println!("🔑 Rekeying from the out-of-band seed every minute");
+    }
+
     // Main event loop using tokio::select!
     loop {
+        let next_rekey = session.next_rekey_at().unwrap_or_else(|| Instant::now() + rotation_interval);
         tokio::select! {
             // Handle stdin input
             Some(text) = stdin_rx.recv() => {
+                // Only an exact command word is a command; `//` sends a leading slash
+                let command = text.split_whitespace().next().unwrap_or_default();
+                if CHAT_COMMANDS.contains(&command) {
+                    if let Err(e) = run_security_command(&mut session, &text).await {
+                        eprintln!("\r❌ Command error: {}", e);
+                    }
+                    print!("> ");
+                    let _ = std::io::stdout().flush();
+                    continue;
+                }
+                let text = text.strip_prefix('/').filter(|rest| rest.starts_with('/')).unwrap_or(&text);
                 if let Err(e) = session.send(text.as_bytes()).await {
                     eprintln!("\r❌ Send error: {}", e);
                     break;
@@ -198,15 +648,43 @@ async fn run_chat_loop(mut session: session::Session, rotation_interval: u64) ->
             }
 
             // Handle incoming network messages
-            result = session.recv() => {
+            result = session.recv_event() => {
                 match result {
-                    Ok(data) => {
-                        if !data.is_empty() {
-                            let text = String::from_utf8_lossy(&data);
-                            println!("\r< {}", text);
-                            print!("> ");
-                            let _ = std::io::stdout().flush();
-                        }
+                    Ok(session::ReceivedEvent::Message(data) | session::ReceivedEvent::TrackedMessage { data, .. }) => {
+                        let text = String::from_utf8_lossy(&data);
+                        println!("\r< {}", text);
+                        print!("> ");
+                        let _ = std::io::stdout().flush();
+                    }
+                    Ok(session::ReceivedEvent::EphemeralMessage { data, expires_at }) => {
+                        let remaining = expires_at.saturating_sub(unix_now());
+                        println!("\r< {} (disappears in {}s)", String::from_utf8_lossy(&data), remaining);
+                        print!("> ");
+                        let _ = std::io::stdout().flush();
+                    }
+                    Ok(
+                        session::ReceivedEvent::Heartbeat
+                        | session::ReceivedEvent::OobData(..)
+                        | session::ReceivedEvent::ContactMessage { .. }
+                        | session::ReceivedEvent::Delivered { .. },
+                    ) => {}
+                    Ok(session::ReceivedEvent::PeerInfo(info)) => {
+                        println!("\r👤 Peer is {}", info.display_name);
+                        print!("> ");
+                        let _ = std::io::stdout().flush();
+                    }
+                    Ok(session::ReceivedEvent::ClockDriftWarning { offset_secs }) => {
+                        println!("\r⚠️  Peer clock is {}s {} ours; check NTP on both hosts", offset_secs.abs(), if offset_secs > 0 { "ahead of" } else { "behind" });
+                        print!("> ");
+                        let _ = std::io::stdout().flush();
+                    }
+                    Ok(session::ReceivedEvent::Disconnected) => {
+                        eprintln!("\r❌ Peer stopped responding");
+                        break;
+                    }
+                    Err(network::NetworkError::PeerClosed { .. }) => {
+                        println!("\r👋 Peer left the chat");
+                        break;
                     }
                     Err(e) => {
                         eprintln!("\r❌ Receive error: {}", e);
@@ -215,16 +693,33 @@ async fn run_chat_loop(mut session: session::Session, rotation_interval: u64) ->
                 }
             }
 
-            // Handle key rotation timer
-            _ = rotation_timer.tick() => {
-                if let Err(e) = session.ratchet.rotate() {
-                    eprintln!("\r❌ Key rotation error: {}", e);
-                    break;
-                } else {
-                    println!("\r🔑 Keys rotated");
-                    print!("> ");
-                    let _ = std::io::stdout().flush();
+            // Handle scheduled key rotation
+            _ = sleep_until(next_rekey.into()) => {
+                match session.rekey_on_schedule().await {
+                    Ok(Some(session::RekeyAction::Symmetric)) => println!("\r🔑 Keys rotated"),
+                    Ok(Some(session::RekeyAction::Dh)) => println!("\r🔑 Kyber rekey started"),
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("\r❌ Key rotation error: {}", e);
+                        break;
+                    }
+                }
+                print!("> ");
+                let _ = std::io::stdout().flush();
+            }
+
+            // Rekey from the out-of-band seed
+            _ = otp_timer.tick(), if session.has_oob_seed() => {
+                match session.otp_rotate().await {
+                    Ok(true) => println!("\r🔑 Keys rotated from out-of-band seed"),
+                    Ok(false) => continue,
+                    Err(e) => {
+                        eprintln!("\r❌ Seed rotation error: {}", e);
+                        break;
+                    }
                 }
+                print!("> ");
+                let _ = std::io::stdout().flush();
             }
 
             // Handle heartbeat timer
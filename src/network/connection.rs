@@ -1,18 +1,28 @@
 // TCP connection handler with TLS 1.3
 // Provides secure, async network connections
 
-use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+use tokio_util::compat::Compat;
 use tokio_rustls::{TlsAcceptor, TlsConnector};
-use rustls::{ServerConfig, ClientConfig, RootCertStore};
+use rustls::{ClientConfig, HandshakeKind, ServerConfig};
+use rustls::crypto::aws_lc_rs::Ticketer;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
-use std::sync::Arc;
-use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
 use thiserror::Error;
 
-use super::{NetworkError, protocol::{Message, frame_message, parse_framed_message}};
+use crate::crypto::signing::{SigningKeyPair, VerifyingKey};
+use super::{NetworkError, protocol::{Message, MessageFramingConfig, SignedMessage, frame_bytes_with_limit, frame_message_into_with_limit, parse_frame_with_limit}};
 
 const READ_BUFFER_SIZE: usize = 8192;
+const MEMORY_PIPE_CAPACITY: usize = 64 * 1024;
 
 #[derive(Error, Debug)]
 pub enum ConnectionError {
@@ -32,100 +42,670 @@ pub enum ConnectionError {
     HandshakeFailed(String),
 }
 
+/// Socket-level tuning for TCP connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpOptions {
+    /// Disable Nagle's algorithm so small frames go out immediately
+    pub nodelay: bool,
+    /// `SO_SNDBUF`; `None` keeps the OS default
+    pub send_buffer_size: Option<usize>,
+    /// `SO_RCVBUF`; `None` keeps the OS default
+    pub recv_buffer_size: Option<usize>,
+    /// IPv4 type-of-service byte (e.g. DSCP marking); 0 leaves it unset
+    pub ip_tos: u8,
+    /// `SO_LINGER`: how long `close` may block sending unsent data
+    pub linger: Option<Duration>,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            ip_tos: 0,
+            linger: None,
+        }
+    }
+}
+
+impl TcpOptions {
+    fn apply(&self, stream: &TcpStream) -> Result<(), NetworkError> {
+        let socket = socket2::SockRef::from(stream);
+
+        socket.set_tcp_nodelay(self.nodelay)?;
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        // IPv6 carries the equivalent in its traffic class, which we don't set
+        if self.ip_tos != 0 && stream.local_addr()?.is_ipv4() {
+            socket.set_tos_v4(self.ip_tos as u32)?;
+        }
+        socket.set_linger(self.linger)?;
+        Ok(())
+    }
+}
+
+/// Range of peer addresses in CIDR notation, e.g. `10.0.0.0/8` or `::1/128`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    /// Range of `prefix_len` leading bits of `addr`; host bits are ignored
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, NetworkError> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max {
+            return Err(NetworkError::ConnectionError(format!("Prefix length {} exceeds {}", prefix_len, max)));
+        }
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// Whether `ip` is in this range (IPv4-mapped IPv6 addresses match IPv4 ranges)
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(net) as u128, u32::from(ip) as u128, 32, self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_matches(u128::from(net), u128::from(ip), 128, self.prefix_len),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, bits: u8, prefix_len: u8) -> bool {
+    if prefix_len == 0 {
+        return true;
+    }
+    let shift = bits - prefix_len;
+    net >> shift == ip >> shift
+}
+
+/// Parses `addr/len`; a bare address is a single host
+impl FromStr for IpCidr {
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || NetworkError::ConnectionError(format!("Invalid CIDR range: {}", s));
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr.parse::<IpAddr>().map_err(|_| invalid())?, len.parse().map_err(|_| invalid())?),
+            None => {
+                let addr = s.parse::<IpAddr>().map_err(|_| invalid())?;
+                (addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        };
+        Self::new(addr, prefix_len)
+    }
+}
+
+/// Where a listener binds and which peers it accepts
+#[derive(Debug, Clone, Default)]
+pub struct ListenerOpts {
+    /// Only accept peers in these ranges; empty accepts any peer
+    pub allow: Vec<IpCidr>,
+    /// Reject peers in these ranges, even if also allowed
+    pub deny: Vec<IpCidr>,
+    /// Bind to this network interface (`SO_BINDTODEVICE`, Linux only)
+    pub interface: Option<String>,
+}
+
+impl ListenerOpts {
+    /// Whether a peer at `ip` passes the allow and deny lists
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|range| range.contains(ip));
+        allowed && !self.deny.iter().any(|range| range.contains(ip))
+    }
+}
+
+/// Byte stream a `Connection` frames messages over
+///
+/// Each transport is its own implementation; the defaults describe a stream
+/// with no socket of its own (in-memory pipes, multiplexed streams).
+pub(super) trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send + Sync {
+    /// The TCP socket underneath, for socket options and addresses
+    fn tcp_stream(&self) -> Option<&TcpStream> {
+        None
+    }
+
+    fn is_tls(&self) -> bool {
+        false
+    }
+
+    /// Whether the TLS handshake resumed an earlier session
+    fn is_tls_resumed(&self) -> bool {
+        false
+    }
+
+    /// Virtual stream id, for streams of a `MuxedConnection`
+    fn mux_stream_id(&self) -> Option<u32> {
+        None
+    }
+
+    /// Unwrap a plain TCP stream so it can be wrapped in TLS
+    fn into_plain_tcp(self: Box<Self>) -> Result<TcpStream, Box<dyn AsyncStream>>;
+
+    /// Whether the stream was detached by a TLS upgrade that did not complete
+    fn is_detached(&self) -> bool {
+        false
+    }
+
+    /// Fill `output` with RFC 5705 keying material exported from the TLS session
+    fn export_keying_material(&self, _label: &[u8], _output: &mut [u8]) -> Result<(), NetworkError> {
+        Err(NetworkError::ProtocolError("Connection is not running over TLS".to_string()))
+    }
+}
+
 /// Connection stream type
-enum ConnectionStream {
-    Plain(TcpStream),
-    TlsClient(Box<tokio_rustls::client::TlsStream<TcpStream>>),
-    TlsServer(Box<tokio_rustls::server::TlsStream<TcpStream>>),
+pub(super) type ConnectionStream = Box<dyn AsyncStream>;
+
+impl AsyncStream for TcpStream {
+    fn tcp_stream(&self) -> Option<&TcpStream> {
+        Some(self)
+    }
+
+    fn into_plain_tcp(self: Box<Self>) -> Result<TcpStream, Box<dyn AsyncStream>> {
+        Ok(*self)
+    }
+}
+
+impl AsyncStream for tokio_rustls::client::TlsStream<TcpStream> {
+    fn tcp_stream(&self) -> Option<&TcpStream> {
+        Some(self.get_ref().0)
+    }
+
+    fn is_tls(&self) -> bool {
+        true
+    }
+
+    fn is_tls_resumed(&self) -> bool {
+        self.get_ref().1.handshake_kind() == Some(HandshakeKind::Resumed)
+    }
+
+    fn into_plain_tcp(self: Box<Self>) -> Result<TcpStream, Box<dyn AsyncStream>> {
+        Err(self)
+    }
+
+    fn export_keying_material(&self, label: &[u8], output: &mut [u8]) -> Result<(), NetworkError> {
+        self.get_ref().1.export_keying_material(output, label, None)
+            .map(|_| ())
+            .map_err(|e| NetworkError::ConnectionError(format!("TLS exporter failed: {}", e)))
+    }
+}
+
+impl AsyncStream for tokio_rustls::server::TlsStream<TcpStream> {
+    fn tcp_stream(&self) -> Option<&TcpStream> {
+        Some(self.get_ref().0)
+    }
+
+    fn is_tls(&self) -> bool {
+        true
+    }
+
+    fn is_tls_resumed(&self) -> bool {
+        self.get_ref().1.handshake_kind() == Some(HandshakeKind::Resumed)
+    }
+
+    fn into_plain_tcp(self: Box<Self>) -> Result<TcpStream, Box<dyn AsyncStream>> {
+        Err(self)
+    }
+
+    fn export_keying_material(&self, label: &[u8], output: &mut [u8]) -> Result<(), NetworkError> {
+        self.get_ref().1.export_keying_material(output, label, None)
+            .map(|_| ())
+            .map_err(|e| NetworkError::ConnectionError(format!("TLS exporter failed: {}", e)))
+    }
+}
+
+/// In-process pipe, for tests and benchmarks
+impl AsyncStream for DuplexStream {
+    fn into_plain_tcp(self: Box<Self>) -> Result<TcpStream, Box<dyn AsyncStream>> {
+        Err(self)
+    }
+}
+
+/// Virtual stream of a `MuxedConnection`
+impl AsyncStream for Compat<yamux::Stream> {
+    fn mux_stream_id(&self) -> Option<u32> {
+        Some(self.get_ref().id().val())
+    }
+
+    fn into_plain_tcp(self: Box<Self>) -> Result<TcpStream, Box<dyn AsyncStream>> {
+        Err(self)
+    }
+}
+
+/// Placeholder left when the underlying stream was handed to a TLS upgrade
+/// that did not complete
+struct Detached;
+
+impl AsyncRead for Detached {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Err(detached_error()))
+    }
+}
+
+impl AsyncWrite for Detached {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Err(detached_error()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Err(detached_error()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncStream for Detached {
+    fn into_plain_tcp(self: Box<Self>) -> Result<TcpStream, Box<dyn AsyncStream>> {
+        Err(self)
+    }
+
+    fn is_detached(&self) -> bool {
+        true
+    }
+}
+
+fn detached_error() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotConnected, "Connection stream detached")
+}
+
+/// Snapshot of a connection's traffic counters
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub errors: u32,
+    pub established_at: Instant,
+    pub last_activity: Instant,
+}
+
+/// Live counters behind `ConnectionStats`, readable without `&mut`
+#[derive(Clone, Default)]
+struct StatsCounters {
+    bytes_sent: Arc<AtomicU64>,
+    bytes_received: Arc<AtomicU64>,
+    messages_sent: Arc<AtomicU64>,
+    messages_received: Arc<AtomicU64>,
+    errors: Arc<AtomicU32>,
+    /// Nanoseconds after `established_at`
+    last_activity_nanos: Arc<AtomicU64>,
+}
+
+impl StatsCounters {
+    fn record_sent(&self, bytes: usize, established_at: Instant) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.touch(established_at);
+    }
+
+    fn record_received(&self, bytes: usize, established_at: Instant) {
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.touch(established_at);
+    }
+
+    fn touch(&self, established_at: Instant) {
+        let nanos = established_at.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        self.last_activity_nanos.store(nanos, Ordering::Relaxed);
+    }
+
+    /// Count the error, if any, and pass the result through
+    fn note<T>(&self, result: Result<T, NetworkError>) -> Result<T, NetworkError> {
+        if result.is_err() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// Stream and coalesced writes, shared with the task that flushes them when
+/// the linger window expires
+///
+/// Only held for a single poll at a time, like the halves of
+/// `tokio::io::split`, so a read pending on the stream never blocks a flush.
+struct Io {
+    stream: ConnectionStream,
+    write_buffer: Vec<u8>,
+    linger_deadline: Option<Instant>,
+}
+
+fn lock_io(io: &Mutex<Io>) -> MutexGuard<'_, Io> {
+    io.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `Connection::flush`, also run by the linger task; the two may overlap
+/// without losing or repeating bytes
+async fn flush_io(io: &Mutex<Io>) -> Result<(), NetworkError> {
+    std::future::poll_fn(|cx| {
+        let mut guard = lock_io(io);
+        let Io { stream, write_buffer, linger_deadline } = &mut *guard;
+        while !write_buffer.is_empty() {
+            let n = ready!(Pin::new(&mut *stream).poll_write(cx, write_buffer))?;
+            if n == 0 {
+                return Poll::Ready(Err(NetworkError::ConnectionError("Connection closed by peer".to_string())));
+            }
+            write_buffer.drain(..n);
+        }
+
+        ready!(Pin::new(&mut *stream).poll_flush(cx))?;
+        *linger_deadline = None;
+        Poll::Ready(Ok(()))
+    })
+    .await
+}
+
+/// Flush at `deadline` unless something flushed first
+///
+/// Holds only a weak reference while it sleeps, so it never keeps a dropped
+/// connection open.
+fn spawn_linger_flush(io: Weak<Mutex<Io>>, deadline: Instant) {
+    tokio::spawn(async move {
+        tokio::time::sleep_until(deadline).await;
+        let Some(io) = io.upgrade() else {
+            return;
+        };
+        if lock_io(&io).linger_deadline != Some(deadline) {
+            return;
+        }
+        // The connection sees the same error on its next send or flush
+        if let Err(e) = flush_io(&io).await {
+            tracing::debug!("Linger flush failed: {}", e);
+        }
+    });
 }
 
 /// Represents an active connection with optional TLS
 pub struct Connection {
-    stream: ConnectionStream,
+    io: Arc<Mutex<Io>>,
     peer_addr: SocketAddr,
     buffer: Vec<u8>,
+    flush_linger: Duration,
+    stats: StatsCounters,
+    established_at: Instant,
+    framing: MessageFramingConfig,
+    /// The server certificate passed a caller-supplied verifier
+    server_verified: bool,
 }
 
 impl Connection {
-    /// Create a new plain TCP connection
-    pub fn from_tcp(stream: TcpStream, peer_addr: SocketAddr) -> Self {
+    fn new(stream: ConnectionStream, peer_addr: SocketAddr) -> Self {
+        Self::new_with_config(stream, peer_addr, MessageFramingConfig::default())
+    }
+
+    fn new_with_config(stream: ConnectionStream, peer_addr: SocketAddr, framing: MessageFramingConfig) -> Self {
         Self {
-            stream: ConnectionStream::Plain(stream),
+            io: Arc::new(Mutex::new(Io { stream, write_buffer: Vec::new(), linger_deadline: None })),
             peer_addr,
             buffer: Vec::with_capacity(READ_BUFFER_SIZE),
+            flush_linger: Duration::ZERO,
+            stats: StatsCounters::default(),
+            established_at: Instant::now(),
+            framing,
+            server_verified: false,
         }
     }
 
+    /// Create a new plain TCP connection
+    pub fn from_tcp(stream: TcpStream, peer_addr: SocketAddr) -> Self {
+        Self::new(Box::new(stream), peer_addr)
+    }
+
     /// Create a new TLS client connection
     pub fn from_tls_client(stream: tokio_rustls::client::TlsStream<TcpStream>, peer_addr: SocketAddr) -> Self {
-        Self {
-            stream: ConnectionStream::TlsClient(Box::new(stream)),
-            peer_addr,
-            buffer: Vec::with_capacity(READ_BUFFER_SIZE),
-        }
+        Self::new(Box::new(stream), peer_addr)
     }
 
     /// Create a new TLS server connection
     pub fn from_tls_server(stream: tokio_rustls::server::TlsStream<TcpStream>, peer_addr: SocketAddr) -> Self {
-        Self {
-            stream: ConnectionStream::TlsServer(Box::new(stream)),
-            peer_addr,
-            buffer: Vec::with_capacity(READ_BUFFER_SIZE),
-        }
+        Self::new(Box::new(stream), peer_addr)
+    }
+
+    /// Set the write coalescing window
+    ///
+    /// With a zero linger (the default) every message is written and flushed
+    /// immediately. Otherwise messages sent within `linger` of the first
+    /// unflushed one are buffered and written together. Buffered data goes
+    /// out when the window expires (from a background task, so nothing needs
+    /// to call into the connection), on `flush()`, and on `close()`.
+    pub fn set_flush_linger(&mut self, linger: Duration) {
+        self.flush_linger = linger;
+    }
+
+    /// Get the write coalescing window
+    pub fn flush_linger(&self) -> Duration {
+        self.flush_linger
+    }
+
+    /// Size limits for frames sent and received from now on (1 MB by default)
+    ///
+    /// Both peers should agree: a frame over the receiver's limit fails its
+    /// `recv_message`.
+    pub fn set_framing_config(&mut self, framing: MessageFramingConfig) {
+        self.framing = framing;
+    }
+
+    /// Get the frame size limits
+    pub fn framing_config(&self) -> MessageFramingConfig {
+        self.framing
     }
 
     /// Send a message over the connection
     pub async fn send_message(&mut self, message: &Message) -> Result<(), NetworkError> {
-        let framed = frame_message(message)?;
+        let result = async {
+            let framed_len = frame_message_into_with_limit(message, &mut self.io().write_buffer, self.framing.max_message_size)?;
+            self.frame_queued(framed_len).await
+        }.await;
+        self.stats.note(result)
+    }
 
-        match &mut self.stream {
-            ConnectionStream::Plain(stream) => {
-                stream.write_all(&framed).await?;
-                stream.flush().await?;
-            }
-            ConnectionStream::TlsClient(stream) => {
-                stream.write_all(&framed).await?;
-                stream.flush().await?;
-            }
-            ConnectionStream::TlsServer(stream) => {
-                stream.write_all(&framed).await?;
-                stream.flush().await?;
+    /// Sign a message and send it wrapped as a `SignedMessage`
+    pub async fn send_signed_message(&mut self, message: &Message, keypair: &SigningKeyPair) -> Result<(), NetworkError> {
+        let result = async {
+            let signed = message.sign(keypair)?;
+            self.send_frame(&signed.to_bytes()?).await
+        }.await;
+        self.stats.note(result)
+    }
+
+    async fn send_frame(&mut self, bytes: &[u8]) -> Result<(), NetworkError> {
+        let framed = frame_bytes_with_limit(bytes, self.framing.max_message_size)?;
+        self.io().write_buffer.extend_from_slice(&framed);
+        self.frame_queued(framed.len()).await
+    }
+
+    /// Account for a frame just appended to the write buffer and flush if due
+    async fn frame_queued(&mut self, framed_len: usize) -> Result<(), NetworkError> {
+        self.stats.record_sent(framed_len, self.established_at);
+
+        let deadline = {
+            let mut io = self.io();
+            match io.linger_deadline {
+                Some(deadline) => deadline,
+                None => {
+                    let deadline = Instant::now() + self.flush_linger;
+                    io.linger_deadline = Some(deadline);
+                    if !self.flush_linger.is_zero() {
+                        spawn_linger_flush(Arc::downgrade(&self.io), deadline);
+                    }
+                    deadline
+                }
             }
+        };
+        if Instant::now() >= deadline {
+            self.flush().await?;
         }
 
         Ok(())
     }
 
+    /// Write out any coalesced messages and flush the stream
+    ///
+    /// Cancel-safe: data is removed from the write buffer only once the
+    /// stream has accepted it.
+    pub async fn flush(&mut self) -> Result<(), NetworkError> {
+        flush_io(&self.io).await
+    }
+
+    fn io(&self) -> MutexGuard<'_, Io> {
+        lock_io(&self.io)
+    }
+
     /// Receive a message from the connection
     pub async fn recv_message(&mut self) -> Result<Message, NetworkError> {
+        let result = async {
+            let frame = self.recv_frame(None).await?;
+            self.decode(&frame)
+        }.await;
+        self.stats.note(result)
+    }
+
+    fn decode(&self, frame: &[u8]) -> Result<Message, NetworkError> {
+        Message::from_bytes_with_limit(frame, self.framing.max_message_size)
+    }
+
+    /// Receive a message, failing as soon as the frame header announces more than `max_len` bytes
+    ///
+    /// For unauthenticated input such as handshakes, so an oversized frame
+    /// is never buffered or deserialized.
+    pub async fn recv_message_with_limit(&mut self, max_len: usize) -> Result<Message, NetworkError> {
+        let result = async {
+            let frame = self.recv_frame(Some(max_len)).await?;
+            self.decode(&frame)
+        }.await;
+        self.stats.note(result)
+    }
+
+    /// Receive a `SignedMessage` and return its inner message if the signature verifies
+    pub async fn recv_signed_message(&mut self, verifying_key: &VerifyingKey) -> Result<Message, NetworkError> {
+        let result = async {
+            SignedMessage::from_bytes_with_limit(&self.recv_frame(None).await?, self.framing.max_message_size)?
+                .verify(verifying_key)
+                .map_err(|e| NetworkError::ProtocolError(format!("Signature verification failed: {}", e)))
+        }.await;
+        self.stats.note(result)
+    }
+
+    /// Snapshot of traffic counters since the connection was created
+    pub fn stats(&self) -> ConnectionStats {
+        let last_activity_nanos = self.stats.last_activity_nanos.load(Ordering::Relaxed);
+
+        ConnectionStats {
+            bytes_sent: self.stats.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.stats.bytes_received.load(Ordering::Relaxed),
+            messages_sent: self.stats.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.stats.messages_received.load(Ordering::Relaxed),
+            errors: self.stats.errors.load(Ordering::Relaxed),
+            established_at: self.established_at,
+            last_activity: self.established_at + Duration::from_nanos(last_activity_nanos),
+        }
+    }
+
+    async fn recv_frame(&mut self, max_len: Option<usize>) -> Result<Vec<u8>, NetworkError> {
+        loop {
+            // Try to parse a frame from the buffer
+            if let Some(frame) = self.parse_buffered_frame(max_len)? {
+                return Ok(frame);
+            }
+
+            self.fill_buffer().await?;
+        }
+    }
+
+    /// Wait until a whole frame is buffered, without taking it
+    ///
+    /// Cancel-safe, so it can race other futures in a `select!`; follow it
+    /// with `try_read_message`. A malformed frame also counts as ready, for
+    /// `try_read_message` to report.
+    pub async fn wait_for_frame(&mut self) -> Result<(), NetworkError> {
         loop {
-            // Try to parse a message from the buffer
             if self.buffer.len() >= 4 {
-                match parse_framed_message(&self.buffer) {
-                    Ok((message, consumed)) => {
-                        self.buffer.drain(..consumed);
-                        return Ok(message);
-                    }
-                    Err(NetworkError::ProtocolError(ref e)) if e.contains("Incomplete") => {
-                        // Need more data, continue reading
-                    }
-                    Err(e) => return Err(e),
+                match parse_frame_with_limit(&self.buffer, self.framing.max_message_size) {
+                    Err(NetworkError::ProtocolError(ref e)) if e.contains("Incomplete") => {}
+                    _ => return Ok(()),
                 }
             }
 
-            // Read more data from the stream
-            let mut temp_buf = vec![0u8; READ_BUFFER_SIZE];
-            let n = match &mut self.stream {
-                ConnectionStream::Plain(stream) => stream.read(&mut temp_buf).await?,
-                ConnectionStream::TlsClient(stream) => stream.read(&mut temp_buf).await?,
-                ConnectionStream::TlsServer(stream) => stream.read(&mut temp_buf).await?,
-            };
+            self.fill_buffer().await?;
+        }
+    }
 
-            if n == 0 {
-                return Err(NetworkError::ConnectionError("Connection closed by peer".to_string()));
-            }
+    /// Read once from the stream into the receive buffer
+    ///
+    /// Cancel-safe: bytes are appended in the same poll that reads them.
+    async fn fill_buffer(&mut self) -> Result<(), NetworkError> {
+        let mut temp_buf = vec![0u8; READ_BUFFER_SIZE];
+        let n = std::future::poll_fn(|cx| {
+            let mut read_buf = ReadBuf::new(&mut temp_buf);
+            ready!(Pin::new(&mut self.io().stream).poll_read(cx, &mut read_buf))?;
+            Poll::Ready(Ok::<_, NetworkError>(read_buf.filled().len()))
+        })
+        .await?;
+
+        if n == 0 {
+            return Err(NetworkError::ConnectionError("Connection closed by peer".to_string()));
+        }
+
+        self.buffer.extend_from_slice(&temp_buf[..n]);
+        Ok(())
+    }
+
+    /// Wait for a whole message, then return it along with every other
+    /// complete message the receive buffer holds
+    ///
+    /// Fails once the peer has closed the stream and no complete message is
+    /// left in the buffer.
+    pub async fn recv_available(&mut self) -> Result<Vec<Message>, NetworkError> {
+        self.wait_for_frame().await?;
+        self.drain_recv_buffer()
+    }
+
+    /// Return every complete message already sitting in the receive buffer
+    /// without waiting for more data from the stream
+    pub fn drain_recv_buffer(&mut self) -> Result<Vec<Message>, NetworkError> {
+        let mut messages = Vec::new();
+        while let Some(message) = self.try_read_message()? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    /// Take the next message if a full frame is already buffered (never waits)
+    pub fn try_read_message(&mut self) -> Result<Option<Message>, NetworkError> {
+        let result = self.parse_buffered_frame(None).and_then(|frame| {
+            frame.map(|frame| self.decode(&frame)).transpose()
+        });
+        self.stats.note(result)
+    }
+
+    fn parse_buffered_frame(&mut self, max_len: Option<usize>) -> Result<Option<Vec<u8>>, NetworkError> {
+        if self.buffer.len() < 4 {
+            return Ok(None);
+        }
 
-            self.buffer.extend_from_slice(&temp_buf[..n]);
+        let limit = max_len.map_or(self.framing.max_message_size, |max_len| max_len.min(self.framing.max_message_size));
+        let parsed = parse_frame_with_limit(&self.buffer, limit);
+        match parsed {
+            Ok((frame, consumed)) => {
+                let frame = frame.to_vec();
+                self.buffer.drain(..consumed);
+                self.stats.record_received(consumed, self.established_at);
+                Ok(Some(frame))
+            }
+            Err(NetworkError::ProtocolError(ref e)) if e.contains("Incomplete") => {
+                // Need more data
+                Ok(None)
+            }
+            Err(e) => Err(e),
         }
     }
 
@@ -134,80 +714,291 @@ impl Connection {
         self.peer_addr
     }
 
+    /// Get the local endpoint address (useful after binding to port 0)
+    ///
+    /// In-memory connections report the same placeholder address as `peer_addr`.
+    pub fn local_addr(&self) -> Result<SocketAddr, NetworkError> {
+        let io = self.io();
+        if io.stream.is_detached() {
+            return Err(detached_error().into());
+        }
+        match io.stream.tcp_stream() {
+            Some(stream) => Ok(stream.local_addr()?),
+            None => Ok(self.peer_addr),
+        }
+    }
+
     /// Close the connection
     pub async fn close(mut self) -> Result<(), NetworkError> {
-        match &mut self.stream {
-            ConnectionStream::Plain(stream) => {
-                stream.shutdown().await?;
-            }
-            ConnectionStream::TlsClient(stream) => {
-                stream.shutdown().await?;
-            }
-            ConnectionStream::TlsServer(stream) => {
-                stream.shutdown().await?;
-            }
+        self.flush().await?;
+
+        std::future::poll_fn(|cx| Pin::new(&mut self.io().stream).poll_shutdown(cx)).await?;
+        Ok(())
+    }
+
+    /// Apply socket options to the underlying TCP socket
+    ///
+    /// Fails for in-memory and multiplexed connections, which have no socket
+    /// of their own.
+    pub fn set_tcp_options(&self, opts: TcpOptions) -> Result<(), NetworkError> {
+        let io = self.io();
+        if io.stream.is_detached() {
+            return Err(detached_error().into());
         }
+        let stream = io.stream.tcp_stream()
+            .ok_or_else(|| NetworkError::ConnectionError("Connection is not a TCP stream".to_string()))?;
+        opts.apply(stream)
+    }
+
+    /// Check if the connection is running over TLS
+    pub fn is_tls(&self) -> bool {
+        self.io().stream.is_tls()
+    }
+
+    /// Whether we are the TLS client and the server's certificate was checked
+    /// by the verifier given to `connect_tls_with_verifier`
+    ///
+    /// False for `connect_tls` and `upgrade_to_tls_client`, whose connector
+    /// accepts any certificate, and on the server side.
+    pub fn is_server_verified(&self) -> bool {
+        self.server_verified
+    }
+
+    /// Whether the TLS handshake resumed an earlier session from a ticket,
+    /// skipping the certificate exchange
+    pub fn is_tls_resumed(&self) -> bool {
+        self.io().stream.is_tls_resumed()
+    }
+
+    /// Export keying material from the TLS session (RFC 5705), the same on both ends
+    ///
+    /// Fails unless the connection is running over TLS.
+    pub fn export_keying_material(&self, label: &[u8], output: &mut [u8]) -> Result<(), NetworkError> {
+        self.io().stream.export_keying_material(label, output)
+    }
+
+    /// Upgrade a plain TCP connection to TLS as the client (STARTTLS-style)
+    ///
+    /// Both peers must switch at the same point in the stream; the other side
+    /// calls `upgrade_to_tls_server`. If the TLS handshake fails the
+    /// connection is no longer usable.
+    pub async fn upgrade_to_tls_client(&mut self, server_name: &str) -> Result<(), NetworkError> {
+        let server_name = parse_server_name(server_name)?;
+        let tcp_stream = self.take_plain_stream().await?;
+
+        let tls_stream = demo_tls_connector()
+            .connect(server_name, tcp_stream)
+            .await
+            .map_err(|e| NetworkError::ConnectionError(format!("TLS upgrade failed: {}", e)))?;
+
+        self.io().stream = Box::new(tls_stream);
         Ok(())
     }
+
+    /// Upgrade a plain TCP connection to TLS as the server, using a fresh
+    /// self-signed certificate
+    pub async fn upgrade_to_tls_server(&mut self) -> Result<(), NetworkError> {
+        let acceptor = self_signed_acceptor()?;
+        let tcp_stream = self.take_plain_stream().await?;
+
+        let tls_stream = acceptor
+            .accept(tcp_stream)
+            .await
+            .map_err(|e| NetworkError::ConnectionError(format!("TLS upgrade failed: {}", e)))?;
+
+        self.io().stream = Box::new(tls_stream);
+        Ok(())
+    }
+
+    /// Virtual stream id when this connection is one stream of a `MuxedConnection`
+    pub fn mux_stream_id(&self) -> Option<u32> {
+        self.io().stream.mux_stream_id()
+    }
+
+    /// Wrap a multiplexed virtual stream
+    pub(super) fn from_mux_stream(stream: Compat<yamux::Stream>, peer_addr: SocketAddr) -> Self {
+        Self::new(Box::new(stream), peer_addr)
+    }
+
+    /// Give up the framing layer and hand back the raw byte stream
+    ///
+    /// Also returns bytes already read past the last frame, which belong to
+    /// whatever layer takes over the stream.
+    pub(super) async fn into_stream(mut self) -> Result<(ConnectionStream, Vec<u8>, SocketAddr), NetworkError> {
+        self.flush().await?;
+
+        let stream = std::mem::replace(&mut self.io().stream, Box::new(Detached));
+        Ok((stream, std::mem::take(&mut self.buffer), self.peer_addr))
+    }
+
+    /// Detach the plain TCP stream so it can be wrapped in TLS
+    async fn take_plain_stream(&mut self) -> Result<TcpStream, NetworkError> {
+        self.flush().await?;
+
+        // Bytes read before the switch were not protected by TLS; accepting
+        // them afterwards would allow plaintext injection into the TLS phase
+        if !self.buffer.is_empty() {
+            return Err(NetworkError::ProtocolError("Unread data buffered before TLS upgrade".to_string()));
+        }
+
+        let mut io = self.io();
+        match std::mem::replace(&mut io.stream, Box::new(Detached)).into_plain_tcp() {
+            Ok(stream) => Ok(stream),
+            Err(stream) => {
+                io.stream = stream;
+                Err(NetworkError::ConnectionError("Connection is not a plain TCP stream".to_string()))
+            }
+        }
+    }
 }
 
 /// Listen for incoming connections
 pub struct Listener {
     tcp_listener: TcpListener,
     tls_acceptor: Option<Arc<TlsAcceptor>>,
+    /// Applied to every accepted socket
+    tcp_options: TcpOptions,
+    /// Frame size limits for accepted connections
+    framing: MessageFramingConfig,
+    /// Peer address filter checked before any handshake
+    opts: ListenerOpts,
 }
 
 impl Listener {
     /// Bind to an address without TLS
     pub async fn bind(addr: &str) -> Result<Self, NetworkError> {
-        let tcp_listener = TcpListener::bind(addr).await?;
-        Ok(Self {
-            tcp_listener,
-            tls_acceptor: None,
-        })
+        Self::bind_with_opts(addr, ListenerOpts::default()).await
     }
 
     /// Bind to an address with TLS
     pub async fn bind_tls(addr: &str) -> Result<Self, NetworkError> {
-        let tcp_listener = TcpListener::bind(addr).await?;
-
-        // Generate self-signed certificate
-        let (certs, key) = generate_self_signed_cert()?;
+        Self::bind_tls_with_opts(addr, ListenerOpts::default()).await
+    }
 
-        // Create TLS config
-        let config = ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, key)
-            .map_err(|e| NetworkError::ConnectionError(format!("TLS config error: {}", e)))?;
+    /// Bind without TLS, restricted to an interface and peer ranges
+    pub async fn bind_with_opts(addr: &str, opts: ListenerOpts) -> Result<Self, NetworkError> {
+        Ok(Self {
+            tcp_listener: bind_listener(addr, opts.interface.as_deref()).await?,
+            tls_acceptor: None,
+            tcp_options: TcpOptions::default(),
+            framing: MessageFramingConfig::default(),
+            opts,
+        })
+    }
 
-        let acceptor = TlsAcceptor::from(Arc::new(config));
+    /// Bind with TLS, restricted to an interface and peer ranges
+    pub async fn bind_tls_with_opts(addr: &str, opts: ListenerOpts) -> Result<Self, NetworkError> {
+        let tcp_listener = bind_listener(addr, opts.interface.as_deref()).await?;
+        let acceptor = self_signed_acceptor()?;
 
         Ok(Self {
             tcp_listener,
             tls_acceptor: Some(Arc::new(acceptor)),
+            tcp_options: TcpOptions::default(),
+            framing: MessageFramingConfig::default(),
+            opts,
         })
     }
 
+    /// Socket options for connections accepted from now on
+    pub fn set_tcp_options(&mut self, opts: TcpOptions) {
+        self.tcp_options = opts;
+    }
+
+    /// Frame size limits for connections accepted from now on
+    pub fn set_framing_config(&mut self, framing: MessageFramingConfig) {
+        self.framing = framing;
+    }
+
     /// Accept a new connection
+    ///
+    /// Peers outside the `ListenerOpts` ranges are disconnected as soon as
+    /// they connect; they are never returned.
     pub async fn accept(&self) -> Result<Connection, NetworkError> {
-        let (stream, peer_addr) = self.tcp_listener.accept().await?;
+        self.accept_pending().await?.finish().await
+    }
+
+    /// Accept a TCP connection without running the TLS handshake yet
+    ///
+    /// Lets a caller move the handshake into its own task (and under its own
+    /// timeout) so one slow client doesn't hold up the accept loop.
+    pub async fn accept_pending(&self) -> Result<PendingConnection, NetworkError> {
+        let (stream, peer_addr) = loop {
+            let (stream, peer_addr) = self.tcp_listener.accept().await?;
+            if !self.opts.permits(peer_addr.ip()) {
+                continue;
+            }
+            break (stream, peer_addr);
+        };
+        self.tcp_options.apply(&stream)?;
+
+        Ok(PendingConnection {
+            stream,
+            peer_addr,
+            tls_acceptor: self.tls_acceptor.clone(),
+            framing: self.framing,
+        })
+    }
 
+    /// Get the local address
+    pub fn local_addr(&self) -> Result<SocketAddr, NetworkError> {
+        Ok(self.tcp_listener.local_addr()?)
+    }
+}
+
+/// TCP connection accepted by a `Listener`, before any TLS handshake
+pub struct PendingConnection {
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    tls_acceptor: Option<Arc<TlsAcceptor>>,
+    framing: MessageFramingConfig,
+}
+
+impl PendingConnection {
+    /// Address of the connecting peer
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Run the TLS handshake, if the listener uses TLS
+    pub async fn finish(self) -> Result<Connection, NetworkError> {
         if let Some(acceptor) = &self.tls_acceptor {
             let tls_stream = acceptor
-                .accept(stream)
+                .accept(self.stream)
                 .await
                 .map_err(|e| NetworkError::ConnectionError(format!("TLS accept failed: {}", e)))?;
 
-            Ok(Connection::from_tls_server(tls_stream, peer_addr))
+            Ok(Connection::new_with_config(Box::new(tls_stream), self.peer_addr, self.framing))
         } else {
-            Ok(Connection::from_tcp(stream, peer_addr))
+            Ok(Connection::new_with_config(Box::new(self.stream), self.peer_addr, self.framing))
         }
     }
+}
 
-    /// Get the local address
-    pub fn local_addr(&self) -> Result<SocketAddr, NetworkError> {
-        Ok(self.tcp_listener.local_addr()?)
-    }
+/// Bind a listening socket, optionally tied to one network interface
+async fn bind_listener(addr: &str, interface: Option<&str>) -> Result<TcpListener, NetworkError> {
+    let Some(interface) = interface else {
+        return Ok(TcpListener::bind(addr).await?);
+    };
+
+    let local = tokio::net::lookup_host(addr).await?.next()
+        .ok_or_else(|| NetworkError::ConnectionError(format!("No address for {}", addr)))?;
+    let socket = if local.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    socket.set_reuseaddr(true)?;
+    bind_to_interface(&socket, interface)?;
+    socket.bind(local)?;
+    Ok(socket.listen(1024)?)
+}
+
+#[cfg(target_os = "linux")]
+fn bind_to_interface(socket: &TcpSocket, interface: &str) -> Result<(), NetworkError> {
+    socket.bind_device(Some(interface.as_bytes()))
+        .map_err(|e| NetworkError::ConnectionError(format!("Cannot bind to interface {}: {}", interface, e)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_to_interface(_socket: &TcpSocket, interface: &str) -> Result<(), NetworkError> {
+    Err(NetworkError::ConnectionError(format!("Binding to interface {} is only supported on Linux", interface)))
 }
 
 /// Connect to a remote peer without TLS
@@ -218,23 +1009,44 @@ pub async fn connect(addr: &str) -> Result<Connection, NetworkError> {
     Ok(Connection::from_tcp(stream, peer_addr))
 }
 
+/// Create two connected in-memory connections (no sockets involved)
+pub fn memory_pair() -> (Connection, Connection) {
+    let (a, b) = tokio::io::duplex(MEMORY_PIPE_CAPACITY);
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+
+    (
+        Connection::new(Box::new(a), addr),
+        Connection::new(Box::new(b), addr),
+    )
+}
+
 /// Connect to a remote peer with TLS
 pub async fn connect_tls(addr: &str, server_name: &str) -> Result<Connection, NetworkError> {
-    let stream = TcpStream::connect(addr).await?;
-    let peer_addr = stream.peer_addr()?;
-
-    // Create TLS config (accepting self-signed certs for demo)
-    let root_store = RootCertStore::empty();
+    connect_tls_with_connector(addr, server_name, demo_tls_connector()).await
+}
 
+/// Connect with TLS, checking the server certificate with `verifier`
+/// (e.g. `ct::CtVerifiedCertVerifier`)
+pub async fn connect_tls_with_verifier(
+    addr: &str,
+    server_name: &str,
+    verifier: Arc<dyn rustls::client::danger::ServerCertVerifier>,
+) -> Result<Connection, NetworkError> {
     let config = ClientConfig::builder()
         .dangerous()
-        .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+        .with_custom_certificate_verifier(verifier)
         .with_no_client_auth();
 
-    let connector = TlsConnector::from(Arc::new(config));
+    let mut connection = connect_tls_with_connector(addr, server_name, TlsConnector::from(Arc::new(config))).await?;
+    connection.server_verified = true;
+    Ok(connection)
+}
+
+async fn connect_tls_with_connector(addr: &str, server_name: &str, connector: TlsConnector) -> Result<Connection, NetworkError> {
+    let stream = TcpStream::connect(addr).await?;
+    let peer_addr = stream.peer_addr()?;
 
-    let server_name = ServerName::try_from(server_name.to_string())
-        .map_err(|e| NetworkError::ConnectionError(format!("Invalid server name: {}", e)))?;
+    let server_name = parse_server_name(server_name)?;
 
     let tls_stream = connector
         .connect(server_name, stream)
@@ -244,9 +1056,45 @@ pub async fn connect_tls(addr: &str, server_name: &str) -> Result<Connection, Ne
     Ok(Connection::from_tls_client(tls_stream, peer_addr))
 }
 
+/// Build a TLS acceptor around a freshly generated self-signed certificate
+fn self_signed_acceptor() -> Result<TlsAcceptor, NetworkError> {
+    let (certs, key) = generate_self_signed_cert()?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| NetworkError::ConnectionError(format!("TLS config error: {}", e)))?;
+    // Stateless TLS 1.3 tickets, so reconnecting clients skip the certificate exchange
+    config.ticketer = Ticketer::new()
+        .map_err(|e| NetworkError::ConnectionError(format!("TLS ticketer error: {}", e)))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Build a TLS connector (accepting self-signed certs for demo)
+///
+/// Every connector shares one config, and so one in-memory ticket store,
+/// letting later connections to the same server resume.
+fn demo_tls_connector() -> TlsConnector {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+
+    let config = CONFIG.get_or_init(|| {
+        Arc::new(ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+            .with_no_client_auth())
+    });
+    TlsConnector::from(config.clone())
+}
+
+fn parse_server_name(server_name: &str) -> Result<ServerName<'static>, NetworkError> {
+    ServerName::try_from(server_name.to_string())
+        .map_err(|e| NetworkError::ConnectionError(format!("Invalid server name: {}", e)))
+}
+
 /// Skip server verification for self-signed certificates (DEMO ONLY - NOT FOR PRODUCTION)
 #[derive(Debug)]
-struct SkipServerVerification;
+pub(crate) struct SkipServerVerification;
 
 impl rustls::client::danger::ServerCertVerifier for SkipServerVerification {
     fn verify_server_cert(
@@ -306,7 +1154,8 @@ pub fn generate_self_signed_cert() -> Result<(Vec<CertificateDer<'static>>, Priv
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::network::protocol::Message;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use crate::network::protocol::{Message, MessageType, frame_message};
 
     #[tokio::test]
     async fn test_listener_bind() {
@@ -314,6 +1163,172 @@ mod tests {
         assert!(listener.local_addr().is_ok());
     }
 
+    #[tokio::test]
+    async fn test_connection_local_addr() {
+        let listener = Listener::bind_tls("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accept_handle = tokio::spawn(async move {
+            listener.accept().await
+        });
+
+        let client = connect_tls(&addr.to_string(), "localhost").await.unwrap();
+        let server = accept_handle.await.unwrap().unwrap();
+
+        assert_eq!(server.local_addr().unwrap(), addr);
+        assert_eq!(client.local_addr().unwrap(), server.peer_addr());
+        assert_ne!(client.local_addr().unwrap().port(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tls_session_resumption() {
+        let listener = Listener::bind_tls("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = tokio::spawn(async move {
+            let mut resumed = Vec::new();
+            for _ in 0..3 {
+                let mut connection = listener.accept().await.unwrap();
+                resumed.push(connection.is_tls_resumed());
+                connection.send_message(&Message::heartbeat()).await.unwrap();
+            }
+            resumed
+        });
+
+        // Tickets arrive after the handshake, so read before reconnecting.
+        // Server names are unique so parallel tests don't share tickets.
+        let mut first = connect_tls(&addr, "resume.test").await.unwrap();
+        first.recv_message().await.unwrap();
+        assert!(!first.is_tls_resumed());
+
+        let mut second = connect_tls(&addr, "resume.test").await.unwrap();
+        second.recv_message().await.unwrap();
+        assert!(second.is_tls_resumed());
+
+        // A server that can't resume falls back to a full handshake
+        let mut fallback = connect_tls(&addr, "fallback.test").await.unwrap();
+        fallback.recv_message().await.unwrap();
+        assert_eq!(server.await.unwrap(), vec![false, true, false]);
+
+        let (certs, key) = generate_self_signed_cert().unwrap();
+        let mut config = ServerConfig::builder().with_no_client_auth().with_single_cert(certs, key).unwrap();
+        config.session_storage = Arc::new(rustls::server::NoServerSessionStorage {});
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let plain = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let plain_addr = plain.local_addr().unwrap().to_string();
+        let no_tickets = tokio::spawn(async move {
+            let (stream, _) = plain.accept().await.unwrap();
+            acceptor.accept(stream).await.unwrap()
+        });
+
+        let connection = connect_tls(&plain_addr, "fallback.test").await.unwrap();
+        no_tickets.await.unwrap();
+        assert!(connection.is_tls());
+        assert!(!connection.is_tls_resumed());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_options() {
+        let mut listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        listener.set_tcp_options(TcpOptions { recv_buffer_size: Some(64 * 1024), ..TcpOptions::default() });
+        let addr = listener.local_addr().unwrap();
+
+        let accept_handle = tokio::spawn(async move { listener.accept().await });
+        let client = connect(&addr.to_string()).await.unwrap();
+        let server = accept_handle.await.unwrap().unwrap();
+
+        let io = server.io();
+        let stream = io.stream.tcp_stream().expect("expected a TCP stream");
+        assert!(stream.nodelay().unwrap());
+        // Linux doubles the requested size for bookkeeping
+        assert!(socket2::SockRef::from(stream).recv_buffer_size().unwrap() >= 64 * 1024);
+
+        client.set_tcp_options(TcpOptions { nodelay: false, linger: Some(Duration::from_secs(1)), ..TcpOptions::default() })
+            .unwrap();
+        let io = client.io();
+        let stream = io.stream.tcp_stream().expect("expected a TCP stream");
+        assert!(!stream.nodelay().unwrap());
+        assert_eq!(stream.linger().unwrap(), Some(Duration::from_secs(1)));
+
+        let (memory, _) = memory_pair();
+        assert!(memory.set_tcp_options(TcpOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_ip_cidr() {
+        let range: IpCidr = "10.1.0.0/16".parse().unwrap();
+        assert!(range.contains("10.1.200.3".parse().unwrap()));
+        assert!(!range.contains("10.2.0.1".parse().unwrap()));
+        assert!(range.contains("::ffff:10.1.0.9".parse().unwrap()));
+        assert!(!range.contains("::1".parse().unwrap()));
+
+        let host: IpCidr = "fd00::1".parse().unwrap();
+        assert!(host.contains("fd00::1".parse().unwrap()));
+        assert!(!host.contains("fd00::2".parse().unwrap()));
+        assert!("0.0.0.0/0".parse::<IpCidr>().unwrap().contains("192.0.2.1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+        assert!("10.0.0/8".parse::<IpCidr>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_framing_config_limits() {
+        let mut listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        listener.set_framing_config(MessageFramingConfig { max_message_size: 4096 });
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let accept_handle = tokio::spawn(async move { listener.accept().await });
+        let mut client = connect(&addr).await.unwrap();
+        let mut server = accept_handle.await.unwrap().unwrap();
+        assert_eq!(server.framing_config().max_message_size, 4096);
+
+        client.send_message(&Message::probe(1024)).await.unwrap();
+        assert_eq!(server.recv_message().await.unwrap().message_type, MessageType::Probe);
+        client.send_message(&Message::probe(8192)).await.unwrap();
+        assert!(server.recv_message().await.is_err());
+
+        // Above the 1 MB default once both ends allow it
+        let big = MessageFramingConfig { max_message_size: 4 * 1024 * 1024 };
+        let (mut a, mut b) = memory_pair();
+        assert!(a.send_message(&Message::probe(2 * 1024 * 1024)).await.is_err());
+        a.set_framing_config(big);
+        b.set_framing_config(big);
+        let reader = tokio::spawn(async move { b.recv_message().await });
+        a.send_message(&Message::probe(2 * 1024 * 1024)).await.unwrap();
+        assert_eq!(reader.await.unwrap().unwrap().message_type, MessageType::Probe);
+    }
+
+    #[tokio::test]
+    async fn test_listener_peer_filter() {
+        // Allowed: loopback is in the allowlist
+        let opts = ListenerOpts { allow: vec!["127.0.0.0/8".parse().unwrap()], ..ListenerOpts::default() };
+        let listener = Listener::bind_with_opts("127.0.0.1:0", opts).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accept_handle = tokio::spawn(async move { listener.accept().await });
+        let _client = connect(&addr.to_string()).await.unwrap();
+        assert!(accept_handle.await.unwrap().is_ok());
+
+        // Rejected: outside the allowlist, or inside it but denied
+        for opts in [
+            ListenerOpts { allow: vec!["10.0.0.0/8".parse().unwrap()], ..ListenerOpts::default() },
+            ListenerOpts {
+                allow: vec!["127.0.0.0/8".parse().unwrap()],
+                deny: vec!["127.0.0.1".parse().unwrap()],
+                ..ListenerOpts::default()
+            },
+        ] {
+            let listener = Listener::bind_with_opts("127.0.0.1:0", opts).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let accept_handle = tokio::spawn(async move { listener.accept().await });
+
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let mut buf = [0u8; 1];
+            assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+            assert!(!accept_handle.is_finished());
+            accept_handle.abort();
+        }
+    }
+
     #[tokio::test]
     async fn test_connection_message_roundtrip() {
         // Start a listener
@@ -340,12 +1355,191 @@ mod tests {
         assert_eq!(received.message_type, msg.message_type);
     }
 
+    #[tokio::test]
+    async fn test_recv_available() {
+        let (mut client, mut server) = memory_pair();
+
+        let mut batch = Vec::new();
+        for _ in 0..3 {
+            batch.extend_from_slice(&frame_message(&Message::heartbeat()).unwrap());
+        }
+        client.io().write_buffer.extend_from_slice(&batch);
+        client.flush().await.unwrap();
+
+        let messages = server.recv_available().await.unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|m| m.message_type == MessageType::Heartbeat));
+
+        // Nothing pending: waits for the next message
+        assert!(tokio::time::timeout(Duration::from_millis(20), server.recv_available()).await.is_err());
+
+        // Messages that arrived before the close are still returned, then EOF is an error
+        client.send_message(&Message::heartbeat()).await.unwrap();
+        client.flush().await.unwrap();
+        drop(client);
+        assert_eq!(server.recv_available().await.unwrap().len(), 1);
+        assert!(server.recv_available().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drain_recv_buffer() {
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accept_handle = tokio::spawn(async move {
+            listener.accept().await
+        });
+
+        // Write three frames in a single write so they land in one read
+        let mut raw = TcpStream::connect(addr).await.unwrap();
+        let mut server = accept_handle.await.unwrap().unwrap();
+
+        let mut batch = Vec::new();
+        for _ in 0..3 {
+            batch.extend_from_slice(&frame_message(&Message::heartbeat()).unwrap());
+        }
+        raw.write_all(&batch).await.unwrap();
+
+        let first = server.recv_message().await.unwrap();
+        assert_eq!(first.message_type, MessageType::Heartbeat);
+
+        let rest = server.drain_recv_buffer().unwrap();
+        assert_eq!(rest.len(), 2);
+
+        // Nothing left, and draining again must not block
+        assert!(server.drain_recv_buffer().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_linger_coalesces_writes() {
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accept_handle = tokio::spawn(async move {
+            listener.accept().await
+        });
+
+        let mut client = connect(&addr.to_string()).await.unwrap();
+        let mut server = accept_handle.await.unwrap().unwrap();
+
+        client.set_flush_linger(Duration::from_secs(60));
+        for _ in 0..3 {
+            client.send_message(&Message::heartbeat()).await.unwrap();
+        }
+
+        // Nothing has hit the wire yet
+        let pending = tokio::time::timeout(Duration::from_millis(50), server.recv_message()).await;
+        assert!(pending.is_err());
+
+        client.flush().await.unwrap();
+        for _ in 0..3 {
+            let msg = server.recv_message().await.unwrap();
+            assert_eq!(msg.message_type, MessageType::Heartbeat);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_frame_cancel_safe() {
+        let (mut client, mut server) = memory_pair();
+        let framed = frame_message(&Message::heartbeat()).unwrap();
+        let (head, tail) = framed.split_at(framed.len() / 2);
+
+        // Dropped mid-frame: the half already read must not be lost
+        client.io().write_buffer.extend_from_slice(head);
+        client.flush().await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(20), server.wait_for_frame()).await.is_err());
+        assert!(server.try_read_message().unwrap().is_none());
+
+        client.io().write_buffer.extend_from_slice(tail);
+        client.flush().await.unwrap();
+        server.wait_for_frame().await.unwrap();
+        let msg = server.try_read_message().unwrap().expect("frame was buffered");
+        assert_eq!(msg.message_type, MessageType::Heartbeat);
+    }
+
+    #[tokio::test]
+    async fn test_flush_linger_expires_on_its_own() {
+        let (mut client, mut server) = memory_pair();
+
+        client.set_flush_linger(Duration::from_millis(20));
+        client.send_message(&Message::heartbeat()).await.unwrap();
+        client.send_message(&Message::heartbeat()).await.unwrap();
+
+        // The client never touches the connection again
+        for _ in 0..2 {
+            let msg = tokio::time::timeout(Duration::from_secs(5), server.recv_message()).await
+                .expect("linger window never flushed")
+                .unwrap();
+            assert_eq!(msg.message_type, MessageType::Heartbeat);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signed_message_roundtrip() {
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accept_handle = tokio::spawn(async move {
+            listener.accept().await
+        });
+
+        let mut client = connect(&addr.to_string()).await.unwrap();
+        let mut server = accept_handle.await.unwrap().unwrap();
+
+        let keypair = SigningKeyPair::generate().unwrap();
+        let impostor = SigningKeyPair::generate().unwrap();
+
+        client.send_signed_message(&Message::heartbeat(), &keypair).await.unwrap();
+        let received = server.recv_signed_message(keypair.verifying_key()).await.unwrap();
+        assert_eq!(received.message_type, MessageType::Heartbeat);
+
+        client.send_signed_message(&Message::heartbeat(), &impostor).await.unwrap();
+        assert!(server.recv_signed_message(keypair.verifying_key()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_pair_roundtrip() {
+        let (mut a, mut b) = memory_pair();
+
+        a.send_message(&Message::heartbeat()).await.unwrap();
+        assert_eq!(b.recv_message().await.unwrap().message_type, MessageType::Heartbeat);
+
+        b.send_message(&Message::disconnect(None)).await.unwrap();
+        assert_eq!(a.recv_message().await.unwrap().message_type, MessageType::Disconnect);
+    }
+
+    #[tokio::test]
+    async fn test_connection_stats() {
+        let (mut a, mut b) = memory_pair();
+        let framed_len = frame_message(&Message::heartbeat()).unwrap().len() as u64;
+
+        a.send_message(&Message::heartbeat()).await.unwrap();
+        a.send_message(&Message::heartbeat()).await.unwrap();
+        b.recv_message().await.unwrap();
+        b.recv_message().await.unwrap();
+
+        let sent = a.stats();
+        assert_eq!(sent.messages_sent, 2);
+        assert_eq!(sent.bytes_sent, 2 * framed_len);
+        assert_eq!(sent.messages_received, 0);
+
+        let received = b.stats();
+        assert_eq!(received.messages_received, 2);
+        assert_eq!(received.bytes_received, 2 * framed_len);
+        assert!(received.last_activity >= received.established_at);
+
+        // A closed peer shows up as an error
+        drop(a);
+        assert!(b.recv_message().await.is_err());
+        assert_eq!(b.stats().errors, 1);
+    }
+
     #[test]
     fn test_generate_self_signed_cert() {
         let result = generate_self_signed_cert();
         assert!(result.is_ok());
 
-        let (certs, key) = result.unwrap();
+        let (certs, _key) = result.unwrap();
         assert!(!certs.is_empty());
     }
 }
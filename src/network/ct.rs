@@ -0,0 +1,590 @@
+// Certificate Transparency checks for TLS server certificates
+// Requires a validly signed embedded SCT from a configured log on top of normal chain validation
+
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::pki_types::{CertificateDer, ServerName, TrustAnchor, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+
+use super::NetworkError;
+
+/// X.509 extension holding the embedded SCT list (1.3.6.1.4.1.11129.2.4.2)
+const SCT_LIST_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0xD6, 0x79, 0x02, 0x04, 0x02];
+
+/// SCT version 1 is encoded as 0 (RFC 6962)
+const SCT_VERSION_V1: u8 = 0;
+
+/// `SignatureType.certificate_timestamp` (RFC 6962 section 3.2)
+const SIGNATURE_TYPE_CERTIFICATE_TIMESTAMP: u8 = 0;
+
+/// `LogEntryType.precert_entry`; embedded SCTs always sign the precertificate
+const LOG_ENTRY_TYPE_PRECERT: u16 = 1;
+
+/// A signed certificate timestamp embedded in a certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sct {
+    /// SHA-256 of the issuing log's public key
+    pub log_id: [u8; 32],
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// `CtExtensions`, covered by the signature
+    pub extensions: Vec<u8>,
+    /// TLS `HashAlgorithm` of the signature (4 is SHA-256)
+    pub hash_algorithm: u8,
+    /// TLS `SignatureAlgorithm` of the signature (1 is RSA, 3 is ECDSA)
+    pub signature_algorithm: u8,
+    /// The log's signature over the precertificate entry
+    pub signature: Vec<u8>,
+}
+
+impl Sct {
+    /// TLS signature scheme of the log's signature, if it's one logs use
+    fn signature_scheme(&self) -> Option<SignatureScheme> {
+        match (self.hash_algorithm, self.signature_algorithm) {
+            (4, 1) => Some(SignatureScheme::RSA_PKCS1_SHA256),
+            (4, 3) => Some(SignatureScheme::ECDSA_NISTP256_SHA256),
+            (5, 3) => Some(SignatureScheme::ECDSA_NISTP384_SHA384),
+            _ => None,
+        }
+    }
+
+    /// The `digitally-signed` input of an SCT for a precertificate entry
+    fn signed_data(&self, issuer_key_hash: &[u8; 32], tbs_certificate: &[u8]) -> Option<Vec<u8>> {
+        let tbs_len = u32::try_from(tbs_certificate.len()).ok().filter(|len| *len < 1 << 24)?;
+        let extensions_len = u16::try_from(self.extensions.len()).ok()?;
+
+        let mut data = Vec::with_capacity(47 + tbs_certificate.len() + self.extensions.len());
+        data.extend_from_slice(&[SCT_VERSION_V1, SIGNATURE_TYPE_CERTIFICATE_TIMESTAMP]);
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&LOG_ENTRY_TYPE_PRECERT.to_be_bytes());
+        data.extend_from_slice(issuer_key_hash);
+        data.extend_from_slice(&tbs_len.to_be_bytes()[1..]);
+        data.extend_from_slice(tbs_certificate);
+        data.extend_from_slice(&extensions_len.to_be_bytes());
+        data.extend_from_slice(&self.extensions);
+        Some(data)
+    }
+}
+
+/// A CT log trusted to vouch for certificates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CtLog {
+    id: [u8; 32],
+    /// Contents of the key's `AlgorithmIdentifier`
+    key_algorithm: Vec<u8>,
+    /// Contents of the `subjectPublicKey` bit string
+    public_key: Vec<u8>,
+}
+
+impl CtLog {
+    /// A log from its DER `SubjectPublicKeyInfo`, as published in the log lists
+    pub fn from_spki_der(spki: &[u8]) -> Result<Self, NetworkError> {
+        let malformed = || NetworkError::ProtocolError("Malformed CT log key".to_string());
+
+        let (_, fields, rest) = der_element(spki, 0x30).ok_or_else(malformed)?;
+        if !rest.is_empty() {
+            return Err(malformed());
+        }
+        let (_, key_algorithm, fields) = der_element(fields, 0x30).ok_or_else(malformed)?;
+        let (_, bits, _) = der_element(fields, 0x03).ok_or_else(malformed)?;
+        let Some((0, public_key)) = bits.split_first() else {
+            return Err(malformed());
+        };
+
+        Ok(Self {
+            id: Sha256::digest(spki).into(),
+            key_algorithm: key_algorithm.to_vec(),
+            public_key: public_key.to_vec(),
+        })
+    }
+
+    /// Log ID: SHA-256 of the log's DER public key
+    pub fn id(&self) -> [u8; 32] {
+        self.id
+    }
+
+    /// Whether `signature` is this log's signature over `message` under `scheme`
+    fn verify(&self, algorithms: &WebPkiSupportedAlgorithms, scheme: SignatureScheme, message: &[u8], signature: &[u8]) -> bool {
+        algorithms.mapping.iter()
+            .filter(|(candidate, _)| *candidate == scheme)
+            .flat_map(|(_, algs)| algs.iter())
+            .filter(|alg| alg.public_key_alg_id().as_ref() == self.key_algorithm.as_slice())
+            .any(|alg| alg.verify_signature(&self.public_key, message, signature).is_ok())
+    }
+}
+
+/// Chain validation plus a check that a trusted CT log saw the certificate
+///
+/// A certificate passes if it chains to a trusted root and embeds an SCT
+/// that isn't dated in the future and carries a valid signature from one of
+/// `ct_logs` over the precertificate entry (RFC 6962 section 3.2). Merkle
+/// inclusion proofs are not checked, so a log that signs but never
+/// publishes goes unnoticed. With no logs configured, only the chain is
+/// validated.
+#[derive(Debug)]
+pub struct CtVerifiedCertVerifier {
+    chain: Arc<WebPkiServerVerifier>,
+    roots: Vec<TrustAnchor<'static>>,
+    algorithms: WebPkiSupportedAlgorithms,
+    ct_logs: Vec<CtLog>,
+}
+
+impl CtVerifiedCertVerifier {
+    /// Validate chains against the Mozilla root store
+    pub fn new(ct_logs: Vec<CtLog>) -> Result<Self, NetworkError> {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        Self::with_roots(roots, ct_logs)
+    }
+
+    /// Validate chains against `roots` (e.g. a private CA)
+    pub fn with_roots(roots: RootCertStore, ct_logs: Vec<CtLog>) -> Result<Self, NetworkError> {
+        let anchors = roots.roots.clone();
+        let chain = WebPkiServerVerifier::builder(Arc::new(roots))
+            .build()
+            .map_err(|e| NetworkError::ConnectionError(format!("TLS verifier error: {}", e)))?;
+        let algorithms = rustls::crypto::aws_lc_rs::default_provider().signature_verification_algorithms;
+        Ok(Self { chain, roots: anchors, algorithms, ct_logs })
+    }
+
+    /// SHA-256 of the DER public key of the certificate that issued `leaf`
+    ///
+    /// Looked up among the presented intermediates, then the trust anchors.
+    fn issuer_key_hash(&self, leaf: &TbsCertificate, intermediates: &[CertificateDer]) -> Option<[u8; 32]> {
+        let from_intermediates = intermediates.iter()
+            .filter_map(|cert| TbsCertificate::parse(cert))
+            .find(|cert| cert.subject == leaf.issuer)
+            .map(|cert| Sha256::digest(cert.spki).into());
+        if from_intermediates.is_some() {
+            return from_intermediates;
+        }
+
+        // Trust anchors keep the contents of the subject and key, without the SEQUENCE header
+        let (_, issuer, _) = der_element(leaf.issuer, 0x30)?;
+        self.roots.iter()
+            .find(|anchor| anchor.subject.as_ref() == issuer)
+            .map(|anchor| {
+                let spki = anchor.subject_public_key_info.as_ref();
+                let mut hasher = Sha256::new();
+                hasher.update(der_header(0x30, spki.len()));
+                hasher.update(spki);
+                hasher.finalize().into()
+            })
+    }
+}
+
+impl ServerCertVerifier for CtVerifiedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        intermediates: &[CertificateDer],
+        server_name: &ServerName,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.chain.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        if self.ct_logs.is_empty() {
+            return Ok(verified);
+        }
+
+        let scts = embedded_scts(end_entity)
+            .map_err(|e| rustls::Error::General(e.to_string()))?;
+        let leaf = TbsCertificate::parse(end_entity)
+            .ok_or_else(|| rustls::Error::General("Malformed certificate".to_string()))?;
+        let precert_tbs = leaf.without_sct_list()
+            .ok_or_else(|| rustls::Error::General("Malformed certificate".to_string()))?;
+        let issuer_key_hash = self.issuer_key_hash(&leaf, intermediates)
+            .ok_or_else(|| rustls::Error::General("Issuer of the certificate not found for CT checks".to_string()))?;
+
+        let now_ms = now.as_secs().saturating_mul(1000);
+        let logged = scts.iter()
+            .filter(|sct| sct.timestamp <= now_ms)
+            .any(|sct| {
+                let (Some(log), Some(scheme)) = (self.ct_logs.iter().find(|log| log.id == sct.log_id), sct.signature_scheme()) else {
+                    return false;
+                };
+                sct.signed_data(&issuer_key_hash, &precert_tbs)
+                    .is_some_and(|data| log.verify(&self.algorithms, scheme, &data, &sct.signature))
+            });
+        if !logged {
+            return Err(rustls::Error::General(format!(
+                "Certificate has no valid timestamp from a trusted CT log ({} SCT(s) present)",
+                scts.len()
+            )));
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.chain.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.chain.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.chain.supported_verify_schemes()
+    }
+}
+
+/// The fields of a certificate's `TBSCertificate` that CT checks need
+struct TbsCertificate<'a> {
+    /// Each field as (tag, contents, full encoding)
+    fields: Vec<(u8, &'a [u8], &'a [u8])>,
+    /// Full encodings of the issuer and subject names and the public key
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    spki: &'a [u8],
+}
+
+impl<'a> TbsCertificate<'a> {
+    fn parse(cert: &'a [u8]) -> Option<Self> {
+        let (_, cert_body, _) = der_element(cert, 0x30)?;
+        let (_, mut tbs, _) = der_element(cert_body, 0x30)?;
+
+        let mut fields = Vec::new();
+        while !tbs.is_empty() {
+            let (tag, contents, rest) = der_any(tbs)?;
+            fields.push((tag, contents, &tbs[..tbs.len() - rest.len()]));
+            tbs = rest;
+        }
+
+        // serial, signature, issuer, validity, subject, subjectPublicKeyInfo after the optional [0] version
+        let skip = usize::from(fields.first()?.0 == 0xA0);
+        let field = |index: usize| fields.get(skip + index).map(|(_, _, raw)| *raw);
+        let (issuer, subject, spki) = (field(2)?, field(4)?, field(5)?);
+        Some(Self { fields, issuer, subject, spki })
+    }
+
+    /// The `[3]` extensions, as a list of `Extension` SEQUENCE contents
+    fn extensions(&self) -> Option<Vec<(&'a [u8], &'a [u8])>> {
+        let Some((_, contents, _)) = self.fields.iter().find(|(tag, _, _)| *tag == 0xA3) else {
+            return Some(Vec::new());
+        };
+        let (_, mut list, _) = der_element(contents, 0x30)?;
+        let mut extensions = Vec::new();
+        while !list.is_empty() {
+            let (_, extension, rest) = der_element(list, 0x30)?;
+            extensions.push((extension, &list[..list.len() - rest.len()]));
+            list = rest;
+        }
+        Some(extensions)
+    }
+
+    /// DER `TBSCertificate` with the SCT list extension removed, as logs sign it
+    fn without_sct_list(&self) -> Option<Vec<u8>> {
+        let mut kept = Vec::new();
+        for (extension, raw) in self.extensions()? {
+            if der_element(extension, 0x06)?.1 != SCT_LIST_OID {
+                kept.extend_from_slice(raw);
+            }
+        }
+
+        let mut body = Vec::new();
+        for (tag, _, raw) in &self.fields {
+            if *tag != 0xA3 {
+                body.extend_from_slice(raw);
+            } else if !kept.is_empty() {
+                let list_header = der_header(0x30, kept.len());
+                body.extend_from_slice(&der_header(0xA3, list_header.len() + kept.len()));
+                body.extend_from_slice(&list_header);
+                body.extend_from_slice(&kept);
+            }
+        }
+
+        let mut tbs = der_header(0x30, body.len());
+        tbs.extend_from_slice(&body);
+        Some(tbs)
+    }
+}
+
+/// SCTs from a certificate's SCT list extension (empty if it has none)
+pub fn embedded_scts(cert: &CertificateDer) -> Result<Vec<Sct>, NetworkError> {
+    let malformed = || NetworkError::ProtocolError("Malformed certificate".to_string());
+
+    let tbs = TbsCertificate::parse(cert).ok_or_else(malformed)?;
+    for (extension, _) in tbs.extensions().ok_or_else(malformed)? {
+        let (_, oid, mut fields) = der_element(extension, 0x06).ok_or_else(malformed)?;
+        if oid != SCT_LIST_OID {
+            continue;
+        }
+        // Skip the criticality flag if present
+        if let Some((_, _, rest)) = der_element(fields, 0x01) {
+            fields = rest;
+        }
+        let (_, value, _) = der_element(fields, 0x04).ok_or_else(malformed)?;
+        let (_, list, _) = der_element(value, 0x04).ok_or_else(malformed)?;
+        return parse_sct_list(list)
+            .ok_or_else(|| NetworkError::ProtocolError("Malformed SCT list".to_string()));
+    }
+
+    Ok(Vec::new())
+}
+
+/// TLS-encoded `SignedCertificateTimestampList` (RFC 6962 section 3.3)
+fn parse_sct_list(list: &[u8]) -> Option<Vec<Sct>> {
+    let (mut entries, rest) = u16_prefixed(list)?;
+    if !rest.is_empty() {
+        return None;
+    }
+
+    let mut scts = Vec::new();
+    while !entries.is_empty() {
+        let (sct, rest) = u16_prefixed(entries)?;
+        entries = rest;
+
+        // version (1) || log_id (32) || timestamp (8) || extensions || signature
+        if sct.len() < 41 || sct[0] != SCT_VERSION_V1 {
+            continue;
+        }
+        let (extensions, signed) = u16_prefixed(&sct[41..])?;
+        let (&[hash_algorithm, signature_algorithm], signed) = signed.split_first_chunk::<2>()?;
+        let (signature, rest) = u16_prefixed(signed)?;
+        if !rest.is_empty() {
+            return None;
+        }
+        scts.push(Sct {
+            log_id: sct[1..33].try_into().ok()?,
+            timestamp: u64::from_be_bytes(sct[33..41].try_into().ok()?),
+            extensions: extensions.to_vec(),
+            hash_algorithm,
+            signature_algorithm,
+            signature: signature.to_vec(),
+        });
+    }
+    Some(scts)
+}
+
+/// DER tag and length for `len` bytes of contents
+fn der_header(tag: u8, len: usize) -> Vec<u8> {
+    let mut header = vec![tag];
+    if len < 0x80 {
+        header.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        header.push(0x80 | (bytes.len() - skip) as u8);
+        header.extend_from_slice(&bytes[skip..]);
+    }
+    header
+}
+
+/// Split a 2-byte length-prefixed field off the front
+fn u16_prefixed(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u16::from_be_bytes(input.get(..2)?.try_into().ok()?) as usize;
+    let body = input.get(2..2 + len)?;
+    Some((body, &input[2 + len..]))
+}
+
+/// Split one DER element with tag `tag` off the front
+fn der_element(input: &[u8], tag: u8) -> Option<(u8, &[u8], &[u8])> {
+    der_any(input).filter(|(found, _, _)| *found == tag)
+}
+
+/// Split one DER element off the front as (tag, contents, rest)
+fn der_any(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7F) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let len = rest.get(..count)?.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        (len, &rest[count..])
+    };
+    let contents = rest.get(..len)?;
+    Some((tag, contents, &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, CustomExtension, IsCa, KeyPair, SerialNumber};
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    /// A private CA, a fixed leaf key and a CT log key
+    struct TestPki {
+        ca: Certificate,
+        ca_key: KeyPair,
+        leaf_key: KeyPair,
+        log_key: KeyPair,
+    }
+
+    impl TestPki {
+        fn new() -> Self {
+            let ca_key = KeyPair::generate().unwrap();
+            let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+            Self { ca, ca_key, leaf_key: KeyPair::generate().unwrap(), log_key: KeyPair::generate().unwrap() }
+        }
+
+        fn roots(&self) -> RootCertStore {
+            let mut roots = RootCertStore::empty();
+            roots.add(self.ca.der().clone()).unwrap();
+            roots
+        }
+
+        fn log(&self) -> CtLog {
+            CtLog::from_spki_der(&self.log_key.public_key_der()).unwrap()
+        }
+
+        /// A leaf for "localhost" carrying the SCT list `scts`, if any
+        ///
+        /// Everything else is fixed, so the leaf without `scts` is exactly
+        /// the precertificate the SCTs sign.
+        fn issue(&self, scts: Option<Vec<u8>>) -> CertificateDer<'static> {
+            let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+            params.serial_number = Some(SerialNumber::from(vec![1, 2, 3]));
+            if let Some(scts) = scts {
+                params.custom_extensions.push(CustomExtension::from_oid_content(&[1, 3, 6, 1, 4, 1, 11129, 2, 4, 2], scts));
+            }
+            params.signed_by(&self.leaf_key, &self.ca, &self.ca_key).unwrap().der().clone()
+        }
+
+        /// A TLS-encoded SCT from `log_key` for the leaf
+        fn sct(&self, log_key: &KeyPair, timestamp: u64) -> Vec<u8> {
+            let precert = self.issue(None);
+            let (_, cert_body, _) = der_element(&precert, 0x30).unwrap();
+            let tbs_len = cert_body.len() - der_any(cert_body).unwrap().2.len();
+            let issuer_key_hash: [u8; 32] = Sha256::digest(self.ca_key.public_key_der()).into();
+
+            let mut sct = Sct {
+                log_id: Sha256::digest(log_key.public_key_der()).into(),
+                timestamp,
+                extensions: Vec::new(),
+                hash_algorithm: 4,
+                signature_algorithm: 3,
+                signature: Vec::new(),
+            };
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(log_key.serialize_der()));
+            let signer = rustls::crypto::aws_lc_rs::sign::any_ecdsa_type(&key).unwrap()
+                .choose_scheme(&[SignatureScheme::ECDSA_NISTP256_SHA256]).unwrap();
+            sct.signature = signer.sign(&sct.signed_data(&issuer_key_hash, &cert_body[..tbs_len]).unwrap()).unwrap();
+
+            let mut encoded = vec![SCT_VERSION_V1];
+            encoded.extend_from_slice(&sct.log_id);
+            encoded.extend_from_slice(&sct.timestamp.to_be_bytes());
+            encoded.extend_from_slice(&[0, 0]); // no extensions
+            encoded.extend_from_slice(&[sct.hash_algorithm, sct.signature_algorithm]);
+            encoded.extend_from_slice(&(sct.signature.len() as u16).to_be_bytes());
+            encoded.extend_from_slice(&sct.signature);
+            encoded
+        }
+    }
+
+    /// The SCT list extension value holding `scts`
+    fn sct_list(scts: &[Vec<u8>]) -> Vec<u8> {
+        let mut entries = Vec::new();
+        for sct in scts {
+            entries.extend_from_slice(&(sct.len() as u16).to_be_bytes());
+            entries.extend_from_slice(sct);
+        }
+
+        let mut list = (entries.len() as u16).to_be_bytes().to_vec();
+        list.extend_from_slice(&entries);
+
+        // The extension value is an OCTET STRING wrapping the list
+        let mut value = der_header(0x04, list.len());
+        value.extend_from_slice(&list);
+        value
+    }
+
+    fn verify(roots: RootCertStore, leaf: &CertificateDer, ct_logs: Vec<CtLog>) -> Result<ServerCertVerified, rustls::Error> {
+        let verifier = CtVerifiedCertVerifier::with_roots(roots, ct_logs).unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        verifier.verify_server_cert(leaf, &[], &server_name, &[], UnixTime::now())
+    }
+
+    #[test]
+    fn test_embedded_scts_parsed() {
+        let pki = TestPki::new();
+        let other_log = KeyPair::generate().unwrap();
+        let leaf = pki.issue(Some(sct_list(&[pki.sct(&pki.log_key, 1_000), pki.sct(&other_log, 2_000)])));
+        let scts = embedded_scts(&leaf).unwrap();
+        assert_eq!(scts.len(), 2);
+        assert_eq!((scts[0].log_id, scts[0].timestamp), (pki.log().id(), 1_000));
+        assert_eq!(scts[1].timestamp, 2_000);
+        assert_eq!(scts[1].signature_scheme(), Some(SignatureScheme::ECDSA_NISTP256_SHA256));
+
+        assert!(embedded_scts(&pki.issue(None)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_precert_tbs_drops_only_the_sct_list() {
+        let pki = TestPki::new();
+        let precert = pki.issue(None);
+        let leaf = pki.issue(Some(sct_list(&[pki.sct(&pki.log_key, 1_000)])));
+
+        let (_, cert_body, _) = der_element(&precert, 0x30).unwrap();
+        let tbs_len = cert_body.len() - der_any(cert_body).unwrap().2.len();
+        assert_eq!(TbsCertificate::parse(&leaf).unwrap().without_sct_list().unwrap(), &cert_body[..tbs_len]);
+    }
+
+    #[test]
+    fn test_ct_verifier_requires_trusted_log() {
+        let pki = TestPki::new();
+        let leaf = pki.issue(Some(sct_list(&[pki.sct(&pki.log_key, 1_000)])));
+        assert!(verify(pki.roots(), &leaf, vec![pki.log()]).is_ok());
+        let other_log = CtLog::from_spki_der(&KeyPair::generate().unwrap().public_key_der()).unwrap();
+        assert!(verify(pki.roots(), &leaf, vec![other_log]).is_err());
+
+        // No logs configured: chain validation only
+        let unlogged = pki.issue(None);
+        assert!(verify(pki.roots(), &unlogged, Vec::new()).is_ok());
+        assert!(verify(pki.roots(), &unlogged, vec![pki.log()]).is_err());
+
+        // A timestamp from the future doesn't count
+        let leaf = pki.issue(Some(sct_list(&[pki.sct(&pki.log_key, u64::MAX)])));
+        assert!(verify(pki.roots(), &leaf, vec![pki.log()]).is_err());
+    }
+
+    #[test]
+    fn test_ct_verifier_checks_sct_signature() {
+        let pki = TestPki::new();
+
+        // Claims the trusted log's ID but is signed by another key
+        let forger = KeyPair::generate().unwrap();
+        let mut forged = pki.sct(&forger, 1_000);
+        forged[1..33].copy_from_slice(&pki.log().id());
+        let leaf = pki.issue(Some(sct_list(&[forged.clone()])));
+        assert!(verify(pki.roots(), &leaf, vec![pki.log()]).is_err());
+
+        // A genuine SCT for a different certificate doesn't transfer
+        let other = TestPki { leaf_key: KeyPair::generate().unwrap(), ..pki };
+        let borrowed = other.sct(&other.log_key, 1_000);
+        let pki = TestPki { leaf_key: KeyPair::generate().unwrap(), ..other };
+        let leaf = pki.issue(Some(sct_list(&[borrowed])));
+        assert!(verify(pki.roots(), &leaf, vec![pki.log()]).is_err());
+
+        // A valid one next to the forgery is enough
+        let leaf = pki.issue(Some(sct_list(&[forged, pki.sct(&pki.log_key, 1_000)])));
+        assert!(verify(pki.roots(), &leaf, vec![pki.log()]).is_ok());
+    }
+
+    #[test]
+    fn test_ct_verifier_still_validates_chain() {
+        let pki = TestPki::new();
+        let leaf = pki.issue(Some(sct_list(&[pki.sct(&pki.log_key, 1_000)])));
+        assert!(verify(TestPki::new().roots(), &leaf, vec![pki.log()]).is_err());
+    }
+}
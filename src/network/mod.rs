@@ -4,6 +4,11 @@
 pub mod protocol;
 pub mod connection;
 pub mod peer;
+pub mod quota;
+pub mod mux;
+pub mod ct;
+#[cfg(feature = "proto-wire")]
+pub mod proto_bridge;
 
 pub use connection::Connection;
 
@@ -31,6 +36,17 @@ pub enum NetworkError {
 
     #[error("Timeout")]
     Timeout,
+
+    #[error("Rate limited: traffic quota exceeded")]
+    RateLimited,
+
+    #[error("Forward secrecy not confirmed: no Kyber rekey has completed since the handshake")]
+    PfsNotConfirmed,
+
+    #[error("Peer closed the session")]
+    PeerClosed {
+        reason: Option<String>,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, NetworkError>;
@@ -0,0 +1,222 @@
+// Stream multiplexing over a single connection (yamux)
+// Lets chat, file transfer and telemetry share one TCP connection
+
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
+
+use super::{Connection, NetworkError, connection::ConnectionStream};
+
+/// Which end of the multiplexed connection we are (stream ids differ by side)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuxMode {
+    /// The side that opened the underlying connection
+    Client,
+    Server,
+}
+
+/// Requests from `MuxedConnection` handles to the driver task
+enum Command {
+    Open(oneshot::Sender<Result<yamux::Stream, yamux::ConnectionError>>),
+    Close(oneshot::Sender<Result<(), yamux::ConnectionError>>),
+}
+
+/// Many independent `Connection`s carried over one underlying connection
+///
+/// A background task drives the multiplexer; it keeps running while any
+/// stream is open, until `close` is called or the peer hangs up.
+pub struct MuxedConnection {
+    commands: mpsc::UnboundedSender<Command>,
+    inbound: mpsc::UnboundedReceiver<yamux::Stream>,
+    peer_addr: SocketAddr,
+    driver: JoinHandle<()>,
+}
+
+impl MuxedConnection {
+    /// Switch `connection` to multiplexed mode; both peers must switch at the same point
+    pub async fn new(connection: Connection, mode: MuxMode) -> Result<Self, NetworkError> {
+        // The peer may switch first, so its first frames can already be buffered
+        let (stream, buffered, peer_addr) = connection.into_stream().await?;
+        let stream = PrefixedStream { prefix: buffered, inner: stream };
+        let mode = match mode {
+            MuxMode::Client => yamux::Mode::Client,
+            MuxMode::Server => yamux::Mode::Server,
+        };
+        let mux = yamux::Connection::new(stream.compat(), yamux::Config::default(), mode);
+
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        let (inbound_tx, inbound) = mpsc::unbounded_channel();
+        let driver = tokio::spawn(drive(mux, command_rx, inbound_tx));
+
+        Ok(Self { commands, inbound, peer_addr, driver })
+    }
+
+    /// Open a new virtual stream to the peer
+    ///
+    /// The peer sees it in `accept_stream` once something is sent on it.
+    pub async fn open_stream(&self) -> Result<Connection, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(Command::Open(reply)).map_err(|_| closed_error())?;
+
+        let stream = response.await
+            .map_err(|_| closed_error())?
+            .map_err(|e| NetworkError::ConnectionError(format!("Failed to open stream: {}", e)))?;
+        Ok(Connection::from_mux_stream(stream.compat(), self.peer_addr))
+    }
+
+    /// Wait for the peer to open a virtual stream
+    pub async fn accept_stream(&mut self) -> Result<Connection, NetworkError> {
+        let stream = self.inbound.recv().await.ok_or_else(closed_error)?;
+        Ok(Connection::from_mux_stream(stream.compat(), self.peer_addr))
+    }
+
+    /// Close every stream and the underlying connection
+    pub async fn close(self) -> Result<(), NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(Command::Close(reply)).map_err(|_| closed_error())?;
+
+        let result = response.await.map_err(|_| closed_error())?;
+        let _ = self.driver.await;
+        result.map_err(|e| NetworkError::ConnectionError(format!("Failed to close multiplexed connection: {}", e)))
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+}
+
+/// Run the multiplexer: serve open/close requests and forward inbound streams
+async fn drive(
+    mut mux: yamux::Connection<Compat<PrefixedStream>>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    inbound: mpsc::UnboundedSender<yamux::Stream>,
+) {
+    let mut pending_opens = VecDeque::new();
+    let mut closing: Option<oneshot::Sender<Result<(), yamux::ConnectionError>>> = None;
+
+    let result = poll_fn(|cx| {
+        while let Poll::Ready(Some(command)) = commands.poll_recv(cx) {
+            match command {
+                Command::Open(reply) => pending_opens.push_back(reply),
+                Command::Close(reply) => closing = Some(reply),
+            }
+        }
+
+        if closing.is_some() {
+            return mux.poll_close(cx).map(|result| {
+                if let Some(reply) = closing.take() {
+                    let _ = reply.send(result);
+                }
+                Ok(())
+            });
+        }
+
+        while !pending_opens.is_empty() {
+            match mux.poll_new_outbound(cx) {
+                Poll::Ready(result) => {
+                    if let Some(reply) = pending_opens.pop_front() {
+                        let _ = reply.send(result);
+                    }
+                }
+                Poll::Pending => break,
+            }
+        }
+
+        // Polling inbound also drives all reads and writes
+        loop {
+            match mux.poll_next_inbound(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    // Nobody accepting; dropping the stream resets it
+                    let _ = inbound.send(stream);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    })
+    .await;
+
+    if let Err(e) = result {
+        tracing::debug!("Multiplexed connection ended: {}", e);
+    }
+}
+
+/// Replays bytes the framing layer had already read before reading the stream itself
+struct PrefixedStream {
+    prefix: Vec<u8>,
+    inner: ConnectionStream,
+}
+
+impl AsyncRead for PrefixedStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.prefix.is_empty() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+
+        let n = self.prefix.len().min(buf.remaining());
+        buf.put_slice(&self.prefix[..n]);
+        self.prefix.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for PrefixedStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn closed_error() -> NetworkError {
+    NetworkError::ConnectionError("Multiplexed connection closed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::connection::memory_pair;
+    use crate::network::protocol::{Message, MessageType};
+
+    #[tokio::test]
+    async fn test_streams_are_independent() {
+        let (client_conn, server_conn) = memory_pair();
+        let client = MuxedConnection::new(client_conn, MuxMode::Client).await.unwrap();
+        let mut server = MuxedConnection::new(server_conn, MuxMode::Server).await.unwrap();
+
+        let mut chat = client.open_stream().await.unwrap();
+        let mut files = client.open_stream().await.unwrap();
+        assert_ne!(chat.mux_stream_id(), files.mux_stream_id());
+
+        files.send_message(&Message::heartbeat()).await.unwrap();
+        chat.send_message(&Message::ack(7)).await.unwrap();
+
+        for _ in 0..2 {
+            let mut stream = server.accept_stream().await.unwrap();
+            let expected = if stream.mux_stream_id() == chat.mux_stream_id() {
+                MessageType::Ack
+            } else {
+                MessageType::Heartbeat
+            };
+            assert_eq!(stream.recv_message().await.unwrap().message_type, expected);
+        }
+
+        client.close().await.unwrap();
+        assert!(server.accept_stream().await.is_err());
+    }
+}
@@ -1,17 +1,25 @@
 // Peer management and lifecycle
 // Manages connected peers and their state
 
-use std::net::SocketAddr;
-use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use std::time::{SystemTime, Duration};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
 
 use crate::crypto::ratchet::RatchetState;
+use crate::crypto::signing::{SigningKeyPair, VerifyingKey};
 use super::{Connection, NetworkError};
+use super::protocol::{Message, MessagePayload, MessageType, ERROR_RATE_LIMITED};
+use super::quota::{Quota, QuotaTracker, QuotaUsage};
 
 const HEARTBEAT_INTERVAL_SECS: u64 = 30;
 const PEER_TIMEOUT_SECS: u64 = 90;
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events buffered per subscriber before the oldest are dropped
+const PEER_EVENT_CAPACITY: usize = 64;
 
 /// Represents a connected peer
 pub struct Peer {
@@ -32,6 +40,15 @@ pub struct Peer {
 
     /// Connection state
     state: PeerState,
+
+    /// Volume cap on what the peer may send us
+    quota: Option<QuotaTracker>,
+
+    /// BLAKE3 hash of the identity key the peer is expected to hold
+    peer_fingerprint: Option<[u8; 32]>,
+
+    /// Value both ends share for this session, signed into challenge responses
+    session_transcript: [u8; 32],
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,6 +76,10 @@ impl Peer {
             last_activity: SystemTime::now(),
             peer_id: None,
             state: PeerState::Handshaking,
+            quota: None,
+            peer_fingerprint: None,
+            // Both ends hold the root key, so this is per-session without revealing it
+            session_transcript: blake3::derive_key("aegis-peer-session-transcript-v1", &root_key),
         }
     }
 
@@ -103,11 +124,153 @@ impl Peer {
     pub fn is_connected(&self) -> bool {
         self.state == PeerState::Connected
     }
+
+    /// Cap how many bytes this peer may send per window (`None` removes the cap)
+    pub fn set_quota(&mut self, quota: Option<Quota>) {
+        self.quota = quota.map(QuotaTracker::new);
+    }
+
+    /// Bytes received against the quota in the current window
+    pub fn quota_usage(&self) -> Option<QuotaUsage> {
+        self.quota.as_ref().map(QuotaTracker::usage)
+    }
+
+    /// Remember the identity fingerprint this peer must prove on reconnection
+    pub fn set_peer_fingerprint(&mut self, fingerprint: [u8; 32]) {
+        self.peer_fingerprint = Some(fingerprint);
+    }
+
+    /// Replace the session transcript (e.g. with the handshake transcript hash)
+    ///
+    /// Both ends must set the same value.
+    pub fn set_session_transcript(&mut self, transcript: [u8; 32]) {
+        self.session_transcript = transcript;
+    }
+
+    /// Check that the peer holds the identity key matching `peer_fingerprint`
+    ///
+    /// Sends `challenge` and expects a signature over challenge || session
+    /// transcript. Returns false when the key or signature doesn't match, and
+    /// an error when no fingerprint is stored or the peer doesn't answer.
+    ///
+    /// Peer connections run no version handshake, so both applications must
+    /// already speak the `Challenge` frames; an older peer drops the frame and
+    /// this times out.
+    pub async fn challenge_response_auth(&mut self, challenge: &[u8; 32]) -> Result<bool, NetworkError> {
+        let expected = self.peer_fingerprint
+            .ok_or_else(|| NetworkError::ProtocolError("No identity fingerprint stored for this peer".to_string()))?;
+
+        self.connection.send_message(&Message::challenge(*challenge)).await?;
+        let reply = tokio::time::timeout(CHALLENGE_TIMEOUT, self.recv_message()).await
+            .map_err(|_| NetworkError::Timeout)??;
+        reply.validate()?;
+
+        let MessagePayload::ChallengeResponse { response, identity } = reply.payload else {
+            return Err(NetworkError::ProtocolError(format!("Expected challenge response, got {:?}", reply.message_type)));
+        };
+        if *blake3::hash(&identity).as_bytes() != expected {
+            return Ok(false);
+        }
+        let Ok(identity) = VerifyingKey::from_bytes(identity) else {
+            return Ok(false);
+        };
+
+        Ok(identity.verify(&self.challenge_data(challenge), &response).is_ok())
+    }
+
+    /// `challenge_response_auth` with the challenge bound to this session
+    ///
+    /// The peer signs BLAKE3(session transcript || challenge), so a response
+    /// captured in another session can't be replayed here.
+    pub async fn establish_identity(&mut self, challenge: &[u8; 32]) -> Result<bool, NetworkError> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.session_transcript);
+        hasher.update(challenge);
+        let bound = *hasher.finalize().as_bytes();
+
+        self.challenge_response_auth(&bound).await
+    }
+
+    /// Sign a received `Challenge` with our identity key and send the response
+    pub async fn answer_challenge(&mut self, msg: &Message, identity: &SigningKeyPair) -> Result<(), NetworkError> {
+        let MessagePayload::Challenge { nonce } = &msg.payload else {
+            return Err(NetworkError::ProtocolError("Invalid challenge payload".to_string()));
+        };
+        if msg.message_type != MessageType::Challenge {
+            return Err(NetworkError::ProtocolError("Not a challenge".to_string()));
+        }
+
+        let signature = identity.sign(&self.challenge_data(nonce))
+            .map_err(|e| NetworkError::ConnectionError(format!("Signing failed: {}", e)))?;
+        let response = Message::challenge_response(signature, identity.verifying_key().as_bytes().to_vec());
+        self.connection.send_message(&response).await
+    }
+
+    /// Bytes a challenge response signs: challenge || session transcript
+    fn challenge_data(&self, challenge: &[u8; 32]) -> Vec<u8> {
+        let mut data = challenge.to_vec();
+        data.extend_from_slice(&self.session_transcript);
+        data
+    }
+
+    /// Receive a message, charging its size against the quota
+    ///
+    /// Over quota, the message is dropped, the peer is sent a rate-limited
+    /// error frame, and `RateLimited` is returned until the window resets.
+    pub async fn recv_message(&mut self) -> Result<Message, NetworkError> {
+        let before = self.connection.stats().bytes_received;
+        let message = self.connection.recv_message().await?;
+        self.update_activity();
+
+        let bytes = self.connection.stats().bytes_received - before;
+        if let Some(quota) = &mut self.quota {
+            if !quota.record(bytes) {
+                self.connection.send_message(&Message::rate_limited()).await?;
+                return Err(NetworkError::RateLimited);
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+/// Named set of peers, e.g. the members of a room
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerGroup {
+    /// Random identifier assigned at creation
+    pub id: [u8; 16],
+    pub name: String,
+    pub members: HashSet<SocketAddr>,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Bumped whenever a member is removed, so the group key can be rotated
+    pub epoch: u32,
+}
+
+/// What `PeerManager::health_check_loop` did, for the application to react to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerEvent {
+    /// A heartbeat went out to an idle peer
+    HeartbeatSent(SocketAddr),
+    /// Sending a heartbeat failed
+    HeartbeatFailed { addr: SocketAddr, error: String },
+    /// The peer was silent too long and has been removed
+    TimedOut(SocketAddr),
 }
 
+/// A peer in the manager's map, locked on its own so sending to it doesn't hold the map
+type SharedPeer = Arc<Mutex<Peer>>;
+
 /// Manages multiple peers
+///
+/// Clones share the same peers, groups, allow list, and event channel
+#[derive(Clone)]
 pub struct PeerManager {
-    peers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
+    peers: Arc<RwLock<HashMap<SocketAddr, SharedPeer>>>,
+    groups: Arc<RwLock<HashMap<[u8; 16], PeerGroup>>>,
+    events: broadcast::Sender<PeerEvent>,
+    /// IPs exempt from `add_peer_deduplicated`'s per-IP limit
+    allow_list: Arc<RwLock<Option<HashSet<IpAddr>>>>,
 }
 
 impl PeerManager {
@@ -115,21 +278,166 @@ impl PeerManager {
     pub fn new() -> Self {
         Self {
             peers: Arc::new(RwLock::new(HashMap::new())),
+            groups: Arc::new(RwLock::new(HashMap::new())),
+            events: broadcast::channel(PEER_EVENT_CAPACITY).0,
+            allow_list: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Receive events from `health_check_loop`
+    pub fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Heartbeat idle peers and drop timed-out ones every `interval`, forever
+    ///
+    /// Meant for `tokio::spawn(manager.health_check_loop(interval))`; abort
+    /// the task to stop it. Timed-out peers have their connections closed.
+    pub fn health_check_loop(&self, interval: Duration) -> impl Future<Output = ()> + Send + 'static {
+        let manager = self.clone();
+        async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                manager.check_health().await;
+            }
+        }
+    }
+
+    async fn check_health(&self) {
+        let heartbeat = Message::heartbeat();
+        for (addr, peer) in self.snapshot_peers(|p| p.needs_heartbeat() && p.is_connected()).await {
+            // Only this peer is locked while its heartbeat goes out
+            let mut peer = peer.lock().await;
+            let event = match peer.connection.send_message(&heartbeat).await {
+                Ok(()) => PeerEvent::HeartbeatSent(addr),
+                Err(e) => PeerEvent::HeartbeatFailed { addr, error: e.to_string() },
+            };
+            drop(peer);
+            let _ = self.events.send(event);
+        }
+
+        for peer in self.take_timed_out_peers().await {
+            let addr = peer.addr;
+            if let Err(e) = peer.connection.close().await {
+                tracing::debug!("Closing timed-out peer {} failed: {}", addr, e);
+            }
+            let _ = self.events.send(PeerEvent::TimedOut(addr));
+        }
+    }
+
+    /// Create a group of peers and return its ID
+    ///
+    /// Members need not be connected yet; `broadcast_to_group` skips those
+    /// that aren't.
+    pub async fn create_group(&self, name: &str, members: impl IntoIterator<Item = SocketAddr>) -> [u8; 16] {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut groups = self.groups.write().await;
+        let id = loop {
+            let id: [u8; 16] = rand::random();
+            if !groups.contains_key(&id) {
+                break id;
+            }
+        };
+        groups.insert(id, PeerGroup { id, name: name.to_string(), members: members.into_iter().collect(), created_at, epoch: 0 });
+        id
+    }
+
+    /// Snapshot of a group
+    pub async fn group(&self, group_id: &[u8; 16]) -> Option<PeerGroup> {
+        let groups = self.groups.read().await;
+        groups.get(group_id).cloned()
+    }
+
+    /// Send a message to every connected member of a group
+    ///
+    /// Returns how many members it was sent to. Every member is attempted
+    /// even if some sends fail; the failures are then reported together.
+    pub async fn broadcast_to_group(&self, group_id: &[u8; 16], message: &Message) -> Result<usize, NetworkError> {
+        let members = self.group(group_id).await
+            .ok_or_else(|| NetworkError::PeerError("Unknown peer group".to_string()))?
+            .members;
+
+        // Members that aren't connected are skipped; the map is released before any send
+        let targets: Vec<(SocketAddr, SharedPeer)> = {
+            let peers = self.peers.read().await;
+            members.iter().filter_map(|addr| Some((*addr, peers.get(addr)?.clone()))).collect()
+        };
+
+        let mut sent = 0;
+        let mut failures = Vec::new();
+        for (addr, peer) in targets {
+            let result = peer.lock().await.connection.send_message(message).await;
+            match result {
+                Ok(()) => sent += 1,
+                Err(e) => failures.push(format!("{}: {}", addr, e)),
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(NetworkError::PeerError(format!("Broadcast failed for {}", failures.join(", "))));
+        }
+        Ok(sent)
+    }
+
+    /// Remove a member from a group, returning the group's epoch afterwards
+    ///
+    /// The epoch only moves if `addr` was a member.
+    pub async fn remove_from_group(&self, group_id: &[u8; 16], addr: &SocketAddr) -> Result<u32, NetworkError> {
+        let mut groups = self.groups.write().await;
+        let group = groups.get_mut(group_id)
+            .ok_or_else(|| NetworkError::PeerError("Unknown peer group".to_string()))?;
+
+        if group.members.remove(addr) {
+            group.epoch += 1;
         }
+        Ok(group.epoch)
     }
 
     /// Add a peer
     pub async fn add_peer(&self, peer: Peer) -> Result<(), NetworkError> {
         let addr = peer.addr;
         let mut peers = self.peers.write().await;
-        peers.insert(addr, peer);
+        peers.insert(addr, Arc::new(Mutex::new(peer)));
         Ok(())
     }
 
+    /// Add a peer unless `max_per_ip` peers from its IP (any port) are already connected
+    ///
+    /// A rejected peer is sent a 429 error before its connection is dropped.
+    /// IPs on the allow list are never limited.
+    pub async fn add_peer_deduplicated(&self, mut peer: Peer, max_per_ip: usize) -> Result<(), NetworkError> {
+        let ip = peer.addr.ip();
+        let exempt = self.allow_list.read().await.as_ref().is_some_and(|allowed| allowed.contains(&ip));
+
+        {
+            let mut peers = self.peers.write().await;
+            let from_ip = peers.keys().filter(|addr| addr.ip() == ip && **addr != peer.addr).count();
+            if exempt || from_ip < max_per_ip {
+                peers.insert(peer.addr, Arc::new(Mutex::new(peer)));
+                return Ok(());
+            }
+        }
+
+        tracing::debug!("Rejecting {}: {} connections from {} already", peer.addr, max_per_ip, ip);
+        let _ = peer.connection.send_message(&Message::error(ERROR_RATE_LIMITED, "Too many connections".to_string())).await;
+        let _ = peer.connection.flush().await;
+        Err(NetworkError::PeerError("Too many connections from IP".to_string()))
+    }
+
+    /// Set the IPs `add_peer_deduplicated` lets through regardless of the limit
+    pub async fn set_allow_list(&self, allow_list: Option<HashSet<IpAddr>>) {
+        *self.allow_list.write().await = allow_list;
+    }
+
     /// Remove a peer
     pub async fn remove_peer(&self, addr: &SocketAddr) -> Option<Peer> {
-        let mut peers = self.peers.write().await;
-        peers.remove(addr)
+        let peer = self.peers.write().await.remove(addr)?;
+        Some(unshare_peer(peer).await)
     }
 
     /// Check if a peer exists
@@ -143,8 +451,32 @@ impl PeerManager {
     where
         F: FnOnce(&mut Peer) -> R,
     {
-        let mut peers = self.peers.write().await;
-        peers.get_mut(addr).map(f)
+        let peer = self.peers.read().await.get(addr).cloned()?;
+        let mut peer = peer.lock().await;
+        Some(f(&mut peer))
+    }
+
+    /// Current quota usage of a peer, if it has a quota
+    pub async fn quota_usage(&self, addr: &SocketAddr) -> Option<QuotaUsage> {
+        let peer = self.peers.read().await.get(addr).cloned()?;
+        let usage = peer.lock().await.quota_usage();
+        usage
+    }
+
+    /// Quota usage of every peer that has a quota
+    pub async fn quota_usages(&self) -> Vec<(SocketAddr, QuotaUsage)> {
+        let peers: Vec<(SocketAddr, SharedPeer)> = {
+            let peers = self.peers.read().await;
+            peers.iter().map(|(addr, p)| (*addr, p.clone())).collect()
+        };
+
+        let mut usages = Vec::new();
+        for (addr, peer) in peers {
+            if let Some(usage) = peer.lock().await.quota_usage() {
+                usages.push((addr, usage));
+            }
+        }
+        usages
     }
 
     /// Get all peer addresses
@@ -160,29 +492,48 @@ impl PeerManager {
     }
 
     /// Get peers that need heartbeat
+    ///
+    /// Peers busy with a send right now are skipped; they aren't idle.
     pub async fn peers_needing_heartbeat(&self) -> Vec<SocketAddr> {
+        self.snapshot_peers(|p| p.needs_heartbeat() && p.is_connected()).await
+            .into_iter()
+            .map(|(addr, _)| addr)
+            .collect()
+    }
+
+    /// Handles to the peers matching `filter`, taken without holding the map afterwards
+    ///
+    /// Peers locked by someone else are skipped rather than waited for.
+    async fn snapshot_peers(&self, filter: impl Fn(&Peer) -> bool) -> Vec<(SocketAddr, SharedPeer)> {
         let peers = self.peers.read().await;
         peers
             .iter()
-            .filter(|(_, p)| p.needs_heartbeat() && p.is_connected())
-            .map(|(addr, _)| *addr)
+            .filter(|(_, p)| p.try_lock().is_ok_and(|p| filter(&p)))
+            .map(|(addr, p)| (*addr, p.clone()))
             .collect()
     }
 
     /// Remove timed out peers
     pub async fn remove_timed_out_peers(&self) -> Vec<SocketAddr> {
-        let mut peers = self.peers.write().await;
-        let timed_out: Vec<SocketAddr> = peers
-            .iter()
-            .filter(|(_, p)| p.is_timed_out())
-            .map(|(addr, _)| *addr)
-            .collect();
+        self.take_timed_out_peers().await.iter().map(|peer| peer.addr).collect()
+    }
 
-        for addr in &timed_out {
-            peers.remove(addr);
+    async fn take_timed_out_peers(&self) -> Vec<Peer> {
+        let timed_out: Vec<SharedPeer> = {
+            let mut peers = self.peers.write().await;
+            let addrs: Vec<SocketAddr> = peers
+                .iter()
+                .filter(|(_, p)| p.try_lock().is_ok_and(|p| p.is_timed_out()))
+                .map(|(addr, _)| *addr)
+                .collect();
+            addrs.iter().filter_map(|addr| peers.remove(addr)).collect()
+        };
+
+        let mut removed = Vec::with_capacity(timed_out.len());
+        for peer in timed_out {
+            removed.push(unshare_peer(peer).await);
         }
-
-        timed_out
+        removed
     }
 
     /// Clear all peers
@@ -192,6 +543,22 @@ impl PeerManager {
     }
 }
 
+/// Take a peer removed from the map back out of its lock
+///
+/// A heartbeat or broadcast may still hold a handle to it; wait for that to finish.
+async fn unshare_peer(mut peer: SharedPeer) -> Peer {
+    loop {
+        match Arc::try_unwrap(peer) {
+            Ok(peer) => return peer.into_inner(),
+            Err(shared) => {
+                drop(shared.lock().await);
+                tokio::task::yield_now().await;
+                peer = shared;
+            }
+        }
+    }
+}
+
 impl Default for PeerManager {
     fn default() -> Self {
         Self::new()
@@ -257,4 +624,239 @@ mod tests {
         let state = PeerState::Handshaking;
         assert_ne!(state, PeerState::Connected);
     }
+
+    #[tokio::test]
+    async fn test_challenge_response_auth() {
+        use crate::network::connection::memory_pair;
+
+        let identity = SigningKeyPair::generate().unwrap();
+        let fingerprint = identity.verifying_key().fingerprint();
+        let (conn, remote_conn) = memory_pair();
+        let mut verifier = Peer::new(conn, create_test_root_key());
+        let mut prover = Peer::new(remote_conn, create_test_root_key());
+
+        // Nothing to check against yet
+        assert!(verifier.challenge_response_auth(&[1u8; 32]).await.is_err());
+
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                let challenge = prover.recv_message().await.unwrap();
+                prover.answer_challenge(&challenge, &identity).await.unwrap();
+            }
+        });
+
+        verifier.set_peer_fingerprint(fingerprint);
+        assert!(verifier.establish_identity(&[1u8; 32]).await.unwrap());
+
+        // Someone else's key
+        verifier.set_peer_fingerprint([0u8; 32]);
+        assert!(!verifier.establish_identity(&[2u8; 32]).await.unwrap());
+
+        // A signature over another session's transcript
+        verifier.set_peer_fingerprint(fingerprint);
+        verifier.set_session_transcript([9u8; 32]);
+        assert!(!verifier.challenge_response_auth(&[3u8; 32]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_loop() {
+        use crate::network::connection::memory_pair;
+
+        let manager = PeerManager::new();
+        let mut events = manager.subscribe();
+
+        let (conn, mut idle_remote) = memory_pair();
+        let mut idle = Peer::new(conn, create_test_root_key());
+        idle.addr = SocketAddr::from(([127, 0, 0, 1], 1));
+        idle.set_state(PeerState::Connected);
+        idle.last_activity = SystemTime::now() - Duration::from_secs(HEARTBEAT_INTERVAL_SECS + 1);
+        manager.add_peer(idle).await.unwrap();
+
+        let (conn, mut gone_remote) = memory_pair();
+        let mut gone = Peer::new(conn, create_test_root_key());
+        gone.addr = SocketAddr::from(([127, 0, 0, 1], 2));
+        gone.last_activity = SystemTime::now() - Duration::from_secs(PEER_TIMEOUT_SECS + 1);
+        manager.add_peer(gone).await.unwrap();
+
+        let task = tokio::spawn(manager.health_check_loop(Duration::from_secs(30)));
+
+        assert_eq!(events.recv().await.unwrap(), PeerEvent::HeartbeatSent(SocketAddr::from(([127, 0, 0, 1], 1))));
+        assert_eq!(events.recv().await.unwrap(), PeerEvent::TimedOut(SocketAddr::from(([127, 0, 0, 1], 2))));
+        task.abort();
+
+        assert_eq!(idle_remote.recv_message().await.unwrap().message_type, MessageType::Heartbeat);
+        assert!(gone_remote.recv_message().await.is_err());
+        assert_eq!(manager.peer_count().await, 1);
+    }
+
+    /// Fill a peer's pipe so the next send to it blocks
+    async fn fill_send_buffer(manager: &PeerManager, addr: &SocketAddr) {
+        let shared = manager.peers.read().await[addr].clone();
+        let mut peer = shared.lock().await;
+        let filler = Message::encrypted([0u8; 24], vec![0u8; 16 * 1024], 0, 0);
+        while tokio::time::timeout(Duration::from_millis(50), async {
+            peer.connection.send_message(&filler).await?;
+            peer.connection.flush().await
+        }).await.is_ok() {}
+    }
+
+    #[tokio::test]
+    async fn test_stuck_heartbeat_does_not_hold_peer_map() {
+        use crate::network::connection::memory_pair;
+
+        let manager = PeerManager::new();
+        let (conn, _stuck_remote) = memory_pair();
+        let mut stuck = Peer::new(conn, create_test_root_key());
+        let stuck_addr = stuck.addr;
+        stuck.set_state(PeerState::Connected);
+        stuck.last_activity = SystemTime::now() - Duration::from_secs(HEARTBEAT_INTERVAL_SECS + 1);
+        manager.add_peer(stuck).await.unwrap();
+
+        fill_send_buffer(&manager, &stuck_addr).await;
+
+        let health = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.check_health().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let (conn, _remote) = memory_pair();
+        let mut other = Peer::new(conn, create_test_root_key());
+        other.addr = SocketAddr::from(([127, 0, 0, 1], 7));
+        tokio::time::timeout(Duration::from_secs(1), manager.add_peer(other)).await.unwrap().unwrap();
+        assert_eq!(manager.peer_count().await, 2);
+        assert!(!health.is_finished());
+        health.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stuck_broadcast_does_not_hold_peer_map() {
+        use crate::network::connection::memory_pair;
+
+        let manager = PeerManager::new();
+        let (conn, _stuck_remote) = memory_pair();
+        let stuck = Peer::new(conn, create_test_root_key());
+        let stuck_addr = stuck.addr;
+        manager.add_peer(stuck).await.unwrap();
+        fill_send_buffer(&manager, &stuck_addr).await;
+
+        let id = manager.create_group("ops", [stuck_addr]).await;
+        let broadcast = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.broadcast_to_group(&id, &Message::heartbeat()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let (conn, _remote) = memory_pair();
+        let mut other = Peer::new(conn, create_test_root_key());
+        other.addr = SocketAddr::from(([127, 0, 0, 1], 7));
+        tokio::time::timeout(Duration::from_secs(1), manager.add_peer(other)).await.unwrap().unwrap();
+        assert!(!broadcast.is_finished());
+        broadcast.abort();
+    }
+
+    #[tokio::test]
+    async fn test_peer_group_broadcast_and_removal() {
+        use crate::network::connection::memory_pair;
+
+        let manager = PeerManager::new();
+        let mut remotes = Vec::new();
+        let mut addrs = Vec::new();
+        for port in 1..=3 {
+            let (conn, remote) = memory_pair();
+            let mut peer = Peer::new(conn, create_test_root_key());
+            peer.addr = SocketAddr::from(([127, 0, 0, 1], port));
+            addrs.push(peer.addr);
+            manager.add_peer(peer).await.unwrap();
+            remotes.push(remote);
+        }
+
+        // Two connected members and one that never connected
+        let offline = SocketAddr::from(([127, 0, 0, 1], 9));
+        let id = manager.create_group("ops", [addrs[0], addrs[1], offline]).await;
+        let group = manager.group(&id).await.unwrap();
+        assert_eq!(group.name, "ops");
+        assert_eq!(group.members.len(), 3);
+        assert_eq!(group.epoch, 0);
+
+        assert_eq!(manager.broadcast_to_group(&id, &Message::heartbeat()).await.unwrap(), 2);
+        for remote in &mut remotes[..2] {
+            assert_eq!(remote.recv_message().await.unwrap().message_type, MessageType::Heartbeat);
+        }
+
+        // Removing a member bumps the epoch; removing a non-member doesn't
+        assert_eq!(manager.remove_from_group(&id, &addrs[1]).await.unwrap(), 1);
+        assert_eq!(manager.remove_from_group(&id, &addrs[2]).await.unwrap(), 1);
+        assert_eq!(manager.broadcast_to_group(&id, &Message::heartbeat()).await.unwrap(), 1);
+
+        assert!(manager.broadcast_to_group(&[0u8; 16], &Message::heartbeat()).await.is_err());
+        assert!(manager.remove_from_group(&[0u8; 16], &addrs[0]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_peer_quota_hit_and_reset() {
+        use crate::network::connection::memory_pair;
+        use crate::network::protocol::{MessagePayload, ERROR_RATE_LIMITED};
+
+        let (conn, mut remote) = memory_pair();
+        let mut peer = Peer::new(conn, create_test_root_key());
+        let frame_len = Message::heartbeat().to_bytes().unwrap().len() as u64 + 4;
+        peer.set_quota(Some(Quota::new(frame_len * 2, Duration::from_millis(100))));
+
+        for _ in 0..3 {
+            remote.send_message(&Message::heartbeat()).await.unwrap();
+        }
+        assert!(peer.recv_message().await.is_ok());
+        assert!(peer.recv_message().await.is_ok());
+        assert!(matches!(peer.recv_message().await, Err(NetworkError::RateLimited)));
+
+        // The sender is told why
+        let reply = remote.recv_message().await.unwrap();
+        assert!(matches!(reply.payload, MessagePayload::Error { code: ERROR_RATE_LIMITED, .. }));
+
+        let manager = PeerManager::new();
+        let addr = peer.addr;
+        manager.add_peer(peer).await.unwrap();
+        assert_eq!(manager.quota_usage(&addr).await.unwrap().used_bytes, frame_len * 3);
+        assert_eq!(manager.quota_usages().await.len(), 1);
+
+        // A new window accepts traffic again
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(manager.quota_usage(&addr).await.unwrap().used_bytes, 0);
+        remote.send_message(&Message::heartbeat()).await.unwrap();
+        let mut peer = manager.remove_peer(&addr).await.unwrap();
+        assert!(peer.recv_message().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_add_peer_deduplicated() {
+        use crate::network::connection::memory_pair;
+
+        let manager = PeerManager::new();
+        let peer_at = |ip: [u8; 4], port: u16| {
+            let (conn, remote) = memory_pair();
+            let mut peer = Peer::new(conn, create_test_root_key());
+            peer.addr = SocketAddr::from((ip, port));
+            (peer, remote)
+        };
+
+        for port in 1..=2 {
+            let (peer, _remote) = peer_at([10, 0, 0, 1], port);
+            manager.add_peer_deduplicated(peer, 2).await.unwrap();
+        }
+
+        // A third connection from the same IP is told why and refused
+        let (peer, mut remote) = peer_at([10, 0, 0, 1], 3);
+        assert!(matches!(manager.add_peer_deduplicated(peer, 2).await, Err(NetworkError::PeerError(_))));
+        let reply = remote.recv_message().await.unwrap();
+        assert!(matches!(reply.payload, MessagePayload::Error { code: ERROR_RATE_LIMITED, .. }));
+
+        // Other IPs, and allow-listed ones, are unaffected
+        let (peer, _remote) = peer_at([10, 0, 0, 2], 1);
+        manager.add_peer_deduplicated(peer, 2).await.unwrap();
+        manager.set_allow_list(Some(HashSet::from([IpAddr::from([10, 0, 0, 1])]))).await;
+        let (peer, _remote) = peer_at([10, 0, 0, 1], 3);
+        manager.add_peer_deduplicated(peer, 2).await.unwrap();
+        assert_eq!(manager.peer_count().await, 4);
+    }
 }
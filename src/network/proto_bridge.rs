@@ -0,0 +1,228 @@
+// Protobuf wire encoding (feature "proto-wire")
+// Schema: proto/aegis_handshake.proto; lets non-Rust clients speak the Aegis protocol
+
+use prost::Message as ProstMessage;
+
+use super::NetworkError;
+use super::protocol::{Message, MessagePayload, MessageType, ProtocolVersion, frame_bytes, parse_frame};
+
+/// Types matching `proto/aegis_handshake.proto` (package `aegis.v1`)
+pub mod proto {
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct HandshakeRequest {
+        #[prost(uint32, tag = "1")]
+        pub version: u32,
+        #[prost(uint64, tag = "2")]
+        pub timestamp: u64,
+        #[prost(bytes = "vec", tag = "3")]
+        pub public_key: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct HandshakeResponse {
+        #[prost(uint32, tag = "1")]
+        pub version: u32,
+        #[prost(uint64, tag = "2")]
+        pub timestamp: u64,
+        #[prost(bytes = "vec", tag = "3")]
+        pub ciphertext: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct EncryptedFrame {
+        #[prost(uint32, tag = "1")]
+        pub version: u32,
+        #[prost(uint64, tag = "2")]
+        pub timestamp: u64,
+        #[prost(uint32, tag = "3")]
+        pub key_id: u32,
+        #[prost(bytes = "vec", tag = "4")]
+        pub nonce: Vec<u8>,
+        #[prost(bytes = "vec", tag = "5")]
+        pub ciphertext: Vec<u8>,
+        #[prost(uint64, tag = "6")]
+        pub message_counter: u64,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Heartbeat {
+        #[prost(uint32, tag = "1")]
+        pub version: u32,
+        #[prost(uint64, tag = "2")]
+        pub timestamp: u64,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Envelope {
+        #[prost(oneof = "envelope::Body", tags = "1, 2, 3, 4")]
+        pub body: Option<envelope::Body>,
+    }
+
+    pub mod envelope {
+        #[derive(Clone, PartialEq, ::prost::Oneof)]
+        pub enum Body {
+            #[prost(message, tag = "1")]
+            HandshakeRequest(super::HandshakeRequest),
+            #[prost(message, tag = "2")]
+            HandshakeResponse(super::HandshakeResponse),
+            #[prost(message, tag = "3")]
+            EncryptedFrame(super::EncryptedFrame),
+            #[prost(message, tag = "4")]
+            Heartbeat(super::Heartbeat),
+        }
+    }
+}
+
+use proto::envelope::Body;
+
+/// Converts between protocol messages and their protobuf form
+pub struct ProtoTranscoder;
+
+impl ProtoTranscoder {
+    /// Convert a message to a protobuf envelope
+    pub fn to_proto(message: &Message) -> Result<proto::Envelope, NetworkError> {
+        let version = message.version.0 as u32;
+        let timestamp = message.timestamp;
+
+        let body = match &message.payload {
+            MessagePayload::Handshake { public_key } => Body::HandshakeRequest(proto::HandshakeRequest {
+                version,
+                timestamp,
+                public_key: public_key.clone(),
+            }),
+            MessagePayload::HandshakeResponse { ciphertext } => Body::HandshakeResponse(proto::HandshakeResponse {
+                version,
+                timestamp,
+                ciphertext: ciphertext.clone(),
+            }),
+            MessagePayload::EncryptedData { nonce, ciphertext, message_counter } => Body::EncryptedFrame(proto::EncryptedFrame {
+                version,
+                timestamp,
+                key_id: message.key_id as u32,
+                nonce: nonce.to_vec(),
+                ciphertext: ciphertext.clone(),
+                message_counter: *message_counter,
+            }),
+            MessagePayload::Heartbeat => Body::Heartbeat(proto::Heartbeat { version, timestamp }),
+            _ => {
+                return Err(NetworkError::ProtocolError(
+                    format!("{:?} has no protobuf encoding", message.message_type)
+                ));
+            }
+        };
+
+        Ok(proto::Envelope { body: Some(body) })
+    }
+
+    /// Convert a protobuf envelope back to a message
+    pub fn from_proto(envelope: proto::Envelope) -> Result<Message, NetworkError> {
+        let body = envelope.body
+            .ok_or_else(|| NetworkError::ProtocolError("Empty protobuf envelope".to_string()))?;
+
+        let (version, timestamp, key_id, message_type, payload) = match body {
+            Body::HandshakeRequest(m) => (
+                m.version, m.timestamp, 0,
+                MessageType::Handshake,
+                MessagePayload::Handshake { public_key: m.public_key },
+            ),
+            Body::HandshakeResponse(m) => (
+                m.version, m.timestamp, 0,
+                MessageType::HandshakeResponse,
+                MessagePayload::HandshakeResponse { ciphertext: m.ciphertext },
+            ),
+            Body::EncryptedFrame(m) => {
+                let nonce: [u8; 24] = m.nonce.as_slice().try_into()
+                    .map_err(|_| NetworkError::ProtocolError("Nonce must be 24 bytes".to_string()))?;
+                (
+                    m.version, m.timestamp, m.key_id,
+                    MessageType::EncryptedMessage,
+                    MessagePayload::EncryptedData {
+                        nonce,
+                        ciphertext: m.ciphertext,
+                        message_counter: m.message_counter,
+                    },
+                )
+            }
+            Body::Heartbeat(m) => (
+                m.version, m.timestamp, 0,
+                MessageType::Heartbeat,
+                MessagePayload::Heartbeat,
+            ),
+        };
+
+        let version = u8::try_from(version)
+            .map_err(|_| NetworkError::ProtocolError(format!("Invalid protocol version: {}", version)))?;
+        let key_id = u16::try_from(key_id)
+            .map_err(|_| NetworkError::ProtocolError(format!("Invalid key ID: {}", key_id)))?;
+
+        Ok(Message {
+            version: ProtocolVersion(version),
+            message_type,
+            timestamp,
+            key_id,
+            payload,
+        })
+    }
+
+    /// Serialize a message as a protobuf envelope
+    pub fn encode(message: &Message) -> Result<Vec<u8>, NetworkError> {
+        Ok(Self::to_proto(message)?.encode_to_vec())
+    }
+
+    /// Deserialize a protobuf envelope into a message
+    pub fn decode(bytes: &[u8]) -> Result<Message, NetworkError> {
+        let envelope = proto::Envelope::decode(bytes)
+            .map_err(|e| NetworkError::SerializationError(format!("Protobuf decode failed: {}", e)))?;
+        Self::from_proto(envelope)
+    }
+
+    /// Frame a message with the protobuf encoding (same length prefix as bincode frames)
+    pub fn frame(message: &Message) -> Result<Vec<u8>, NetworkError> {
+        frame_bytes(&Self::encode(message)?)
+    }
+
+    /// Parse a protobuf-encoded frame, returning the message and bytes consumed
+    pub fn parse_frame(data: &[u8]) -> Result<(Message, usize), NetworkError> {
+        let (body, consumed) = parse_frame(data)?;
+        Ok((Self::decode(body)?, consumed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let msg = Message::encrypted([7u8; 24], vec![1, 2, 3], 42, 5);
+        let restored = ProtoTranscoder::decode(&ProtoTranscoder::encode(&msg).unwrap()).unwrap();
+
+        assert_eq!(restored.message_type, MessageType::EncryptedMessage);
+        assert_eq!(restored.key_id, 5);
+        assert_eq!(restored.timestamp, msg.timestamp);
+        match restored.payload {
+            MessagePayload::EncryptedData { nonce, ciphertext, message_counter } => {
+                assert_eq!(nonce, [7u8; 24]);
+                assert_eq!(ciphertext, vec![1, 2, 3]);
+                assert_eq!(message_counter, 42);
+            }
+            other => panic!("unexpected payload: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unsupported_type_rejected() {
+        assert!(ProtoTranscoder::encode(&Message::disconnect(None)).is_err());
+    }
+
+    #[test]
+    fn test_bad_nonce_rejected() {
+        let envelope = proto::Envelope {
+            body: Some(Body::EncryptedFrame(proto::EncryptedFrame {
+                nonce: vec![0u8; 12],
+                ..Default::default()
+            })),
+        };
+        assert!(ProtoTranscoder::from_proto(envelope).is_err());
+    }
+}
@@ -2,15 +2,44 @@
 // Wire format: [Version:1][Type:1][Timestamp:8][KeyID:2][Nonce:24][Ciphertext:N][Tag:16]
 
 use serde::{Serialize, Deserialize};
+use std::ops::RangeInclusive;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::crypto::kyber::{PublicKey, Ciphertext as KyberCiphertext};
+use crate::crypto::CryptoError;
+use crate::crypto::kyber::{KyberLevel, PublicKey, Ciphertext as KyberCiphertext};
+use crate::crypto::prekey::PreKeyInitiation;
+use crate::crypto::signing::{SigningKeyPair, VerifyingKey};
 use super::NetworkError;
 
 const CURRENT_PROTOCOL_VERSION: u8 = 1;
+const CURRENT_MINOR_VERSION: u8 = 0;
 const MAX_MESSAGE_SIZE: usize = 1024 * 1024; // 1MB limit
 
+/// Size limits a `Connection` applies to the frames it sends and receives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageFramingConfig {
+    /// Largest serialized message, excluding the 4-byte length prefix
+    pub max_message_size: usize,
+}
+
+impl Default for MessageFramingConfig {
+    fn default() -> Self {
+        Self { max_message_size: MAX_MESSAGE_SIZE }
+    }
+}
+
+/// `Error` frame code sent when a peer exceeds its traffic quota
+pub const ERROR_RATE_LIMITED: u16 = 429;
+
+/// Clock skew tolerated on frame timestamps, and the largest clock offset
+/// a session will track on top of it
+pub const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// Type codes reserved for `Extension` messages
+pub const EXTENSION_TYPES: RangeInclusive<u8> = 0x80..=0xFE;
+
 /// Protocol version
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProtocolVersion(pub u8);
 
@@ -20,8 +49,171 @@ impl Default for ProtocolVersion {
     }
 }
 
+/// The header byte carries only the major version
+impl From<WireVersion> for ProtocolVersion {
+    fn from(version: WireVersion) -> Self {
+        Self(version.major)
+    }
+}
+
+/// Protocol version plus the optional features a peer supports
+///
+/// Peers with the same major version can talk; they use the lower minor
+/// version and only the features both have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WireVersion {
+    pub major: u8,
+    pub minor: u8,
+    /// Bitmask of `WireVersion::FEATURE_*`
+    pub features: u32,
+}
+
+impl WireVersion {
+    /// AEAD additional data binds header fields (`AadSchema` above V0)
+    pub const FEATURE_AAD_BINDING: u32 = 1 << 0;
+    /// 0-RTT handshakes to a cached server key
+    pub const FEATURE_CACHED_HANDSHAKE: u32 = 1 << 1;
+    /// Asynchronous session setup from pre-key bundles
+    pub const FEATURE_PREKEY: u32 = 1 << 2;
+    /// Moving a session to a new connection
+    pub const FEATURE_MIGRATION: u32 = 1 << 3;
+    /// Many sessions over one multiplexed connection
+    pub const FEATURE_MUX: u32 = 1 << 4;
+    /// Kyber rekeys (`RekeyOffer`, `RekeyResponse`, `RekeyCommit`)
+    pub const FEATURE_DH_REKEY: u32 = 1 << 5;
+    /// `DisconnectAck` replies to `Disconnect`
+    pub const FEATURE_DISCONNECT_ACK: u32 = 1 << 6;
+    /// `OobData` side-channel frames
+    pub const FEATURE_OOB_DATA: u32 = 1 << 7;
+    /// `RotationInterval` and `OtpRotation` frames
+    pub const FEATURE_ROTATION_SYNC: u32 = 1 << 8;
+    /// `EphemeralData` messages
+    pub const FEATURE_EPHEMERAL: u32 = 1 << 9;
+    /// `ContactData` and `ContactKeyRotation` for relayed contacts
+    pub const FEATURE_CONTACTS: u32 = 1 << 10;
+    /// `TrackedData` messages and their `DeliveryAck`s
+    pub const FEATURE_DELIVERY_ACKS: u32 = 1 << 11;
+    /// `HeartbeatResponse` replies to `Heartbeat`
+    pub const FEATURE_HEARTBEAT_RESPONSE: u32 = 1 << 12;
+    /// `Probe` and `ProbeResponse` path MTU probes
+    ///
+    /// Shares the heartbeat-response bit: both shipped together, and the
+    /// handshake offer has no bit to spare.
+    pub const FEATURE_MTU_PROBE: u32 = Self::FEATURE_HEARTBEAT_RESPONSE;
+    /// `PeerInfo` introductions
+    pub const FEATURE_PEER_INFO: u32 = 1 << 13;
+
+    /// Feature bits that fit in a handshake offer (see `handshake_offer`)
+    const HANDSHAKE_FEATURES: u32 = (1 << 14) - 1;
+    /// Feature bits above the `key_id` high 12, carried in the schema nibble
+    const SCHEMA_NIBBLE_FEATURES: u32 = Self::HANDSHAKE_FEATURES & !((1 << 12) - 1);
+
+    /// What this build speaks
+    pub const fn current() -> Self {
+        Self {
+            major: CURRENT_PROTOCOL_VERSION,
+            minor: CURRENT_MINOR_VERSION,
+            features: Self::FEATURE_AAD_BINDING
+                | Self::FEATURE_CACHED_HANDSHAKE
+                | Self::FEATURE_PREKEY
+                | Self::FEATURE_MIGRATION
+                | Self::FEATURE_MUX
+                | Self::FEATURE_DH_REKEY
+                | Self::FEATURE_DISCONNECT_ACK
+                | Self::FEATURE_OOB_DATA
+                | Self::FEATURE_ROTATION_SYNC
+                | Self::FEATURE_EPHEMERAL
+                | Self::FEATURE_CONTACTS
+                | Self::FEATURE_DELIVERY_ACKS
+                | Self::FEATURE_HEARTBEAT_RESPONSE
+                | Self::FEATURE_PEER_INFO,
+        }
+    }
+
+    /// Handshake `key_id` carrying an AAD schema in the low 2 bits, feature
+    /// bits 12-13 in the 2 bits above it and feature bits 0-11 in the high 12
+    ///
+    /// Peers from before feature negotiation read the whole field as a schema,
+    /// fail to parse it and fall back to their own latest, so offering
+    /// features never breaks their handshake. Peers that read the low 4 bits
+    /// as the schema treat bits 12-13 the same way, and are never sent them
+    /// back since they don't offer them. Only the low 14 feature bits fit.
+    pub fn handshake_offer(schema: AadSchema, features: u32) -> u16 {
+        let features = features & Self::HANDSHAKE_FEATURES;
+        let nibble_features = ((features & Self::SCHEMA_NIBBLE_FEATURES) >> 12) as u16;
+        schema as u16 | nibble_features << 2 | ((features & !Self::SCHEMA_NIBBLE_FEATURES) as u16) << 4
+    }
+
+    /// Split a handshake `key_id` into its schema value and feature bits
+    pub fn parse_handshake_offer(key_id: u16) -> (u16, u32) {
+        let nibble_features = ((key_id >> 2) & 0x3) as u32;
+        (key_id & 0x3, (key_id >> 4) as u32 | nibble_features << 12)
+    }
+
+    /// Version both sides support, or `None` if the major versions differ
+    pub fn negotiate(local: WireVersion, remote: WireVersion) -> Option<WireVersion> {
+        (local.major == remote.major).then(|| WireVersion {
+            major: local.major,
+            minor: local.minor.min(remote.minor),
+            features: local.features & remote.features,
+        })
+    }
+
+    /// Whether every bit of `feature` is set
+    pub fn supports(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+}
+
+/// Which header fields an encrypted message binds into its AEAD additional data
+///
+/// Agreed during the handshake: handshake frames belong to no key epoch, so
+/// their `key_id` carries the initiator's highest schema and then the
+/// responder's choice, alongside feature bits (`WireVersion::handshake_offer`).
+/// Peers from before negotiation leave it 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[repr(u16)]
+pub enum AadSchema {
+    /// Nothing bound
+    #[default]
+    V0 = 0,
+    /// Message counter
+    V1 = 1,
+    /// Version, type, key ID, counter and timestamp
+    V2 = 2,
+}
+
+impl AadSchema {
+    /// Highest schema this version supports
+    pub const LATEST: AadSchema = AadSchema::V2;
+
+    /// Whether the AAD covers the frame timestamp, making it authenticated
+    pub fn covers_timestamp(self) -> bool {
+        self >= AadSchema::V2
+    }
+
+    /// Schema to use when the peer supports up to `peer_max`
+    pub fn negotiate(peer_max: u16) -> Self {
+        Self::try_from(peer_max).unwrap_or(Self::LATEST).min(Self::LATEST)
+    }
+}
+
+impl TryFrom<u16> for AadSchema {
+    type Error = NetworkError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AadSchema::V0),
+            1 => Ok(AadSchema::V1),
+            2 => Ok(AadSchema::V2),
+            _ => Err(NetworkError::ProtocolError(format!("Unknown AAD schema: {}", value))),
+        }
+    }
+}
+
 /// Message types in the protocol
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum MessageType {
     /// Initial handshake with public key exchange
@@ -45,8 +237,47 @@ pub enum MessageType {
     /// Disconnect notification
     Disconnect = 0x07,
 
+    /// Request (or confirm) switching the transport to TLS
+    TlsUpgrade = 0x08,
+
+    /// Path MTU probe carrying padding of a chosen size
+    Probe = 0x09,
+
+    /// Reply to a probe that arrived intact
+    ProbeResponse = 0x0A,
+
+    /// Reply to a heartbeat (used for RTT measurement)
+    HeartbeatResponse = 0x0B,
+
+    /// 0-RTT handshake encapsulated to the server's cached semi-static key
+    CachedHandshake = 0x0C,
+
+    /// Encrypted display name and capability list, sent once after the handshake
+    PeerInfo = 0x0D,
+
+    /// Session start encapsulated to a peer's published pre-key bundle
+    PreKeyMessage = 0x0E,
+
     /// Error message
     Error = 0xFF,
+
+    /// Message type from a later protocol revision (code in `EXTENSION_TYPES`)
+    ///
+    /// Its wire index must never move, so new types go after it; types that
+    /// older peers should be able to skip ride on it instead.
+    Extension = 0x80,
+
+    /// Side-channel data outside the ratchet, authenticated but not encrypted
+    OobData = 0x0F,
+
+    /// Nonce the peer must sign with its identity key
+    Challenge = 0x10,
+
+    /// Identity key and signature answering a challenge
+    ChallengeResponse = 0x11,
+
+    /// First frame on a replacement connection for an existing session
+    Migrate = 0x12,
 }
 
 impl TryFrom<u8> for MessageType {
@@ -61,13 +292,80 @@ impl TryFrom<u8> for MessageType {
             0x05 => Ok(MessageType::Ack),
             0x06 => Ok(MessageType::Heartbeat),
             0x07 => Ok(MessageType::Disconnect),
+            0x08 => Ok(MessageType::TlsUpgrade),
+            0x09 => Ok(MessageType::Probe),
+            0x0A => Ok(MessageType::ProbeResponse),
+            0x0B => Ok(MessageType::HeartbeatResponse),
+            0x0C => Ok(MessageType::CachedHandshake),
+            0x0D => Ok(MessageType::PeerInfo),
+            0x0E => Ok(MessageType::PreKeyMessage),
+            0x0F => Ok(MessageType::OobData),
+            0x10 => Ok(MessageType::Challenge),
+            0x11 => Ok(MessageType::ChallengeResponse),
+            0x12 => Ok(MessageType::Migrate),
             0xFF => Ok(MessageType::Error),
+            0x80 => Ok(MessageType::Extension),
             _ => Err(NetworkError::ProtocolError(format!("Unknown message type: {}", value))),
         }
     }
 }
 
+/// Longest display name accepted in a `PeerInfo`, in characters
+pub const MAX_DISPLAY_NAME_LEN: usize = 64;
+
+/// Most capability strings accepted in a `PeerInfo`
+pub const MAX_CAPABILITIES: usize = 32;
+
+/// Longest capability string accepted in a `PeerInfo`, in bytes
+pub const MAX_CAPABILITY_LEN: usize = 32;
+
+/// Display name and feature advertisement exchanged after the handshake
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub display_name: String,
+    /// Feature strings such as `"group_sessions"`, `"file_transfer"`, `"signed_messages"`
+    pub capabilities: Vec<String>,
+}
+
+impl PeerInfo {
+    pub fn new(display_name: impl Into<String>, capabilities: Vec<String>) -> Self {
+        Self {
+            display_name: display_name.into(),
+            capabilities,
+        }
+    }
+
+    /// Whether the peer advertised `capability`
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// Reject names that are too long or could mess with a terminal, and
+    /// capabilities that are not short `snake_case` words
+    pub fn validate(&self) -> Result<(), NetworkError> {
+        if self.display_name.is_empty() || self.display_name.chars().count() > MAX_DISPLAY_NAME_LEN {
+            return Err(NetworkError::ProtocolError("Invalid display name length".to_string()));
+        }
+        if self.display_name.chars().any(char::is_control) {
+            return Err(NetworkError::ProtocolError("Display name contains control characters".to_string()));
+        }
+        if self.capabilities.len() > MAX_CAPABILITIES {
+            return Err(NetworkError::ProtocolError("Too many capabilities".to_string()));
+        }
+        let well_formed = |c: &String| {
+            !c.is_empty()
+                && c.len() <= MAX_CAPABILITY_LEN
+                && c.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+        };
+        if !self.capabilities.iter().all(well_formed) {
+            return Err(NetworkError::ProtocolError("Invalid capability string".to_string()));
+        }
+        Ok(())
+    }
+}
+
 /// Wire format message
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     /// Protocol version
@@ -87,6 +385,7 @@ pub struct Message {
 }
 
 /// Message payload variants
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessagePayload {
     /// Handshake with Kyber public key
@@ -111,7 +410,7 @@ pub enum MessagePayload {
         new_key_id: u16,
     },
 
-    /// Acknowledgement with message ID
+    /// Acknowledgement carrying the acked message's `replay_safe_id`
     Ack {
         message_id: u64,
     },
@@ -124,11 +423,139 @@ pub enum MessagePayload {
         reason: Option<String>,
     },
 
+    /// TLS upgrade proposal or confirmation (empty payload)
+    TlsUpgrade,
+
+    /// MTU probe padding
+    Probe {
+        payload: Vec<u8>,
+    },
+
+    /// Size of the probe being answered
+    ProbeResponse {
+        probe_size: u64,
+    },
+
+    /// Heartbeat reply (empty payload)
+    HeartbeatResponse,
+
+    /// Kyber ciphertext for the server key with the given fingerprint
+    CachedHandshake {
+        key_fingerprint: [u8; 32],
+        ciphertext: Vec<u8>,
+    },
+
+    /// Ciphertexts for a pre-key bundle (see `crypto::prekey::PreKeyInitiation`)
+    PreKey {
+        signed_prekey_fingerprint: [u8; 32],
+        signed_ciphertext: Vec<u8>,
+        one_time: Option<(u32, Vec<u8>)>,
+    },
+
     /// Error with description
     Error {
         code: u16,
         message: String,
     },
+
+    /// Extension type code and its opaque body
+    Extension {
+        extension_type: u8,
+        data: Vec<u8>,
+    },
+
+    /// Ephemeral Kyber key offered for a DH-style rekey
+    RekeyOffer {
+        public_key: Vec<u8>,
+    },
+
+    /// Encapsulation to a `RekeyOffer`; the sender's chain moved to `new_key_id` with the secret mixed in
+    RekeyResponse {
+        new_key_id: u16,
+        ciphertext: Vec<u8>,
+    },
+
+    /// The offerer's chain moved to `new_key_id` with the same secret mixed in
+    RekeyCommit {
+        new_key_id: u16,
+    },
+
+    /// Confirms a received disconnect (empty payload)
+    DisconnectAck,
+
+    /// Out-of-band data with an HMAC under the sender's key for the channel
+    OobData {
+        channel: u8,
+        /// Per-channel, per-direction sequence number; replays are rejected
+        counter: u64,
+        data: Vec<u8>,
+        mac: [u8; 32],
+    },
+
+    /// Nonce to sign, bound to the session by the sender
+    Challenge {
+        nonce: [u8; 32],
+    },
+
+    /// Signature over nonce || session transcript, and the key that made it
+    ChallengeResponse {
+        response: Vec<u8>,
+        identity: Vec<u8>,
+    },
+
+    /// The sender switched to a new rotation interval; the receiver follows
+    ///
+    /// Sealed like `EncryptedData`; the plaintext is the interval in seconds
+    /// as a big-endian u64.
+    RotationInterval {
+        nonce: [u8; 24],
+        ciphertext: Vec<u8>,
+        message_counter: u64,
+    },
+
+    /// BLAKE3 commitment to the out-of-band seed and the minute it was used
+    OtpRotation {
+        otp_commitment: [u8; 32],
+    },
+
+    /// Proof that the sender holds the session being moved to this connection
+    Migrate {
+        /// Which session to move (`Session::connection_id`)
+        connection_id: [u8; 16],
+        session_token: [u8; 32],
+    },
+
+    /// `EncryptedData` the receiver should delete `ttl_secs` after receipt
+    EphemeralData {
+        nonce: [u8; 24],
+        ciphertext: Vec<u8>,
+        message_counter: u64,
+        ttl_secs: u32,
+    },
+
+    /// `EncryptedData` under the ratchet of one relayed contact
+    ContactData {
+        contact_id: u64,
+        nonce: [u8; 24],
+        ciphertext: Vec<u8>,
+        message_counter: u64,
+    },
+
+    /// `KeyRotation` of one relayed contact's sending chain
+    ContactKeyRotation { contact_id: u64, new_key_id: u16 },
+
+    /// `EncryptedData` the receiving application should `Ack` once handled
+    TrackedData {
+        nonce: [u8; 24],
+        ciphertext: Vec<u8>,
+        message_counter: u64,
+    },
+
+    /// `Ack` for a `TrackedData` message, MACed with a session subkey
+    DeliveryAck {
+        message_id: u64,
+        mac: [u8; 32],
+    },
 }
 
 impl Message {
@@ -163,6 +590,29 @@ impl Message {
         )
     }
 
+    /// Create a 0-RTT handshake for the server key with `key_fingerprint`
+    pub fn cached_handshake(key_fingerprint: [u8; 32], ciphertext: KyberCiphertext) -> Self {
+        Self::new(
+            MessageType::CachedHandshake,
+            MessagePayload::CachedHandshake {
+                key_fingerprint,
+                ciphertext: ciphertext.as_bytes().to_vec(),
+            },
+        )
+    }
+
+    /// Create a session start for a peer's pre-key bundle
+    pub fn prekey(initiation: PreKeyInitiation) -> Self {
+        Self::new(
+            MessageType::PreKeyMessage,
+            MessagePayload::PreKey {
+                signed_prekey_fingerprint: initiation.signed_prekey_fingerprint,
+                signed_ciphertext: initiation.signed_ciphertext,
+                one_time: initiation.one_time,
+            },
+        )
+    }
+
     /// Create an encrypted message
     pub fn encrypted(nonce: [u8; 24], ciphertext: Vec<u8>, message_counter: u64, key_id: u16) -> Self {
         let mut msg = Self::new(
@@ -177,11 +627,111 @@ impl Message {
         msg
     }
 
+    /// Create an encrypted message that disappears `ttl_secs` after receipt
+    pub fn ephemeral(nonce: [u8; 24], ciphertext: Vec<u8>, message_counter: u64, key_id: u16, ttl_secs: u32) -> Self {
+        let mut msg = Self::new(
+            MessageType::EncryptedMessage,
+            MessagePayload::EphemeralData {
+                nonce,
+                ciphertext,
+                message_counter,
+                ttl_secs,
+            },
+        );
+        msg.key_id = key_id;
+        msg
+    }
+
+    /// Create an encrypted message the peer's application should acknowledge
+    pub fn tracked(nonce: [u8; 24], ciphertext: Vec<u8>, message_counter: u64, key_id: u16) -> Self {
+        let mut msg = Self::new(
+            MessageType::EncryptedMessage,
+            MessagePayload::TrackedData {
+                nonce,
+                ciphertext,
+                message_counter,
+            },
+        );
+        msg.key_id = key_id;
+        msg
+    }
+
+    /// Create an encrypted message for a relayed contact's ratchet
+    pub fn contact(contact_id: u64, nonce: [u8; 24], ciphertext: Vec<u8>, message_counter: u64, key_id: u16) -> Self {
+        let mut msg = Self::new(
+            MessageType::EncryptedMessage,
+            MessagePayload::ContactData {
+                contact_id,
+                nonce,
+                ciphertext,
+                message_counter,
+            },
+        );
+        msg.key_id = key_id;
+        msg
+    }
+
+    /// Encrypted `PeerInfo`, sealed with a ratchet message key like application data
+    pub fn peer_info(nonce: [u8; 24], ciphertext: Vec<u8>, message_counter: u64, key_id: u16) -> Self {
+        let mut msg = Self::encrypted(nonce, ciphertext, message_counter, key_id);
+        msg.message_type = MessageType::PeerInfo;
+        msg
+    }
+
+    /// Acknowledge a message by its `replay_safe_id`
+    pub fn ack(message_id: u64) -> Self {
+        Self::new(MessageType::Ack, MessagePayload::Ack { message_id })
+    }
+
+    /// Acknowledge a `TrackedData` message; see `delivery_ack_mac_input`
+    pub fn delivery_ack(message_id: u64, mac: [u8; 32]) -> Self {
+        Self::new(MessageType::Ack, MessagePayload::DeliveryAck { message_id, mac })
+    }
+
+    /// Bytes a `DeliveryAck` MAC covers: a type label and the acked ID
+    pub fn delivery_ack_mac_input(message_id: u64) -> Vec<u8> {
+        let mut input = b"delivery-ack".to_vec();
+        input.extend_from_slice(&message_id.to_be_bytes());
+        input
+    }
+
     /// Create a heartbeat message
     pub fn heartbeat() -> Self {
         Self::new(MessageType::Heartbeat, MessagePayload::Heartbeat)
     }
 
+    /// Create an out-of-band message; `mac` covers `oob_mac_input`
+    pub fn oob_data(channel: u8, counter: u64, data: Vec<u8>, mac: [u8; 32]) -> Self {
+        Self::new(MessageType::OobData, MessagePayload::OobData { channel, counter, data, mac })
+    }
+
+    /// Ask the peer to prove its identity by signing `nonce`
+    pub fn challenge(nonce: [u8; 32]) -> Self {
+        Self::new(MessageType::Challenge, MessagePayload::Challenge { nonce })
+    }
+
+    /// Answer a challenge with a signature and the identity key that made it
+    pub fn challenge_response(response: Vec<u8>, identity: Vec<u8>) -> Self {
+        Self::new(MessageType::ChallengeResponse, MessagePayload::ChallengeResponse { response, identity })
+    }
+
+    /// Bytes an `OobData` MAC covers: type || timestamp (BE) || channel || counter (BE) || data
+    pub fn oob_mac_input(&self) -> Vec<u8> {
+        let mut input = vec![self.message_type as u8];
+        input.extend_from_slice(&self.timestamp.to_be_bytes());
+        if let MessagePayload::OobData { channel, counter, data, .. } = &self.payload {
+            input.push(*channel);
+            input.extend_from_slice(&counter.to_be_bytes());
+            input.extend_from_slice(data);
+        }
+        input
+    }
+
+    /// Create a heartbeat reply
+    pub fn heartbeat_response() -> Self {
+        Self::new(MessageType::HeartbeatResponse, MessagePayload::HeartbeatResponse)
+    }
+
     /// Create a disconnect message
     pub fn disconnect(reason: Option<String>) -> Self {
         Self::new(
@@ -190,6 +740,99 @@ impl Message {
         )
     }
 
+    /// Acknowledge the peer's disconnect so it can close cleanly
+    pub fn disconnect_ack() -> Self {
+        Self::new(MessageType::Disconnect, MessagePayload::DisconnectAck)
+    }
+
+    /// Announce that the sender's sending chain moved to `new_key_id`
+    pub fn key_rotation(new_key_id: u16) -> Self {
+        let mut message = Self::new(
+            MessageType::KeyRotation,
+            MessagePayload::KeyRotation { new_key_id },
+        );
+        message.key_id = new_key_id;
+        message
+    }
+
+    /// Announce that a relayed contact's sending chain moved to `new_key_id`
+    pub fn contact_key_rotation(contact_id: u64, new_key_id: u16) -> Self {
+        let mut message = Self::new(
+            MessageType::KeyRotation,
+            MessagePayload::ContactKeyRotation { contact_id, new_key_id },
+        );
+        message.key_id = new_key_id;
+        message
+    }
+
+    /// Offer an ephemeral Kyber key to start a DH-style rekey
+    pub fn rekey_offer(public_key: &PublicKey) -> Self {
+        Self::new(
+            MessageType::KeyRotation,
+            MessagePayload::RekeyOffer { public_key: public_key.as_bytes().to_vec() },
+        )
+    }
+
+    /// Answer a rekey offer; the sending chain is now at `new_key_id`
+    pub fn rekey_response(new_key_id: u16, ciphertext: KyberCiphertext) -> Self {
+        let mut message = Self::new(
+            MessageType::KeyRotation,
+            MessagePayload::RekeyResponse { new_key_id, ciphertext: ciphertext.as_bytes().to_vec() },
+        );
+        message.key_id = new_key_id;
+        message
+    }
+
+    /// Announce a new rotation interval for both sides, sealed like application data
+    pub fn rotation_interval(nonce: [u8; 24], ciphertext: Vec<u8>, message_counter: u64, key_id: u16) -> Self {
+        let mut msg = Self::new(
+            MessageType::KeyRotation,
+            MessagePayload::RotationInterval { nonce, ciphertext, message_counter },
+        );
+        msg.key_id = key_id;
+        msg
+    }
+
+    /// Announce a sending chain rekeyed from the out-of-band seed
+    pub fn otp_rotation(otp_commitment: [u8; 32], new_key_id: u16) -> Self {
+        let mut message = Self::new(MessageType::KeyRotation, MessagePayload::OtpRotation { otp_commitment });
+        message.key_id = new_key_id;
+        message
+    }
+
+    /// Move an existing session onto the connection this is sent over
+    pub fn migrate(connection_id: [u8; 16], session_token: [u8; 32]) -> Self {
+        Self::new(MessageType::Migrate, MessagePayload::Migrate { connection_id, session_token })
+    }
+
+    /// Finish a rekey from the offering side
+    pub fn rekey_commit(new_key_id: u16) -> Self {
+        let mut message = Self::new(MessageType::KeyRotation, MessagePayload::RekeyCommit { new_key_id });
+        message.key_id = new_key_id;
+        message
+    }
+
+    /// Create a TLS upgrade proposal/confirmation
+    pub fn tls_upgrade() -> Self {
+        Self::new(MessageType::TlsUpgrade, MessagePayload::TlsUpgrade)
+    }
+
+    /// Create an MTU probe with exactly `size` bytes of padding
+    pub fn probe(size: usize) -> Self {
+        Self::new(
+            MessageType::Probe,
+            MessagePayload::Probe { payload: vec![0u8; size] },
+        )
+    }
+
+    /// Create the reply to a probe of `probe_size` bytes
+    pub fn probe_response(probe_size: usize) -> Self {
+        Self::new(
+            MessageType::ProbeResponse,
+            MessagePayload::ProbeResponse { probe_size: probe_size as u64 },
+        )
+    }
+
     /// Create an error message
     pub fn error(code: u16, message: String) -> Self {
         Self::new(
@@ -198,15 +841,37 @@ impl Message {
         )
     }
 
+    /// Create the error frame telling a peer it exceeded its quota
+    pub fn rate_limited() -> Self {
+        Self::error(ERROR_RATE_LIMITED, "Traffic quota exceeded".to_string())
+    }
+
+    /// Create an extension message with a type code from `EXTENSION_TYPES`
+    pub fn extension(extension_type: u8, data: Vec<u8>) -> Self {
+        Self::new(MessageType::Extension, MessagePayload::Extension { extension_type, data })
+    }
+
     /// Serialize message to bytes
     pub fn to_bytes(&self) -> Result<Vec<u8>, NetworkError> {
         bincode::serialize(self)
             .map_err(|e| NetworkError::SerializationError(format!("Serialization failed: {}", e)))
     }
 
+    /// Exact length of `to_bytes()` without serializing
+    pub fn serialized_size(&self) -> Result<usize, NetworkError> {
+        bincode::serialized_size(self)
+            .map(|size| size as usize)
+            .map_err(|e| NetworkError::SerializationError(format!("Serialization failed: {}", e)))
+    }
+
     /// Deserialize message from bytes
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, NetworkError> {
-        if bytes.len() > MAX_MESSAGE_SIZE {
+        Self::from_bytes_with_limit(bytes, MAX_MESSAGE_SIZE)
+    }
+
+    /// `from_bytes` with a size limit other than the default 1 MB
+    pub fn from_bytes_with_limit(bytes: &[u8], max_len: usize) -> Result<Self, NetworkError> {
+        if bytes.len() > max_len {
             return Err(NetworkError::ProtocolError("Message too large".to_string()));
         }
 
@@ -216,6 +881,22 @@ impl Message {
 
     /// Validate message structure
     pub fn validate(&self) -> Result<(), NetworkError> {
+        self.validate_with_offset(0)
+    }
+
+    /// Validate, allowing for a peer clock that runs `clock_offset` seconds ahead of ours
+    ///
+    /// A peer clock that runs behind never tightens the check below the
+    /// default window, so a peer that later fixes its clock isn't rejected.
+    pub fn validate_with_offset(&self, clock_offset: i64) -> Result<(), NetworkError> {
+        self.validate_at(current_timestamp(), clock_offset)
+    }
+
+    /// `validate_with_offset` against the clock reading `now` (Unix seconds)
+    ///
+    /// The offset is clamped to `MAX_CLOCK_SKEW_SECS`, so the window never
+    /// grows past twice the default skew.
+    pub fn validate_at(&self, now: u64, clock_offset: i64) -> Result<(), NetworkError> {
         // Check version
         if self.version.0 > CURRENT_PROTOCOL_VERSION {
             return Err(NetworkError::ProtocolError(
@@ -223,10 +904,9 @@ impl Message {
             ));
         }
 
-        // Check timestamp (allow up to 5 minutes of clock skew)
-        let now = current_timestamp();
-        let max_skew = 300; // 5 minutes
-        if self.timestamp > now + max_skew {
+        // Check timestamp (allow up to 5 minutes of clock skew on top of the known offset)
+        let ahead = clock_offset.clamp(0, MAX_CLOCK_SKEW_SECS) as i128;
+        if self.timestamp as i128 > now as i128 + ahead + MAX_CLOCK_SKEW_SECS as i128 {
             return Err(NetworkError::ProtocolError("Timestamp too far in the future".to_string()));
         }
 
@@ -236,19 +916,221 @@ impl Message {
             (MessageType::HandshakeResponse, MessagePayload::HandshakeResponse { .. }) => Ok(()),
             (MessageType::EncryptedMessage, MessagePayload::EncryptedData { .. }) => Ok(()),
             (MessageType::KeyRotation, MessagePayload::KeyRotation { .. }) => Ok(()),
+            (MessageType::KeyRotation, MessagePayload::RekeyOffer { .. }) => Ok(()),
+            (MessageType::KeyRotation, MessagePayload::RekeyResponse { .. }) => Ok(()),
+            (MessageType::KeyRotation, MessagePayload::RekeyCommit { .. }) => Ok(()),
+            (MessageType::KeyRotation, MessagePayload::RotationInterval { .. }) => Ok(()),
+            (MessageType::KeyRotation, MessagePayload::OtpRotation { .. }) => Ok(()),
+            (MessageType::Migrate, MessagePayload::Migrate { .. }) => Ok(()),
+            (MessageType::EncryptedMessage, MessagePayload::EphemeralData { .. }) => Ok(()),
+            (MessageType::EncryptedMessage, MessagePayload::ContactData { .. }) => Ok(()),
+            (MessageType::KeyRotation, MessagePayload::ContactKeyRotation { .. }) => Ok(()),
+            (MessageType::EncryptedMessage, MessagePayload::TrackedData { .. }) => Ok(()),
             (MessageType::Ack, MessagePayload::Ack { .. }) => Ok(()),
+            (MessageType::Ack, MessagePayload::DeliveryAck { .. }) => Ok(()),
             (MessageType::Heartbeat, MessagePayload::Heartbeat) => Ok(()),
             (MessageType::Disconnect, MessagePayload::Disconnect { .. }) => Ok(()),
+            (MessageType::Disconnect, MessagePayload::DisconnectAck) => Ok(()),
+            (MessageType::OobData, MessagePayload::OobData { .. }) => Ok(()),
+            (MessageType::Challenge, MessagePayload::Challenge { .. }) => Ok(()),
+            (MessageType::ChallengeResponse, MessagePayload::ChallengeResponse { .. }) => Ok(()),
+            (MessageType::TlsUpgrade, MessagePayload::TlsUpgrade) => Ok(()),
+            (MessageType::Probe, MessagePayload::Probe { .. }) => Ok(()),
+            (MessageType::ProbeResponse, MessagePayload::ProbeResponse { .. }) => Ok(()),
+            (MessageType::HeartbeatResponse, MessagePayload::HeartbeatResponse) => Ok(()),
+            (MessageType::CachedHandshake, MessagePayload::CachedHandshake { .. }) => Ok(()),
+            (MessageType::PeerInfo, MessagePayload::EncryptedData { .. }) => Ok(()),
+            (MessageType::PreKeyMessage, MessagePayload::PreKey { .. }) => Ok(()),
             (MessageType::Error, MessagePayload::Error { .. }) => Ok(()),
+            (MessageType::Extension, MessagePayload::Extension { extension_type, .. }) => {
+                if EXTENSION_TYPES.contains(extension_type) {
+                    Ok(())
+                } else {
+                    Err(NetworkError::ProtocolError(format!("Extension type {:#04x} outside the reserved range", extension_type)))
+                }
+            }
             _ => Err(NetworkError::ProtocolError("Message type and payload mismatch".to_string())),
         }
     }
 
+    /// AEAD additional data for this message's `EncryptedData` under `schema`
+    ///
+    /// Integers are big-endian; the nonce and ciphertext are not covered.
+    /// `EphemeralData` always appends its TTL, `ContactData` its contact ID,
+    /// `TrackedData` a 0x01 byte and `RotationInterval` a 0x02 byte,
+    /// whatever the schema.
+    pub fn associated_data(&self, schema: AadSchema) -> Vec<u8> {
+        let (counter, suffix) = match &self.payload {
+            MessagePayload::EncryptedData { message_counter, .. } => (*message_counter, Vec::new()),
+            MessagePayload::EphemeralData { message_counter, ttl_secs, .. } => {
+                (*message_counter, ttl_secs.to_be_bytes().to_vec())
+            }
+            MessagePayload::ContactData { contact_id, message_counter, .. } => {
+                (*message_counter, contact_id.to_be_bytes().to_vec())
+            }
+            MessagePayload::TrackedData { message_counter, .. } => (*message_counter, vec![0x01]),
+            MessagePayload::RotationInterval { message_counter, .. } => (*message_counter, vec![0x02]),
+            _ => (0, Vec::new()),
+        };
+
+        let mut aad = match schema {
+            AadSchema::V0 => Vec::new(),
+            AadSchema::V1 => counter.to_be_bytes().to_vec(),
+            AadSchema::V2 => {
+                let mut aad = Vec::with_capacity(24);
+                aad.extend_from_slice(&[self.version.0, self.message_type as u8]);
+                aad.extend_from_slice(&self.key_id.to_be_bytes());
+                aad.extend_from_slice(&counter.to_be_bytes());
+                aad.extend_from_slice(&self.timestamp.to_be_bytes());
+                aad
+            }
+        };
+        aad.extend_from_slice(&suffix);
+        aad
+    }
+
+    /// Deterministic ID both peers can compute for this message
+    ///
+    /// First 8 bytes (little-endian) of
+    /// `BLAKE3(version || type || timestamp || key_id || payload)`, with the
+    /// integers little-endian and the payload in its bincode encoding. Used
+    /// for `Ack` and as the input to `ReplayProtection::check_message_id`.
+    pub fn replay_safe_id(&self) -> u64 {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&[self.version.0, self.message_type as u8]);
+        hasher.update(&self.timestamp.to_le_bytes());
+        hasher.update(&self.key_id.to_le_bytes());
+        bincode::serialize_into(&mut hasher, &self.payload)
+            .expect("payload encoding is infallible");
+
+        let hash = hasher.finalize();
+        u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("hash is 32 bytes"))
+    }
+
+    /// `WireVersion` feature both peers must have agreed before this frame
+    /// may be sent, if it isn't part of the base protocol
+    pub fn required_feature(&self) -> Option<u32> {
+        match self.payload {
+            MessagePayload::EncryptedData { .. } if self.message_type == MessageType::PeerInfo => {
+                Some(WireVersion::FEATURE_PEER_INFO)
+            }
+            MessagePayload::RekeyOffer { .. }
+            | MessagePayload::RekeyResponse { .. }
+            | MessagePayload::RekeyCommit { .. } => Some(WireVersion::FEATURE_DH_REKEY),
+            MessagePayload::DisconnectAck => Some(WireVersion::FEATURE_DISCONNECT_ACK),
+            MessagePayload::OobData { .. } => Some(WireVersion::FEATURE_OOB_DATA),
+            MessagePayload::RotationInterval { .. } | MessagePayload::OtpRotation { .. } => {
+                Some(WireVersion::FEATURE_ROTATION_SYNC)
+            }
+            MessagePayload::Migrate { .. } => Some(WireVersion::FEATURE_MIGRATION),
+            MessagePayload::EphemeralData { .. } => Some(WireVersion::FEATURE_EPHEMERAL),
+            MessagePayload::ContactData { .. } | MessagePayload::ContactKeyRotation { .. } => {
+                Some(WireVersion::FEATURE_CONTACTS)
+            }
+            MessagePayload::TrackedData { .. } | MessagePayload::DeliveryAck { .. } => {
+                Some(WireVersion::FEATURE_DELIVERY_ACKS)
+            }
+            MessagePayload::HeartbeatResponse => Some(WireVersion::FEATURE_HEARTBEAT_RESPONSE),
+            MessagePayload::Probe { .. } | MessagePayload::ProbeResponse { .. } => Some(WireVersion::FEATURE_MTU_PROBE),
+            _ => None,
+        }
+    }
+
+    /// Whether this message carries application data (possibly for a third
+    /// party) rather than steering the session
+    pub fn is_data_message(&self) -> bool {
+        match self.message_type {
+            MessageType::EncryptedMessage | MessageType::OobData => true,
+            MessageType::Handshake
+            | MessageType::HandshakeResponse
+            | MessageType::KeyRotation
+            | MessageType::Ack
+            | MessageType::Heartbeat
+            | MessageType::Disconnect
+            | MessageType::TlsUpgrade
+            | MessageType::Probe
+            | MessageType::ProbeResponse
+            | MessageType::HeartbeatResponse
+            | MessageType::CachedHandshake
+            | MessageType::PeerInfo
+            | MessageType::PreKeyMessage
+            | MessageType::Challenge
+            | MessageType::ChallengeResponse
+            | MessageType::Migrate
+            | MessageType::Extension
+            | MessageType::Error => false,
+        }
+    }
+
+    /// Whether this message is consumed by the session itself (handshakes,
+    /// rotation, liveness, teardown, errors); the opposite of `is_data_message`
+    pub fn is_control_message(&self) -> bool {
+        !self.is_data_message()
+    }
+
     /// Check if message is recent (within last 60 seconds)
     pub fn is_recent(&self) -> bool {
         let now = current_timestamp();
         now.saturating_sub(self.timestamp) < 60
     }
+
+    /// Wrap this message with a Dilithium signature over its serialized form
+    pub fn sign(&self, signing_keypair: &SigningKeyPair) -> Result<SignedMessage, NetworkError> {
+        let digest = signing_digest(&self.to_bytes()?);
+        let signature = signing_keypair.sign(&digest)
+            .map_err(|e| NetworkError::ProtocolError(format!("Signing failed: {}", e)))?;
+
+        Ok(SignedMessage {
+            inner: self.clone(),
+            signature,
+        })
+    }
+}
+
+/// A message wrapped with a detached signature
+///
+/// Signing is independent of the encryption layer: any message, encrypted
+/// or not, can be signed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub inner: Message,
+    pub signature: Vec<u8>,
+}
+
+impl SignedMessage {
+    /// Check the signature and return the inner message
+    pub fn verify(self, verifying_key: &VerifyingKey) -> Result<Message, CryptoError> {
+        let message_bytes = self.inner.to_bytes()
+            .map_err(|_| CryptoError::AuthenticationFailed)?;
+        verifying_key.verify(&signing_digest(&message_bytes), &self.signature)?;
+        Ok(self.inner)
+    }
+
+    /// Serialize signed message to bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, NetworkError> {
+        bincode::serialize(self)
+            .map_err(|e| NetworkError::SerializationError(format!("Serialization failed: {}", e)))
+    }
+
+    /// Deserialize signed message from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NetworkError> {
+        Self::from_bytes_with_limit(bytes, MAX_MESSAGE_SIZE)
+    }
+
+    /// `from_bytes` with a size limit other than the default 1 MB
+    pub fn from_bytes_with_limit(bytes: &[u8], max_len: usize) -> Result<Self, NetworkError> {
+        if bytes.len() > max_len {
+            return Err(NetworkError::ProtocolError("Message too large".to_string()));
+        }
+
+        bincode::deserialize(bytes)
+            .map_err(|e| NetworkError::SerializationError(format!("Deserialization failed: {}", e)))
+    }
+}
+
+/// Digest that is actually signed: BLAKE3 of the serialized message
+fn signing_digest(message_bytes: &[u8]) -> [u8; 32] {
+    *blake3::hash(message_bytes).as_bytes()
 }
 
 /// Get current Unix timestamp in seconds
@@ -259,47 +1141,188 @@ fn current_timestamp() -> u64 {
         .unwrap_or(0)
 }
 
+/// Largest serialized handshake frame body (any handshake type) for `level`
+///
+/// The handshake fields must match the Kyber sizes exactly, so anything
+/// bigger can be refused from the frame header before it is buffered.
+pub fn max_handshake_size(level: KyberLevel) -> usize {
+    let key_bytes = level.public_key_bytes().max(level.ciphertext_bytes());
+    [
+        Message::new(MessageType::Handshake, MessagePayload::Handshake { public_key: vec![0; key_bytes] }),
+        Message::new(MessageType::HandshakeResponse, MessagePayload::HandshakeResponse { ciphertext: vec![0; key_bytes] }),
+        Message::new(
+            MessageType::CachedHandshake,
+            MessagePayload::CachedHandshake { key_fingerprint: [0; 32], ciphertext: vec![0; key_bytes] },
+        ),
+    ]
+    .iter()
+    .filter_map(|msg| msg.serialized_size().ok())
+    .max()
+    .unwrap_or(0)
+}
+
+/// Seconds the peer's clock runs ahead of ours, from a timestamp it just sent
+pub fn measure_clock_offset(peer_timestamp: u64) -> i64 {
+    clock_offset_at(peer_timestamp, current_timestamp())
+}
+
+/// `measure_clock_offset` against the clock reading `now`
+///
+/// Clamped to `MAX_CLOCK_SKEW_SECS` either way, so a single sample can't
+/// widen the timestamp check beyond that.
+pub fn clock_offset_at(peer_timestamp: u64, now: u64) -> i64 {
+    (peer_timestamp as i128 - now as i128).clamp(-(MAX_CLOCK_SKEW_SECS as i128), MAX_CLOCK_SKEW_SECS as i128) as i64
+}
+
 /// Frame a message for transmission (add length prefix)
 pub fn frame_message(message: &Message) -> Result<Vec<u8>, NetworkError> {
-    let message_bytes = message.to_bytes()?;
-    let len = message_bytes.len() as u32;
+    let mut framed = Vec::new();
+    frame_message_into(message, &mut framed)?;
+    Ok(framed)
+}
 
-    let mut framed = Vec::with_capacity(4 + message_bytes.len());
-    framed.extend_from_slice(&len.to_be_bytes());
-    framed.extend_from_slice(&message_bytes);
+/// Append a framed message to `buf`, serializing straight after the length prefix
+///
+/// Returns the number of bytes appended. `buf` is left unchanged on error.
+pub fn frame_message_into(message: &Message, buf: &mut Vec<u8>) -> Result<usize, NetworkError> {
+    frame_message_into_with_limit(message, buf, MAX_MESSAGE_SIZE)
+}
 
-    Ok(framed)
+/// `frame_message_into` with a size limit other than the default 1 MB
+pub fn frame_message_into_with_limit(message: &Message, buf: &mut Vec<u8>, max_len: usize) -> Result<usize, NetworkError> {
+    let size = message.serialized_size()?;
+    if size > max_len {
+        return Err(NetworkError::ProtocolError("Message too large".to_string()));
+    }
+
+    let start = buf.len();
+    buf.reserve_exact(4 + size);
+    buf.extend_from_slice(&(size as u32).to_be_bytes());
+
+    if let Err(e) = bincode::serialize_into(&mut *buf, message) {
+        buf.truncate(start);
+        return Err(NetworkError::SerializationError(format!("Serialization failed: {}", e)));
+    }
+
+    Ok(4 + size)
 }
 
 /// Parse a framed message (extract from length-prefixed format)
 pub fn parse_framed_message(data: &[u8]) -> Result<(Message, usize), NetworkError> {
+    let (body, consumed) = parse_frame(data)?;
+    let message = Message::from_bytes(body)?;
+    Ok((message, consumed))
+}
+
+/// Add a length prefix to already-serialized bytes
+pub fn frame_bytes(bytes: &[u8]) -> Result<Vec<u8>, NetworkError> {
+    frame_bytes_with_limit(bytes, MAX_MESSAGE_SIZE)
+}
+
+/// `frame_bytes` with a size limit other than the default 1 MB
+pub fn frame_bytes_with_limit(bytes: &[u8], max_len: usize) -> Result<Vec<u8>, NetworkError> {
+    if bytes.len() > max_len {
+        return Err(NetworkError::ProtocolError("Message too large".to_string()));
+    }
+    let len = bytes.len() as u32;
+
+    let mut framed = Vec::with_capacity(4 + bytes.len());
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(bytes);
+
+    Ok(framed)
+}
+
+/// Extract the body of a length-prefixed frame and the number of bytes consumed
+pub fn parse_frame(data: &[u8]) -> Result<(&[u8], usize), NetworkError> {
+    parse_frame_with_limit(data, MAX_MESSAGE_SIZE)
+}
+
+/// `parse_frame` with a different size limit, checked from the header alone
+pub fn parse_frame_with_limit(data: &[u8], max_len: usize) -> Result<(&[u8], usize), NetworkError> {
     if data.len() < 4 {
         return Err(NetworkError::ProtocolError("Insufficient data for frame header".to_string()));
     }
 
     let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
 
-    if len > MAX_MESSAGE_SIZE {
-        return Err(NetworkError::ProtocolError("Message too large".to_string()));
+    if len > max_len {
+        return Err(NetworkError::ProtocolError(format!("Message too large ({} bytes, limit {})", len, max_len)));
     }
 
     if data.len() < 4 + len {
         return Err(NetworkError::ProtocolError("Incomplete message frame".to_string()));
     }
 
-    let message = Message::from_bytes(&data[4..4 + len])?;
-    Ok((message, 4 + len))
+    Ok((&data[4..4 + len], 4 + len))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::random::fill_with_pattern;
+
+    #[test]
+    fn test_replay_safe_id() {
+        let msg = Message::encrypted([1u8; 24], vec![1, 2, 3], 7, 0);
+        let copy = Message::from_bytes(&msg.to_bytes().unwrap()).unwrap();
+        assert_eq!(msg.replay_safe_id(), copy.replay_safe_id());
+
+        let mut other = msg.clone();
+        other.key_id = 1;
+        assert_ne!(msg.replay_safe_id(), other.replay_safe_id());
+
+        let ack = Message::ack(msg.replay_safe_id());
+        assert!(ack.validate().is_ok());
+        assert!(matches!(ack.payload, MessagePayload::Ack { message_id } if message_id == copy.replay_safe_id()));
+    }
 
     #[test]
     fn test_message_type_conversion() {
         assert_eq!(MessageType::try_from(0x01).unwrap(), MessageType::Handshake);
         assert_eq!(MessageType::try_from(0x03).unwrap(), MessageType::EncryptedMessage);
         assert!(MessageType::try_from(0x99).is_err());
+        assert_eq!(MessageType::try_from(0x80).unwrap(), MessageType::Extension);
+    }
+
+    #[test]
+    fn test_parse_frame_with_limit() {
+        let framed = frame_message(&Message::handshake_response(
+            KyberCiphertext::from_bytes(vec![0; KyberLevel::Kyber1024.ciphertext_bytes()], KyberLevel::Kyber1024).unwrap()
+        )).unwrap();
+        let limit = max_handshake_size(KyberLevel::Kyber1024);
+
+        assert!(parse_frame_with_limit(&framed, limit).is_ok());
+        // Only the header is needed to refuse a frame
+        assert!(parse_frame_with_limit(&framed[..4], framed.len() - 5).unwrap_err().to_string().contains("limit"));
+    }
+
+    #[test]
+    fn test_extension_type_range() {
+        let msg = Message::extension(0x90, b"from the future".to_vec());
+        let restored = Message::from_bytes(&msg.to_bytes().unwrap()).unwrap();
+        assert!(restored.validate().is_ok());
+
+        assert!(Message::extension(0x10, Vec::new()).validate().is_err());
+        assert!(Message::extension(0xFF, Vec::new()).validate().is_err());
+    }
+
+    #[test]
+    fn test_aad_schema_negotiation() {
+        assert_eq!(AadSchema::negotiate(0), AadSchema::V0);
+        assert_eq!(AadSchema::negotiate(1), AadSchema::V1);
+        assert_eq!(AadSchema::negotiate(7), AadSchema::LATEST);
+
+        let mut msg = Message::encrypted([0u8; 24], Vec::new(), 5, 1);
+        assert!(msg.associated_data(AadSchema::V0).is_empty());
+        assert_eq!(msg.associated_data(AadSchema::V1), 5u64.to_be_bytes());
+
+        // Only V2 covers the rest of the header
+        let v1 = msg.associated_data(AadSchema::V1);
+        let v2 = msg.associated_data(AadSchema::V2);
+        msg.timestamp += 1;
+        assert_eq!(msg.associated_data(AadSchema::V1), v1);
+        assert_ne!(msg.associated_data(AadSchema::V2), v2);
     }
 
     #[test]
@@ -318,6 +1341,31 @@ mod tests {
         assert!(msg.validate().is_ok());
     }
 
+    #[test]
+    fn test_validate_with_clock_offset() {
+        let mut msg = Message::heartbeat();
+        msg.timestamp += 400;
+
+        assert!(msg.validate().is_err());
+        assert!(msg.validate_with_offset(200).is_ok());
+        assert!(msg.validate_with_offset(-200).is_err());
+
+        // A slow peer clock doesn't shrink the default window
+        msg.timestamp -= 200;
+        assert!(msg.validate_with_offset(-600).is_ok());
+        assert!((measure_clock_offset(msg.timestamp) - 200).abs() <= 1);
+
+        // Offsets never widen the window past twice the default skew
+        let now = 1_700_000_000;
+        msg.timestamp = now + 2 * MAX_CLOCK_SKEW_SECS as u64;
+        assert!(msg.validate_at(now, i64::MAX).is_ok());
+        msg.timestamp += 1;
+        assert!(msg.validate_at(now, i64::MAX).is_err());
+        assert_eq!(clock_offset_at(now + 900, now), MAX_CLOCK_SKEW_SECS);
+        assert_eq!(clock_offset_at(now - 900, now), -MAX_CLOCK_SKEW_SECS);
+        assert_eq!(clock_offset_at(now + 42, now), 42);
+    }
+
     #[test]
     fn test_message_framing() {
         let msg = Message::heartbeat();
@@ -328,6 +1376,20 @@ mod tests {
         assert_eq!(parsed.message_type, msg.message_type);
     }
 
+    #[test]
+    fn test_frame_message_into_appends_exact_frame() {
+        let mut ciphertext = vec![0u8; 300];
+        fill_with_pattern(&mut ciphertext, b"ciphertext");
+        let msg = Message::encrypted([7u8; 24], ciphertext, 5, 0);
+        assert_eq!(msg.serialized_size().unwrap(), msg.to_bytes().unwrap().len());
+
+        let mut buf = b"queued".to_vec();
+        let appended = frame_message_into(&msg, &mut buf).unwrap();
+        assert_eq!(appended, 4 + msg.serialized_size().unwrap());
+        assert_eq!(&buf[..6], b"queued");
+        assert_eq!(&buf[6..], frame_bytes(&msg.to_bytes().unwrap()).unwrap());
+    }
+
     #[test]
     fn test_invalid_frame_short() {
         let data = vec![0u8; 2]; // Too short for length prefix
@@ -341,6 +1403,41 @@ mod tests {
         assert!(parse_framed_message(&data).is_err());
     }
 
+    #[test]
+    fn test_signed_message_roundtrip() {
+        let keypair = SigningKeyPair::generate().unwrap();
+        let signed = Message::heartbeat().sign(&keypair).unwrap();
+
+        let bytes = signed.to_bytes().unwrap();
+        let restored = SignedMessage::from_bytes(&bytes).unwrap();
+        let message = restored.verify(keypair.verifying_key()).unwrap();
+        assert_eq!(message.message_type, MessageType::Heartbeat);
+    }
+
+    #[test]
+    fn test_signed_message_tampering_detected() {
+        let keypair = SigningKeyPair::generate().unwrap();
+        let mut signed = Message::disconnect(Some("bye".to_string())).sign(&keypair).unwrap();
+
+        signed.inner.timestamp += 1;
+        assert!(matches!(
+            signed.verify(keypair.verifying_key()),
+            Err(CryptoError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_control_and_data_messages() {
+        let data = Message::encrypted([0u8; 24], vec![1, 2, 3], 0, 0);
+        assert!(data.is_data_message());
+        assert!(!data.is_control_message());
+
+        for control in [Message::heartbeat(), Message::key_rotation(1), Message::disconnect(None), Message::error(1, "x".to_string())] {
+            assert!(control.is_control_message(), "{:?}", control.message_type);
+            assert!(!control.is_data_message());
+        }
+    }
+
     #[test]
     fn test_is_recent() {
         let msg = Message::heartbeat();
@@ -350,4 +1447,64 @@ mod tests {
         old_msg.timestamp = 1000; // Very old timestamp
         assert!(!old_msg.is_recent());
     }
+
+    #[test]
+    fn test_wire_version_negotiate() {
+        let local = WireVersion::current();
+        assert_eq!(ProtocolVersion::from(local), ProtocolVersion::default());
+
+        let older = WireVersion { minor: 0, features: WireVersion::FEATURE_AAD_BINDING | 1 << 31, ..local };
+        let newer = WireVersion { minor: 7, ..local };
+        let agreed = WireVersion::negotiate(local, older).unwrap();
+        assert_eq!(agreed.minor, 0);
+        assert_eq!(agreed.features, WireVersion::FEATURE_AAD_BINDING);
+        assert!(agreed.supports(WireVersion::FEATURE_AAD_BINDING));
+        assert!(!agreed.supports(WireVersion::FEATURE_MIGRATION));
+        assert_eq!(WireVersion::negotiate(local, newer), Some(local));
+        assert_eq!(WireVersion::negotiate(newer, local), WireVersion::negotiate(local, newer));
+
+        assert_eq!(WireVersion::negotiate(local, WireVersion { major: 2, ..local }), None);
+    }
+
+    #[test]
+    fn test_handshake_offer_carries_features() {
+        let features = WireVersion::FEATURE_CONTACTS | WireVersion::FEATURE_DELIVERY_ACKS;
+        let key_id = WireVersion::handshake_offer(AadSchema::LATEST, features | 1 << 20);
+        assert_eq!(WireVersion::parse_handshake_offer(key_id), (AadSchema::LATEST as u16, features));
+
+        // Bits past the high 12 ride above the schema, where older peers
+        // see an unknown schema and fall back to their latest
+        let key_id = WireVersion::handshake_offer(AadSchema::V1, WireVersion::current().features);
+        assert_eq!(WireVersion::parse_handshake_offer(key_id), (AadSchema::V1 as u16, WireVersion::current().features));
+        assert_eq!(AadSchema::negotiate(key_id & 0xF), AadSchema::LATEST);
+        assert_eq!((key_id >> 4) as u32, WireVersion::current().features & 0xFFF);
+
+        // Peers from before feature bits send a bare schema
+        assert_eq!(WireVersion::parse_handshake_offer(AadSchema::LATEST as u16), (AadSchema::LATEST as u16, 0));
+
+        assert_eq!(Message::heartbeat().required_feature(), None);
+        assert_eq!(Message::heartbeat_response().required_feature(), Some(WireVersion::FEATURE_HEARTBEAT_RESPONSE));
+        assert_eq!(Message::probe(512).required_feature(), Some(WireVersion::FEATURE_MTU_PROBE));
+        assert_eq!(Message::encrypted([0u8; 24], vec![1], 1, 0).required_feature(), None);
+        assert_eq!(Message::peer_info([0u8; 24], vec![1], 1, 0).required_feature(), Some(WireVersion::FEATURE_PEER_INFO));
+        assert_eq!(Message::oob_data(1, 1, vec![1], [0u8; 32]).required_feature(), Some(WireVersion::FEATURE_OOB_DATA));
+    }
+
+    #[test]
+    fn test_ephemeral_ttl_roundtrip_and_aad() {
+        let msg = Message::ephemeral([7u8; 24], vec![1, 2, 3], 9, 2, 3600);
+        assert!(msg.validate().is_ok());
+        let restored = Message::from_bytes(&msg.to_bytes().unwrap()).unwrap();
+        assert!(matches!(restored.payload, MessagePayload::EphemeralData { ttl_secs: 3600, message_counter: 9, .. }));
+
+        // The TTL is covered under every schema
+        let mut tampered = restored.clone();
+        if let MessagePayload::EphemeralData { ttl_secs, .. } = &mut tampered.payload {
+            *ttl_secs = 60;
+        }
+        for schema in [AadSchema::V0, AadSchema::V1, AadSchema::V2] {
+            assert_eq!(restored.associated_data(schema), msg.associated_data(schema));
+            assert_ne!(tampered.associated_data(schema), msg.associated_data(schema));
+        }
+    }
 }
\ No newline at end of file
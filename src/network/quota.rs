@@ -0,0 +1,110 @@
+// Per-peer traffic volume quotas
+// Caps the total bytes a peer may send per fixed window (volume, not rate)
+
+use std::time::{Duration, Instant};
+
+/// Limit on received bytes per window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub bytes_per_window: u64,
+    pub window: Duration,
+}
+
+impl Quota {
+    /// Create a quota of `bytes_per_window` bytes every `window`
+    pub fn new(bytes_per_window: u64, window: Duration) -> Self {
+        Self { bytes_per_window, window }
+    }
+}
+
+/// Snapshot of quota consumption, for monitoring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub used_bytes: u64,
+    pub limit_bytes: u64,
+    /// Time until the window resets
+    pub resets_in: Duration,
+}
+
+/// Tracks usage of a `Quota` across fixed windows
+#[derive(Debug, Clone)]
+pub struct QuotaTracker {
+    quota: Quota,
+    used: u64,
+    window_start: Instant,
+}
+
+impl QuotaTracker {
+    /// Start tracking with an empty window
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            used: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Charge `bytes` to the current window; returns false once it is over quota
+    ///
+    /// Bytes that push the window over the limit are still counted, so a
+    /// peer that keeps sending stays rejected until the window resets.
+    pub fn record(&mut self, bytes: u64) -> bool {
+        self.roll_window();
+        self.used = self.used.saturating_add(bytes);
+        self.used <= self.quota.bytes_per_window
+    }
+
+    /// Current usage within the active window
+    pub fn usage(&self) -> QuotaUsage {
+        let elapsed = self.window_start.elapsed();
+        let expired = elapsed >= self.quota.window;
+
+        QuotaUsage {
+            used_bytes: if expired { 0 } else { self.used },
+            limit_bytes: self.quota.bytes_per_window,
+            resets_in: if expired { self.quota.window } else { self.quota.window - elapsed },
+        }
+    }
+
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// Start a new window if the current one has ended
+    fn roll_window(&mut self) {
+        if self.window_start.elapsed() >= self.quota.window {
+            self.used = 0;
+            self.window_start = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_exceeded_within_window() {
+        let mut tracker = QuotaTracker::new(Quota::new(100, Duration::from_secs(60)));
+
+        assert!(tracker.record(60));
+        assert!(tracker.record(40));
+        assert!(!tracker.record(1));
+        assert!(!tracker.record(0));
+
+        let usage = tracker.usage();
+        assert_eq!(usage.used_bytes, 101);
+        assert_eq!(usage.limit_bytes, 100);
+    }
+
+    #[test]
+    fn test_quota_resets_each_window() {
+        let mut tracker = QuotaTracker::new(Quota::new(10, Duration::from_millis(20)));
+
+        assert!(!tracker.record(11));
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(tracker.usage().used_bytes, 0);
+        assert!(tracker.record(10));
+    }
+}
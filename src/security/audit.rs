@@ -0,0 +1,344 @@
+// Tamper-evident audit log for session security events
+// Entries are hash-chained so deletion or modification is detectable
+
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use crate::crypto::kyber::KyberLevel;
+use crate::crypto::ratchet::RotationRecord;
+use crate::session::SessionRole;
+use crate::storage::ephemeral::AccessReport;
+
+/// Hash used as the predecessor of the first entry in a chain
+pub const GENESIS_HASH: [u8; 32] = [0u8; 32];
+
+const ENTRY_HASH_CONTEXT: &str = "aegis-audit-entry-v1";
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("Audit sink error: {0}")]
+    SinkError(String),
+
+    #[error("Malformed audit entry: {0}")]
+    Malformed(String),
+
+    #[error("Audit chain broken at entry {0}")]
+    ChainBroken(u64),
+}
+
+/// Security-relevant session events (never message content or key material)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditEvent {
+    /// Key exchange finished and the session is usable
+    HandshakeCompleted {
+        role: SessionRole,
+        peer_addr: String,
+        /// Kyber level the keys rest on; `None` for TLS-only sessions
+        kem: Option<KyberLevel>,
+        psk: bool,
+        tls: bool,
+    },
+
+    /// Chain keys were rotated
+    KeyRotated {
+        send_counter: u64,
+        recv_counter: u64,
+    },
+
+    /// Result of a peer authentication attempt
+    AuthResult {
+        /// "signature" (`recv_verified`) or "seed-commitment" (a peer's `otp_rotate`)
+        method: String,
+        success: bool,
+    },
+
+    /// Session ended, locally or by the peer
+    Disconnected {
+        initiated_locally: bool,
+        reason: Option<String>,
+    },
+
+    /// Ratchet rotated, with commitments to the chain keys on either side
+    RatchetRotated(RotationRecord),
+
+    /// Key material was read more often than its use requires (possible key reuse)
+    KeyOveruse {
+        key: String,
+        accesses: u64,
+        expected: u64,
+    },
+}
+
+/// A single hash-chained audit record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the chain, starting at 0
+    pub sequence: u64,
+
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+
+    /// The recorded event
+    pub event: AuditEvent,
+
+    /// Hash of the previous entry (GENESIS_HASH for the first)
+    pub prev_hash: [u8; 32],
+
+    /// Hash over this entry's fields and `prev_hash`
+    pub hash: [u8; 32],
+}
+
+impl AuditEntry {
+    /// Recompute the hash this entry should carry
+    pub fn compute_hash(&self) -> Result<[u8; 32], AuditError> {
+        let event_bytes = bincode::serialize(&self.event)
+            .map_err(|e| AuditError::Malformed(format!("Event serialization failed: {}", e)))?;
+
+        let mut hasher = blake3::Hasher::new_derive_key(ENTRY_HASH_CONTEXT);
+        hasher.update(&self.prev_hash);
+        hasher.update(&self.sequence.to_le_bytes());
+        hasher.update(&self.timestamp.to_le_bytes());
+        hasher.update(&event_bytes);
+        Ok(*hasher.finalize().as_bytes())
+    }
+}
+
+/// Destination for audit entries
+pub trait AuditSink: Send {
+    /// Append an entry; sinks must never rewrite earlier entries
+    fn append(&mut self, entry: &AuditEntry) -> Result<(), AuditError>;
+}
+
+/// Writes one JSON object per line to any writer (file, pipe, ...)
+pub struct JsonLinesSink<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write + Send> AuditSink for JsonLinesSink<W> {
+    fn append(&mut self, entry: &AuditEntry) -> Result<(), AuditError> {
+        let line = serde_json::to_string(entry)
+            .map_err(|e| AuditError::SinkError(format!("Serialization failed: {}", e)))?;
+        writeln!(self.writer, "{}", line)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| AuditError::SinkError(e.to_string()))
+    }
+}
+
+/// In-memory sink whose entries can be inspected through any clone
+#[derive(Clone, Default)]
+pub struct MemoryAuditSink {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of all entries appended so far
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.lock().map(|e| e.clone()).unwrap_or_default()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn append(&mut self, entry: &AuditEntry) -> Result<(), AuditError> {
+        self.entries
+            .lock()
+            .map_err(|_| AuditError::SinkError("Sink lock poisoned".to_string()))?
+            .push(entry.clone());
+        Ok(())
+    }
+}
+
+/// Append-only, hash-chained audit log
+pub struct AuditLog {
+    sink: Box<dyn AuditSink>,
+    last_hash: [u8; 32],
+    next_sequence: u64,
+}
+
+impl AuditLog {
+    /// Start a new chain writing to the given sink
+    pub fn new<S: AuditSink + 'static>(sink: S) -> Self {
+        Self {
+            sink: Box::new(sink),
+            last_hash: GENESIS_HASH,
+            next_sequence: 0,
+        }
+    }
+
+    /// Append an event to the chain
+    pub fn record(&mut self, event: AuditEvent) -> Result<AuditEntry, AuditError> {
+        let mut entry = AuditEntry {
+            sequence: self.next_sequence,
+            timestamp: current_timestamp(),
+            event,
+            prev_hash: self.last_hash,
+            hash: [0u8; 32],
+        };
+        entry.hash = entry.compute_hash()?;
+
+        self.sink.append(&entry)?;
+
+        self.last_hash = entry.hash;
+        self.next_sequence += 1;
+
+        Ok(entry)
+    }
+
+    /// Record a `KeyOveruse` warning if `key` was read more than `expected` times
+    pub fn check_key_access(&mut self, key: &str, report: &AccessReport, expected: u64) -> Result<Option<AuditEntry>, AuditError> {
+        if report.count <= expected {
+            return Ok(None);
+        }
+        self.record(AuditEvent::KeyOveruse { key: key.to_string(), accesses: report.count, expected }).map(Some)
+    }
+
+    /// Hash of the most recent entry (the chain head)
+    pub fn head(&self) -> [u8; 32] {
+        self.last_hash
+    }
+
+    /// Number of entries recorded so far
+    pub fn len(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Check if nothing has been recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.next_sequence == 0
+    }
+}
+
+/// Verify that entries form an intact chain starting from the genesis hash
+pub fn verify_chain(entries: &[AuditEntry]) -> Result<(), AuditError> {
+    let mut expected_prev = GENESIS_HASH;
+
+    for (index, entry) in entries.iter().enumerate() {
+        let index = index as u64;
+        if entry.sequence != index
+            || entry.prev_hash != expected_prev
+            || entry.compute_hash()? != entry.hash
+        {
+            return Err(AuditError::ChainBroken(index));
+        }
+        expected_prev = entry.hash;
+    }
+
+    Ok(())
+}
+
+/// Read entries written by `JsonLinesSink`
+pub fn read_json_lines<R: BufRead>(reader: R) -> Result<Vec<AuditEntry>, AuditError> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|e| AuditError::Malformed(e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| AuditError::Malformed(e.to_string()))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Get current Unix timestamp in seconds
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log() -> (AuditLog, MemoryAuditSink) {
+        let sink = MemoryAuditSink::new();
+        let mut log = AuditLog::new(sink.clone());
+
+        log.record(AuditEvent::HandshakeCompleted {
+            role: SessionRole::Initiator,
+            peer_addr: "127.0.0.1:9999".to_string(),
+            kem: Some(KyberLevel::Kyber1024),
+            psk: false,
+            tls: false,
+        }).unwrap();
+        log.record(AuditEvent::KeyRotated { send_counter: 3, recv_counter: 2 }).unwrap();
+        log.record(AuditEvent::Disconnected { initiated_locally: true, reason: None }).unwrap();
+
+        (log, sink)
+    }
+
+    #[test]
+    fn test_chain_verifies() {
+        let (log, sink) = sample_log();
+        let entries = sink.entries();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.head(), entries[2].hash);
+        assert!(verify_chain(&entries).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_entry_detected() {
+        let (_, sink) = sample_log();
+        let mut entries = sink.entries();
+
+        entries[1].event = AuditEvent::KeyRotated { send_counter: 99, recv_counter: 2 };
+        assert!(matches!(verify_chain(&entries), Err(AuditError::ChainBroken(1))));
+    }
+
+    #[test]
+    fn test_deleted_entry_detected() {
+        let (_, sink) = sample_log();
+        let mut entries = sink.entries();
+
+        entries.remove(1);
+        assert!(matches!(verify_chain(&entries), Err(AuditError::ChainBroken(1))));
+    }
+
+    #[test]
+    fn test_key_overuse_recorded() {
+        let sink = MemoryAuditSink::new();
+        let mut log = AuditLog::new(sink.clone());
+        let key = crate::crypto::symmetric::SymmetricKey::new([3u8; 32]);
+
+        key.as_bytes();
+        assert!(log.check_key_access("message key", &key.access_report(), 1).unwrap().is_none());
+
+        key.as_bytes();
+        log.check_key_access("message key", &key.access_report(), 1).unwrap();
+        assert_eq!(
+            sink.entries()[0].event,
+            AuditEvent::KeyOveruse { key: "message key".to_string(), accesses: 2, expected: 1 }
+        );
+    }
+
+    #[test]
+    fn test_json_lines_roundtrip() {
+        let mut buffer = Vec::new();
+        let mut sink = JsonLinesSink::new(&mut buffer);
+        let (_, memory) = sample_log();
+        for entry in memory.entries() {
+            sink.append(&entry).unwrap();
+        }
+
+        let entries = read_json_lines(buffer.as_slice()).unwrap();
+        assert_eq!(entries, memory.entries());
+        assert!(verify_chain(&entries).is_ok());
+    }
+}
@@ -1,5 +1,5 @@
 // Security utilities module
-// Contains replay protection and additional security measures
+// Contains replay protection, audit logging, and additional security measures
 
 pub mod replay;
-
+pub mod audit;
@@ -1,7 +1,7 @@
 // Replay protection using timestamps and sequence numbers
 // Prevents replay attacks and ensures message freshness
 
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 const MAX_WINDOW_SIZE: usize = 10000;
@@ -17,6 +17,10 @@ pub struct ReplayProtection {
 
     /// Window of acceptable sequence numbers
     window_size: usize,
+
+    /// Message IDs seen by `check_message_id`, oldest first
+    seen_ids: HashSet<u64>,
+    id_order: VecDeque<u64>,
 }
 
 impl ReplayProtection {
@@ -26,6 +30,8 @@ impl ReplayProtection {
             seen_messages: HashSet::new(),
             last_sequence: 0,
             window_size: MAX_WINDOW_SIZE,
+            seen_ids: HashSet::new(),
+            id_order: VecDeque::new(),
         }
     }
 
@@ -63,6 +69,31 @@ impl ReplayProtection {
         true
     }
 
+    /// Check a message by unordered ID (e.g. `Message::replay_safe_id`)
+    /// instead of a sequence number, for protocols without monotonic counters
+    ///
+    /// Returns true if the message is valid (not a replay). Only the most
+    /// recent `window_size` IDs are remembered; the timestamp check bounds
+    /// how late a forgotten ID could be replayed.
+    pub fn check_message_id(&mut self, message_id: u64, timestamp: u64) -> bool {
+        if !self.is_timestamp_valid(timestamp) {
+            return false;
+        }
+
+        if !self.seen_ids.insert(message_id) {
+            return false;
+        }
+
+        self.id_order.push_back(message_id);
+        if self.id_order.len() > self.window_size {
+            if let Some(oldest) = self.id_order.pop_front() {
+                self.seen_ids.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
     /// Check if timestamp is within acceptable range
     fn is_timestamp_valid(&self, timestamp: u64) -> bool {
         let now = current_timestamp();
@@ -84,6 +115,8 @@ impl ReplayProtection {
     pub fn reset(&mut self) {
         self.seen_messages.clear();
         self.last_sequence = 0;
+        self.seen_ids.clear();
+        self.id_order.clear();
     }
 
     /// Get the current sequence number
@@ -98,6 +131,81 @@ impl Default for ReplayProtection {
     }
 }
 
+/// Replay protection over a sliding window of sequence numbers, backed by a bitset
+///
+/// Each of the `window_size` bits tracks one sequence number (`seq % window_size`);
+/// bits are cleared as the window slides forward, so memory stays at
+/// roughly `window_size / 8` bytes.
+pub struct SlidingWindowReplayProtection {
+    bits: Vec<u64>,
+    window_size: u32,
+    /// Highest sequence number accepted so far
+    last_sequence: Option<u64>,
+}
+
+impl SlidingWindowReplayProtection {
+    /// Create a window covering `window_size` sequence numbers (at least one)
+    pub fn new(window_size: u32) -> Self {
+        let window_size = window_size.max(1);
+        Self {
+            bits: vec![0; window_size.div_ceil(64) as usize],
+            window_size,
+            last_sequence: None,
+        }
+    }
+
+    /// Returns true if `sequence` is new and inside the window, recording it
+    pub fn check_message(&mut self, sequence: u64) -> bool {
+        match self.last_sequence {
+            Some(last) if sequence > last => self.advance(last, sequence),
+            Some(last) if last - sequence >= self.window_size as u64 => return false,
+            _ => {}
+        }
+
+        let last = self.last_sequence.map_or(sequence, |last| last.max(sequence));
+        self.last_sequence = Some(last);
+
+        let (word, mask) = self.slot(sequence);
+        let seen = self.bits[word] & mask != 0;
+        self.bits[word] |= mask;
+        !seen
+    }
+
+    /// Clear the slots of sequence numbers the window slides over
+    fn advance(&mut self, last: u64, sequence: u64) {
+        if sequence - last >= self.window_size as u64 {
+            self.bits.fill(0);
+            return;
+        }
+
+        for seq in (last + 1)..=sequence {
+            let (word, mask) = self.slot(seq);
+            self.bits[word] &= !mask;
+        }
+    }
+
+    fn slot(&self, sequence: u64) -> (usize, u64) {
+        let bit = (sequence % self.window_size as u64) as usize;
+        (bit / 64, 1 << (bit % 64))
+    }
+
+    /// Reset the replay protection state
+    pub fn reset(&mut self) {
+        self.bits.fill(0);
+        self.last_sequence = None;
+    }
+
+    /// Get the highest sequence number seen (0 before any message)
+    pub fn current_sequence(&self) -> u64 {
+        self.last_sequence.unwrap_or(0)
+    }
+
+    /// Number of sequence numbers the window covers
+    pub fn window_size(&self) -> u32 {
+        self.window_size
+    }
+}
+
 /// Get current Unix timestamp in seconds
 fn current_timestamp() -> u64 {
     SystemTime::now()
@@ -185,6 +293,23 @@ mod tests {
         assert!(rp.check_message(1, now)); // Should work after reset
     }
 
+    #[test]
+    fn test_replay_protection_message_ids() {
+        use crate::network::protocol::Message;
+
+        let mut rp = ReplayProtection::new();
+        let first = Message::heartbeat();
+        let second = Message::ack(first.replay_safe_id());
+
+        // IDs are unordered hashes, so no sequence window applies
+        assert!(rp.check_message_id(second.replay_safe_id(), second.timestamp));
+        assert!(rp.check_message_id(first.replay_safe_id(), first.timestamp));
+        assert!(!rp.check_message_id(first.replay_safe_id(), first.timestamp));
+
+        let stale = current_timestamp() - MAX_TIME_SKEW_SECS - 10;
+        assert!(!rp.check_message_id(42, stale));
+    }
+
     #[test]
     fn test_timestamp_validity() {
         let rp = ReplayProtection::new();
@@ -196,4 +321,52 @@ mod tests {
         assert!(!rp.is_timestamp_valid(now - MAX_TIME_SKEW_SECS - 10));
         assert!(!rp.is_timestamp_valid(now + MAX_TIME_SKEW_SECS + 10));
     }
+
+    #[test]
+    fn test_sliding_window_duplicates_and_reordering() {
+        let mut rp = SlidingWindowReplayProtection::new(64);
+
+        assert!(rp.check_message(10));
+        assert!(rp.check_message(12));
+        assert!(rp.check_message(11)); // Out of order but within window
+        assert!(!rp.check_message(11));
+        assert!(!rp.check_message(12));
+        assert_eq!(rp.current_sequence(), 12);
+    }
+
+    #[test]
+    fn test_sliding_window_rejects_old_sequences() {
+        let mut rp = SlidingWindowReplayProtection::new(100);
+
+        assert!(rp.check_message(500));
+        assert!(rp.check_message(401));
+        assert!(!rp.check_message(400)); // Exactly window_size behind
+        assert!(!rp.check_message(1));
+    }
+
+    #[test]
+    fn test_sliding_window_clears_reused_slots() {
+        let mut rp = SlidingWindowReplayProtection::new(8);
+
+        assert!(rp.check_message(3));
+        // 11 maps to the same slot as 3 once the window has moved past it
+        assert!(rp.check_message(11));
+        assert!(!rp.check_message(11));
+        assert!(!rp.check_message(3));
+
+        // A jump larger than the window clears everything
+        assert!(rp.check_message(1000));
+        assert!(rp.check_message(995));
+    }
+
+    #[test]
+    fn test_sliding_window_memory_and_reset() {
+        let mut rp = SlidingWindowReplayProtection::new(1000);
+        assert_eq!(rp.bits.len() * 8, 128);
+
+        rp.check_message(7);
+        rp.reset();
+        assert_eq!(rp.current_sequence(), 0);
+        assert!(rp.check_message(7));
+    }
 }
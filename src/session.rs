@@ -1,280 +1,4312 @@
 // Session management and handshake coordination
 // Orchestrates key exchange and secure session establishment
 
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use hdrhistogram::Histogram;
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
 use tokio::time::{Duration, timeout};
+use tokio_stream::{Stream, StreamExt, wrappers::IntervalStream};
 
 use crate::crypto::{
-    kyber::{KeyPair, PublicKey, Ciphertext},
+    kyber::{KeyPair, KyberLevel, PublicKey, Ciphertext, SemiStaticKeyPair},
+    prekey::{self, PreKeyBundle, PreKeyInitiation, PreKeyStore},
     ratchet::RatchetState,
-    kdf::derive_master_key,
+    random::jittered_duration,
+    signing::{self, SigningKeyPair, VerifyingKey},
+    symmetric::AEAD,
+    kdf::{derive_keys, derive_master_key, derive_master_key_bound, ratchet_key_hmac, verify_mac},
 };
 use crate::network::{
     Connection,
-    protocol::{Message, MessageType, MessagePayload},
+    connection::{ConnectionStats, Listener},
+    protocol::{
+        clock_offset_at, max_handshake_size, measure_clock_offset, AadSchema, Message, MessageType, MessagePayload, PeerInfo, WireVersion,
+        ERROR_RATE_LIMITED,
+    },
+    mux::{MuxMode, MuxedConnection},
+    quota::{Quota, QuotaTracker, QuotaUsage},
     NetworkError,
 };
+use crate::security::audit::{AuditLog, AuditEvent};
+use crate::storage::ephemeral::SecretBytes;
+use crate::telemetry::{NoopTelemetry, SessionTelemetry};
+use zeroize::{Zeroize, Zeroizing};
 
 const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Reads of a message key needed to encrypt once; more is flagged as reuse
+const MESSAGE_KEY_ACCESSES: u64 = 1;
+
+/// Kyber level used by the handshake; peer keys must match it exactly
+const SESSION_KYBER_LEVEL: KyberLevel = KyberLevel::Kyber1024;
+
+/// Master key salts; cached handshakes are kept apart from ephemeral ones
+const HANDSHAKE_SALT: &[u8] = b"aegis-v1-salt";
+const CACHED_HANDSHAKE_SALT: &[u8] = b"aegis-v1-cached-salt";
+const TLS_ONLY_SALT: &[u8] = b"aegis-v1-tls-only-salt";
+
+/// RFC 5705 exporter label for `connect_tls_only` / `accept_tls_only`
+const TLS_EXPORTER_LABEL: &[u8] = b"EXPORTER-aegis-tls-only-v1";
+
+/// `transcript_hash` label for sessions set up without a two-way handshake
+const SESSION_BINDING_LABEL: &[u8] = b"aegis-session-binding-v1";
+
+/// Domain separation for `otp_rotate` seed commitments
+const OTP_COMMITMENT_LABEL: &[u8] = b"aegis-otp-commitment-v1";
+
+/// Probe size range searched by `discover_mtu`
+const MTU_PROBE_MIN: usize = 512;
+const MTU_PROBE_MAX: usize = 65535;
+const MTU_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Highest RTT the histogram tracks (60 s, in microseconds)
+const MAX_TRACKED_RTT_US: u64 = 60_000_000;
+
+/// Non-handshake frames `HandshakeStrictness::Lenient` will skip
+const MAX_LENIENT_PRELUDE_FRAMES: usize = 8;
+
+/// Peer clock offset (seconds) beyond which we suggest syncing clocks
+const CLOCK_OFFSET_WARN_SECS: i64 = 60;
+
+/// How long `close` waits for the peer to acknowledge the disconnect
+const CLOSE_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Largest plaintext chunk a `SessionStream` puts in one message
+const STREAM_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Bytes an `EncryptedData` frame adds over a `Probe` padded to the same
+/// length: the nonce, the message counter and the AEAD tag
+const SEALED_FRAME_OVERHEAD: usize = 24 + 8 + 16;
+
+/// How often `Session::tunnel` sends heartbeats
+const TUNNEL_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Handshakes a `SessionPool` runs at once unless configured otherwise
+const DEFAULT_MAX_CONCURRENT_HANDSHAKES: usize = 64;
+
+/// Pause after a failed accept (e.g. out of file descriptors) before retrying
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Relayed contacts one session keeps ratchets for (see `Session::add_contact`)
+const MAX_CONTACTS: usize = 256;
+
+/// Out-of-order keys kept per contact, bounding the total at `MAX_CONTACTS` times this
+const CONTACT_MAX_SKIPPED_KEYS: usize = 64;
+
+/// Shortest interval `Session::set_rotation_interval` accepts
+pub const MIN_ROTATION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest interval `Session::set_rotation_interval` accepts
+pub const MAX_ROTATION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
 /// Session role
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SessionRole {
     Initiator,  // Client (connector)
     Responder,  // Server (listener)
 }
 
+impl SessionRole {
+    /// The role on the other end
+    fn peer(self) -> Self {
+        match self {
+            SessionRole::Initiator => SessionRole::Responder,
+            SessionRole::Responder => SessionRole::Initiator,
+        }
+    }
+}
+
+/// What a call to `Session::recv_event` produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReceivedEvent {
+    /// Decrypted application data (may legitimately be empty)
+    Message(Vec<u8>),
+
+    /// Application data sent with `send_ephemeral`, to be deleted at
+    /// `expires_at` (Unix seconds, by our clock)
+    EphemeralMessage { data: Vec<u8>, expires_at: u64 },
+
+    /// Application data from a relayed contact (see `Session::add_contact`)
+    ContactMessage { contact_id: u64, data: Vec<u8> },
+
+    /// Application data sent with `send_tracked`; ack it with `token` once
+    /// it has been durably handled
+    TrackedMessage { data: Vec<u8>, token: DeliveryToken },
+
+    /// The peer's application acknowledged a `send_tracked` message
+    Delivered { message_id: u64 },
+
+    /// Peer heartbeat (already answered)
+    Heartbeat,
+
+    /// Peer stopped responding within the dead-peer timeout; the session is closed
+    Disconnected,
+
+    /// Authenticated side-channel data (see `Session::send_oob`)
+    OobData(u8, Vec<u8>),
+
+    /// The peer's clock is now more than a minute off ours; timestamp checks
+    /// may start rejecting its messages unless one side fixes its clock
+    ClockDriftWarning { offset_secs: i64 },
+
+    /// The peer introduced itself (also kept as `Session::peer_info`)
+    PeerInfo(PeerInfo),
+}
+
+/// Receipt for a `send_tracked` message, handed to the receiving application
+///
+/// Acking tells the sender the message was processed, not just received,
+/// so a consumer that crashes before acking gets it resent.
+#[must_use = "the sender sees an unacked message as undelivered"]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryToken {
+    message_id: u64,
+}
+
+impl DeliveryToken {
+    /// The ID `send_tracked` returned to the sender
+    pub fn message_id(&self) -> u64 {
+        self.message_id
+    }
+
+    /// Confirm the message was handled by sending a `DeliveryAck` frame
+    ///
+    /// The ack is MACed with a subkey only the two peers hold, so nobody on
+    /// the path can fake a delivery.
+    pub async fn ack(self, session: &mut Session) -> Result<(), NetworkError> {
+        let key = session.delivery_ack_key(session.role)?;
+        let mac = ratchet_key_hmac(&key, &Message::delivery_ack_mac_input(self.message_id))
+            .map_err(|e| NetworkError::ConnectionError(format!("MAC failed: {}", e)))?;
+        let result = session.connection.send_message(&Message::delivery_ack(self.message_id, mac)).await;
+        session.report(result)
+    }
+}
+
+/// Algorithms a session runs with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedSuite {
+    pub kem: KyberLevel,
+    pub aad_schema: AadSchema,
+    /// `WireVersion::FEATURE_*` bits both peers offered at the handshake
+    pub features: u32,
+}
+
+impl NegotiatedSuite {
+    /// Whether both peers speak every frame of `feature`
+    pub fn supports(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+}
+
+/// Everything that determines how well a session is protected, for display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecurityParams {
+    pub suite: NegotiatedSuite,
+    pub aead: AEAD,
+    /// The keys rest on a Kyber exchange (see `Session::is_post_quantum`)
+    pub post_quantum: bool,
+    /// A Kyber rekey has completed since the handshake
+    pub pfs_confirmed: bool,
+    /// A pre-shared key was mixed into the handshake
+    pub psk: bool,
+    /// The handshake transcript was bound into the key
+    pub transcript_bound: bool,
+    /// The connection underneath runs over TLS
+    pub tls: bool,
+}
+
+/// Snapshot yielded by `Session::statistics_interval`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SessionStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    /// Sending-chain rotations and rekeys since the handshake
+    pub key_rotations: u64,
+    /// Heartbeat round-trip percentiles in microseconds (0 before any ping)
+    pub rtt_p50_us: u64,
+    pub rtt_p99_us: u64,
+    /// Rotations since the last rekey (`RatchetState::epoch`)
+    pub epoch: u32,
+    /// Share of frames sent or received without error, from 0.0 to 1.0
+    pub health_score: f64,
+}
+
+/// Frames and bytes received of one message type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeStats {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+/// Running counts of frames through the receive path (see `Session::recv_stats`)
+///
+/// Covers control frames as well as application messages, counted before
+/// they are processed, so floods of any type and oversized frames show up.
+#[derive(Debug, Clone)]
+pub struct RecvStats {
+    pub frames: u64,
+    /// Serialized bytes, excluding the length prefix
+    pub bytes: u64,
+    pub largest_frame: usize,
+    pub by_type: HashMap<MessageType, TypeStats>,
+    /// When counting started (session establishment)
+    pub since: Instant,
+}
+
+impl RecvStats {
+    fn new() -> Self {
+        Self { frames: 0, bytes: 0, largest_frame: 0, by_type: HashMap::new(), since: Instant::now() }
+    }
+
+    fn record(&mut self, message_type: MessageType, size: usize) {
+        self.frames += 1;
+        self.bytes += size as u64;
+        self.largest_frame = self.largest_frame.max(size);
+
+        let entry = self.by_type.entry(message_type).or_default();
+        entry.count += 1;
+        entry.bytes += size as u64;
+    }
+
+    /// Counts for one message type (zero if none arrived)
+    pub fn of_type(&self, message_type: MessageType) -> TypeStats {
+        self.by_type.get(&message_type).copied().unwrap_or_default()
+    }
+
+    /// Fraction of frames that were `message_type`
+    pub fn share(&self, message_type: MessageType) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.of_type(message_type).count as f64 / self.frames as f64
+    }
+
+    /// Mean frame size in bytes
+    pub fn mean_frame_size(&self) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.bytes as f64 / self.frames as f64
+    }
+
+    /// Frames per second since counting started
+    pub fn frames_per_sec(&self) -> f64 {
+        let elapsed = self.since.elapsed().as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        self.frames as f64 / elapsed
+    }
+}
+
+/// How `Session::close` ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseOutcome {
+    /// The peer confirmed the disconnect (or was closing at the same time)
+    Acknowledged,
+    /// No confirmation within the close timeout; closed anyway
+    TimedOut,
+    /// The connection failed before the peer confirmed
+    ConnectionLost,
+    /// The session was not established, so the peer was not told
+    NotEstablished,
+    /// The peer predates disconnect acks, so `close` didn't wait for one
+    AckUnsupported,
+}
+
+/// Extra inputs mixed into the master key at the handshake
+///
+/// Both peers must use the same settings, or their keys won't match and the
+/// first message fails to decrypt. Applies to ephemeral handshakes.
+#[derive(Clone, Default)]
+pub struct SessionConfig {
+    /// Bind a hash of the handshake frames into the key
+    pub bind_transcript: bool,
+    /// Pre-shared key mixed in alongside the KEM secret
+    pub psk: Option<Zeroizing<Vec<u8>>>,
+    /// Kyber parameter set for the handshake and later rekeys (Kyber1024 by default)
+    pub kem: KyberLevel,
+    /// Largest handshake frame accepted, checked from the frame header before
+    /// anything is buffered (`None`: the exact bound for the session's Kyber
+    /// level, about 1.6 KB). Local only; the peer need not match it.
+    pub max_handshake_bytes: Option<usize>,
+}
+
+impl SessionConfig {
+    /// Handshake frame cap in effect
+    pub fn max_handshake_bytes(&self) -> usize {
+        self.max_handshake_bytes.unwrap_or_else(|| max_handshake_size(self.kem))
+    }
+}
+
+/// How `Session::accept_with_strictness` treats frames ahead of the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HandshakeStrictness {
+    /// The first frame must be a handshake
+    #[default]
+    Strict,
+    /// Skip up to `MAX_LENIENT_PRELUDE_FRAMES` other frames first (interop testing)
+    Lenient,
+}
+
+/// What `Session::recv` does with well-formed messages it doesn't understand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownMessagePolicy {
+    /// Skip them and keep receiving (talking to a newer peer)
+    Ignore,
+    /// Fail the receive with a protocol error
+    #[default]
+    Error,
+}
+
+/// How often `Session::rekey_on_schedule` rotates keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RekeyPolicy {
+    /// Cheap HMAC rotation of our sending chain
+    pub symmetric_every: Duration,
+    /// Kyber rekey of both chains, for post-compromise security
+    pub dh_every: Duration,
+    /// Random shift (±) applied to each deadline so rotations don't form a
+    /// precise periodic signal on the wire; capped at half a period
+    pub jitter: Duration,
+}
+
+/// Rotation performed by `Session::rekey_on_schedule`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RekeyAction {
+    Symmetric,
+    Dh,
+}
+
+/// Deadlines for the two rekey cadences; a DH rekey also restarts the symmetric one
+///
+/// Periods start on an un-jittered grid and only the deadline within each
+/// period is jittered, so the cadence doesn't drift. A deadline too far out
+/// for `Instant` is `None` and never fires.
+struct RekeySchedule {
+    policy: RekeyPolicy,
+    last_symmetric: Instant,
+    last_dh: Instant,
+    symmetric_due: Option<Instant>,
+    dh_due: Option<Instant>,
+}
+
+impl RekeySchedule {
+    fn new(policy: RekeyPolicy, now: Instant) -> Self {
+        let mut schedule = Self { policy, last_symmetric: now, last_dh: now, symmetric_due: None, dh_due: None };
+        schedule.restart_dh(now);
+        schedule
+    }
+
+    /// What is due at `now`, marking it done
+    fn take_due(&mut self, now: Instant) -> Option<RekeyAction> {
+        if self.dh_due.is_some_and(|due| now >= due) {
+            self.restart_dh(next_period(self.last_dh, self.policy.dh_every, now));
+            Some(RekeyAction::Dh)
+        } else if self.symmetric_due.is_some_and(|due| now >= due) {
+            self.restart_symmetric(next_period(self.last_symmetric, self.policy.symmetric_every, now));
+            Some(RekeyAction::Symmetric)
+        } else {
+            None
+        }
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        match (self.dh_due, self.symmetric_due) {
+            (Some(dh), Some(symmetric)) => Some(dh.min(symmetric)),
+            (dh, symmetric) => dh.or(symmetric),
+        }
+    }
+
+    fn set_symmetric_every(&mut self, every: Duration) {
+        self.policy.symmetric_every = every;
+        self.restart_symmetric(self.last_symmetric);
+    }
+
+    fn restart_symmetric(&mut self, start: Instant) {
+        self.last_symmetric = start;
+        self.symmetric_due = start.checked_add(jittered_duration(self.policy.symmetric_every, self.policy.jitter));
+    }
+
+    fn restart_dh(&mut self, start: Instant) {
+        self.last_dh = start;
+        self.dh_due = start.checked_add(jittered_duration(self.policy.dh_every, self.policy.jitter));
+        self.restart_symmetric(start);
+    }
+}
+
+/// Start of the period after the one beginning at `start`, or `now` if
+/// we've fallen more than a whole period behind
+fn next_period(start: Instant, every: Duration, now: Instant) -> Instant {
+    match start.checked_add(every) {
+        Some(next) if next.checked_add(every).is_none_or(|after| after > now) => next,
+        _ => now,
+    }
+}
+
+/// Lifecycle of a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// Key exchange still in progress
+    Handshaking,
+    /// Keys agreed; messages can flow
+    Established,
+    /// Local side is shutting the session down
+    Closing,
+    /// Session ended by either side
+    Closed,
+}
+
 /// Session represents an established encrypted session with a peer
 pub struct Session {
     pub connection: Connection,
     pub ratchet: RatchetState,
     pub peer_addr: SocketAddr,
-    pub established: bool,
+    state: SessionState,
     pub role: SessionRole,
+    audit_log: Option<AuditLog>,
+    /// Largest probe size the peer answered, once discovered
+    mtu: Option<usize>,
+    /// Events received while waiting for a control reply
+    pending_events: VecDeque<ReceivedEvent>,
+    /// Heartbeat round-trip times in microseconds
+    rtt_histogram: Histogram<u64>,
+    /// When the unanswered heartbeat was sent
+    heartbeat_sent_at: Option<Instant>,
+    /// Volume cap on what the peer may send us
+    quota: Option<QuotaTracker>,
+    /// Received byte total already charged to the quota
+    quota_charged_bytes: u64,
+    /// Wipe ciphertext and nonce buffers once decrypted
+    zeroize_ciphertext: bool,
+    /// Observer for message events
+    telemetry: Arc<dyn SessionTelemetry>,
+    /// Latest statistics, shared with `statistics_interval` streams
+    published_stats: Arc<Mutex<SessionStats>>,
+    /// Sending-chain rotations and rekeys since the handshake
+    key_rotations: u64,
+    /// Silence allowed after an unanswered send before the peer is declared dead
+    dead_peer_timeout: Option<Duration>,
+    /// When the last frame of any kind arrived from the peer
+    last_frame_at: Instant,
+    /// First send since the last received frame
+    unanswered_since: Option<Instant>,
+    /// Display name and capabilities the peer advertised
+    peer_info: Option<PeerInfo>,
+    /// Seconds the peer's clock runs ahead of ours, measured at the handshake
+    clock_offset: i64,
+    /// Our clock in Unix seconds; replaced in tests
+    clock: fn() -> u64,
+    /// Handling of extension messages from newer peers
+    unknown_message_policy: UnknownMessagePolicy,
+    /// How long `close` waits for the disconnect to be acknowledged
+    close_timeout: Duration,
+    /// Algorithms agreed at the handshake
+    suite: NegotiatedSuite,
+    /// Automatic rotation cadences, if enabled
+    rekey_schedule: Option<RekeySchedule>,
+    /// Ephemeral key of our rekey offer, until the peer answers
+    pending_rekey: Option<KeyPair>,
+    /// Secret from the peer's rekey offer, until its commit arrives
+    pending_rekey_commit: Option<Zeroizing<[u8; 32]>>,
+    /// Size and type counts of received frames
+    recv_stats: RecvStats,
+    /// Seed shared out of band for `otp_rotate`
+    oob_seed: Option<Zeroizing<Vec<u8>>>,
+    /// Last out-of-band counter we sent on each channel
+    oob_sent: HashMap<u8, u64>,
+    /// Highest out-of-band counter accepted from the peer on each channel
+    oob_received: HashMap<u8, u64>,
+    /// Minute of our last seed rotation
+    last_otp_minute: Option<u64>,
+    /// Minute of the peer's last seed rotation
+    peer_otp_minute: Option<u64>,
+    /// Refuse `send` until `pfs_confirmed`
+    require_pfs: bool,
+    /// A Kyber rekey has completed since the handshake
+    pfs_confirmed: bool,
+    /// The root key came from a Kyber exchange rather than the TLS exporter
+    post_quantum: bool,
+    /// Hash of the handshake frames, when there was a handshake to hash
+    handshake_transcript: Option<[u8; 32]>,
+    /// `SessionConfig` mixed a PSK into the key
+    psk_bound: bool,
+    /// `SessionConfig` bound the transcript into the key
+    transcript_bound: bool,
+    /// Messages sent with `send_signed`, bound into each signature
+    signed_sent: u64,
+    /// Messages accepted by `recv_verified`
+    signed_received: u64,
+    /// Connection migrations completed, bound into each migration token
+    migrations: u32,
+    /// Independent ratchets for relayed contacts, by contact ID
+    contacts: HashMap<u64, RatchetState>,
+    /// `send_tracked` messages not yet acked, by message ID, with when they were sent
+    awaiting_ack: HashMap<u64, Instant>,
+    /// Every nonce we have sealed with, when nonce reuse detection is on
+    sent_nonces: Option<HashSet<[u8; 24]>>,
+    /// Where `dump_keys` writes message keys
+    #[cfg(debug_assertions)]
+    key_log: Option<KeyLog>,
 }
 
 impl Session {
     /// Initiate a session as a client (connector)
-    pub async fn connect(mut connection: Connection) -> Result<Self, NetworkError> {
-        // Generate ephemeral Kyber keypair
-        let keypair = KeyPair::generate()
-            .map_err(|e| NetworkError::ConnectionError(format!("Key generation failed: {}", e)))?;
-
-        // Send handshake with our public key
-        let handshake_msg = Message::handshake(keypair.public_key().clone());
-        connection.send_message(&handshake_msg).await?;
+    pub async fn connect(connection: Connection) -> Result<Self, NetworkError> {
+        Self::connect_with_config(connection, &SessionConfig::default()).await
+    }
 
-        // Wait for handshake response
-        let response = timeout(HANDSHAKE_TIMEOUT, connection.recv_message()).await
-            .map_err(|_| NetworkError::Timeout)?
-            .map_err(|e| NetworkError::ConnectionError(format!("Handshake failed: {}", e)))?;
+    /// Initiate a session, mixing the transcript and PSK from `config` into the key
+    pub async fn connect_with_config(mut connection: Connection, config: &SessionConfig) -> Result<Self, NetworkError> {
+        let outcome = initiator_handshake(&mut connection, config).await?;
 
-        // Validate response
-        response.validate()?;
-        if response.message_type != MessageType::HandshakeResponse {
-            return Err(NetworkError::ProtocolError("Expected handshake response".to_string()));
+        let mut session = Self::established(connection, RatchetState::new(outcome.root_key), SessionRole::Initiator);
+        session.suite.aad_schema = outcome.aad_schema;
+        session.suite.features = outcome.features;
+        session.suite.kem = config.kem;
+        session.handshake_transcript = outcome.transcript_hash;
+        session.psk_bound = config.psk.is_some();
+        session.transcript_bound = config.bind_transcript;
+        // The handshake timestamps are only authenticated through the transcript
+        if session.transcript_bound {
+            session.note_clock_offset(outcome.clock_offset);
         }
+        Ok(session)
+    }
 
-        // Extract ciphertext and derive shared secret
-        let ciphertext_bytes = match response.payload {
-            MessagePayload::HandshakeResponse { ciphertext } => ciphertext,
-            _ => return Err(NetworkError::ProtocolError("Invalid handshake response payload".to_string())),
-        };
+    /// Handshake once as the client, then carry many sessions over one multiplexed connection
+    pub async fn connect_muxed(mut connection: Connection) -> Result<MuxedSession, NetworkError> {
+        let outcome = initiator_handshake(&mut connection, &SessionConfig::default()).await?;
+        MuxedSession::new(connection, &outcome, MuxMode::Client).await
+    }
 
-        let ciphertext = Ciphertext::from_bytes(ciphertext_bytes)
-            .map_err(|e| NetworkError::ProtocolError(format!("Invalid ciphertext: {}", e)))?;
+    /// Handshake once as the server, then accept many sessions over one multiplexed connection
+    pub async fn accept_muxed(mut connection: Connection) -> Result<MuxedSession, NetworkError> {
+        let outcome = responder_handshake(&mut connection, None, HandshakeStrictness::Strict, &SessionConfig::default()).await?;
+        MuxedSession::new(connection, &outcome, MuxMode::Server).await
+    }
 
-        let shared_secret = keypair.decapsulate(&ciphertext)
-            .map_err(|e| NetworkError::ConnectionError(format!("Decapsulation failed: {}", e)))?;
+    /// Initiate a session by encapsulating to a cached server key (0-RTT)
+    ///
+    /// The handshake is a single message and the session is usable as soon
+    /// as it is sent. The server must run `accept_with_key` with the
+    /// matching `SemiStaticKeyPair`; if it has rotated the key since it was
+    /// cached, it disconnects and the first `recv` fails, after which the
+    /// client should fall back to `connect`.
+    ///
+    /// Forward secrecy is weaker than with `connect`: the shared secret
+    /// depends only on the server's semi-static key, so anyone who obtains
+    /// that secret key before it is rotated can decrypt every session made
+    /// with it. Keep the rotation schedule short. With no reply to negotiate
+    /// in, messages bind no header fields (`AadSchema::V0`).
+    ///
+    /// The server contributes no fresh randomness, so a recorded handshake
+    /// plus early data would be a complete session to replay. The server's
+    /// `SemiStaticKeyPair` refuses any ciphertext it has already accepted
+    /// under the current key; a server that reloads the key (e.g. after a
+    /// restart) loses that memory and should rotate it instead.
+    pub async fn connect_cached(mut connection: Connection, cached_server_key: &PublicKey) -> Result<Self, NetworkError> {
+        if cached_server_key.level() != SESSION_KYBER_LEVEL {
+            return Err(NetworkError::ProtocolError("Cached server key has the wrong Kyber level".to_string()));
+        }
 
-        // Derive master key from shared secret
-        let salt = b"aegis-v1-salt";
-        let master_key = derive_master_key(shared_secret.as_bytes(), salt)
-            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?;
+        let (shared_secret, ciphertext) = cached_server_key.encapsulate()
+            .map_err(|e| NetworkError::ConnectionError(format!("Encapsulation failed: {}", e)))?;
 
-        // Initialize ratchet state
-        let mut root_key = [0u8; 32];
-        root_key.copy_from_slice(master_key.as_bytes());
-        let ratchet = RatchetState::new(root_key);
+        let handshake_msg = Message::cached_handshake(cached_server_key.fingerprint(), ciphertext);
+        connection.send_message(&handshake_msg).await?;
 
-        let peer_addr = connection.peer_addr();
+        let root_key = derive_root_key(shared_secret.as_bytes(), CACHED_HANDSHAKE_SALT)?;
+        Ok(Self::established(connection, RatchetState::new(root_key), SessionRole::Initiator))
+    }
 
-        Ok(Session {
-            connection,
-            ratchet,
-            peer_addr,
-            established: true,
-            role: SessionRole::Initiator,
-        })
+    /// Initiate a session keyed from the TLS exporter, with no Kyber exchange
+    ///
+    /// NOT POST-QUANTUM: the session is only as strong as the TLS key
+    /// exchange underneath, so a recorded connection can be decrypted by
+    /// anyone who later breaks that. A Kyber rekey (`dh_rekey`) restores
+    /// post-quantum keys later. The server must call `accept_tls_only`.
+    ///
+    /// Fails unless the connection came from `connect_tls_with_verifier`:
+    /// `connect_tls` accepts any certificate, and keys exported from an
+    /// unverified TLS session would be shared with whoever sits in the middle.
+    pub fn connect_tls_only(connection: Connection) -> Result<Self, NetworkError> {
+        if !connection.is_server_verified() {
+            return Err(NetworkError::ProtocolError(
+                "TLS-only sessions need a verified server certificate (see connect_tls_with_verifier)".to_string(),
+            ));
+        }
+        let root_key = tls_exporter_root_key(&connection)?;
+        Ok(Self::tls_only(connection, RatchetState::new(root_key), SessionRole::Initiator))
+    }
+
+    /// Accept a session keyed from the TLS exporter (see `connect_tls_only`)
+    ///
+    /// NOT POST-QUANTUM, for the same reasons.
+    pub fn accept_tls_only(connection: Connection) -> Result<Self, NetworkError> {
+        let root_key = tls_exporter_root_key(&connection)?;
+        Ok(Self::tls_only(connection, RatchetState::new_responder(root_key), SessionRole::Responder))
+    }
+
+    fn tls_only(connection: Connection, ratchet: RatchetState, role: SessionRole) -> Self {
+        tracing::warn!("Session with {} is keyed from TLS only and is not post-quantum", connection.peer_addr());
+        let mut session = Self::established(connection, ratchet, role);
+        session.suite.aad_schema = AadSchema::LATEST;
+        session.post_quantum = false;
+        session
     }
 
     /// Accept a session as a server (listener)
-    pub async fn accept(mut connection: Connection) -> Result<Self, NetworkError> {
-        // Wait for handshake
-        let handshake = timeout(HANDSHAKE_TIMEOUT, connection.recv_message()).await
-            .map_err(|_| NetworkError::Timeout)?
-            .map_err(|e| NetworkError::ConnectionError(format!("Handshake failed: {}", e)))?;
+    pub async fn accept(connection: Connection) -> Result<Self, NetworkError> {
+        Self::accept_inner(connection, None, HandshakeStrictness::Strict, &SessionConfig::default()).await
+    }
+
+    /// Accept a session, choosing how to treat frames that arrive before the handshake
+    pub async fn accept_with_strictness(connection: Connection, strictness: HandshakeStrictness) -> Result<Self, NetworkError> {
+        Self::accept_inner(connection, None, strictness, &SessionConfig::default()).await
+    }
+
+    /// Accept a session, mixing the transcript and PSK from `config` into the key
+    pub async fn accept_with_config(connection: Connection, config: &SessionConfig) -> Result<Self, NetworkError> {
+        Self::accept_inner(connection, None, HandshakeStrictness::Strict, config).await
+    }
+
+    /// Accept a session as a server that also holds a semi-static key, so
+    /// clients that cached it can use `connect_cached`
+    ///
+    /// Ordinary handshakes are still answered with a fresh encapsulation.
+    pub async fn accept_with_key(connection: Connection, server_key: &SemiStaticKeyPair) -> Result<Self, NetworkError> {
+        Self::accept_inner(connection, Some(server_key), HandshakeStrictness::Strict, &SessionConfig::default()).await
+    }
+
+    async fn accept_inner(
+        mut connection: Connection,
+        server_key: Option<&SemiStaticKeyPair>,
+        strictness: HandshakeStrictness,
+        config: &SessionConfig,
+    ) -> Result<Self, NetworkError> {
+        let outcome = responder_handshake(&mut connection, server_key, strictness, config).await?;
 
-        // Validate handshake
-        handshake.validate()?;
-        if handshake.message_type != MessageType::Handshake {
-            return Err(NetworkError::ProtocolError("Expected handshake".to_string()));
+        // Responder has swapped chains
+        let mut session = Self::established(connection, RatchetState::new_responder(outcome.root_key), SessionRole::Responder);
+        session.suite.aad_schema = outcome.aad_schema;
+        session.suite.features = outcome.features;
+        session.handshake_transcript = outcome.transcript_hash;
+        if outcome.transcript_hash.is_some() {
+            session.suite.kem = config.kem;
+            session.psk_bound = config.psk.is_some();
+            session.transcript_bound = config.bind_transcript;
         }
+        if session.transcript_bound {
+            session.note_clock_offset(outcome.clock_offset);
+        }
+        Ok(session)
+    }
 
-        // Extract peer's public key
-        let peer_public_key_bytes = match handshake.payload {
-            MessagePayload::Handshake { public_key } => public_key,
-            _ => return Err(NetworkError::ProtocolError("Invalid handshake payload".to_string())),
-        };
+    /// Start a session with a peer through its published pre-key bundle
+    ///
+    /// The peer need not be online: the `PreKeyMessage` sent here can wait
+    /// in a mailbox until the owner passes it to `accept_prekey`. The bundle's
+    /// signature is checked, but nothing authenticates us to the peer. As
+    /// with `connect_cached`, messages use `AadSchema::V0`. `one_time_id`
+    /// picks the bundle's one-time pre-key (see `prekey::initiate`).
+    pub async fn init_from_prekey(mut connection: Connection, bundle: &PreKeyBundle, one_time_id: Option<u32>) -> Result<Self, NetworkError> {
+        let (root_key, initiation) = prekey::initiate(bundle, one_time_id)
+            .map_err(|e| NetworkError::ProtocolError(format!("Pre-key bundle rejected: {}", e)))?;
 
-        let peer_public_key = PublicKey::from_bytes(peer_public_key_bytes)
-            .map_err(|e| NetworkError::ProtocolError(format!("Invalid public key: {}", e)))?;
+        connection.send_message(&Message::prekey(initiation)).await?;
 
-        // Encapsulate a shared secret for the peer
-        let (shared_secret, ciphertext) = peer_public_key.encapsulate()
-            .map_err(|e| NetworkError::ConnectionError(format!("Encapsulation failed: {}", e)))?;
+        Ok(Self::established(connection, RatchetState::new(root_key), SessionRole::Initiator))
+    }
 
-        // Send handshake response
-        let response = Message::handshake_response(ciphertext);
-        connection.send_message(&response).await?;
+    /// Resume a session from a `PreKeyMessage` sent to our published bundle
+    ///
+    /// Consumes the one-time pre-key the message used, so the same message
+    /// can't start a second session.
+    pub fn accept_prekey(
+        connection: Connection,
+        our_prekeys: &mut PreKeyStore,
+        initial_message: &Message,
+    ) -> Result<Self, NetworkError> {
+        initial_message.validate()?;
 
-        // Derive master key
-        let salt = b"aegis-v1-salt";
-        let master_key = derive_master_key(shared_secret.as_bytes(), salt)
-            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?;
+        let initiation = match &initial_message.payload {
+            MessagePayload::PreKey { signed_prekey_fingerprint, signed_ciphertext, one_time } => PreKeyInitiation {
+                signed_prekey_fingerprint: *signed_prekey_fingerprint,
+                signed_ciphertext: signed_ciphertext.clone(),
+                one_time: one_time.clone(),
+            },
+            _ => return Err(NetworkError::ProtocolError(
+                format!("Expected pre-key message, got {:?}", initial_message.message_type)
+            )),
+        };
 
-        // Initialize ratchet state (responder has swapped chains)
-        let mut root_key = [0u8; 32];
-        root_key.copy_from_slice(master_key.as_bytes());
-        let ratchet = RatchetState::new_responder(root_key);
+        let root_key = prekey::respond(our_prekeys, &initiation)
+            .map_err(|e| NetworkError::ProtocolError(format!("Pre-key message rejected: {}", e)))?;
+
+        Ok(Self::established(connection, RatchetState::new_responder(root_key), SessionRole::Responder))
+    }
 
+    /// Build a session around a completed key exchange
+    fn established(connection: Connection, ratchet: RatchetState, role: SessionRole) -> Self {
         let peer_addr = connection.peer_addr();
 
-        Ok(Session {
+        Session {
             connection,
             ratchet,
             peer_addr,
-            established: true,
-            role: SessionRole::Responder,
-        })
+            state: SessionState::Established,
+            role,
+            audit_log: None,
+            mtu: None,
+            pending_events: VecDeque::new(),
+            rtt_histogram: new_rtt_histogram(),
+            heartbeat_sent_at: None,
+            quota: None,
+            quota_charged_bytes: 0,
+            zeroize_ciphertext: false,
+            telemetry: Arc::new(NoopTelemetry),
+            published_stats: Arc::new(Mutex::new(SessionStats { health_score: 1.0, ..SessionStats::default() })),
+            key_rotations: 0,
+            dead_peer_timeout: None,
+            last_frame_at: Instant::now(),
+            unanswered_since: None,
+            peer_info: None,
+            clock_offset: 0,
+            clock: current_timestamp,
+            unknown_message_policy: UnknownMessagePolicy::default(),
+            close_timeout: CLOSE_ACK_TIMEOUT,
+            suite: NegotiatedSuite { kem: SESSION_KYBER_LEVEL, aad_schema: AadSchema::V0, features: 0 },
+            rekey_schedule: None,
+            pending_rekey: None,
+            pending_rekey_commit: None,
+            recv_stats: RecvStats::new(),
+            oob_seed: None,
+            oob_sent: HashMap::new(),
+            oob_received: HashMap::new(),
+            last_otp_minute: None,
+            peer_otp_minute: None,
+            require_pfs: false,
+            pfs_confirmed: false,
+            post_quantum: true,
+            handshake_transcript: None,
+            psk_bound: false,
+            transcript_bound: false,
+            signed_sent: 0,
+            signed_received: 0,
+            migrations: 0,
+            contacts: HashMap::new(),
+            awaiting_ack: HashMap::new(),
+            sent_nonces: cfg!(debug_assertions).then(HashSet::new),
+            #[cfg(debug_assertions)]
+            key_log: None,
+        }
+    }
+
+    /// Seconds the peer's clock runs ahead of ours (negative if behind)
+    ///
+    /// Measured from the peer's handshake frame when the transcript is bound,
+    /// then re-measured from each data frame whose AAD covers its timestamp,
+    /// so drift after the handshake shows up. Unauthenticated frames such as
+    /// heartbeats are never used. Each sample is clamped to
+    /// `MAX_CLOCK_SKEW_SECS`. Zero until an authenticated timestamp arrives.
+    pub fn estimated_clock_offset(&self) -> i64 {
+        self.clock_offset
+    }
+
+    /// Same as `estimated_clock_offset`
+    pub fn clock_offset(&self) -> i64 {
+        self.clock_offset
+    }
+
+    /// Record the peer's clock offset, warning when the clocks drift apart
+    ///
+    /// The warning is logged and queued as `ReceivedEvent::ClockDriftWarning`
+    /// once per crossing of the threshold, not on every measurement.
+    fn note_clock_offset(&mut self, clock_offset: i64) {
+        let drifting = clock_offset.abs() > CLOCK_OFFSET_WARN_SECS;
+        if drifting && self.clock_offset.abs() <= CLOCK_OFFSET_WARN_SECS {
+            tracing::warn!(
+                "Peer clock is {}s {} ours; check NTP sync on both hosts",
+                clock_offset.abs(),
+                if clock_offset > 0 { "ahead of" } else { "behind" }
+            );
+            self.pending_events.push_back(ReceivedEvent::ClockDriftWarning { offset_secs: clock_offset });
+        }
+        self.clock_offset = clock_offset;
     }
 
     /// Send an encrypted message
     pub async fn send(&mut self, plaintext: &[u8]) -> Result<(), NetworkError> {
-        if !self.established {
-            return Err(NetworkError::ConnectionError("Session not established".to_string()));
+        self.require_established("send")?;
+        if self.require_pfs && !self.pfs_confirmed {
+            return Err(NetworkError::PfsNotConfirmed);
         }
 
-        // Get next sending key and counter
-        let (message_key, counter) = self.ratchet.next_send_key()
-            .map_err(|e| NetworkError::ConnectionError(format!("Key rotation failed: {}", e)))?;
-
-        // Encrypt the message
-        let encrypted = crate::crypto::symmetric::encrypt_simple(&message_key, plaintext)
-            .map_err(|e| NetworkError::ConnectionError(format!("Encryption failed: {}", e)))?;
-
-        // Create encrypted message
-        let msg = Message::encrypted(encrypted.nonce, encrypted.ciphertext, counter, 0);
+        let mut msg = Message::encrypted([0u8; 24], Vec::new(), 0, self.ratchet.epoch() as u16);
+        let counter = self.seal_next(&mut msg, plaintext)?;
 
         // Send
-        self.connection.send_message(&msg).await?;
+        let result = self.connection.send_message(&msg).await;
+        self.report(result)?;
+        self.note_sent();
+        self.telemetry.on_send(counter, plaintext.len());
 
         Ok(())
     }
 
-    /// Receive and decrypt a message
-    pub async fn recv(&mut self) -> Result<Vec<u8>, NetworkError> {
-        if !self.established {
-            return Err(NetworkError::ConnectionError("Session not established".to_string()));
+    /// Send a message the peer's application must acknowledge, returning its ID
+    ///
+    /// The peer gets `ReceivedEvent::TrackedMessage` and acks it with the
+    /// `DeliveryToken` once handled; the ack arrives here as
+    /// `ReceivedEvent::Delivered` with the returned ID. Messages never acked
+    /// stay pending until `overdue_deliveries` reports them, at which point
+    /// the application can resend (at-least-once delivery).
+    pub async fn send_tracked(&mut self, plaintext: &[u8]) -> Result<u64, NetworkError> {
+        self.require_established("send")?;
+        self.require_feature(WireVersion::FEATURE_DELIVERY_ACKS, "delivery acks")?;
+        if self.require_pfs && !self.pfs_confirmed {
+            return Err(NetworkError::PfsNotConfirmed);
         }
 
-        // Receive message
-        let msg = self.connection.recv_message().await?;
+        let mut msg = Message::tracked([0u8; 24], Vec::new(), 0, self.ratchet.epoch() as u16);
+        let counter = self.seal_next(&mut msg, plaintext)?;
 
-        // Validate
-        msg.validate()?;
+        let result = self.connection.send_message(&msg).await;
+        self.report(result)?;
+        self.note_sent();
+        self.telemetry.on_send(counter, plaintext.len());
 
-        // Handle different message types
-        match msg.message_type {
-            MessageType::EncryptedMessage => {
-                // Extract encrypted data
-                let (nonce, ciphertext, counter) = match msg.payload {
-                    MessagePayload::EncryptedData { nonce, ciphertext, message_counter } => {
-                        (nonce, ciphertext, message_counter)
-                    }
-                    _ => return Err(NetworkError::ProtocolError("Invalid encrypted message payload".to_string())),
-                };
+        let message_id = msg.replay_safe_id();
+        self.awaiting_ack.insert(message_id, Instant::now());
+        Ok(message_id)
+    }
+
+    /// Stop waiting for acks sent more than `timeout` ago, returning their IDs
+    pub fn overdue_deliveries(&mut self, timeout: Duration) -> Vec<u64> {
+        let mut overdue = Vec::new();
+        self.awaiting_ack.retain(|message_id, sent_at| {
+            let waiting = sent_at.elapsed() <= timeout;
+            if !waiting {
+                overdue.push(*message_id);
+            }
+            waiting
+        });
+        overdue
+    }
 
-                // Get receiving key
-                let message_key = self.ratchet.get_recv_key(counter)
-                    .map_err(|e| NetworkError::ConnectionError(format!("Key retrieval failed: {}", e)))?;
+    /// Number of `send_tracked` messages not yet acked or reported overdue
+    pub fn awaiting_ack_count(&self) -> usize {
+        self.awaiting_ack.len()
+    }
 
-                // Decrypt
-                let encrypted_msg = crate::crypto::symmetric::EncryptedMessage {
-                    nonce,
-                    ciphertext,
-                };
+    /// Send a message with a Dilithium signature over it, so the peer can
+    /// prove we sent it even if the session keys later leak
+    ///
+    /// The signature covers `transcript_hash`, the number of signed messages
+    /// sent before this one, and the plaintext. It is appended to the
+    /// plaintext inside the encryption, so only the peer sees it. The peer
+    /// must read every signed message with `recv_verified`.
+    pub async fn send_signed(&mut self, plaintext: &[u8], signing_key: &SigningKeyPair) -> Result<(), NetworkError> {
+        let digest = self.signed_digest(self.signed_sent, plaintext)?;
+        let signature = signing_key.sign(&digest)
+            .map_err(|e| NetworkError::ConnectionError(format!("Signing failed: {}", e)))?;
 
-                let plaintext = crate::crypto::symmetric::decrypt_simple(&message_key, &encrypted_msg)
-                    .map_err(|e| NetworkError::ConnectionError(format!("Decryption failed: {}", e)))?;
+        let mut signed = Zeroizing::new(Vec::with_capacity(plaintext.len() + signature.len()));
+        signed.extend_from_slice(plaintext);
+        signed.extend_from_slice(&signature);
+        self.send(&signed).await?;
+        self.signed_sent += 1;
+        Ok(())
+    }
 
-                Ok(plaintext)
-            }
-            MessageType::Heartbeat => {
-                // Respond to heartbeat
-                let response = Message::heartbeat();
-                self.connection.send_message(&response).await?;
-                // Return empty to indicate heartbeat (caller should handle)
-                Ok(Vec::new())
-            }
-            MessageType::Disconnect => {
-                self.established = false;
-                Err(NetworkError::ConnectionError("Peer disconnected".to_string()))
-            }
-            _ => {
-                Err(NetworkError::ProtocolError(format!("Unexpected message type: {:?}", msg.message_type)))
-            }
-        }
+    /// Receive a message sent with `send_signed`, checking its signature
+    /// against `verifying_key` before returning the plaintext
+    ///
+    /// Fails on an unsigned, forged, replayed or reordered message.
+    pub async fn recv_verified(&mut self, verifying_key: &VerifyingKey) -> Result<Vec<u8>, NetworkError> {
+        let mut data = self.recv_data().await?;
+        let verified = self.strip_signature(&mut data, verifying_key);
+        self.audit(AuditEvent::AuthResult { method: "signature".to_string(), success: verified.is_ok() });
+        verified?;
+        self.signed_received += 1;
+        Ok(data)
     }
 
-    /// Send a heartbeat
-    pub async fn send_heartbeat(&mut self) -> Result<(), NetworkError> {
-        let msg = Message::heartbeat();
-        self.connection.send_message(&msg).await
+    /// Split the trailing signature off `data` and check it
+    fn strip_signature(&self, data: &mut Vec<u8>, verifying_key: &VerifyingKey) -> Result<(), NetworkError> {
+        let Some(split) = data.len().checked_sub(signing::signature_len()) else {
+            return Err(NetworkError::ProtocolError("Message is too short to carry a signature".to_string()));
+        };
+
+        let signature = data.split_off(split);
+        let digest = self.signed_digest(self.signed_received, data)?;
+        verifying_key.verify(&digest, &signature)
+            .map_err(|_| NetworkError::ProtocolError("Message signature is invalid".to_string()))
     }
 
-    /// Close the session
-    pub async fn close(mut self) -> Result<(), NetworkError> {
-        let disconnect_msg = Message::disconnect(Some("User requested disconnect".to_string()));
-        let _ = self.connection.send_message(&disconnect_msg).await;
-        self.connection.close().await
+    /// What `send_signed` signs for the message at `sequence`
+    fn signed_digest(&self, sequence: u64, plaintext: &[u8]) -> Result<[u8; 32], NetworkError> {
+        let mut hasher = blake3::Hasher::new_derive_key("aegis-signed-message-v1");
+        hasher.update(&self.transcript_hash()?);
+        hasher.update(&sequence.to_be_bytes());
+        hasher.update(plaintext);
+        Ok(*hasher.finalize().as_bytes())
     }
 
-    /// Get seconds until next key rotation
-    pub fn seconds_until_rotation(&self) -> u64 {
-        self.ratchet.seconds_until_rotation()
+    /// Value both peers share that identifies this session
+    ///
+    /// The hash of the handshake frames for sessions from `connect` /
+    /// `accept`; for sessions set up some other way (cached, pre-key,
+    /// TLS-only, multiplexed), a value derived from the agreed root key.
+    pub fn transcript_hash(&self) -> Result<[u8; 32], NetworkError> {
+        match self.handshake_transcript {
+            Some(hash) => Ok(hash),
+            None => self.ratchet.derive_subkey(SESSION_BINDING_LABEL)
+                .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e))),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::network::connection::Listener;
+    /// Send a disappearing message: the peer is told to delete it `ttl`
+    /// after it arrives (whole seconds, at least one)
+    ///
+    /// Deletion is up to the receiving client; nothing stops a modified
+    /// client, a log, or a screenshot from keeping it. The TTL travels in the
+    /// clear but is bound into the AEAD additional data, so it can't be
+    /// changed in transit.
+    pub async fn send_ephemeral(&mut self, plaintext: &[u8], ttl: Duration) -> Result<(), NetworkError> {
+        self.require_established("send")?;
+        self.require_feature(WireVersion::FEATURE_EPHEMERAL, "ephemeral messages")?;
+        if self.require_pfs && !self.pfs_confirmed {
+            return Err(NetworkError::PfsNotConfirmed);
+        }
+        let ttl_secs = u32::try_from(ttl.as_secs()).unwrap_or(u32::MAX);
+        if ttl_secs == 0 {
+            return Err(NetworkError::ProtocolError("Ephemeral message TTL must be at least one second".to_string()));
+        }
 
-    #[tokio::test]
-    async fn test_session_handshake() {
-        // Start listener
-        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
-        let addr = listener.local_addr().unwrap();
+        let mut msg = Message::ephemeral([0u8; 24], Vec::new(), 0, self.ratchet.epoch() as u16, ttl_secs);
+        let counter = self.seal_next(&mut msg, plaintext)?;
 
-        // Spawn accept task
-        let accept_handle = tokio::spawn(async move {
-            let conn = listener.accept().await.unwrap();
-            Session::accept(conn).await
-        });
+        let result = self.connection.send_message(&msg).await;
+        self.report(result)?;
+        self.note_sent();
+        self.telemetry.on_send(counter, plaintext.len());
 
-        // Connect
-        let client_conn = crate::network::connection::connect(&addr.to_string()).await.unwrap();
-        let client_session = Session::connect(client_conn).await.unwrap();
+        Ok(())
+    }
 
-        // Accept
-        let server_session = accept_handle.await.unwrap().unwrap();
+    /// Start a ratchet for a contact reached through this connection
+    ///
+    /// For relays, where one connection carries traffic for many end
+    /// contacts: each contact gets its own forward-secret ratchet, so a
+    /// compromise of one contact's keys (or of the session's own ratchet)
+    /// exposes nothing sent to the others. `root_key` must be agreed with
+    /// the contact end to end, e.g. with `crypto::prekey`, and both ends must
+    /// add the contact under the same ID. `role` is our side of that
+    /// agreement and the contact must take the other one; it has nothing to
+    /// do with who opened this connection, since both contacts usually
+    /// connect to the relay as initiators. Up to `MAX_CONTACTS` contacts,
+    /// each keeping at most `CONTACT_MAX_SKIPPED_KEYS` out-of-order keys.
+    pub fn add_contact(&mut self, contact_id: u64, root_key: [u8; 32], role: SessionRole) -> Result<(), NetworkError> {
+        self.require_feature(WireVersion::FEATURE_CONTACTS, "relayed contacts")?;
+        if self.contacts.contains_key(&contact_id) {
+            return Err(NetworkError::ProtocolError(format!("Contact {} already has a ratchet", contact_id)));
+        }
+        if self.contacts.len() >= MAX_CONTACTS {
+            return Err(NetworkError::ProtocolError(format!("Too many contacts (max {})", MAX_CONTACTS)));
+        }
 
-        // Both sessions should be established
-        assert!(client_session.established);
-        assert!(server_session.established);
+        let mut ratchet = match role {
+            SessionRole::Initiator => RatchetState::new(root_key),
+            SessionRole::Responder => RatchetState::new_responder(root_key),
+        };
+        ratchet.set_max_skipped_keys(CONTACT_MAX_SKIPPED_KEYS);
+        self.contacts.insert(contact_id, ratchet);
+        Ok(())
     }
 
-    #[tokio::test]
-    async fn test_session_message_exchange() {
-        // Start listener
-        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
-        let addr = listener.local_addr().unwrap();
+    /// Drop a contact's ratchet, wiping its keys; returns whether it existed
+    pub fn remove_contact(&mut self, contact_id: u64) -> bool {
+        self.contacts.remove(&contact_id).is_some()
+    }
 
-        // Spawn server task
-        let server_handle = tokio::spawn(async move {
-            let conn = listener.accept().await.unwrap();
-            let mut session = Session::accept(conn).await.unwrap();
+    /// IDs of the contacts with a ratchet, in no particular order
+    pub fn contact_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.contacts.keys().copied()
+    }
+
+    /// Encrypt and send to `contact_id` under that contact's ratchet
+    pub async fn send_to(&mut self, contact_id: u64, plaintext: &[u8]) -> Result<(), NetworkError> {
+        self.require_established("send")?;
+        let key_id = self.contact_ratchet(contact_id)?.send_epoch();
+
+        let mut msg = Message::contact(contact_id, [0u8; 24], Vec::new(), 0, key_id);
+        let counter = self.seal_next(&mut msg, plaintext)?;
+
+        let result = self.connection.send_message(&msg).await;
+        self.report(result)?;
+        self.note_sent();
+        self.telemetry.on_send(counter, plaintext.len());
+
+        Ok(())
+    }
+
+    /// Rotate a contact's sending chain and announce it, like `rotate_keys`
+    pub async fn rotate_contact_keys(&mut self, contact_id: u64) -> Result<(), NetworkError> {
+        self.require_established("rotate keys")?;
+
+        let epoch = self.contact_ratchet(contact_id)?.rotate_send()
+            .map_err(|e| NetworkError::ConnectionError(format!("Key rotation failed: {}", e)))?;
+        self.connection.send_message(&Message::contact_key_rotation(contact_id, epoch)).await
+    }
+
+    fn contact_ratchet(&mut self, contact_id: u64) -> Result<&mut RatchetState, NetworkError> {
+        self.contacts.get_mut(&contact_id)
+            .ok_or_else(|| NetworkError::ProtocolError(format!("Unknown contact {}", contact_id)))
+    }
+
+    /// The session's ratchet, or the contact's one for `ContactData`
+    fn ratchet_for(&mut self, payload: &MessagePayload) -> Result<&mut RatchetState, NetworkError> {
+        match payload {
+            MessagePayload::ContactData { contact_id, .. } => self.contact_ratchet(*contact_id),
+            _ => Ok(&mut self.ratchet),
+        }
+    }
+
+    /// Encrypt into `msg`'s `EncryptedData` under the next sending key, returning its counter
+    ///
+    /// The rest of the header must be final, since the AAD schema may bind it.
+    fn seal_next(&mut self, msg: &mut Message, plaintext: &[u8]) -> Result<u64, NetworkError> {
+        // Get next sending key and counter
+        let (message_key, counter) = self.ratchet_for(&msg.payload)?.next_send_key()
+            .map_err(|e| NetworkError::ConnectionError(format!("Key rotation failed: {}", e)))?;
+
+        let Some((_, _, message_counter)) = sealed_fields(&mut msg.payload) else {
+            return Err(NetworkError::ProtocolError("Invalid encrypted message payload".to_string()));
+        };
+        *message_counter = counter;
+        let aad = msg.associated_data(self.suite.aad_schema);
+
+        // Encrypt the message
+        let encrypted = crate::crypto::symmetric::encrypt(&message_key, plaintext, &aad)
+            .map_err(|e| NetworkError::ConnectionError(format!("Encryption failed: {}", e)))?;
+        if let Some(log) = self.audit_log.as_mut() {
+            if let Err(e) = log.check_key_access("message key", &message_key.access_report(), MESSAGE_KEY_ACCESSES) {
+                tracing::warn!("Failed to write audit entry: {}", e);
+            }
+        }
+
+        self.check_nonce_fresh(&encrypted.nonce)?;
+        #[cfg(debug_assertions)]
+        self.log_key("AEGIS_SEND_KEY", msg.key_id, counter, &message_key);
+
+        if let Some((nonce, ciphertext, _)) = sealed_fields(&mut msg.payload) {
+            *nonce = encrypted.nonce;
+            *ciphertext = encrypted.ciphertext;
+        }
+        Ok(counter)
+    }
+
+    /// Refuse to send under a nonce this session has already used
+    fn check_nonce_fresh(&mut self, nonce: &[u8; 24]) -> Result<(), NetworkError> {
+        let Some(sent) = self.sent_nonces.as_mut() else {
+            return Ok(());
+        };
+        if crate::crypto::symmetric::null_cipher_active() {
+            return Ok(());
+        }
+
+        let fresh = sent.insert(*nonce);
+        debug_assert!(fresh, "AEAD nonce reused within the session");
+        if !fresh {
+            return Err(NetworkError::ConnectionError("AEAD nonce reused within the session".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Algorithms agreed with the peer
+    pub fn suite(&self) -> NegotiatedSuite {
+        self.suite
+    }
+
+    /// Tell the peer our display name and capabilities
+    ///
+    /// Meant to be sent once, right after the session is established and
+    /// before any chat messages. It is encrypted like application data.
+    /// Fails if the peer didn't negotiate `PeerInfo`.
+    pub async fn send_peer_info(&mut self, info: &PeerInfo) -> Result<(), NetworkError> {
+        self.require_established("send peer info")?;
+        self.require_feature(WireVersion::FEATURE_PEER_INFO, "peer info")?;
+        info.validate()?;
+
+        let encoded = bincode::serialize(info)
+            .map_err(|e| NetworkError::SerializationError(format!("Serialization failed: {}", e)))?;
+        let mut msg = Message::peer_info([0u8; 24], Vec::new(), 0, self.ratchet.send_epoch());
+        self.seal_next(&mut msg, &encoded)?;
+        let result = self.connection.send_message(&msg).await;
+        self.report(result)?;
+        self.note_sent();
+        Ok(())
+    }
+
+    /// What the peer told us about itself, once its `PeerInfo` has arrived
+    pub fn peer_info(&self) -> Option<&PeerInfo> {
+        self.peer_info.as_ref()
+    }
+
+    /// Peer's display name if known, otherwise its address
+    pub fn peer_label(&self) -> String {
+        match &self.peer_info {
+            Some(info) => info.display_name.clone(),
+            None => self.peer_addr.to_string(),
+        }
+    }
+
+    /// Install a telemetry hook, replacing the default no-op
+    ///
+    /// The handshake has already completed by the time a session exists, so
+    /// the hook's `on_handshake_complete` is called immediately.
+    pub fn telemetry_hook(&mut self, hook: Arc<dyn SessionTelemetry>) {
+        hook.on_handshake_complete(self.role);
+        self.telemetry = hook;
+    }
+
+    /// Pass a result through, telling the telemetry hook about errors
+    fn report<T>(&self, result: Result<T, NetworkError>) -> Result<T, NetworkError> {
+        if let Err(e) = &result {
+            self.telemetry.on_error(e);
+        }
+        self.publish_stats();
+        result
+    }
+
+    /// Periodic `SessionStats` snapshots, one per `interval` (the first immediately)
+    ///
+    /// The stream doesn't borrow the session: snapshots are refreshed as
+    /// messages are sent and received and rotations happen, while at least
+    /// one stream is alive, and each tick yields the latest one. Ends only
+    /// when dropped.
+    pub fn statistics_interval(&self, interval: Duration) -> impl Stream<Item = SessionStats> + Send + 'static {
+        let published = Arc::clone(&self.published_stats);
+        self.publish_stats();
+        IntervalStream::new(tokio::time::interval(interval))
+            .map(move |_| *published.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Current counters, as `statistics_interval` reports them
+    pub fn statistics(&self) -> SessionStats {
+        let traffic = self.connection.stats();
+        let frames = traffic.messages_sent + traffic.messages_received;
+        let attempts = frames + traffic.errors as u64;
+        let health_score = if attempts == 0 { 1.0 } else { frames as f64 / attempts as f64 };
+
+        SessionStats {
+            bytes_sent: traffic.bytes_sent,
+            bytes_received: traffic.bytes_received,
+            messages_sent: traffic.messages_sent,
+            messages_received: traffic.messages_received,
+            key_rotations: self.key_rotations,
+            rtt_p50_us: self.rtt_histogram.value_at_quantile(0.5),
+            rtt_p99_us: self.rtt_histogram.value_at_quantile(0.99),
+            epoch: self.ratchet.epoch(),
+            health_score,
+        }
+    }
+
+    /// Refresh the snapshot `statistics_interval` streams read; skipped when none is alive
+    fn publish_stats(&self) {
+        if Arc::strong_count(&self.published_stats) == 1 {
+            return;
+        }
+        *self.published_stats.lock().unwrap_or_else(|e| e.into_inner()) = self.statistics();
+    }
+
+    /// Receive and decrypt a message
+    ///
+    /// Heartbeats are reported as an empty vector; use `recv_event` to tell
+    /// them apart from empty application messages, or `recv_data` to skip
+    /// both. Out-of-band data and contact messages are skipped; only
+    /// `recv_event` returns them. Tracked messages come back without their
+    /// `DeliveryToken`, so they are never acked; see `recv_with_token`.
+    pub async fn recv(&mut self) -> Result<Vec<u8>, NetworkError> {
+        loop {
+            match self.recv_event().await? {
+                ReceivedEvent::Message(data)
+                | ReceivedEvent::EphemeralMessage { data, .. }
+                | ReceivedEvent::TrackedMessage { data, .. } => return Ok(data),
+                ReceivedEvent::Heartbeat => return Ok(Vec::new()),
+                ReceivedEvent::Disconnected => return Err(dead_peer_error()),
+                ReceivedEvent::OobData(..)
+                | ReceivedEvent::ClockDriftWarning { .. }
+                | ReceivedEvent::ContactMessage { .. }
+                | ReceivedEvent::Delivered { .. }
+                | ReceivedEvent::PeerInfo(..) => continue,
+            }
+        }
+    }
+
+    /// Receive and decrypt the next non-empty application message
+    ///
+    /// Like `recv`, but heartbeats are answered and skipped, as are empty
+    /// messages, so callers need no `is_empty()` check.
+    pub async fn recv_data(&mut self) -> Result<Vec<u8>, NetworkError> {
+        loop {
+            match self.recv_event().await? {
+                ReceivedEvent::Message(data)
+                | ReceivedEvent::EphemeralMessage { data, .. }
+                | ReceivedEvent::TrackedMessage { data, .. } if !data.is_empty() => {
+                    return Ok(data)
+                }
+                ReceivedEvent::Disconnected => return Err(dead_peer_error()),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Like `recv_data`, with the `DeliveryToken` of messages sent with `send_tracked`
+    pub async fn recv_with_token(&mut self) -> Result<(Vec<u8>, Option<DeliveryToken>), NetworkError> {
+        loop {
+            match self.recv_event().await? {
+                ReceivedEvent::TrackedMessage { data, token } => return Ok((data, Some(token))),
+                ReceivedEvent::Message(data) | ReceivedEvent::EphemeralMessage { data, .. } if !data.is_empty() => {
+                    return Ok((data, None))
+                }
+                ReceivedEvent::Disconnected => return Err(dead_peer_error()),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Receive the next message from a relayed contact, with its contact ID
+    ///
+    /// Skips everything else, including the session's own messages; use
+    /// `recv_event` when both kinds share the connection.
+    pub async fn recv_from(&mut self) -> Result<(u64, Vec<u8>), NetworkError> {
+        loop {
+            match self.recv_event().await? {
+                ReceivedEvent::ContactMessage { contact_id, data } => return Ok((contact_id, data)),
+                ReceivedEvent::Disconnected => return Err(dead_peer_error()),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Receive and decrypt a message into a buffer that is zeroized on drop
+    ///
+    /// Heartbeats are reported as empty, as with `recv`.
+    pub async fn recv_secret(&mut self) -> Result<SecretBytes, NetworkError> {
+        self.recv().await.map(SecretBytes::new)
+    }
+
+    /// Choose whether extension messages this version doesn't handle are skipped or fatal
+    pub fn set_unknown_message_policy(&mut self, policy: UnknownMessagePolicy) {
+        self.unknown_message_policy = policy;
+    }
+
+    /// Wipe ciphertext and nonce buffers after decryption (hardened mode)
+    pub fn set_zeroize_ciphertext(&mut self, enabled: bool) {
+        self.zeroize_ciphertext = enabled;
+    }
+
+    /// Track every sent nonce and refuse to reuse one (on by default in debug
+    /// builds)
+    ///
+    /// Nonces are random and each message has its own key, so a repeat means
+    /// the RNG or key schedule is broken. The set grows by 24 bytes per
+    /// message for the life of the session.
+    pub fn set_nonce_reuse_detection(&mut self, enabled: bool) {
+        if enabled != self.sent_nonces.is_some() {
+            self.sent_nonces = enabled.then(HashSet::new);
+        }
+    }
+
+    /// Append the root key and every later message key to `path`, so a
+    /// capture of this session can be decrypted offline (debug builds only)
+    ///
+    /// One line per key, SSLKEYLOGFILE-style: `LABEL <flow> [<epoch> <counter>] <hex key>`,
+    /// where the flow is `<local addr>-<peer addr>`. Anyone with the file can
+    /// read the traffic. Not compiled into release builds.
+    #[cfg(debug_assertions)]
+    pub fn dump_keys(&mut self, path: impl AsRef<std::path::Path>) -> Result<(), NetworkError> {
+        use std::io::Write;
+
+        let path = path.as_ref();
+        tracing::warn!(
+            "!!! SESSION KEY LOGGING ENABLED: writing keys for {} to {} - this traffic is NOT confidential !!!",
+            self.peer_addr,
+            path.display()
+        );
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let local = self.connection.local_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "-".to_string());
+        let flow = format!("{}-{}", local, self.peer_addr);
+        writeln!(file, "AEGIS_ROOT_KEY {} {}", flow, hex::encode(self.ratchet.root_key()))?;
+        self.key_log = Some(KeyLog { file, flow });
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    fn log_key(&mut self, label: &str, epoch: u16, counter: u64, key: &crate::crypto::symmetric::SymmetricKey) {
+        use std::io::Write;
+
+        if let Some(log) = self.key_log.as_mut() {
+            let line = format!("{} {} {} {} {}", label, log.flow, epoch, counter, hex::encode(key.as_bytes()));
+            if let Err(e) = writeln!(log.file, "{}", line) {
+                tracing::warn!("Failed to write key log: {}", e);
+            }
+        }
+    }
+
+    /// Set the seed shared out of band with the peer (`None` disables `otp_rotate`)
+    pub fn set_oob_seed(&mut self, seed: Option<Vec<u8>>) {
+        self.oob_seed = seed.map(Zeroizing::new);
+    }
+
+    /// Whether an out-of-band seed is set
+    pub fn has_oob_seed(&self) -> bool {
+        self.oob_seed.is_some()
+    }
+
+    /// Receive the next event from the peer
+    pub async fn recv_event(&mut self) -> Result<ReceivedEvent, NetworkError> {
+        self.require_established("receive")?;
+
+        if let Some(event) = self.pending_events.pop_front() {
+            return Ok(event);
+        }
+
+        loop {
+            // Receive message
+            let msg = match self.recv_or_probe().await {
+                Ok(Some(msg)) => Ok(msg),
+                Ok(None) => return Ok(self.declare_peer_dead()),
+                Err(e) => Err(e),
+            };
+            let msg = self.report(msg)?;
+
+            if let Some(event) = self.handle_message(msg).await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Receive up to `max` application messages in one call
+    ///
+    /// Waits for the first message, then takes whatever is already buffered
+    /// without waiting again. Heartbeats and control messages are handled
+    /// but not counted toward `max`.
+    pub async fn recv_many(&mut self, max: usize) -> Vec<Result<Vec<u8>, NetworkError>> {
+        let mut results = Vec::new();
+        if max == 0 {
+            return results;
+        }
+
+        loop {
+            match self.recv_event().await {
+                Ok(
+                    ReceivedEvent::Message(data)
+                    | ReceivedEvent::EphemeralMessage { data, .. }
+                    | ReceivedEvent::TrackedMessage { data, .. },
+                ) => {
+                    results.push(Ok(data));
+                    break;
+                }
+                Ok(
+                    ReceivedEvent::Heartbeat
+                    | ReceivedEvent::OobData(..)
+                    | ReceivedEvent::ClockDriftWarning { .. }
+                    | ReceivedEvent::ContactMessage { .. }
+                    | ReceivedEvent::Delivered { .. }
+                    | ReceivedEvent::PeerInfo(..),
+                ) => continue,
+                Ok(ReceivedEvent::Disconnected) => {
+                    results.push(Err(dead_peer_error()));
+                    return results;
+                }
+                Err(e) => {
+                    results.push(Err(e));
+                    return results;
+                }
+            }
+        }
+
+        while results.len() < max && self.state == SessionState::Established {
+            let msg = match self.connection.try_read_message() {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(e) => {
+                    results.push(Err(e));
+                    break;
+                }
+            };
+
+            match self.handle_message(msg).await {
+                Ok(Some(
+                    ReceivedEvent::Message(data)
+                    | ReceivedEvent::EphemeralMessage { data, .. }
+                    | ReceivedEvent::TrackedMessage { data, .. },
+                )) => results.push(Ok(data)),
+                Ok(_) => {}
+                Err(e) => results.push(Err(e)),
+            }
+        }
+
+        results
+    }
+
+    /// Process one incoming message; `None` means it was consumed internally
+    async fn handle_message(&mut self, msg: Message) -> Result<Option<ReceivedEvent>, NetworkError> {
+        let size = msg.serialized_size().unwrap_or(0);
+        self.recv_stats.record(msg.message_type, size);
+        self.telemetry.on_frame_received(msg.message_type, size);
+
+        let result = self.process_message(msg).await;
+        self.report(result)
+    }
+
+    /// Decrypt an `EncryptedData` message with the matching receiving key
+    fn decrypt_payload(&mut self, msg: Message) -> Result<(Vec<u8>, u64), NetworkError> {
+        let aad = msg.associated_data(self.suite.aad_schema);
+
+        // Get receiving key
+        let ratchet = self.ratchet_for(&msg.payload)?;
+
+        // Extract encrypted data
+        let (nonce, ciphertext, counter) = match msg.payload {
+            MessagePayload::EncryptedData { nonce, ciphertext, message_counter }
+            | MessagePayload::EphemeralData { nonce, ciphertext, message_counter, .. }
+            | MessagePayload::ContactData { nonce, ciphertext, message_counter, .. }
+            | MessagePayload::TrackedData { nonce, ciphertext, message_counter }
+            | MessagePayload::RotationInterval { nonce, ciphertext, message_counter } => {
+                (nonce, ciphertext, message_counter)
+            }
+            _ => return Err(NetworkError::ProtocolError("Invalid encrypted message payload".to_string())),
+        };
+
+        let message_key = ratchet.get_recv_key(counter)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key retrieval failed: {}", e)))?;
+
+        // Decrypt
+        // Frames carry no cipher tag; sessions always use XChaCha20-Poly1305
+        let mut encrypted_msg = crate::crypto::symmetric::EncryptedMessage {
+            nonce,
+            ciphertext,
+            aead: crate::crypto::symmetric::AEAD::XChaCha20Poly1305,
+        };
+
+        let plaintext = crate::crypto::symmetric::decrypt(&message_key, &encrypted_msg, &aad);
+        #[cfg(debug_assertions)]
+        self.log_key("AEGIS_RECV_KEY", msg.key_id, counter, &message_key);
+
+        if self.zeroize_ciphertext {
+            encrypted_msg.ciphertext.zeroize();
+            encrypted_msg.nonce.zeroize();
+        }
+
+        let plaintext = plaintext
+            .map_err(|e| NetworkError::ConnectionError(format!("Decryption failed: {}", e)))?;
+        Ok((plaintext, counter))
+    }
+
+    async fn process_message(&mut self, msg: Message) -> Result<Option<ReceivedEvent>, NetworkError> {
+        self.last_frame_at = Instant::now();
+        self.unanswered_since = None;
+
+        if !self.charge_quota() {
+            self.connection.send_message(&Message::rate_limited()).await?;
+            return Err(NetworkError::RateLimited);
+        }
+
+        // Validate
+        let now = (self.clock)();
+        msg.validate_at(now, self.clock_offset)?;
+        if let Some(feature) = msg.required_feature() {
+            self.require_feature(feature, &format!("{:?} frames", msg.message_type))?;
+        }
+
+        // Handle different message types
+        match msg.message_type {
+            MessageType::EncryptedMessage => {
+                let kind = match &msg.payload {
+                    MessagePayload::EphemeralData { ttl_secs, .. } => SealedKind::Ephemeral(*ttl_secs),
+                    MessagePayload::ContactData { contact_id, .. } => SealedKind::Contact(*contact_id),
+                    MessagePayload::TrackedData { .. } => SealedKind::Tracked(msg.replay_safe_id()),
+                    _ => SealedKind::Plain,
+                };
+                let timestamp = msg.timestamp;
+                let (plaintext, counter) = self.decrypt_payload(msg)?;
+                self.telemetry.on_recv(counter, plaintext.len());
+
+                // Re-measure the peer's clock only from timestamps the AEAD
+                // just authenticated, so a drifting clock is tracked and an
+                // attacker can't skew the window
+                if self.suite.aad_schema.covers_timestamp() {
+                    self.note_clock_offset(clock_offset_at(timestamp, now));
+                }
+
+                Ok(Some(match kind {
+                    SealedKind::Plain => ReceivedEvent::Message(plaintext),
+                    SealedKind::Ephemeral(ttl_secs) => ReceivedEvent::EphemeralMessage {
+                        data: plaintext,
+                        expires_at: now.saturating_add(ttl_secs as u64),
+                    },
+                    SealedKind::Contact(contact_id) => ReceivedEvent::ContactMessage { contact_id, data: plaintext },
+                    SealedKind::Tracked(message_id) => ReceivedEvent::TrackedMessage {
+                        data: plaintext,
+                        token: DeliveryToken { message_id },
+                    },
+                }))
+            }
+            MessageType::PeerInfo => {
+                let (plaintext, _) = self.decrypt_payload(msg)?;
+                let info: PeerInfo = bincode::deserialize(&plaintext)
+                    .map_err(|e| NetworkError::SerializationError(format!("Invalid peer info: {}", e)))?;
+                info.validate()?;
+                self.peer_info = Some(info.clone());
+                Ok(Some(ReceivedEvent::PeerInfo(info)))
+            }
+            MessageType::Heartbeat => {
+                // Respond to heartbeat; peers without `HeartbeatResponse`
+                // echo heartbeats instead, so theirs may be the answer to
+                // ours, which goes untimed and unechoed to end the volley
+                if self.suite.supports(WireVersion::FEATURE_HEARTBEAT_RESPONSE) {
+                    self.connection.send_message(&Message::heartbeat_response()).await?;
+                } else if self.heartbeat_sent_at.take().is_some() {
+                    return Ok(None);
+                } else {
+                    self.connection.send_message(&Message::heartbeat()).await?;
+                }
+                Ok(Some(ReceivedEvent::Heartbeat))
+            }
+            MessageType::HeartbeatResponse => {
+                if let Some(sent_at) = self.heartbeat_sent_at.take() {
+                    let rtt_us = sent_at.elapsed().as_micros().min(MAX_TRACKED_RTT_US as u128) as u64;
+                    self.rtt_histogram.saturating_record(rtt_us);
+                }
+                Ok(None)
+            }
+            MessageType::Disconnect => {
+                let reason = match msg.payload {
+                    MessagePayload::Disconnect { reason } => reason,
+                    // We never asked; nothing to do
+                    _ => return Ok(None),
+                };
+                self.state = SessionState::Closed;
+                self.audit(AuditEvent::Disconnected { initiated_locally: false, reason: reason.clone() });
+
+                // Let the peer close cleanly; it falls back to a hard close if this is lost
+                if self.suite.supports(WireVersion::FEATURE_DISCONNECT_ACK) {
+                    let _ = self.connection.send_message(&Message::disconnect_ack()).await;
+                }
+                let _ = self.connection.flush().await;
+                Err(NetworkError::PeerClosed { reason })
+            }
+            MessageType::OobData => {
+                let mac_input = msg.oob_mac_input();
+                let MessagePayload::OobData { channel, counter, data, mac } = msg.payload else {
+                    return Err(NetworkError::ProtocolError("Invalid out-of-band payload".to_string()));
+                };
+                if !verify_mac(&*self.oob_key(self.role.peer(), channel)?, &mac_input, &mac) {
+                    return Err(NetworkError::ProtocolError("Out-of-band data failed authentication".to_string()));
+                }
+                let last = self.oob_received.entry(channel).or_insert(0);
+                if counter <= *last {
+                    return Err(NetworkError::ProtocolError(format!("Replayed out-of-band data on channel {}", channel)));
+                }
+                *last = counter;
+                Ok(Some(ReceivedEvent::OobData(channel, data)))
+            }
+            MessageType::TlsUpgrade => {
+                // Confirm, then switch; the proposer waits for this confirmation
+                // before starting the TLS handshake
+                self.connection.send_message(&Message::tls_upgrade()).await?;
+                self.connection.upgrade_to_tls_server().await?;
+                Ok(None)
+            }
+            MessageType::KeyRotation if matches!(msg.payload, MessagePayload::RotationInterval { .. }) => {
+                let (plaintext, _) = self.decrypt_payload(msg)?;
+                let seconds = <[u8; 8]>::try_from(plaintext.as_slice())
+                    .map(u64::from_be_bytes)
+                    .map_err(|_| NetworkError::ProtocolError("Invalid rotation interval".to_string()))?;
+                self.apply_rotation_interval(seconds).await?;
+                Ok(None)
+            }
+            MessageType::KeyRotation => {
+                match msg.payload {
+                    MessagePayload::KeyRotation { new_key_id } => {
+                        self.ratchet.rotate_recv(new_key_id)
+                            .map_err(|e| NetworkError::ProtocolError(format!("Unexpected key rotation: {}", e)))?;
+                    }
+                    MessagePayload::ContactKeyRotation { contact_id, new_key_id } => {
+                        self.contact_ratchet(contact_id)?.rotate_recv(new_key_id)
+                            .map_err(|e| NetworkError::ProtocolError(format!("Unexpected key rotation: {}", e)))?;
+                    }
+                    MessagePayload::RekeyOffer { public_key } => self.answer_rekey_offer(public_key).await?,
+                    MessagePayload::RekeyResponse { new_key_id, ciphertext } => {
+                        self.finish_rekey(new_key_id, ciphertext).await?
+                    }
+                    MessagePayload::RekeyCommit { new_key_id } => {
+                        let secret = self.pending_rekey_commit.take()
+                            .ok_or_else(|| NetworkError::ProtocolError("Rekey commit without an offer".to_string()))?;
+                        self.ratchet.rotate_recv_with_secret(new_key_id, &secret)
+                            .map_err(|e| NetworkError::ProtocolError(format!("Unexpected key rotation: {}", e)))?;
+                        self.pfs_confirmed = true;
+                    }
+                    MessagePayload::OtpRotation { otp_commitment } => {
+                        self.follow_otp_rotation(msg.key_id, &otp_commitment)?
+                    }
+                    _ => return Err(NetworkError::ProtocolError("Invalid key rotation payload".to_string())),
+                }
+                Ok(None)
+            }
+            MessageType::Probe => {
+                if let MessagePayload::Probe { payload } = &msg.payload {
+                    self.connection.send_message(&Message::probe_response(payload.len())).await?;
+                }
+                Ok(None)
+            }
+            MessageType::ProbeResponse => {
+                // Late reply to a probe that already timed out
+                Ok(None)
+            }
+            MessageType::Ack => match msg.payload {
+                MessagePayload::DeliveryAck { message_id, mac } => {
+                    let key = self.delivery_ack_key(self.role.peer())?;
+                    if !verify_mac(&key, &Message::delivery_ack_mac_input(message_id), &mac) {
+                        return Err(NetworkError::ProtocolError("Delivery ack failed authentication".to_string()));
+                    }
+                    // Acks for messages already reported overdue are dropped
+                    if self.awaiting_ack.remove(&message_id).is_some() {
+                        Ok(Some(ReceivedEvent::Delivered { message_id }))
+                    } else {
+                        Ok(None)
+                    }
+                }
+                // Unauthenticated acks never count as a delivery
+                _ => Ok(None),
+            },
+            MessageType::Error => match msg.payload {
+                MessagePayload::Error { code: ERROR_RATE_LIMITED, .. } => Err(NetworkError::RateLimited),
+                MessagePayload::Error { code, message } => {
+                    Err(NetworkError::PeerError(format!("Peer reported error {}: {}", code, message)))
+                }
+                _ => Err(NetworkError::ProtocolError("Invalid error payload".to_string())),
+            },
+            MessageType::Extension => {
+                let extension_type = match msg.payload {
+                    MessagePayload::Extension { extension_type, .. } => extension_type,
+                    _ => return Err(NetworkError::ProtocolError("Invalid extension payload".to_string())),
+                };
+                match self.unknown_message_policy {
+                    UnknownMessagePolicy::Ignore => {
+                        tracing::debug!("Ignoring unknown extension message {:#04x}", extension_type);
+                        Ok(None)
+                    }
+                    UnknownMessagePolicy::Error => Err(NetworkError::ProtocolError(
+                        format!("Unknown extension message type {:#04x}", extension_type)
+                    )),
+                }
+            }
+            _ => {
+                Err(NetworkError::ProtocolError(format!("Unexpected message type: {:?}", msg.message_type)))
+            }
+        }
+    }
+
+    /// Cap how many bytes the peer may send per window (`None` removes the cap)
+    ///
+    /// Messages received over quota are dropped: the peer gets a rate-limited
+    /// error frame and the receive call fails with `RateLimited` until the
+    /// window resets.
+    pub fn set_quota(&mut self, quota: Option<Quota>) {
+        self.quota = quota.map(QuotaTracker::new);
+        self.quota_charged_bytes = self.connection.stats().bytes_received;
+    }
+
+    /// Bytes received against the quota in the current window
+    pub fn quota_usage(&self) -> Option<QuotaUsage> {
+        self.quota.as_ref().map(QuotaTracker::usage)
+    }
+
+    /// Charge everything received since the last call; false if over quota
+    fn charge_quota(&mut self) -> bool {
+        let Some(quota) = &mut self.quota else {
+            return true;
+        };
+
+        let received = self.connection.stats().bytes_received;
+        let bytes = received - self.quota_charged_bytes;
+        self.quota_charged_bytes = received;
+        quota.record(bytes)
+    }
+
+    /// Send side-channel data that bypasses the ratchet
+    ///
+    /// Authenticated with an HMAC under a key derived from the root key for
+    /// this channel and direction, but not encrypted. It carries its own
+    /// per-channel counter rather than the ratchet's, so it can't
+    /// desynchronize the data channel (e.g. during a key rotation); the peer
+    /// rejects any counter it has already seen. The peer sees it as
+    /// `ReceivedEvent::OobData` from `recv_event`.
+    pub async fn send_oob(&mut self, data: &[u8], channel: u8) -> Result<(), NetworkError> {
+        self.require_established("send out-of-band data")?;
+        self.require_feature(WireVersion::FEATURE_OOB_DATA, "out-of-band data")?;
+
+        let counter = self.oob_sent.get(&channel).copied().unwrap_or(0).checked_add(1)
+            .ok_or_else(|| NetworkError::ProtocolError(format!("Out-of-band channel {} exhausted", channel)))?;
+        let mut msg = Message::oob_data(channel, counter, data.to_vec(), [0u8; 32]);
+        let mac = ratchet_key_hmac(&*self.oob_key(self.role, channel)?, &msg.oob_mac_input())
+            .map_err(|e| NetworkError::ConnectionError(format!("MAC failed: {}", e)))?;
+        if let MessagePayload::OobData { mac: slot, .. } = &mut msg.payload {
+            *slot = mac;
+        }
+        self.oob_sent.insert(channel, counter);
+
+        let result = self.connection.send_message(&msg).await;
+        self.report(result)
+    }
+
+    /// Subkey for acks sent by `sender`, so an ack can't be reflected back
+    fn delivery_ack_key(&self, sender: SessionRole) -> Result<Zeroizing<[u8; 32]>, NetworkError> {
+        let label = match sender {
+            SessionRole::Initiator => b"delivery-ack-initiator".as_slice(),
+            SessionRole::Responder => b"delivery-ack-responder".as_slice(),
+        };
+        self.ratchet.derive_subkey(label)
+            .map(Zeroizing::new)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))
+    }
+
+    /// Subkey for out-of-band data `sender` sends on `channel`, so a frame can't be reflected back
+    fn oob_key(&self, sender: SessionRole, channel: u8) -> Result<Zeroizing<[u8; 32]>, NetworkError> {
+        let direction = match sender {
+            SessionRole::Initiator => "initiator",
+            SessionRole::Responder => "responder",
+        };
+        self.ratchet.derive_subkey(format!("oob-{}-channel-{}", direction, channel).as_bytes())
+            .map(Zeroizing::new)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))
+    }
+
+    /// Send a heartbeat; its reply is recorded in the RTT histogram
+    pub async fn send_heartbeat(&mut self) -> Result<(), NetworkError> {
+        self.require_established("send heartbeat")?;
+
+        let msg = Message::heartbeat();
+        self.connection.send_message(&msg).await?;
+        self.heartbeat_sent_at = Some(Instant::now());
+        self.note_sent();
+        Ok(())
+    }
+
+    /// Detect a silently vanished peer (e.g. a host that lost power)
+    ///
+    /// With a timeout set, `recv_event` sends a heartbeat after half the
+    /// timeout without hearing from the peer, and returns
+    /// `ReceivedEvent::Disconnected` once a full timeout passes after an
+    /// unanswered send. Any incoming frame counts as an answer. Disabled
+    /// (`None`) by default, leaving detection to TCP.
+    pub fn set_dead_peer_timeout(&mut self, timeout: Option<Duration>) {
+        self.dead_peer_timeout = timeout;
+    }
+
+    /// Start the dead-peer clock if nothing is already awaiting an answer
+    fn note_sent(&mut self) {
+        self.unanswered_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Wait for the next frame, probing a quiet peer with heartbeats
+    ///
+    /// Returns `None` once the peer has been silent for the dead-peer
+    /// timeout after an unanswered send.
+    async fn recv_or_probe(&mut self) -> Result<Option<Message>, NetworkError> {
+        loop {
+            let Some(wake_at) = self.liveness_deadline() else {
+                return self.connection.recv_message().await.map(Some);
+            };
+
+            let deadline = tokio::time::Instant::from_std(wake_at);
+            match tokio::time::timeout_at(deadline, self.connection.recv_message()).await {
+                Ok(msg) => return msg.map(Some),
+                Err(_) if self.peer_overdue() => return Ok(None),
+                Err(_) => self.send_heartbeat().await?,
+            }
+        }
+    }
+
+    /// When the dead-peer check next has to act: probe a quiet peer, or
+    /// give up on one that never answered (`None` without a timeout)
+    fn liveness_deadline(&self) -> Option<Instant> {
+        let limit = self.dead_peer_timeout?;
+        let dead_at = self.unanswered_since.map(|sent| sent + limit);
+        let probe_at = match self.heartbeat_sent_at {
+            None => Some(self.last_frame_at + limit / 2),
+            Some(_) => None,
+        };
+        dead_at.into_iter().chain(probe_at).min()
+    }
+
+    /// Whether a send has gone unanswered for the whole dead-peer timeout
+    fn peer_overdue(&self) -> bool {
+        self.dead_peer_timeout
+            .zip(self.unanswered_since)
+            .is_some_and(|(limit, sent)| sent + limit <= Instant::now())
+    }
+
+    /// Wait until `next_buffered_event` has a frame or queued event to look at
+    ///
+    /// Unlike `recv_event`, which can lose a message it already decrypted if
+    /// dropped part-way, this only buffers bytes, so it may race other
+    /// futures in a `select!`.
+    async fn readable(&mut self) -> Result<(), NetworkError> {
+        if self.pending_events.is_empty() {
+            let result = self.connection.wait_for_frame().await;
+            self.report(result)?;
+        }
+        Ok(())
+    }
+
+    /// Handle already-buffered frames until one yields an event, never
+    /// waiting on the network; `None` once nothing is left
+    async fn next_buffered_event(&mut self) -> Result<Option<ReceivedEvent>, NetworkError> {
+        self.require_established("receive")?;
+
+        if let Some(event) = self.pending_events.pop_front() {
+            return Ok(Some(event));
+        }
+
+        loop {
+            let msg = self.connection.try_read_message();
+            let Some(msg) = self.report(msg)? else {
+                return Ok(None);
+            };
+            if let Some(event) = self.handle_message(msg).await? {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    /// Close the session after the dead-peer timeout expired
+    fn declare_peer_dead(&mut self) -> ReceivedEvent {
+        self.state = SessionState::Closed;
+        self.audit(AuditEvent::Disconnected {
+            initiated_locally: true,
+            reason: Some("Dead peer timeout".to_string()),
+        });
+        ReceivedEvent::Disconnected
+    }
+
+    /// Send a heartbeat and wait for the reply, returning the round-trip time
+    ///
+    /// Application messages that arrive meanwhile are kept for `recv`.
+    pub async fn ping(&mut self) -> Result<Duration, NetworkError> {
+        self.require_established("ping")?;
+
+        self.send_heartbeat().await?;
+        let sent_at = Instant::now();
+
+        let wait = async {
+            while self.heartbeat_sent_at.is_some() {
+                let msg = self.connection.recv_message().await?;
+                if let Some(event) = self.handle_message(msg).await? {
+                    self.pending_events.push_back(event);
+                }
+            }
+            Ok::<_, NetworkError>(())
+        };
+
+        timeout(HANDSHAKE_TIMEOUT, wait).await.map_err(|_| NetworkError::Timeout)??;
+        Ok(sent_at.elapsed())
+    }
+
+    /// Heartbeat round-trip times in microseconds
+    pub fn ping_rtt_histogram(&self) -> &Histogram<u64> {
+        &self.rtt_histogram
+    }
+
+    /// Mean heartbeat RTT in microseconds
+    pub fn mean_rtt_us(&self) -> f64 {
+        self.rtt_histogram.mean()
+    }
+
+    /// 99th percentile heartbeat RTT in microseconds
+    pub fn p99_rtt_us(&self) -> u64 {
+        self.rtt_histogram.value_at_quantile(0.99)
+    }
+
+    /// Largest heartbeat RTT in microseconds
+    pub fn max_rtt_us(&self) -> u64 {
+        self.rtt_histogram.max()
+    }
+
+    /// Close the session
+    ///
+    /// Sends a disconnect and waits up to the close timeout for the peer to
+    /// acknowledge it, which it does from `recv` (its `recv` then fails with
+    /// `NetworkError::PeerClosed`). Frames arriving meanwhile are dropped.
+    /// The connection is shut down however that ends; only a session that
+    /// was not established can return an error.
+    pub async fn close(mut self) -> Result<CloseOutcome, NetworkError> {
+        if self.state != SessionState::Established {
+            self.state = SessionState::Closed;
+            self.connection.close().await?;
+            return Ok(CloseOutcome::NotEstablished);
+        }
+
+        self.state = SessionState::Closing;
+
+        let reason = "User requested disconnect".to_string();
+        self.audit(AuditEvent::Disconnected { initiated_locally: true, reason: Some(reason.clone()) });
+
+        let disconnect_msg = Message::disconnect(Some(reason));
+        let sent = match self.connection.send_message(&disconnect_msg).await {
+            Ok(()) => self.connection.flush().await,
+            Err(e) => Err(e),
+        };
+        let outcome = match sent {
+            Ok(()) if !self.suite.supports(WireVersion::FEATURE_DISCONNECT_ACK) => CloseOutcome::AckUnsupported,
+            Ok(()) => self.await_disconnect_ack().await,
+            Err(_) => CloseOutcome::ConnectionLost,
+        };
+
+        self.state = SessionState::Closed;
+        // The peer may hang up as soon as it has acknowledged
+        let _ = self.connection.close().await;
+        Ok(outcome)
+    }
+
+    /// How long `close` waits for the peer to acknowledge (2 seconds by default)
+    pub fn set_close_timeout(&mut self, timeout: Duration) {
+        self.close_timeout = timeout;
+    }
+
+    async fn await_disconnect_ack(&mut self) -> CloseOutcome {
+        let wait = async {
+            loop {
+                match self.connection.recv_message().await {
+                    // An ack, or the peer's own disconnect crossing ours
+                    Ok(msg) if msg.message_type == MessageType::Disconnect => return CloseOutcome::Acknowledged,
+                    Ok(_) => continue,
+                    Err(_) => return CloseOutcome::ConnectionLost,
+                }
+            }
+        };
+
+        timeout(self.close_timeout, wait).await.unwrap_or(CloseOutcome::TimedOut)
+    }
+
+    /// Current lifecycle state
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Fail unless both peers agreed `feature` at the handshake
+    ///
+    /// Older peers can't decode the frames behind a feature, so they are
+    /// never sent to a peer that didn't offer it.
+    fn require_feature(&self, feature: u32, operation: &str) -> Result<(), NetworkError> {
+        if self.suite.supports(feature) {
+            Ok(())
+        } else {
+            Err(NetworkError::ProtocolError(format!("Peer does not support {}", operation)))
+        }
+    }
+
+    /// Fail with a clear error unless the session is established
+    fn require_established(&self, operation: &str) -> Result<(), NetworkError> {
+        match self.state {
+            SessionState::Established => Ok(()),
+            state => Err(NetworkError::ConnectionError(
+                format!("Cannot {} while session is {:?}", operation, state)
+            )),
+        }
+    }
+
+    /// Get seconds until next key rotation
+    pub fn seconds_until_rotation(&self) -> u64 {
+        self.ratchet.seconds_until_rotation()
+    }
+
+    /// Rotate our sending chain, announce it to the peer, and record the
+    /// rotation in the audit log
+    ///
+    /// The peer rotates its receiving chain when the announcement arrives,
+    /// so the two sides never have to rotate simultaneously.
+    pub async fn rotate_keys(&mut self) -> Result<(), NetworkError> {
+        self.require_established("rotate keys")?;
+
+        let epoch = self.ratchet.rotate_send()
+            .map_err(|e| NetworkError::ConnectionError(format!("Key rotation failed: {}", e)))?;
+        self.connection.send_message(&Message::key_rotation(epoch)).await?;
+
+        self.record_rotation(epoch);
+        Ok(())
+    }
+
+    /// Refuse `send` with `PfsNotConfirmed` until a Kyber rekey completes
+    ///
+    /// Until then, both chains derive from the handshake alone, so anyone
+    /// who logged it and later breaks that one KEM exchange can read the
+    /// traffic. After a rekey, keys also depend on a secret that was never
+    /// used for anything else. Start one with `dh_rekey` (either side may)
+    /// and keep receiving until `pfs_confirmed` is true.
+    pub fn require_pfs_confirmed(&mut self) {
+        self.require_pfs = true;
+    }
+
+    /// Whether a Kyber rekey has completed on both our chains since the handshake
+    pub fn pfs_confirmed(&self) -> bool {
+        self.pfs_confirmed
+    }
+
+    /// What protects this session: algorithms, key bindings, and transport
+    pub fn negotiated_params(&self) -> SecurityParams {
+        SecurityParams {
+            suite: self.suite,
+            aead: AEAD::XChaCha20Poly1305,
+            post_quantum: self.is_post_quantum(),
+            pfs_confirmed: self.pfs_confirmed,
+            psk: self.psk_bound,
+            transcript_bound: self.transcript_bound,
+            tls: self.connection.is_tls(),
+        }
+    }
+
+    /// Whether the session keys rest on a Kyber exchange
+    ///
+    /// False for `connect_tls_only` sessions until a Kyber rekey completes.
+    pub fn is_post_quantum(&self) -> bool {
+        self.post_quantum || self.pfs_confirmed
+    }
+
+    /// Start a Kyber rekey that mixes a fresh shared secret into both chains
+    ///
+    /// Sends an ephemeral public key; the rest happens inside `recv` on both
+    /// sides (response, then commit), each chain switching at a point the
+    /// peer sees in order. Does nothing if our previous offer is unanswered.
+    pub async fn dh_rekey(&mut self) -> Result<(), NetworkError> {
+        self.require_established("rekey")?;
+        self.require_feature(WireVersion::FEATURE_DH_REKEY, "Kyber rekeys")?;
+        if self.pending_rekey.is_some() {
+            return Ok(());
+        }
+
+        let keypair = KeyPair::generate_with_level(self.suite.kem)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key generation failed: {}", e)))?;
+        self.connection.send_message(&Message::rekey_offer(keypair.public_key())).await?;
+        self.pending_rekey = Some(keypair);
+        Ok(())
+    }
+
+    /// Change how often keys rotate, and have the peer switch to the same interval
+    ///
+    /// Updates the ratchet's interval and the symmetric cadence of the rekey
+    /// policy, if one is set. If our sending chain is already older than the
+    /// new interval it rotates at once; the peer does the same on receipt.
+    /// Intervals are whole seconds between `MIN_ROTATION_INTERVAL` and
+    /// `MAX_ROTATION_INTERVAL`, and are sent sealed like application data.
+    pub async fn set_rotation_interval(&mut self, interval: Duration) -> Result<(), NetworkError> {
+        self.require_established("change rotation interval")?;
+        self.require_feature(WireVersion::FEATURE_ROTATION_SYNC, "rotation interval changes")?;
+        let seconds = interval.as_secs();
+        check_rotation_interval(seconds)?;
+
+        let mut msg = Message::rotation_interval([0u8; 24], Vec::new(), 0, self.ratchet.send_epoch());
+        self.seal_next(&mut msg, &seconds.to_be_bytes())?;
+        self.connection.send_message(&msg).await?;
+        self.apply_rotation_interval(seconds).await
+    }
+
+    /// Switch to a new interval, rotating now if our chain is already overdue
+    async fn apply_rotation_interval(&mut self, seconds: u64) -> Result<(), NetworkError> {
+        check_rotation_interval(seconds)?;
+        let interval = Duration::from_secs(seconds);
+        self.ratchet.set_rotation_interval(interval);
+        if let Some(schedule) = &mut self.rekey_schedule {
+            schedule.set_symmetric_every(interval);
+        }
+
+        if self.ratchet.seconds_since_rotation() >= seconds {
+            self.rotate_keys().await?;
+            if let Some(schedule) = &mut self.rekey_schedule {
+                schedule.restart_symmetric(Instant::now());
+            }
+        }
+        Ok(())
+    }
+
+    /// Rekey our sending chain from the out-of-band seed and the current minute
+    ///
+    /// Sends a commitment to (seed, minute), MACed under a session subkey for
+    /// our direction, so the peer can check it holds the same seed before
+    /// mixing HKDF(seed || timestamp) into its receiving chain. Being keyed,
+    /// the commitment gives an eavesdropper nothing to test seed guesses
+    /// against. An attacker who later recovers the session keys but never
+    /// saw the seed can't follow the rotation. Returns `false` if we already rotated
+    /// this minute, since the same minute would yield the same secret.
+    pub async fn otp_rotate(&mut self) -> Result<bool, NetworkError> {
+        self.require_established("rotate keys")?;
+        self.require_feature(WireVersion::FEATURE_ROTATION_SYNC, "seed rotations")?;
+        let seed = self.oob_seed.clone()
+            .ok_or_else(|| NetworkError::ProtocolError("No out-of-band seed set".to_string()))?;
+
+        let minute = unix_minute();
+        if self.last_otp_minute.is_some_and(|last| minute <= last) {
+            return Ok(false);
+        }
+
+        let commitment = self.otp_commitment(self.role, &seed, minute)?;
+        let secret = otp_secret(&seed, minute)?;
+        let epoch = self.ratchet.rotate_send_with_secret(&secret)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key rotation failed: {}", e)))?;
+        self.connection.send_message(&Message::otp_rotation(commitment, epoch)).await?;
+        self.last_otp_minute = Some(minute);
+
+        self.record_rotation(epoch);
+        Ok(true)
+    }
+
+    /// Check the peer's seed commitment and follow its rotation
+    ///
+    /// Accepts the minute before and after ours to allow for clock skew and
+    /// a rotation sent just before a minute boundary, but never a minute the
+    /// peer already used.
+    fn follow_otp_rotation(&mut self, new_key_id: u16, commitment: &[u8; 32]) -> Result<(), NetworkError> {
+        let seed = self.oob_seed.clone()
+            .ok_or_else(|| NetworkError::ProtocolError("Seed rotation without an out-of-band seed".to_string()))?;
+
+        let key = self.otp_commitment_key(self.role.peer())?;
+        let now = unix_minute();
+        let minute = (now.saturating_sub(1)..=now + 1)
+            .filter(|minute| self.peer_otp_minute.is_none_or(|last| *minute > last))
+            .find(|minute| verify_mac(&key, &otp_commitment_input(&seed, *minute), commitment));
+        self.audit(AuditEvent::AuthResult { method: "seed-commitment".to_string(), success: minute.is_some() });
+        let minute = minute
+            .ok_or_else(|| NetworkError::ProtocolError("Seed rotation commitment does not match".to_string()))?;
+
+        let secret = otp_secret(&seed, minute)?;
+        self.ratchet.rotate_recv_with_secret(new_key_id, &secret)
+            .map_err(|e| NetworkError::ProtocolError(format!("Unexpected key rotation: {}", e)))?;
+        self.peer_otp_minute = Some(minute);
+        Ok(())
+    }
+
+    /// Commitment `sender` makes to holding `seed` in `minute`
+    fn otp_commitment(&self, sender: SessionRole, seed: &[u8], minute: u64) -> Result<[u8; 32], NetworkError> {
+        ratchet_key_hmac(&*self.otp_commitment_key(sender)?, &otp_commitment_input(seed, minute))
+            .map_err(|e| NetworkError::ConnectionError(format!("MAC failed: {}", e)))
+    }
+
+    fn otp_commitment_key(&self, sender: SessionRole) -> Result<Zeroizing<[u8; 32]>, NetworkError> {
+        let label = match sender {
+            SessionRole::Initiator => b"otp-commitment-initiator".as_slice(),
+            SessionRole::Responder => b"otp-commitment-responder".as_slice(),
+        };
+        self.ratchet.derive_subkey(label)
+            .map(Zeroizing::new)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))
+    }
+
+    /// Rotate keys automatically (`None` turns it off); the clock starts now
+    pub fn set_rekey_policy(&mut self, policy: Option<RekeyPolicy>) {
+        self.rekey_schedule = policy.map(|policy| RekeySchedule::new(policy, Instant::now()));
+    }
+
+    /// When `rekey_on_schedule` next has work to do
+    pub fn next_rekey_at(&self) -> Option<Instant> {
+        self.rekey_schedule.as_ref().and_then(RekeySchedule::next_due)
+    }
+
+    /// Run whichever rotation the policy says is due, if any
+    ///
+    /// Call it when `next_rekey_at` passes, e.g. from the same `select!`
+    /// loop that drives `recv`.
+    pub async fn rekey_on_schedule(&mut self) -> Result<Option<RekeyAction>, NetworkError> {
+        self.rekey_on_schedule_at(Instant::now()).await
+    }
+
+    async fn rekey_on_schedule_at(&mut self, now: Instant) -> Result<Option<RekeyAction>, NetworkError> {
+        let action = self.rekey_schedule.as_mut().and_then(|schedule| schedule.take_due(now));
+        match action {
+            Some(RekeyAction::Symmetric) => self.rotate_keys().await?,
+            Some(RekeyAction::Dh) if self.suite.supports(WireVersion::FEATURE_DH_REKEY) => self.dh_rekey().await?,
+            // A peer without Kyber rekeys still gets its keys rotated
+            Some(RekeyAction::Dh) => {
+                self.rotate_keys().await?;
+                return Ok(Some(RekeyAction::Symmetric));
+            }
+            None => {}
+        }
+        Ok(action)
+    }
+
+    /// Peer offered a rekey: encapsulate, move our sending chain, and answer
+    async fn answer_rekey_offer(&mut self, public_key: Vec<u8>) -> Result<(), NetworkError> {
+        let peer_key = PublicKey::from_bytes(public_key, self.suite.kem)
+            .map_err(|e| NetworkError::ProtocolError(format!("Invalid rekey key: {}", e)))?;
+        let (shared_secret, ciphertext) = peer_key.encapsulate()
+            .map_err(|e| NetworkError::ConnectionError(format!("Encapsulation failed: {}", e)))?;
+
+        let epoch = self.ratchet.rotate_send_with_secret(shared_secret.as_bytes())
+            .map_err(|e| NetworkError::ConnectionError(format!("Key rotation failed: {}", e)))?;
+        self.connection.send_message(&Message::rekey_response(epoch, ciphertext)).await?;
+        self.pending_rekey_commit = Some(Zeroizing::new(*shared_secret.as_bytes()));
+
+        self.record_rotation(epoch);
+        Ok(())
+    }
+
+    /// Peer answered our offer: follow its chain, then move ours and commit
+    async fn finish_rekey(&mut self, new_key_id: u16, ciphertext: Vec<u8>) -> Result<(), NetworkError> {
+        let keypair = self.pending_rekey.take()
+            .ok_or_else(|| NetworkError::ProtocolError("Rekey response without an offer".to_string()))?;
+        let ciphertext = Ciphertext::from_bytes(ciphertext, self.suite.kem)
+            .map_err(|e| NetworkError::ProtocolError(format!("Invalid ciphertext: {}", e)))?;
+        let shared_secret = keypair.decapsulate(&ciphertext)
+            .map_err(|e| NetworkError::ConnectionError(format!("Decapsulation failed: {}", e)))?;
+
+        self.ratchet.rotate_recv_with_secret(new_key_id, shared_secret.as_bytes())
+            .map_err(|e| NetworkError::ProtocolError(format!("Unexpected key rotation: {}", e)))?;
+        let epoch = self.ratchet.rotate_send_with_secret(shared_secret.as_bytes())
+            .map_err(|e| NetworkError::ConnectionError(format!("Key rotation failed: {}", e)))?;
+        self.connection.send_message(&Message::rekey_commit(epoch)).await?;
+        self.pfs_confirmed = true;
+
+        self.record_rotation(epoch);
+        Ok(())
+    }
+
+    /// Audit and report a move of our sending chain to `epoch`
+    fn record_rotation(&mut self, epoch: u16) {
+        self.audit(AuditEvent::KeyRotated {
+            send_counter: self.ratchet.send_counter(),
+            recv_counter: self.ratchet.recv_counter(),
+        });
+        self.telemetry.on_rotate(epoch as u32);
+        self.key_rotations += 1;
+        self.publish_stats();
+    }
+
+    /// Add TLS underneath an established plain-TCP session (STARTTLS-style)
+    ///
+    /// Sends a `TlsUpgrade` proposal and waits for the peer's confirmation,
+    /// which `recv` on the other side sends automatically before switching.
+    /// The peer must not have data in flight when the proposal arrives. The
+    /// ratchet state is untouched, so messages continue with the same keys
+    /// and counters over the new TLS layer.
+    pub async fn upgrade_to_tls(&mut self, server_name: &str) -> Result<(), NetworkError> {
+        self.require_established("upgrade to TLS")?;
+        if self.connection.is_tls() {
+            return Err(NetworkError::ConnectionError("Session is already using TLS".to_string()));
+        }
+
+        self.connection.send_message(&Message::tls_upgrade()).await?;
+
+        let confirmation = timeout(HANDSHAKE_TIMEOUT, self.connection.recv_message()).await
+            .map_err(|_| NetworkError::Timeout)??;
+        confirmation.validate()?;
+        if confirmation.message_type != MessageType::TlsUpgrade {
+            return Err(NetworkError::ProtocolError(
+                format!("Expected TLS upgrade confirmation, got {:?}", confirmation.message_type)
+            ));
+        }
+
+        self.connection.upgrade_to_tls_client(server_name).await
+    }
+
+    /// Move the session onto `new_connection` after the old path broke
+    /// (e.g. the client switched networks), like QUIC connection migration
+    ///
+    /// Sends our `connection_id` and a token proving we hold the session; the
+    /// peer finds the session by the ID, checks the token with
+    /// `complete_migration` and confirms. Only then does the session switch
+    /// to `new_connection` and move on to the next ID and token, so a
+    /// migration lost in transit can be retried on another connection. The
+    /// ratchet is untouched, so messages lost with the old connection show
+    /// up as skipped counters. Per-connection tracking (MTU, heartbeat,
+    /// liveness, quota accounting) starts over.
+    pub async fn transfer_to_new_connection(&mut self, mut new_connection: Connection) -> Result<(), NetworkError> {
+        self.require_established("migrate")?;
+        self.require_feature(WireVersion::FEATURE_MIGRATION, "migration")?;
+
+        let connection_id = self.connection_id()?;
+        let token = self.migration_token(b"migrate-v1")?;
+        new_connection.send_message(&Message::migrate(connection_id, *token.as_bytes())).await?;
+        new_connection.flush().await?;
+
+        let reply = timeout(HANDSHAKE_TIMEOUT, new_connection.recv_message()).await
+            .map_err(|_| NetworkError::Timeout)??;
+        reply.validate()?;
+        let expected = self.migration_token(b"migrate-confirm-v1")?;
+        match reply.payload {
+            MessagePayload::Migrate { connection_id: id, session_token }
+                if id == connection_id && expected == blake3::Hash::from(session_token) => {}
+            _ => return Err(NetworkError::ProtocolError("Migration was not confirmed".to_string())),
+        }
+
+        self.replace_connection(new_connection);
+        Ok(())
+    }
+
+    /// Take over the connection of a migration read with `PendingMigration::read`
+    ///
+    /// Read the frame first, then borrow the session only for the check and
+    /// the confirmation. On failure the session keeps its current connection.
+    pub async fn complete_migration(&mut self, mut pending: PendingMigration) -> Result<(), NetworkError> {
+        self.require_established("accept migration")?;
+        self.require_feature(WireVersion::FEATURE_MIGRATION, "migration")?;
+
+        let connection_id = self.connection_id()?;
+        if pending.connection_id != connection_id {
+            return Err(NetworkError::ProtocolError("Migration is for another session".to_string()));
+        }
+        let expected = self.migration_token(b"migrate-v1")?;
+        if expected != blake3::Hash::from(pending.session_token) {
+            return Err(NetworkError::ProtocolError("Migration token does not match this session".to_string()));
+        }
+
+        let confirmation = self.migration_token(b"migrate-confirm-v1")?;
+        pending.connection.send_message(&Message::migrate(connection_id, *confirmation.as_bytes())).await?;
+        pending.connection.flush().await?;
+        self.replace_connection(pending.connection);
+        Ok(())
+    }
+
+    /// Identifies this session to a server that gets a migration on a fresh
+    /// socket, so it can find the session to hand it to
+    ///
+    /// Both peers compute the same value. It changes with every migration,
+    /// so an observer can't link the old path to the new one.
+    pub fn connection_id(&self) -> Result<[u8; 16], NetworkError> {
+        let key = Zeroizing::new(self.ratchet.derive_subkey(b"connection-id-v1")
+            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?);
+        let mut hasher = blake3::Hasher::new_keyed(&key);
+        hasher.update(&self.migrations.to_be_bytes());
+
+        let mut id = [0u8; 16];
+        id.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+        Ok(id)
+    }
+
+    /// Keyed BLAKE3 of the migration count, under the subkey for `label`
+    /// (the client's request or the server's confirmation); the count keeps
+    /// an observed token from being replayed
+    ///
+    /// No address goes in: behind NAT or a load balancer the address the
+    /// client dialled isn't the one the server's socket is bound to.
+    fn migration_token(&self, label: &[u8]) -> Result<blake3::Hash, NetworkError> {
+        let key = Zeroizing::new(self.ratchet.derive_subkey(label)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?);
+        let mut hasher = blake3::Hasher::new_keyed(&key);
+        hasher.update(&self.migrations.to_be_bytes());
+        Ok(hasher.finalize())
+    }
+
+    fn replace_connection(&mut self, connection: Connection) {
+        self.connection = connection;
+        self.peer_addr = self.connection.peer_addr();
+        self.migrations = self.migrations.wrapping_add(1);
+        self.mtu = None;
+        self.heartbeat_sent_at = None;
+        self.quota_charged_bytes = 0;
+        self.last_frame_at = Instant::now();
+        self.unanswered_since = None;
+    }
+
+    /// Find the largest message the path delivers, by binary search over
+    /// probe sizes between 512 and 65535 bytes
+    ///
+    /// The peer must be receiving (it answers probes inside `recv`) and
+    /// must have negotiated probes. Application messages that arrive
+    /// meanwhile are kept for `recv`; later `into_stream` and `tunnel`
+    /// chunks are sized to fit the path.
+    pub async fn discover_mtu(&mut self) -> Result<usize, NetworkError> {
+        self.require_established("discover MTU")?;
+        self.require_feature(WireVersion::FEATURE_MTU_PROBE, "MTU probes")?;
+
+        let (mut low, mut high) = (MTU_PROBE_MIN, MTU_PROBE_MAX);
+        let mut best = None;
+
+        while low <= high {
+            let size = low + (high - low) / 2;
+            if self.probe(size).await? {
+                best = Some(size);
+                low = size + 1;
+            } else {
+                high = size - 1;
+            }
+        }
+
+        let mtu = best.ok_or_else(|| {
+            NetworkError::ConnectionError(format!("No probe of {} bytes or more was answered", MTU_PROBE_MIN))
+        })?;
+        self.mtu = Some(mtu);
+        Ok(mtu)
+    }
+
+    /// Raw byte and message counters of the underlying connection
+    pub fn connection_info(&self) -> ConnectionStats {
+        self.connection.stats()
+    }
+
+    /// Size, rate and type distribution of frames received so far
+    pub fn recv_stats(&self) -> &RecvStats {
+        &self.recv_stats
+    }
+
+    /// Path MTU found by `discover_mtu`, if it has run
+    pub fn mtu(&self) -> Option<usize> {
+        self.mtu
+    }
+
+    /// Largest plaintext chunk that fits one frame on the discovered path
+    fn stream_chunk_size(&self) -> usize {
+        self.mtu.map_or(STREAM_CHUNK_SIZE, |mtu| mtu.saturating_sub(SEALED_FRAME_OVERHEAD).min(STREAM_CHUNK_SIZE))
+    }
+
+    /// Send one probe and report whether it was answered in time
+    async fn probe(&mut self, size: usize) -> Result<bool, NetworkError> {
+        self.connection.send_message(&Message::probe(size)).await?;
+
+        let wait = async {
+            loop {
+                let msg = self.connection.recv_message().await?;
+                if let MessagePayload::ProbeResponse { probe_size } = msg.payload {
+                    if probe_size == size as u64 {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                if let Some(event) = self.handle_message(msg).await? {
+                    self.pending_events.push_back(event);
+                }
+            }
+        };
+
+        match timeout(MTU_PROBE_TIMEOUT, wait).await {
+            Ok(result) => result.map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Attach a tamper-evident audit log to this session
+    ///
+    /// The handshake has already completed by the time a `Session` exists,
+    /// so its negotiated parameters are recorded as the first entry. Only
+    /// metadata is ever written: no plaintext and no key material.
+    pub fn set_audit_log(&mut self, log: AuditLog) {
+        let params = self.negotiated_params();
+        self.audit_log = Some(log);
+        self.audit(AuditEvent::HandshakeCompleted {
+            role: self.role,
+            peer_addr: self.peer_addr.to_string(),
+            kem: params.post_quantum.then_some(params.suite.kem),
+            psk: params.psk,
+            tls: params.tls,
+        });
+    }
+
+    /// Detach and return the audit log, if any
+    pub fn take_audit_log(&mut self) -> Option<AuditLog> {
+        self.audit_log.take()
+    }
+
+    /// Record an event if auditing is enabled; sink failures never break the session
+    fn audit(&mut self, event: AuditEvent) {
+        if let Some(log) = self.audit_log.as_mut() {
+            if let Err(e) = log.record(event) {
+                tracing::warn!("Failed to write audit entry: {}", e);
+            }
+        }
+    }
+
+    /// Turn the session into a plain encrypted byte stream
+    ///
+    /// Must be called inside a Tokio runtime: a background task moves bytes
+    /// between the returned stream and the session, chunking writes into
+    /// encrypted messages. Shutting down or dropping the stream closes the
+    /// session; the peer disconnecting shows up as end-of-file.
+    pub fn into_stream(self) -> SessionStream {
+        let (pipe, inner) = tokio::io::duplex(STREAM_CHUNK_SIZE * 4);
+
+        tokio::spawn(async move {
+            if let Err(e) = pump_session_stream(self, inner).await {
+                tracing::warn!("Session stream ended with error: {}", e);
+            }
+        });
+
+        SessionStream { pipe }
+    }
+
+    /// Pipe stdin to the peer and the peer's data to stdout, for scripting
+    ///
+    /// EOF on stdin (Ctrl+D) closes the session gracefully. Ctrl+C is left to
+    /// the default signal handler, which ends the process at once.
+    pub fn into_stdio_tunnel(self) -> impl std::future::Future<Output = Result<(), NetworkError>> {
+        self.tunnel(tokio::io::stdin(), tokio::io::stdout())
+    }
+
+    /// Send everything read from `reader` and write everything received to
+    /// `writer`, until `reader` ends or the peer leaves
+    ///
+    /// Heartbeats go out every 30 seconds and the rekey policy, if any, is
+    /// applied while the tunnel runs.
+    pub async fn tunnel<R, W>(self, reader: R, writer: W) -> Result<(), NetworkError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        pump_bytes(self, reader, writer, Some(TUNNEL_HEARTBEAT_INTERVAL)).await
+    }
+}
+
+/// A connection whose first frame asked to take over an existing session
+///
+/// Servers read one from each connection that arrives for migration, look
+/// up the session by `connection_id`, and pass it to
+/// `Session::complete_migration`.
+pub struct PendingMigration {
+    connection: Connection,
+    connection_id: [u8; 16],
+    session_token: [u8; 32],
+}
+
+impl PendingMigration {
+    /// Read the `Migrate` frame a migrating peer sends first
+    pub async fn read(mut connection: Connection) -> Result<Self, NetworkError> {
+        let msg = timeout(HANDSHAKE_TIMEOUT, connection.recv_message()).await
+            .map_err(|_| NetworkError::Timeout)??;
+        msg.validate()?;
+        let MessagePayload::Migrate { connection_id, session_token } = msg.payload else {
+            return Err(NetworkError::ProtocolError(
+                format!("Expected migration, got {:?}", msg.message_type)
+            ));
+        };
+        Ok(Self { connection, connection_id, session_token })
+    }
+
+    /// The session the peer wants to move (see `Session::connection_id`)
+    pub fn connection_id(&self) -> [u8; 16] {
+        self.connection_id
+    }
+}
+
+/// Independent sessions over one multiplexed connection, keyed from a single handshake
+///
+/// Each virtual stream gets its own ratchet, rooted at
+/// `HKDF(handshake root, "mux-stream-<stream id>")`.
+pub struct MuxedSession {
+    mux: MuxedConnection,
+    master_key: Zeroizing<[u8; 32]>,
+    aad_schema: AadSchema,
+    features: u32,
+}
+
+impl MuxedSession {
+    async fn new(connection: Connection, handshake: &HandshakeOutcome, mode: MuxMode) -> Result<Self, NetworkError> {
+        Ok(Self {
+            mux: MuxedConnection::new(connection, mode).await?,
+            master_key: Zeroizing::new(handshake.root_key),
+            aad_schema: handshake.aad_schema,
+            features: handshake.features,
+        })
+    }
+
+    /// Open a session on a new stream; the peer's `accept_session` sees it once we send
+    pub async fn open_session(&self) -> Result<Session, NetworkError> {
+        let connection = self.mux.open_stream().await?;
+        let root_key = self.stream_root_key(&connection)?;
+        let mut session = Session::established(connection, RatchetState::new(root_key), SessionRole::Initiator);
+        session.suite.aad_schema = self.aad_schema;
+        session.suite.features = self.features;
+        Ok(session)
+    }
+
+    /// Wait for the peer to open a session
+    pub async fn accept_session(&mut self) -> Result<Session, NetworkError> {
+        let connection = self.mux.accept_stream().await?;
+        let root_key = self.stream_root_key(&connection)?;
+        let mut session = Session::established(connection, RatchetState::new_responder(root_key), SessionRole::Responder);
+        session.suite.aad_schema = self.aad_schema;
+        session.suite.features = self.features;
+        Ok(session)
+    }
+
+    /// Close every session and the underlying connection
+    pub async fn close(self) -> Result<(), NetworkError> {
+        self.mux.close().await
+    }
+
+    fn stream_root_key(&self, connection: &Connection) -> Result<[u8; 32], NetworkError> {
+        let stream_id = connection.mux_stream_id()
+            .ok_or_else(|| NetworkError::ProtocolError("Not a multiplexed stream".to_string()))?;
+        let derived = derive_keys(self.master_key.as_ref(), b"", format!("mux-stream-{}", stream_id).as_bytes(), 32)
+            .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?;
+
+        let mut root_key = [0u8; 32];
+        root_key.copy_from_slice(&derived);
+        Ok(root_key)
+    }
+}
+
+/// Open key log file for `Session::dump_keys`
+#[cfg(debug_assertions)]
+struct KeyLog {
+    file: std::fs::File,
+    flow: String,
+}
+
+/// Limits for a `SessionPool`
+#[derive(Clone)]
+pub struct SessionPoolConfig {
+    /// Handshakes in progress at once; further connections wait to be accepted
+    pub max_concurrent_handshakes: usize,
+    /// Connections that haven't finished the handshake by then are dropped
+    pub handshake_timeout: Duration,
+    /// Settings every accepted session uses
+    pub session: SessionConfig,
+}
+
+impl Default for SessionPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_handshakes: DEFAULT_MAX_CONCURRENT_HANDSHAKES,
+            handshake_timeout: HANDSHAKE_TIMEOUT,
+            session: SessionConfig::default(),
+        }
+    }
+}
+
+/// Accepts inbound connections and hands over the ones that complete a handshake
+///
+/// Owns the listener and runs handshakes concurrently in the background;
+/// failed or timed-out handshakes are logged and dropped. Dropping the pool
+/// stops accepting.
+pub struct SessionPool {
+    sessions: mpsc::Receiver<Session>,
+    local_addr: SocketAddr,
+    accept_task: JoinHandle<()>,
+}
+
+impl SessionPool {
+    /// Start accepting on `listener` with the default limits
+    pub fn new(listener: Listener) -> Result<Self, NetworkError> {
+        Self::with_config(listener, SessionPoolConfig::default())
+    }
+
+    /// Start accepting on `listener`
+    pub fn with_config(listener: Listener, config: SessionPoolConfig) -> Result<Self, NetworkError> {
+        let local_addr = listener.local_addr()?;
+        let limit = config.max_concurrent_handshakes.max(1);
+        let (tx, sessions) = mpsc::channel(limit);
+        let accept_task = tokio::spawn(accept_sessions(listener, config, Arc::new(Semaphore::new(limit)), tx));
+        Ok(Self { sessions, local_addr, accept_task })
+    }
+
+    /// Next established session, in the order handshakes finish
+    ///
+    /// Returns `None` once the pool has stopped accepting.
+    pub async fn next(&mut self) -> Option<Session> {
+        self.sessions.recv().await
+    }
+
+    /// Address the listener is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for SessionPool {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+async fn accept_sessions(listener: Listener, config: SessionPoolConfig, limit: Arc<Semaphore>, tx: mpsc::Sender<Session>) {
+    let config = Arc::new(config);
+    loop {
+        let Ok(permit) = limit.clone().acquire_owned().await else {
+            return;
+        };
+        let pending = match listener.accept_pending().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                tracing::warn!("Accept failed: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        if tx.is_closed() {
+            return;
+        }
+
+        let config = config.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            // The TLS handshake runs here, under the same timeout as ours
+            let peer_addr = pending.peer_addr();
+            let handshake = async {
+                let connection = pending.finish().await?;
+                Session::accept_with_config(connection, &config.session).await
+            };
+            match timeout(config.handshake_timeout, handshake).await {
+                Ok(Ok(session)) => {
+                    let _ = tx.send(session).await;
+                }
+                Ok(Err(e)) => tracing::debug!("Handshake with {} failed: {}", peer_addr, e),
+                Err(_) => tracing::debug!("Handshake with {} timed out", peer_addr),
+            }
+            // Holding the permit until the session is handed over caps queued sessions too
+            drop(permit);
+        });
+    }
+}
+
+/// What a handshake agreed on
+struct HandshakeOutcome {
+    root_key: [u8; 32],
+    /// Seconds the peer's clock runs ahead of ours
+    clock_offset: i64,
+    aad_schema: AadSchema,
+    /// Feature bits both sides offered (none for a one-message cached handshake)
+    features: u32,
+    /// Hash of both handshake frames (none for a one-message cached handshake)
+    transcript_hash: Option<[u8; 32]>,
+}
+
+/// Client side of the ephemeral Kyber handshake
+async fn initiator_handshake(connection: &mut Connection, config: &SessionConfig) -> Result<HandshakeOutcome, NetworkError> {
+    // Generate ephemeral Kyber keypair
+    let keypair = KeyPair::generate_with_level(config.kem)
+        .map_err(|e| NetworkError::ConnectionError(format!("Key generation failed: {}", e)))?;
+
+    // Send handshake with our public key, offering our highest AAD schema and our features
+    let local_features = WireVersion::current().features;
+    let mut handshake_msg = Message::handshake(keypair.public_key().clone());
+    handshake_msg.key_id = WireVersion::handshake_offer(AadSchema::LATEST, local_features);
+    connection.send_message(&handshake_msg).await?;
+
+    // Wait for handshake response
+    let response = timeout(HANDSHAKE_TIMEOUT, connection.recv_message_with_limit(config.max_handshake_bytes())).await
+        .map_err(|_| NetworkError::Timeout)?
+        .map_err(|e| NetworkError::ConnectionError(format!("Handshake failed: {}", e)))?;
+
+    // Validate response
+    response.validate()?;
+    let clock_offset = measure_clock_offset(response.timestamp);
+    if response.message_type != MessageType::HandshakeResponse {
+        return Err(NetworkError::ProtocolError("Expected handshake response".to_string()));
+    }
+    let (schema, peer_features) = WireVersion::parse_handshake_offer(response.key_id);
+    let aad_schema = AadSchema::try_from(schema)?;
+    let features = peer_features & local_features;
+    let transcript = [handshake_msg.to_bytes()?, response.to_bytes()?];
+
+    // Extract ciphertext and derive shared secret
+    let ciphertext_bytes = match response.payload {
+        MessagePayload::HandshakeResponse { ciphertext } => ciphertext,
+        _ => return Err(NetworkError::ProtocolError("Invalid handshake response payload".to_string())),
+    };
+
+    let ciphertext = Ciphertext::from_bytes(ciphertext_bytes, config.kem)
+        .map_err(|e| NetworkError::ProtocolError(format!("Invalid ciphertext: {}", e)))?;
+
+    let shared_secret = keypair.decapsulate(&ciphertext)
+        .map_err(|e| NetworkError::ConnectionError(format!("Decapsulation failed: {}", e)))?;
+
+    let root_key = derive_bound_root_key(shared_secret.as_bytes(), HANDSHAKE_SALT, config, &transcript)?;
+    Ok(HandshakeOutcome { root_key, clock_offset, aad_schema, features, transcript_hash: Some(transcript_hash(&transcript)) })
+}
+
+/// Server side of the handshake (ephemeral or cached)
+async fn responder_handshake(
+    connection: &mut Connection,
+    server_key: Option<&SemiStaticKeyPair>,
+    strictness: HandshakeStrictness,
+    config: &SessionConfig,
+) -> Result<HandshakeOutcome, NetworkError> {
+    // Wait for handshake
+    let handshake = timeout(HANDSHAKE_TIMEOUT, recv_handshake(connection, server_key.is_some(), strictness, config)).await
+        .map_err(|_| NetworkError::Timeout)??;
+
+    // Validate handshake
+    handshake.validate()?;
+    let clock_offset = measure_clock_offset(handshake.timestamp);
+    let handshake_bytes = handshake.to_bytes()?;
+
+    let (root_key, aad_schema, features, transcript_hash) = match (handshake.payload, server_key) {
+        (MessagePayload::Handshake { public_key }, _) => {
+            let (schema, peer_features) = WireVersion::parse_handshake_offer(handshake.key_id);
+            let aad_schema = AadSchema::negotiate(schema);
+            let features = peer_features & WireVersion::current().features;
+            let peer_public_key = PublicKey::from_bytes(public_key, config.kem)
+                .map_err(|e| NetworkError::ProtocolError(format!("Invalid public key: {}", e)))?;
+
+            // Encapsulate a shared secret for the peer
+            let (shared_secret, ciphertext) = peer_public_key.encapsulate()
+                .map_err(|e| NetworkError::ConnectionError(format!("Encapsulation failed: {}", e)))?;
+
+            // Send handshake response with the schema we picked, and the
+            // agreed features only to peers that offered some
+            let mut response = Message::handshake_response(ciphertext);
+            response.key_id = WireVersion::handshake_offer(aad_schema, features);
+            connection.send_message(&response).await?;
+
+            let transcript = [handshake_bytes, response.to_bytes()?];
+            let root_key = derive_bound_root_key(shared_secret.as_bytes(), HANDSHAKE_SALT, config, &transcript)?;
+            (root_key, aad_schema, features, Some(transcript_hash(&transcript)))
+        }
+        (MessagePayload::CachedHandshake { key_fingerprint, ciphertext }, Some(server_key)) => {
+            if key_fingerprint != server_key.public_key().fingerprint() {
+                let reason = "Cached server key is no longer valid".to_string();
+                let _ = connection.send_message(&Message::disconnect(Some(reason.clone()))).await;
+                let _ = connection.flush().await;
+                return Err(NetworkError::ProtocolError(reason));
+            }
+
+            let ciphertext = Ciphertext::from_bytes(ciphertext, SESSION_KYBER_LEVEL)
+                .map_err(|e| NetworkError::ProtocolError(format!("Invalid ciphertext: {}", e)))?;
+            if !server_key.first_use(&ciphertext) {
+                return Err(NetworkError::ProtocolError("Replayed cached handshake".to_string()));
+            }
+
+            let shared_secret = server_key.keypair().decapsulate(&ciphertext)
+                .map_err(|e| NetworkError::ConnectionError(format!("Decapsulation failed: {}", e)))?;
+
+            (derive_root_key(shared_secret.as_bytes(), CACHED_HANDSHAKE_SALT)?, AadSchema::V0, 0, None)
+        }
+        (payload, _) => return Err(NetworkError::ProtocolError(
+            format!("Expected handshake, got invalid {:?} payload", payload_kind(&payload))
+        )),
+    };
+
+    Ok(HandshakeOutcome { root_key, clock_offset, aad_schema, features, transcript_hash })
+}
+
+/// Wait for the first handshake frame, explaining exactly what arrived instead
+///
+/// A `CachedHandshake` only counts when the server holds a semi-static key.
+async fn recv_handshake(
+    connection: &mut Connection,
+    accepts_cached: bool,
+    strictness: HandshakeStrictness,
+    config: &SessionConfig,
+) -> Result<Message, NetworkError> {
+    let mut skipped = 0;
+
+    loop {
+        let msg = connection.recv_message_with_limit(config.max_handshake_bytes()).await
+            .map_err(|e| NetworkError::ConnectionError(format!("Handshake failed: {}", e)))?;
+
+        let is_handshake = match msg.message_type {
+            MessageType::Handshake => true,
+            MessageType::CachedHandshake => accepts_cached,
+            _ => false,
+        };
+        if is_handshake {
+            return Ok(msg);
+        }
+
+        if strictness == HandshakeStrictness::Lenient && skipped < MAX_LENIENT_PRELUDE_FRAMES {
+            skipped += 1;
+            continue;
+        }
+
+        let size = msg.serialized_size()?;
+        let hint = if msg.message_type == MessageType::CachedHandshake {
+            " (server has no semi-static key for cached handshakes)"
+        } else {
+            ""
+        };
+        return Err(NetworkError::ProtocolError(format!(
+            "Expected handshake, got {:?} ({} bytes serialized) after skipping {} frame(s){}",
+            msg.message_type, size, skipped, hint
+        )));
+    }
+}
+
+/// How to surface the application data of an `EncryptedMessage` frame
+enum SealedKind {
+    Plain,
+    Ephemeral(u32),
+    Contact(u64),
+    /// Carries the frame's `replay_safe_id`, for the `DeliveryToken`
+    Tracked(u64),
+}
+
+/// Reject rotation intervals outside `MIN_ROTATION_INTERVAL..=MAX_ROTATION_INTERVAL`
+///
+/// The peer picks the value, so it must neither disable rotation nor push
+/// deadlines past what `Instant` can hold.
+fn check_rotation_interval(seconds: u64) -> Result<(), NetworkError> {
+    if (MIN_ROTATION_INTERVAL.as_secs()..=MAX_ROTATION_INTERVAL.as_secs()).contains(&seconds) {
+        Ok(())
+    } else {
+        Err(NetworkError::ProtocolError(format!(
+            "Rotation interval must be between {} and {} seconds, got {}",
+            MIN_ROTATION_INTERVAL.as_secs(), MAX_ROTATION_INTERVAL.as_secs(), seconds
+        )))
+    }
+}
+
+/// Nonce, ciphertext and counter of a payload that carries sealed application data
+fn sealed_fields(payload: &mut MessagePayload) -> Option<(&mut [u8; 24], &mut Vec<u8>, &mut u64)> {
+    match payload {
+        MessagePayload::EncryptedData { nonce, ciphertext, message_counter }
+        | MessagePayload::EphemeralData { nonce, ciphertext, message_counter, .. }
+        | MessagePayload::ContactData { nonce, ciphertext, message_counter, .. }
+        | MessagePayload::TrackedData { nonce, ciphertext, message_counter }
+        | MessagePayload::RotationInterval { nonce, ciphertext, message_counter } => Some((nonce, ciphertext, message_counter)),
+        _ => None,
+    }
+}
+
+/// Payload variant name for diagnostics (without dumping key material)
+fn payload_kind(payload: &MessagePayload) -> &'static str {
+    match payload {
+        MessagePayload::Handshake { .. } => "Handshake",
+        MessagePayload::CachedHandshake { .. } => "CachedHandshake",
+        _ => "non-handshake",
+    }
+}
+
+/// Root key for a TLS-only session, from keying material both TLS ends export
+fn tls_exporter_root_key(connection: &Connection) -> Result<[u8; 32], NetworkError> {
+    let mut exported = Zeroizing::new([0u8; 32]);
+    connection.export_keying_material(TLS_EXPORTER_LABEL, exported.as_mut())?;
+    derive_root_key(&exported, TLS_ONLY_SALT)
+}
+
+/// Derive the ratchet root key from a KEM shared secret
+fn derive_root_key(shared_secret: &[u8; 32], salt: &[u8]) -> Result<[u8; 32], NetworkError> {
+    let master_key = derive_master_key(shared_secret, salt)
+        .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?;
+    Ok(*master_key.as_bytes())
+}
+
+/// Root key that also covers the transcript and PSK, as `config` asks
+fn derive_bound_root_key(
+    shared_secret: &[u8; 32],
+    salt: &[u8],
+    config: &SessionConfig,
+    transcript: &[Vec<u8>],
+) -> Result<[u8; 32], NetworkError> {
+    let transcript_hash = config.bind_transcript.then(|| transcript_hash(transcript));
+    let psk = config.psk.as_deref().map(Vec::as_slice);
+    let master_key = derive_master_key_bound(shared_secret, transcript_hash.as_ref(), psk, salt)
+        .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?;
+    Ok(*master_key.as_bytes())
+}
+
+/// BLAKE3 over the serialized handshake frames in order, each length-prefixed
+fn transcript_hash(frames: &[Vec<u8>]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key("aegis-handshake-transcript-v1");
+    for frame in frames {
+        hasher.update(&(frame.len() as u64).to_be_bytes());
+        hasher.update(frame);
+    }
+    *hasher.finalize().as_bytes()
+}
+
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn unix_minute() -> u64 {
+    current_timestamp() / 60
+}
+
+/// label || minute (BE) || seed, MACed to prove knowledge of the seed for that minute
+fn otp_commitment_input(seed: &[u8], minute: u64) -> Zeroizing<Vec<u8>> {
+    let mut input = Zeroizing::new(OTP_COMMITMENT_LABEL.to_vec());
+    input.extend_from_slice(&minute.to_be_bytes());
+    input.extend_from_slice(seed);
+    input
+}
+
+/// HKDF(seed || timestamp) for the start of `minute`
+fn otp_secret(seed: &[u8], minute: u64) -> Result<Zeroizing<[u8; 32]>, NetworkError> {
+    let mut ikm = Zeroizing::new(seed.to_vec());
+    ikm.extend_from_slice(&(minute * 60).to_be_bytes());
+    let derived = Zeroizing::new(derive_keys(&ikm, b"aegis-otp-rotation-v1", b"otp rotation", 32)
+        .map_err(|e| NetworkError::ConnectionError(format!("Key derivation failed: {}", e)))?);
+
+    let mut secret = Zeroizing::new([0u8; 32]);
+    secret.copy_from_slice(&derived);
+    Ok(secret)
+}
+
+fn new_rtt_histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, MAX_TRACKED_RTT_US, 3)
+        .expect("static histogram bounds are valid")
+}
+
+/// Error form of `ReceivedEvent::Disconnected` for callers that only see data
+fn dead_peer_error() -> NetworkError {
+    NetworkError::ConnectionError("Peer stopped responding".to_string())
+}
+
+/// `AsyncRead`/`AsyncWrite` adapter over a session (see `Session::into_stream`)
+pub struct SessionStream {
+    pipe: DuplexStream,
+}
+
+impl AsyncRead for SessionStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SessionStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.pipe).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_shutdown(cx)
+    }
+}
+
+/// Move bytes between the local pipe and the session until either side ends
+async fn pump_session_stream(session: Session, pipe: DuplexStream) -> Result<(), NetworkError> {
+    let (reader, writer) = tokio::io::split(pipe);
+    pump_bytes(session, reader, writer, None).await
+}
+
+/// Move bytes between a local reader/writer pair and the session until either side ends
+async fn pump_bytes<R, W>(
+    mut session: Session,
+    mut reader: R,
+    mut writer: W,
+    heartbeat_every: Option<Duration>,
+) -> Result<(), NetworkError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut chunk = vec![0u8; session.stream_chunk_size()];
+    let mut heartbeat = heartbeat_every.map(|every| tokio::time::interval_at(tokio::time::Instant::now() + every, every));
+
+    // Only cancel-safe futures race in the select; whatever a branch wins
+    // is handled to completion in its body
+    loop {
+        let next_rekey = session.next_rekey_at();
+        let liveness = session.liveness_deadline();
+        tokio::select! {
+            read = reader.read(&mut chunk) => {
+                let n = read?;
+                if n == 0 {
+                    return session.close().await.map(|_| ());
+                }
+                session.send(&chunk[..n]).await?;
+            }
+            ready = session.readable() => {
+                if let Err(e) = ready {
+                    return end_of_stream(&session, e);
+                }
+                loop {
+                    let data = match session.next_buffered_event().await {
+                        Ok(Some(
+                            ReceivedEvent::Message(data)
+                            | ReceivedEvent::EphemeralMessage { data, .. }
+                            | ReceivedEvent::TrackedMessage { data, .. },
+                        )) => data,
+                        Ok(Some(
+                            ReceivedEvent::Heartbeat
+                            | ReceivedEvent::OobData(..)
+                            | ReceivedEvent::ClockDriftWarning { .. }
+                            | ReceivedEvent::ContactMessage { .. }
+                            | ReceivedEvent::Delivered { .. }
+                            | ReceivedEvent::PeerInfo(..),
+                        )) => continue,
+                        Ok(Some(ReceivedEvent::Disconnected)) => return Err(dead_peer_error()),
+                        Ok(None) => break,
+                        Err(e) => return end_of_stream(&session, e),
+                    };
+                    if writer.write_all(&data).await.is_err() || writer.flush().await.is_err() {
+                        // Local side dropped the stream
+                        return session.close().await.map(|_| ());
+                    }
+                }
+            }
+            _ = tick(&mut heartbeat) => session.send_heartbeat().await?,
+            _ = wait_until(next_rekey) => {
+                session.rekey_on_schedule().await?;
+            }
+            _ = wait_until(liveness) => {
+                if session.peer_overdue() {
+                    session.declare_peer_dead();
+                    return Err(dead_peer_error());
+                }
+                session.send_heartbeat().await?;
+            }
+        }
+    }
+}
+
+/// A receive error ends the pump; one caused by the peer disconnecting is a
+/// clean end of stream
+fn end_of_stream(session: &Session, error: NetworkError) -> Result<(), NetworkError> {
+    if session.state() == SessionState::Closed {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// Wait for the next tick, or forever without a timer
+async fn tick(timer: &mut Option<tokio::time::Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep until `deadline`, or forever without one
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::random::fill_with_pattern;
+    use crate::network::connection::Listener;
+    use crate::network::protocol::{MAX_CAPABILITY_LEN, MAX_CLOCK_SKEW_SECS};
+
+    #[tokio::test]
+    async fn test_session_handshake() {
+        // Start listener
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Spawn accept task
+        let accept_handle = tokio::spawn(async move {
+            let conn = listener.accept().await.unwrap();
+            Session::accept(conn).await
+        });
+
+        // Connect
+        let client_conn = crate::network::connection::connect(&addr.to_string()).await.unwrap();
+        let client_session = Session::connect(client_conn).await.unwrap();
+
+        // Accept
+        let server_session = accept_handle.await.unwrap().unwrap();
+
+        // Both sessions should be established
+        assert_eq!(client_session.state(), SessionState::Established);
+        assert_eq!(server_session.state(), SessionState::Established);
+    }
+
+    #[tokio::test]
+    async fn test_session_state_guards_operations() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        client_session.close().await.unwrap();
+        assert!(server_session.recv().await.is_err());
+        assert_eq!(server_session.state(), SessionState::Closed);
+
+        // Nothing works after the peer hung up
+        assert!(server_session.send(b"late").await.is_err());
+        assert!(server_session.send_heartbeat().await.is_err());
+        assert!(server_session.rotate_keys().await.is_err());
+        assert!(server_session.recv().await.is_err());
+
+        server_session.state = SessionState::Closing;
+        let err = server_session.send(b"late").await.unwrap_err();
+        assert!(err.to_string().contains("Closing"));
+    }
+
+    #[tokio::test]
+    async fn test_session_config_binds_psk_and_transcript() {
+        let config = SessionConfig { bind_transcript: true, psk: Some(Zeroizing::new(b"shared".to_vec())), ..SessionConfig::default() };
+
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_config = config.clone();
+        let server_handle = tokio::spawn(async move { Session::accept_with_config(server_conn, &server_config).await });
+        let mut client = Session::connect_with_config(client_conn, &config).await.unwrap();
+        let mut server = server_handle.await.unwrap().unwrap();
+        client.send(b"bound").await.unwrap();
+        assert_eq!(server.recv().await.unwrap(), b"bound");
+        for params in [client.negotiated_params(), server.negotiated_params()] {
+            assert!(params.psk && params.transcript_bound && params.post_quantum);
+        }
+
+        // A peer with another PSK derives a different key
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let other = SessionConfig { bind_transcript: true, psk: Some(Zeroizing::new(b"other".to_vec())), ..SessionConfig::default() };
+        let server_handle = tokio::spawn(async move { Session::accept_with_config(server_conn, &other).await });
+        let mut client = Session::connect_with_config(client_conn, &config).await.unwrap();
+        let mut server = server_handle.await.unwrap().unwrap();
+        client.send(b"bound").await.unwrap();
+        assert!(server.recv().await.is_err());
+
+        // Every frame and byte of the transcript counts
+        let frames = vec![b"handshake".to_vec(), b"response".to_vec()];
+        let base = transcript_hash(&frames);
+        assert_ne!(transcript_hash(&[b"handshakf".to_vec(), b"response".to_vec()]), base);
+        assert_ne!(transcript_hash(&[b"handshake".to_vec(), b"responsE".to_vec()]), base);
+        assert_ne!(transcript_hash(&[b"handshakeresponse".to_vec()]), base);
+        assert_ne!(transcript_hash(&[b"response".to_vec(), b"handshake".to_vec()]), base);
+    }
+
+    #[tokio::test]
+    async fn test_close_handshake() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        let server_handle = tokio::spawn(async move {
+            let err = server_session.recv().await.unwrap_err();
+            (err, server_session.state())
+        });
+
+        assert_eq!(client_session.close().await.unwrap(), CloseOutcome::Acknowledged);
+        let (err, state) = server_handle.await.unwrap();
+        assert!(matches!(err, NetworkError::PeerClosed { reason: Some(_) }));
+        assert_eq!(state, SessionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_close_times_out_without_ack() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        // Connected but never reads again
+        let _server_session = server_handle.await.unwrap().unwrap();
+
+        client_session.set_close_timeout(Duration::from_millis(50));
+        assert_eq!(client_session.close().await.unwrap(), CloseOutcome::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_accept_reports_unexpected_first_frame() {
+        let (mut client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+
+        client_conn.send_message(&Message::heartbeat()).await.unwrap();
+        client_conn.flush().await.unwrap();
+
+        let err = server_handle.await.unwrap().err().unwrap().to_string();
+        assert!(err.contains("got Heartbeat"), "{}", err);
+        let size = Message::heartbeat().serialized_size().unwrap();
+        assert!(err.contains(&format!("{} bytes", size)), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_lenient_accept_skips_prelude_frames() {
+        let (mut client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept_with_strictness(server_conn, HandshakeStrictness::Lenient));
+
+        client_conn.send_message(&Message::heartbeat()).await.unwrap();
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        client_session.send(b"after prelude").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"after prelude");
+    }
+
+    #[tokio::test]
+    async fn test_cached_handshake() {
+        let server_key = SemiStaticKeyPair::generate(SESSION_KYBER_LEVEL, Duration::from_secs(3600)).unwrap();
+        let cached = server_key.public_key().clone();
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept_with_key(server_conn, &server_key).await.unwrap();
+            assert_eq!(session.recv().await.unwrap(), b"early data");
+            session.send(b"welcome back").await.unwrap();
+            session
+        });
+
+        // The first message goes out without waiting for the server
+        let mut client_session = Session::connect_cached(client_conn, &cached).await.unwrap();
+        client_session.send(b"early data").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"welcome back");
+
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cached_handshake_replay_rejected() {
+        let server_key = SemiStaticKeyPair::generate(SESSION_KYBER_LEVEL, Duration::from_secs(3600)).unwrap();
+        let (client_conn, mut recorder) = crate::network::connection::memory_pair();
+
+        let mut client_session = Session::connect_cached(client_conn, server_key.public_key()).await.unwrap();
+        client_session.send(b"transfer 100").await.unwrap();
+        client_session.connection.flush().await.unwrap();
+        let handshake = recorder.recv_message().await.unwrap();
+        let early_data = recorder.recv_message().await.unwrap();
+
+        // The first delivery is accepted; the same recording played again is not
+        for replay in [false, true] {
+            let (mut attacker, server_conn) = crate::network::connection::memory_pair();
+            attacker.send_message(&handshake).await.unwrap();
+            attacker.send_message(&early_data).await.unwrap();
+            attacker.flush().await.unwrap();
+
+            let accepted = Session::accept_with_key(server_conn, &server_key).await;
+            if replay {
+                assert!(accepted.is_err());
+            } else {
+                assert_eq!(accepted.unwrap().recv().await.unwrap(), b"transfer 100");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clock_offset_widens_timestamp_check() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        assert!(client_session.clock_offset().abs() <= 1);
+
+        // A peer running 400s fast would trip the 5-minute check without the offset
+        server_session.note_clock_offset(400);
+        let mut msg = Message::encrypted([0u8; 24], Vec::new(), 0, client_session.ratchet.send_epoch());
+        msg.timestamp += 400;
+        client_session.seal_next(&mut msg, b"from the future").unwrap();
+        client_session.connection.send_message(&msg).await.unwrap();
+
+        assert_eq!(server_session.recv().await.unwrap(), b"from the future");
+    }
+
+    #[tokio::test]
+    async fn test_clock_drift_tracked_from_authenticated_frames() {
+        const NOW: u64 = 1_700_000_000;
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        client_session.clock = || NOW;
+        assert_eq!(client_session.estimated_clock_offset(), 0);
+
+        let mut sealed = |timestamp: u64, plaintext: &[u8]| {
+            let mut msg = Message::encrypted([0u8; 24], Vec::new(), 0, server_session.ratchet.send_epoch());
+            msg.timestamp = timestamp;
+            server_session.seal_next(&mut msg, plaintext).unwrap();
+            msg
+        };
+        let drifted = sealed(NOW + 120, b"drifted");
+        let far = sealed(NOW + 400, b"far ahead");
+        let too_far = sealed(NOW + 601, b"too far");
+
+        // Heartbeats are unauthenticated and never move the offset
+        let mut heartbeat = Message::heartbeat();
+        heartbeat.timestamp = NOW + 250;
+        server_session.connection.send_message(&heartbeat).await.unwrap();
+        assert_eq!(client_session.recv_event().await.unwrap(), ReceivedEvent::Heartbeat);
+        assert_eq!(client_session.estimated_clock_offset(), 0);
+
+        // A sealed frame covers its timestamp, so it is measured, with one warning
+        server_session.connection.send_message(&drifted).await.unwrap();
+        assert_eq!(client_session.recv_event().await.unwrap(), ReceivedEvent::Message(b"drifted".to_vec()));
+        assert_eq!(client_session.recv_event().await.unwrap(), ReceivedEvent::ClockDriftWarning { offset_secs: 120 });
+        assert_eq!(client_session.estimated_clock_offset(), 120);
+
+        // Further drift is followed, but each sample stops at the skew window
+        server_session.connection.send_message(&far).await.unwrap();
+        assert_eq!(client_session.recv_event().await.unwrap(), ReceivedEvent::Message(b"far ahead".to_vec()));
+        assert_eq!(client_session.estimated_clock_offset(), MAX_CLOCK_SKEW_SECS);
+        server_session.connection.send_message(&too_far).await.unwrap();
+        assert!(client_session.recv_event().await.is_err());
+        assert_eq!(client_session.estimated_clock_offset(), MAX_CLOCK_SKEW_SECS);
+    }
+
+    #[tokio::test]
+    async fn test_aad_schema_mismatch_fails_to_decrypt() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        assert_eq!(client_session.suite().aad_schema, AadSchema::LATEST);
+        assert_eq!(server_session.suite(), client_session.suite());
+
+        client_session.send(b"agreed").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"agreed");
+
+        // Same keys, different AAD: the tag no longer verifies
+        client_session.suite.aad_schema = AadSchema::V1;
+        client_session.send(b"downgraded").await.unwrap();
+        assert!(server_session.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_aad_schema_falls_back_for_older_peers() {
+        let (mut client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+
+        // A peer from before negotiation leaves `key_id` at 0
+        let keypair = KeyPair::generate_with_level(SESSION_KYBER_LEVEL).unwrap();
+        client_conn.send_message(&Message::handshake(keypair.public_key().clone())).await.unwrap();
+        client_conn.flush().await.unwrap();
+        let response = client_conn.recv_message().await.unwrap();
+        assert_eq!(response.key_id, AadSchema::V0 as u16);
+
+        let server_session = server_handle.await.unwrap().unwrap();
+        assert_eq!(server_session.suite().aad_schema, AadSchema::V0);
+    }
+
+    #[tokio::test]
+    async fn test_oob_data_skips_the_ratchet() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        client_session.send_oob(b"sync", 3).await.unwrap();
+        assert_eq!(client_session.ratchet.send_counter(), 0);
+        assert_eq!(server_session.recv_event().await.unwrap(), ReceivedEvent::OobData(3, b"sync".to_vec()));
+
+        // Still usable mid-rotation, before the peer has seen the announcement
+        client_session.rotate_keys().await.unwrap();
+        client_session.send_oob(b"probe", 3).await.unwrap();
+        client_session.send(b"data").await.unwrap();
+        assert_eq!(server_session.recv_event().await.unwrap(), ReceivedEvent::OobData(3, b"probe".to_vec()));
+        assert_eq!(server_session.recv().await.unwrap(), b"data");
+
+        // The MAC key is per channel
+        let mut forged = Message::oob_data(4, 1, b"forged".to_vec(), [0u8; 32]);
+        forged.payload = MessagePayload::OobData {
+            channel: 4,
+            counter: 1,
+            data: b"forged".to_vec(),
+            mac: ratchet_key_hmac(&client_session.oob_key(SessionRole::Initiator, 3).unwrap(), &forged.oob_mac_input()).unwrap(),
+        };
+        client_session.connection.send_message(&forged).await.unwrap();
+        assert!(server_session.recv_event().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oob_data_rejects_replays_and_reflection() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        // Capture a genuine frame off the wire, deliver it, then deliver it again
+        let (tap, mut tap_remote) = crate::network::connection::memory_pair();
+        let real = std::mem::replace(&mut client_session.connection, tap);
+        client_session.send_oob(b"once", 2).await.unwrap();
+        client_session.connection.flush().await.unwrap();
+        let captured = tap_remote.recv_message().await.unwrap();
+        client_session.connection = real;
+
+        client_session.connection.send_message(&captured).await.unwrap();
+        client_session.connection.send_message(&captured).await.unwrap();
+        client_session.connection.flush().await.unwrap();
+        assert_eq!(server_session.recv_event().await.unwrap(), ReceivedEvent::OobData(2, b"once".to_vec()));
+        assert!(server_session.recv_event().await.is_err());
+
+        // The server's frames are keyed for its direction, so the client's can't be reflected at the client
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        let mut reflected = Message::oob_data(2, 1, b"mirror".to_vec(), [0u8; 32]);
+        let mac = ratchet_key_hmac(&client_session.oob_key(SessionRole::Initiator, 2).unwrap(), &reflected.oob_mac_input()).unwrap();
+        reflected.payload = MessagePayload::OobData { channel: 2, counter: 1, data: b"mirror".to_vec(), mac };
+        server_session.connection.send_message(&reflected).await.unwrap();
+        server_session.connection.flush().await.unwrap();
+        assert!(client_session.recv_event().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_handshake_rejected_from_header() {
+        let (mut client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        // A ~1MB public key is still under the general frame limit
+        let handshake = Message::new(MessageType::Handshake, MessagePayload::Handshake { public_key: vec![0; 1000 * 1000] });
+        tokio::spawn(async move {
+            let _ = client_conn.send_message(&handshake).await;
+            let _ = client_conn.flush().await;
+        });
+
+        let err = Session::accept(server_conn).await.err().unwrap();
+        assert!(err.to_string().contains(&format!("limit {}", max_handshake_size(SESSION_KYBER_LEVEL))));
+    }
+
+    #[tokio::test]
+    async fn test_max_handshake_bytes() {
+        let key_bytes = SESSION_KYBER_LEVEL.public_key_bytes();
+        let oversized = |extra: usize| Message::new(MessageType::Handshake, MessagePayload::Handshake { public_key: vec![0; key_bytes + extra] });
+        let limit = SessionConfig::default().max_handshake_bytes();
+
+        // One byte over the default cap
+        let frame = oversized(limit + 1 - oversized(0).serialized_size().unwrap());
+        assert_eq!(frame.serialized_size().unwrap(), limit + 1);
+        let (mut client_conn, server_conn) = crate::network::connection::memory_pair();
+        client_conn.send_message(&frame).await.unwrap();
+        client_conn.flush().await.unwrap();
+        let err = Session::accept(server_conn).await.err().unwrap();
+        assert!(err.to_string().contains(&format!("limit {}", limit)));
+
+        // A tighter configured cap refuses even a well-formed handshake
+        let config = SessionConfig { max_handshake_bytes: Some(1024), ..SessionConfig::default() };
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        tokio::spawn(Session::connect(client_conn));
+        let err = Session::accept_with_config(server_conn, &config).await.err().unwrap();
+        assert!(err.to_string().contains("limit 1024"));
+    }
+
+    #[tokio::test]
+    async fn test_configured_kyber_level() {
+        let config = SessionConfig { kem: KyberLevel::Kyber768, ..SessionConfig::default() };
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_config = config.clone();
+        let server_handle = tokio::spawn(async move { Session::accept_with_config(server_conn, &server_config).await });
+        let mut client = Session::connect_with_config(client_conn, &config).await.unwrap();
+        let mut server = server_handle.await.unwrap().unwrap();
+        assert_eq!(client.suite().kem, KyberLevel::Kyber768);
+        assert_eq!(server.suite().kem, KyberLevel::Kyber768);
+
+        // Rekeys stay at the configured level
+        client.dh_rekey().await.unwrap();
+        client.send(b"after offer").await.unwrap();
+        assert_eq!(server.recv().await.unwrap(), b"after offer");
+        server.send(b"after response").await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), b"after response");
+        assert!(client.pfs_confirmed());
+
+        // Mismatched levels don't get past the handshake
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        tokio::spawn(Session::connect(client_conn));
+        assert!(Session::accept_with_config(server_conn, &config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_muxed_sessions_share_one_handshake() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept_muxed(server_conn));
+        let client = Session::connect_muxed(client_conn).await.unwrap();
+        let mut server = server_handle.await.unwrap().unwrap();
+
+        let mut chat = client.open_session().await.unwrap();
+        let mut files = client.open_session().await.unwrap();
+        chat.send(b"hello").await.unwrap();
+        files.send(b"chunk 0").await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let mut session = server.accept_session().await.unwrap();
+            let data = session.recv().await.unwrap();
+            session.send(&data).await.unwrap();
+            received.push(data);
+        }
+        received.sort();
+        assert_eq!(received, vec![b"chunk 0".to_vec(), b"hello".to_vec()]);
+
+        // Each stream has its own keys, so echoes only decrypt on their own stream
+        assert_eq!(chat.recv().await.unwrap(), b"hello");
+        assert_eq!(files.recv().await.unwrap(), b"chunk 0");
+
+        client.close().await.unwrap();
+        assert!(server.accept_session().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rekey_on_schedule_cadences() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        server_session.set_rekey_policy(Some(RekeyPolicy {
+            symmetric_every: Duration::from_secs(10),
+            dh_every: Duration::from_secs(60),
+            jitter: Duration::ZERO,
+        }));
+        let start = Instant::now();
+
+        // Step a mock clock through two minutes, exchanging traffic each tick
+        let mut actions = Vec::new();
+        for tick in 0..=12u64 {
+            let now = start + Duration::from_secs(tick * 10);
+            if let Some(action) = server_session.rekey_on_schedule_at(now).await.unwrap() {
+                actions.push((tick, action));
+            }
+
+            server_session.send(b"ping").await.unwrap();
+            assert_eq!(client_session.recv().await.unwrap(), b"ping");
+            client_session.send(b"pong").await.unwrap();
+            assert_eq!(server_session.recv().await.unwrap(), b"pong");
+        }
+
+        let dh_ticks: Vec<u64> = actions.iter().filter(|(_, a)| *a == RekeyAction::Dh).map(|(t, _)| *t).collect();
+        assert_eq!(dh_ticks, vec![6, 12]);
+        assert_eq!(actions.iter().filter(|(_, a)| *a == RekeyAction::Symmetric).count(), 10);
+
+        // The last commit is delivered ahead of this message
+        server_session.send(b"still in sync").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"still in sync");
+
+        // Both DH rekeys moved both chains on both sides
+        assert_eq!(server_session.ratchet.send_epoch(), 12);
+        assert_eq!(client_session.ratchet.recv_epoch(), 12);
+        assert_eq!(client_session.ratchet.send_epoch(), 2);
+        assert_eq!(server_session.ratchet.recv_epoch(), 2);
+    }
+
+    #[test]
+    fn test_rekey_jitter_stays_centered() {
+        let policy = RekeyPolicy {
+            symmetric_every: Duration::from_secs(60),
+            dh_every: Duration::from_secs(100_000),
+            jitter: Duration::from_secs(10),
+        };
+        let start = Instant::now();
+        let mut schedule = RekeySchedule::new(policy, start);
+
+        let mut fired = Vec::new();
+        // Run past the last deadline's upper bound
+        for second in 0..=60 * 50 + 10 {
+            let now = start + Duration::from_secs(second);
+            if schedule.take_due(now).is_some() {
+                fired.push(second);
+            }
+        }
+
+        // Every rotation lands within the bound of its own period, so the
+        // jitter never accumulates
+        assert_eq!(fired.len(), 50);
+        for (period, second) in fired.iter().enumerate() {
+            let nominal = (period as u64 + 1) * 60;
+            assert!(second.abs_diff(nominal) <= 10, "rotation {} at {}s", period, second);
+        }
+        let offsets: std::collections::HashSet<u64> = fired.iter().map(|s| s % 60).collect();
+        assert!(offsets.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_set_rotation_interval_mid_session() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        let policy = RekeyPolicy {
+            symmetric_every: Duration::from_secs(60),
+            dh_every: Duration::from_secs(3600),
+            jitter: Duration::ZERO,
+        };
+        client_session.set_rekey_policy(Some(policy));
+        server_session.set_rekey_policy(Some(policy));
+        let start = Instant::now();
+
+        // Tighter than the chains' age, so no rotation yet
+        client_session.set_rotation_interval(Duration::from_secs(5)).await.unwrap();
+        client_session.send(b"tightened").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"tightened");
+        for session in [&client_session, &server_session] {
+            assert_eq!(session.ratchet.rotation_interval(), Duration::from_secs(5));
+            assert_eq!(session.ratchet.send_epoch(), 0);
+            assert!(session.next_rekey_at().unwrap() <= start + Duration::from_secs(5));
+        }
+
+        // The new cadence drives the schedule
+        let action = server_session.rekey_on_schedule_at(start + Duration::from_secs(6)).await.unwrap();
+        assert_eq!(action, Some(RekeyAction::Symmetric));
+
+        // Once the chains are older than the new interval, both rotate immediately
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        client_session.set_rotation_interval(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(client_session.ratchet.send_epoch(), 1);
+
+        server_session.send(b"after").await.unwrap();
+        client_session.send(b"rotated").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"rotated");
+        assert_eq!(client_session.recv().await.unwrap(), b"after");
+        // The scheduled rotation above, then the immediate one
+        assert_eq!(server_session.ratchet.send_epoch(), 2);
+        assert_eq!(server_session.ratchet.recv_epoch(), 1);
+        server_session.send(b"again").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"again");
+        assert_eq!(client_session.ratchet.recv_epoch(), 2);
+
+        assert!(client_session.set_rotation_interval(Duration::ZERO).await.is_err());
+        assert!(client_session.set_rotation_interval(MAX_ROTATION_INTERVAL + Duration::from_secs(1)).await.is_err());
+
+        // The announcement is sealed, and out-of-range values are refused on receipt
+        let mut msg = Message::rotation_interval([0u8; 24], Vec::new(), 0, client_session.ratchet.send_epoch());
+        client_session.seal_next(&mut msg, &u64::MAX.to_be_bytes()).unwrap();
+        client_session.connection.send_message(&msg).await.unwrap();
+        assert!(server_session.recv().await.is_err());
+        assert_eq!(server_session.ratchet.rotation_interval(), Duration::from_secs(1));
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn test_dump_keys() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        let path = std::env::temp_dir().join(format!("aegis-keylog-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        client_session.dump_keys(&path).unwrap();
+
+        client_session.send(b"one").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"one");
+        server_session.send(b"two").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"two");
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let labels: Vec<&str> = log.lines().map(|line| line.split(' ').next().unwrap()).collect();
+        assert_eq!(labels, vec!["AEGIS_ROOT_KEY", "AEGIS_SEND_KEY", "AEGIS_RECV_KEY"]);
+        assert!(log.lines().all(|line| line.rsplit(' ').next().unwrap().len() == 64));
+    }
+
+    #[tokio::test]
+    async fn test_nonces_fresh_across_rekey() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut session = Session::connect(client_conn).await.unwrap();
+        let _server = server_handle.await.unwrap().unwrap();
+        session.set_nonce_reuse_detection(true);
+
+        let seal = |session: &mut Session| {
+            let mut msg = Message::encrypted([0u8; 24], Vec::new(), 0, 0);
+            let counter = session.seal_next(&mut msg, b"same plaintext").unwrap();
+            let MessagePayload::EncryptedData { nonce, .. } = msg.payload else { unreachable!() };
+            (counter, nonce)
+        };
+
+        let before: Vec<_> = (0..8).map(|_| seal(&mut session)).collect();
+        // Rekeying resets the counters to 0
+        session.ratchet.rekey([7u8; 32]).unwrap();
+        let after: Vec<_> = (0..8).map(|_| seal(&mut session)).collect();
+
+        assert_eq!(before[0].0, 0);
+        assert_eq!(after[0].0, 0);
+        let nonces: HashSet<[u8; 24]> = before.iter().chain(&after).map(|(_, nonce)| *nonce).collect();
+        assert_eq!(nonces.len(), 16);
+        assert_eq!(session.sent_nonces.as_ref().unwrap().len(), 16);
+    }
+
+    #[tokio::test]
+    async fn test_session_pool_accepts_concurrent_clients() {
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let mut pool = SessionPool::with_config(listener, SessionPoolConfig {
+            max_concurrent_handshakes: 2,
+            handshake_timeout: Duration::from_millis(500),
+            ..SessionPoolConfig::default()
+        }).unwrap();
+        let addr = pool.local_addr().to_string();
+
+        // A client that never handshakes is reaped rather than holding a slot
+        let _idle = tokio::net::TcpStream::connect(&addr).await.unwrap();
+
+        let clients: Vec<_> = (0..4u8).map(|i| {
+            let addr = addr.clone();
+            tokio::spawn(async move {
+                let connection = crate::network::connection::connect(&addr).await.unwrap();
+                let mut session = Session::connect(connection).await.unwrap();
+                session.send(&[i]).await.unwrap();
+                session
+            })
+        }).collect();
+
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            let mut session = timeout(Duration::from_secs(10), pool.next()).await.unwrap().unwrap();
+            seen.push(session.recv().await.unwrap()[0]);
+        }
+        seen.sort();
+        assert_eq!(seen, vec![0, 1, 2, 3]);
+
+        for client in clients {
+            client.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_pool_tls_accept_does_not_block() {
+        let listener = Listener::bind_tls("127.0.0.1:0").await.unwrap();
+        let mut pool = SessionPool::with_config(listener, SessionPoolConfig {
+            max_concurrent_handshakes: 2,
+            handshake_timeout: Duration::from_secs(5),
+            ..SessionPoolConfig::default()
+        }).unwrap();
+        let addr = pool.local_addr().to_string();
+
+        // Never sends a ClientHello; the next client must not wait behind it
+        let _idle = tokio::net::TcpStream::connect(&addr).await.unwrap();
+
+        let client = tokio::spawn(async move {
+            let connection = crate::network::connection::connect_tls(&addr, "localhost").await.unwrap();
+            let mut session = Session::connect(connection).await.unwrap();
+            session.send(b"tls").await.unwrap();
+            session
+        });
+
+        let mut session = timeout(Duration::from_secs(2), pool.next()).await.unwrap().unwrap();
+        assert_eq!(session.recv().await.unwrap(), b"tls");
+        client.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transfer_to_new_connection() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        // Lost with the old path
+        client_session.send(b"lost").await.unwrap();
+
+        let (new_client, new_server) = crate::network::connection::memory_pair();
+        let first_token = client_session.migration_token(b"migrate-v1").unwrap();
+        let first_id = client_session.connection_id().unwrap();
+        let (transferred, accepted) = tokio::join!(
+            client_session.transfer_to_new_connection(new_client),
+            async { server_session.complete_migration(PendingMigration::read(new_server).await?).await },
+        );
+        transferred.unwrap();
+        accepted.unwrap();
+        assert_ne!(client_session.connection_id().unwrap(), first_id);
+
+        client_session.send(b"after").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"after");
+        server_session.send(b"reply").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"reply");
+
+        // The same token can't be used again
+        let (mut replay, replay_server) = crate::network::connection::memory_pair();
+        replay.send_message(&Message::migrate(client_session.connection_id().unwrap(), *first_token.as_bytes())).await.unwrap();
+        let pending = PendingMigration::read(replay_server).await.unwrap();
+        assert!(server_session.complete_migration(pending).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_migration_can_be_retried() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        let id = client_session.connection_id().unwrap();
+
+        // The new path dies before the server ever sees the request
+        let (lost, lost_server) = crate::network::connection::memory_pair();
+        drop(lost_server);
+        assert!(client_session.transfer_to_new_connection(lost).await.is_err());
+        assert_eq!(client_session.connection_id().unwrap(), id);
+
+        // Still in step, so the next attempt goes through
+        let (new_client, new_server) = crate::network::connection::memory_pair();
+        let (transferred, accepted) = tokio::join!(
+            client_session.transfer_to_new_connection(new_client),
+            async { server_session.complete_migration(PendingMigration::read(new_server).await?).await },
+        );
+        transferred.unwrap();
+        accepted.unwrap();
+        client_session.send(b"retried").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"retried");
+    }
+
+    #[tokio::test]
+    async fn test_migration_found_by_connection_id() {
+        let mut sessions = HashMap::new();
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let (client_conn, server_conn) = crate::network::connection::memory_pair();
+            let server_handle = tokio::spawn(Session::accept(server_conn));
+            let client_session = Session::connect(client_conn).await.unwrap();
+            let server_session = server_handle.await.unwrap().unwrap();
+            assert_eq!(client_session.connection_id().unwrap(), server_session.connection_id().unwrap());
+            sessions.insert(server_session.connection_id().unwrap(), server_session);
+            clients.push(client_session);
+        }
+
+        // The second client roams; the server finds its session from a fresh transport
+        let client_session = clients.pop().unwrap();
+        let old_id = client_session.connection_id().unwrap();
+        let (new_client, new_server) = crate::network::connection::memory_pair();
+        let mut client_session = client_session;
+        let client_handle = tokio::spawn(async move {
+            client_session.transfer_to_new_connection(new_client).await.map(|()| client_session)
+        });
+
+        let pending = PendingMigration::read(new_server).await.unwrap();
+        assert_eq!(pending.connection_id(), old_id);
+        let mut server_session = sessions.remove(&pending.connection_id()).unwrap();
+        server_session.complete_migration(pending).await.unwrap();
+        let mut client_session = client_handle.await.unwrap().unwrap();
+        assert_ne!(server_session.connection_id().unwrap(), old_id);
+        assert_eq!(server_session.connection_id().unwrap(), client_session.connection_id().unwrap());
+
+        client_session.send(b"roamed").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"roamed");
+
+        // Another session's ID doesn't fit
+        let (mut forged, forged_server) = crate::network::connection::memory_pair();
+        let other_id = *sessions.keys().next().unwrap();
+        forged.send_message(&Message::migrate(other_id, [0u8; 32])).await.unwrap();
+        let pending = PendingMigration::read(forged_server).await.unwrap();
+        assert!(server_session.complete_migration(pending).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_require_pfs_confirmed_blocks_send_until_rekey() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        client_session.require_pfs_confirmed();
+        assert!(matches!(client_session.send(b"secret").await, Err(NetworkError::PfsNotConfirmed)));
+        // A symmetric rotation doesn't count
+        client_session.rotate_keys().await.unwrap();
+        assert!(matches!(client_session.send(b"secret").await, Err(NetworkError::PfsNotConfirmed)));
+
+        // Offer, response, commit
+        client_session.dh_rekey().await.unwrap();
+        client_session.send_heartbeat().await.unwrap();
+        assert_eq!(server_session.recv_event().await.unwrap(), ReceivedEvent::Heartbeat);
+        server_session.send(b"ready").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"ready");
+        assert!(client_session.pfs_confirmed());
+
+        client_session.send(b"secret").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"secret");
+        assert!(server_session.pfs_confirmed());
+    }
+
+    #[tokio::test]
+    async fn test_otp_rotation() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        assert!(client_session.otp_rotate().await.is_err());
+
+        client_session.set_oob_seed(Some(b"printed on paper".to_vec()));
+        server_session.set_oob_seed(Some(b"printed on paper".to_vec()));
+        assert!(client_session.otp_rotate().await.unwrap());
+        // Once per minute
+        assert!(!client_session.otp_rotate().await.unwrap());
+
+        client_session.send(b"seeded").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"seeded");
+        assert_eq!(server_session.ratchet.recv_epoch(), 1);
+
+        // The commitment is keyed to the session, not a bare hash of the seed
+        let sent = client_session.otp_commitment(SessionRole::Initiator, b"printed on paper", unix_minute()).unwrap();
+        assert_ne!(sent, *blake3::hash(&otp_commitment_input(b"printed on paper", unix_minute())).as_bytes());
+        assert_ne!(sent, server_session.otp_commitment(SessionRole::Responder, b"printed on paper", unix_minute()).unwrap());
+
+        // A peer holding a different seed fails the commitment check
+        server_session.set_oob_seed(Some(b"guessed".to_vec()));
+        client_session.last_otp_minute = None;
+        client_session.otp_rotate().await.unwrap();
+        client_session.send(b"rejected").await.unwrap();
+        assert!(matches!(server_session.recv().await, Err(NetworkError::ProtocolError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_message_policy() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        // Ignored under the forward-compatible policy
+        server_session.set_unknown_message_policy(UnknownMessagePolicy::Ignore);
+        client_session.connection.send_message(&Message::extension(0x90, b"v2 feature".to_vec())).await.unwrap();
+        client_session.send(b"still here").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"still here");
+
+        // Fatal by default
+        server_session.set_unknown_message_policy(UnknownMessagePolicy::Error);
+        client_session.connection.send_message(&Message::extension(0x90, Vec::new())).await.unwrap();
+        let err = server_session.recv().await.unwrap_err();
+        assert!(err.to_string().contains("0x90"));
+    }
+
+    #[tokio::test]
+    async fn test_prekey_session() {
+        let identity = crate::crypto::signing::SigningKeyPair::generate().unwrap();
+        let mut store = PreKeyStore::generate(identity, SESSION_KYBER_LEVEL, 1).unwrap();
+        let bundle = store.bundle();
+        let (client_conn, mut server_conn) = crate::network::connection::memory_pair();
+
+        let one_time_id = bundle.one_time_prekeys[0].id;
+        let mut client_session = Session::init_from_prekey(client_conn, &bundle, Some(one_time_id)).await.unwrap();
+        client_session.send(b"while you were out").await.unwrap();
+
+        // The owner comes online later and picks up the initial message
+        let initial = server_conn.recv_message().await.unwrap();
+        let mut server_session = Session::accept_prekey(server_conn, &mut store, &initial).unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"while you were out");
+        server_session.send(b"back now").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"back now");
+
+        // The one-time pre-key is spent, so the message can't be replayed
+        let (_, replay_conn) = crate::network::connection::memory_pair();
+        assert!(Session::accept_prekey(replay_conn, &mut store, &initial).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_handshake_with_rotated_key() {
+        let server_key = SemiStaticKeyPair::generate(SESSION_KYBER_LEVEL, Duration::ZERO).unwrap();
+        let stale = server_key.public_key().clone();
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut server_key = server_key;
+            assert!(server_key.rotate_if_due().unwrap());
+            Session::accept_with_key(server_conn, &server_key).await.map(|_| ())
+        });
+
+        let mut client_session = Session::connect_cached(client_conn, &stale).await.unwrap();
+        assert!(server_handle.await.unwrap().is_err());
+        assert!(client_session.recv().await.is_err());
+        assert_eq!(client_session.state(), SessionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_accept_with_key_allows_full_handshake() {
+        let server_key = SemiStaticKeyPair::generate(SESSION_KYBER_LEVEL, Duration::from_secs(3600)).unwrap();
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept_with_key(server_conn, &server_key).await.unwrap();
+            session.recv().await.unwrap()
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        client_session.send(b"no cache").await.unwrap();
+        assert_eq!(server_handle.await.unwrap(), b"no cache");
+    }
+
+    #[tokio::test]
+    async fn test_session_quota() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let mut first = [0u8; 600];
+        let mut second = [0u8; 600];
+        fill_with_pattern(&mut first, b"first");
+        fill_with_pattern(&mut second, b"second");
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            session.set_quota(Some(Quota::new(1024, Duration::from_millis(200))));
+
+            assert_eq!(session.recv().await.unwrap(), first);
+            assert!(matches!(session.recv().await, Err(NetworkError::RateLimited)));
+            assert!(session.quota_usage().unwrap().used_bytes > 1024);
+
+            // Next window
+            assert_eq!(session.recv().await.unwrap(), b"later");
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        client_session.send(&first).await.unwrap();
+        client_session.send(&second).await.unwrap();
+        assert!(matches!(client_session.recv().await, Err(NetworkError::RateLimited)));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        client_session.send(b"later").await.unwrap();
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recv_secret_hardened() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            session.set_zeroize_ciphertext(true);
+            let secret = session.recv_secret().await.unwrap();
+            assert_eq!(&*secret, b"top secret");
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        client_session.send(b"top secret").await.unwrap();
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_telemetry_hook() {
+        use crate::telemetry::PrometheusSessionTelemetry;
+
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let telemetry = Arc::new(PrometheusSessionTelemetry::new());
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            session.recv().await.unwrap();
+            session
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        client_session.telemetry_hook(telemetry.clone());
+        client_session.send(b"counted").await.unwrap();
+        client_session.rotate_keys().await.unwrap();
+        let server_session = server_handle.await.unwrap();
+        drop(server_session);
+        assert!(client_session.recv().await.is_err());
+
+        let text = telemetry.render();
+        assert!(text.contains("aegis_handshakes_total 1\n"));
+        assert!(text.contains("aegis_messages_sent_total 1\n"));
+        assert!(text.contains("aegis_bytes_sent_total 7\n"));
+        assert!(text.contains("aegis_key_rotations_total 1\n"));
+        assert!(text.contains("aegis_session_errors_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_dead_peer_detected() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        // Peer completes the handshake, then goes silent without closing
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let _silent_peer = server_handle.await.unwrap().unwrap();
+
+        client_session.set_dead_peer_timeout(Some(Duration::from_millis(200)));
+        client_session.send(b"anyone there?").await.unwrap();
+
+        let started = Instant::now();
+        let event = timeout(Duration::from_secs(2), client_session.recv_event()).await.unwrap().unwrap();
+        assert_eq!(event, ReceivedEvent::Disconnected);
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(client_session.state(), SessionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_responsive_peer_not_declared_dead() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            // Answers heartbeats but never sends data
+            while session.recv().await.is_ok() {}
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        client_session.set_dead_peer_timeout(Some(Duration::from_millis(100)));
+        client_session.send(b"quiet listener").await.unwrap();
+
+        assert!(timeout(Duration::from_millis(500), client_session.recv_event()).await.is_err());
+        assert_eq!(client_session.state(), SessionState::Established);
+        server_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_null_cipher_exposes_plaintext() {
+        use crate::crypto::symmetric::NullCipher;
+
+        let _null = NullCipher::enable();
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            let raw = session.connection.recv_message().await.unwrap();
+            match raw.payload {
+                MessagePayload::EncryptedData { ciphertext, message_counter, .. } => {
+                    assert_eq!(ciphertext, b"in the clear");
+                    assert_eq!(message_counter, 0);
+                }
+                other => panic!("unexpected payload: {:?}", other),
+            }
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        client_session.send(b"in the clear").await.unwrap();
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_peer_info_exchange() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            assert_eq!(session.peer_label(), session.peer_addr.to_string());
+            let ReceivedEvent::PeerInfo(info) = session.recv_event().await.unwrap() else {
+                panic!("expected the peer's PeerInfo first");
+            };
+            assert_eq!(info.display_name, "alice");
+            assert_eq!(session.recv().await.unwrap(), b"hi");
+            session
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let info = PeerInfo::new("alice", vec!["signed_messages".to_string()]);
+        client_session.send_peer_info(&info).await.unwrap();
+        client_session.send(b"hi").await.unwrap();
+
+        let server_session = server_handle.await.unwrap();
+        assert_eq!(server_session.peer_info(), Some(&info));
+        assert!(server_session.peer_info().unwrap().has_capability("signed_messages"));
+        assert_eq!(server_session.peer_label(), "alice");
+
+        let bad = PeerInfo::new("evil\x1b[2J", Vec::new());
+        assert!(client_session.send_peer_info(&bad).await.is_err());
+        let bad = PeerInfo::new("bob", vec!["x".repeat(MAX_CAPABILITY_LEN + 1)]);
+        assert!(client_session.send_peer_info(&bad).await.is_err());
+        let bad = PeerInfo::new("bob", vec!["file transfer\n".to_string()]);
+        assert!(client_session.send_peer_info(&bad).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_message_exchange() {
+        // Start listener
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Spawn server task
+        let server_handle = tokio::spawn(async move {
+            let conn = listener.accept().await.unwrap();
+            let mut session = Session::accept(conn).await.unwrap();
 
             // Receive message
             let received = session.recv().await.unwrap();
@@ -297,4 +4329,633 @@ mod tests {
 
         server_handle.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_empty_message_distinct_from_heartbeat() {
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_handle = tokio::spawn(async move {
+            let conn = listener.accept().await.unwrap();
+            let mut session = Session::accept(conn).await.unwrap();
+
+            assert_eq!(session.recv_event().await.unwrap(), ReceivedEvent::Heartbeat);
+            assert_eq!(session.recv_event().await.unwrap(), ReceivedEvent::Message(Vec::new()));
+        });
+
+        let client_conn = crate::network::connection::connect(&addr.to_string()).await.unwrap();
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+
+        client_session.send_heartbeat().await.unwrap();
+        client_session.send(b"").await.unwrap();
+
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recv_data_skips_heartbeats_and_empty_messages() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client = Session::connect(client_conn).await.unwrap();
+        let mut server = server_handle.await.unwrap().unwrap();
+
+        client.send_heartbeat().await.unwrap();
+        client.send(b"").await.unwrap();
+        client.send(b"hello").await.unwrap();
+        assert_eq!(server.recv_data().await.unwrap(), b"hello");
+
+        // Plain recv still reports the heartbeat as empty
+        client.send_heartbeat().await.unwrap();
+        assert!(server.recv().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recv_stats_track_type_distribution() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client = Session::connect(client_conn).await.unwrap();
+        let mut server = server_handle.await.unwrap().unwrap();
+
+        for _ in 0..3 {
+            client.send_heartbeat().await.unwrap();
+        }
+        client.send(b"small").await.unwrap();
+        client.send(&[7u8; 4096]).await.unwrap();
+        client.send_oob(b"side", 1).await.unwrap();
+        client.send(b"last").await.unwrap();
+
+        assert_eq!(server.recv_data().await.unwrap(), b"small");
+        assert_eq!(server.recv_data().await.unwrap().len(), 4096);
+        assert_eq!(server.recv_data().await.unwrap(), b"last");
+
+        let stats = server.recv_stats();
+        assert_eq!(stats.frames, 7);
+        assert_eq!(stats.of_type(MessageType::Heartbeat).count, 3);
+        assert_eq!(stats.of_type(MessageType::EncryptedMessage).count, 3);
+        assert_eq!(stats.of_type(MessageType::OobData).count, 1);
+        assert_eq!(stats.of_type(MessageType::Handshake).count, 0);
+        assert!((stats.share(MessageType::Heartbeat) - 3.0 / 7.0).abs() < 1e-9);
+        assert!(stats.largest_frame > 4096);
+        assert_eq!(stats.bytes, stats.by_type.values().map(|t| t.bytes).sum::<u64>());
+    }
+
+    #[tokio::test]
+    async fn test_recv_many_batches_buffered_messages() {
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_handle = tokio::spawn(async move {
+            let conn = listener.accept().await.unwrap();
+            let mut session = Session::accept(conn).await.unwrap();
+
+            let first: Vec<_> = session.recv_many(3).await.into_iter().map(|r| r.unwrap()).collect();
+            assert_eq!(first, vec![b"m0".to_vec(), b"m1".to_vec(), b"m2".to_vec()]);
+
+            // The heartbeat in between is handled but not counted
+            let rest: Vec<_> = session.recv_many(10).await.into_iter().map(|r| r.unwrap()).collect();
+            assert_eq!(rest, vec![b"m3".to_vec(), b"m4".to_vec()]);
+        });
+
+        let client_conn = crate::network::connection::connect(&addr.to_string()).await.unwrap();
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+
+        // Coalesce everything into one write so it is all buffered at once
+        client_session.connection.set_flush_linger(Duration::from_secs(60));
+        for i in 0..5 {
+            if i == 3 {
+                client_session.send_heartbeat().await.unwrap();
+            }
+            client_session.send(format!("m{}", i).as_bytes()).await.unwrap();
+        }
+        client_session.connection.flush().await.unwrap();
+
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_discover_mtu() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            // Probes are answered inside recv
+            assert_eq!(session.recv().await.unwrap(), b"after discovery");
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        assert_eq!(client_session.mtu(), None);
+
+        let mtu = client_session.discover_mtu().await.unwrap();
+        assert_eq!(mtu, MTU_PROBE_MAX);
+        assert_eq!(client_session.mtu(), Some(MTU_PROBE_MAX));
+
+        client_session.send(b"after discovery").await.unwrap();
+        server_handle.await.unwrap();
+        assert_eq!(client_session.stream_chunk_size(), STREAM_CHUNK_SIZE);
+
+        // Stream chunks shrink to fit a smaller path
+        client_session.mtu = Some(1200);
+        let chunk = vec![0u8; client_session.stream_chunk_size()];
+        let mut sealed = Message::encrypted([0u8; 24], Vec::new(), 0, 0);
+        client_session.seal_next(&mut sealed, &chunk).unwrap();
+        assert_eq!(sealed.to_bytes().unwrap().len(), Message::probe(1200).to_bytes().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_session_stream_full_duplex() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let client = Session::connect(client_conn).await.unwrap().into_stream();
+        let server = server_handle.await.unwrap().unwrap().into_stream();
+
+        // Both ends send and receive at once, so each pump keeps switching
+        // between its local reader and the session without losing a frame
+        // (kept under the in-memory pipe's capacity, as neither pump reads
+        // while its own send waits for room)
+        let payload: Vec<u8> = (0..(STREAM_CHUNK_SIZE * 2 + 5)).map(|i| (i % 251) as u8).collect();
+        let transfer = |stream: SessionStream, payload: Vec<u8>| async move {
+            let (mut reader, mut writer) = tokio::io::split(stream);
+            let expected = payload.len();
+            let write = tokio::spawn(async move { writer.write_all(&payload).await.unwrap() });
+            let mut received = vec![0u8; expected];
+            reader.read_exact(&mut received).await.unwrap();
+            write.await.unwrap();
+            received
+        };
+
+        let (from_server, from_client) = tokio::join!(
+            transfer(client, payload.clone()),
+            transfer(server, payload.clone()),
+        );
+        assert_eq!(from_server, payload);
+        assert_eq!(from_client, payload);
+    }
+
+    #[tokio::test]
+    async fn test_session_stream_echo() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let session = Session::accept(server_conn).await.unwrap();
+            let mut stream = session.into_stream();
+
+            // Echo until the client closes
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(&buf[..n]).await.unwrap();
+            }
+        });
+
+        let client_session = Session::connect(client_conn).await.unwrap();
+        let mut stream = client_session.into_stream();
+
+        // Larger than one chunk, so it is split across several messages
+        let payload: Vec<u8> = (0..(STREAM_CHUNK_SIZE * 3 + 17)).map(|i| i as u8).collect();
+        stream.write_all(&payload).await.unwrap();
+
+        let mut echoed = vec![0u8; payload.len()];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(echoed, payload);
+
+        stream.shutdown().await.unwrap();
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_one_sided_rotation_followed_by_peer() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+
+            assert_eq!(session.recv().await.unwrap(), b"before");
+            // The KeyRotation announcement is applied inside recv
+            assert_eq!(session.recv().await.unwrap(), b"after");
+            assert_eq!(session.ratchet.recv_epoch(), 1);
+            assert_eq!(session.ratchet.send_epoch(), 0);
+
+            session.send(b"reply").await.unwrap();
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+
+        client_session.send(b"before").await.unwrap();
+        client_session.rotate_keys().await.unwrap();
+        client_session.send(b"after").await.unwrap();
+
+        // Our receiving chain was not rotated, matching the peer's unrotated send chain
+        assert_eq!(client_session.recv().await.unwrap(), b"reply");
+        assert_eq!(client_session.ratchet.send_epoch(), 1);
+        assert_eq!(client_session.ratchet.recv_epoch(), 0);
+
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ping_records_rtt() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+
+        let server_handle = tokio::spawn(async move {
+            let mut session = Session::accept(server_conn).await.unwrap();
+            for _ in 0..3 {
+                assert_eq!(session.recv_event().await.unwrap(), ReceivedEvent::Heartbeat);
+            }
+            assert_eq!(session.recv().await.unwrap(), b"done");
+        });
+
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        assert_eq!(client_session.ping_rtt_histogram().len(), 0);
+
+        for _ in 0..3 {
+            client_session.ping().await.unwrap();
+        }
+
+        assert_eq!(client_session.ping_rtt_histogram().len(), 3);
+        assert!(client_session.max_rtt_us() >= client_session.p99_rtt_us());
+        assert!(client_session.mean_rtt_us() > 0.0);
+
+        client_session.send(b"done").await.unwrap();
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_upgrade_to_tls() {
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_handle = tokio::spawn(async move {
+            let conn = listener.accept().await.unwrap();
+            let mut session = Session::accept(conn).await.unwrap();
+
+            assert_eq!(session.recv().await.unwrap(), b"before upgrade");
+
+            // The upgrade is handled transparently inside recv
+            assert_eq!(session.recv().await.unwrap(), b"after upgrade");
+            assert!(session.connection.is_tls());
+            session.send(b"over tls").await.unwrap();
+        });
+
+        let client_conn = crate::network::connection::connect(&addr.to_string()).await.unwrap();
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+
+        client_session.send(b"before upgrade").await.unwrap();
+        client_session.upgrade_to_tls("localhost").await.unwrap();
+        assert!(client_session.connection.is_tls());
+
+        // Ratchet continues across the upgrade
+        client_session.send(b"after upgrade").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"over tls");
+        assert_eq!(client_session.ratchet.send_counter(), 2);
+
+        // A second upgrade is refused
+        assert!(client_session.upgrade_to_tls("localhost").await.is_err());
+
+        server_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_audit_log() {
+        use crate::security::audit::{MemoryAuditSink, verify_chain};
+
+        let listener = Listener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_handle = tokio::spawn(async move {
+            let conn = listener.accept().await.unwrap();
+            let mut session = Session::accept(conn).await.unwrap();
+            assert_eq!(session.recv().await.unwrap(), b"never logged");
+            assert!(session.recv().await.is_err());
+        });
+
+        let client_conn = crate::network::connection::connect(&addr.to_string()).await.unwrap();
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+
+        let sink = MemoryAuditSink::new();
+        client_session.set_audit_log(AuditLog::new(sink.clone()));
+        client_session.send(b"never logged").await.unwrap();
+        client_session.rotate_keys().await.unwrap();
+        client_session.close().await.unwrap();
+
+        server_handle.await.unwrap();
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(entries[0].event, AuditEvent::HandshakeCompleted {
+            role: SessionRole::Initiator,
+            kem: Some(KyberLevel::Kyber1024),
+            tls: false,
+            ..
+        }));
+        assert!(matches!(entries[1].event, AuditEvent::KeyRotated { send_counter: 1, .. }));
+        assert!(matches!(entries[2].event, AuditEvent::Disconnected { initiated_locally: true, .. }));
+        assert!(verify_chain(&entries).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tls_only_session_keys_agree() {
+        let listener = Listener::bind_tls("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server_handle = tokio::spawn(async move {
+            let _unverified = listener.accept().await.unwrap();
+            let conn = listener.accept().await.unwrap();
+            let mut session = Session::accept_tls_only(conn).unwrap();
+            let received = session.recv().await.unwrap();
+            session.send(&received).await.unwrap();
+            session
+        });
+
+        // Without a verifier there is no telling who the keys are shared with
+        let unverified = crate::network::connection::connect_tls(&addr, "tls-only.test").await.unwrap();
+        assert!(Session::connect_tls_only(unverified).is_err());
+
+        // (accepting any certificate here, as the listener's is self-signed)
+        let verifier = Arc::new(crate::network::connection::SkipServerVerification);
+        let conn = crate::network::connection::connect_tls_with_verifier(&addr, "tls-only.test", verifier).await.unwrap();
+        let mut client_session = Session::connect_tls_only(conn).unwrap();
+        assert!(!client_session.is_post_quantum());
+
+        // The audit log must not claim a Kyber exchange that never happened
+        let sink = crate::security::audit::MemoryAuditSink::new();
+        client_session.set_audit_log(AuditLog::new(sink.clone()));
+        assert!(matches!(sink.entries()[0].event, AuditEvent::HandshakeCompleted { kem: None, tls: true, .. }));
+
+        client_session.send(b"exporter keyed").await.unwrap();
+        assert_eq!(client_session.recv().await.unwrap(), b"exporter keyed");
+        assert!(!server_handle.await.unwrap().is_post_quantum());
+
+        // Without TLS there is nothing to export
+        let (plain, _peer) = crate::network::connection::memory_pair();
+        assert!(Session::connect_tls_only(plain).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_signed_recv_verified() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        assert_eq!(client_session.transcript_hash().unwrap(), server_session.transcript_hash().unwrap());
+
+        let sink = crate::security::audit::MemoryAuditSink::new();
+        server_session.set_audit_log(AuditLog::new(sink.clone()));
+        let signer = SigningKeyPair::generate().unwrap();
+        let other = SigningKeyPair::generate().unwrap();
+
+        client_session.send_signed(b"first", &signer).await.unwrap();
+        client_session.send_signed(b"second", &signer).await.unwrap();
+        assert_eq!(server_session.recv_verified(signer.verifying_key()).await.unwrap(), b"first");
+        assert_eq!(server_session.recv_verified(signer.verifying_key()).await.unwrap(), b"second");
+
+        // Wrong signer, and an unsigned message
+        client_session.send_signed(b"third", &other).await.unwrap();
+        assert!(server_session.recv_verified(signer.verifying_key()).await.is_err());
+        client_session.send(b"unsigned").await.unwrap();
+        assert!(server_session.recv_verified(signer.verifying_key()).await.is_err());
+
+        let results: Vec<bool> = sink.entries().into_iter()
+            .filter_map(|entry| match entry.event {
+                AuditEvent::AuthResult { method, success } if method == "signature" => Some(success),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results, [true, true, false, false]);
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_until_eof() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let client_session = Session::connect(client_conn).await.unwrap();
+        let server_session = server_handle.await.unwrap().unwrap();
+
+        // The server's input never ends; the client's ends after one line
+        let (_server_input, idle) = tokio::io::duplex(64);
+        let server = tokio::spawn(async move {
+            let mut output = Vec::new();
+            server_session.tunnel(idle, &mut output).await.map(|_| output)
+        });
+
+        client_session.tunnel(&b"hello\n"[..], tokio::io::sink()).await.unwrap();
+        assert_eq!(server.await.unwrap().unwrap(), b"hello\n");
+    }
+
+    #[tokio::test]
+    async fn test_send_ephemeral_ttl_bound() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        assert!(client_session.send_ephemeral(b"now", Duration::from_millis(500)).await.is_err());
+        // Expiry follows the receiver's clock
+        server_session.clock = || current_timestamp() + 5;
+        let before = current_timestamp();
+        client_session.send_ephemeral(b"gone soon", Duration::from_secs(30)).await.unwrap();
+        match server_session.recv_event().await.unwrap() {
+            ReceivedEvent::EphemeralMessage { data, expires_at } => {
+                assert_eq!(data, b"gone soon");
+                assert!((before + 35..=current_timestamp() + 35).contains(&expires_at));
+            }
+            other => panic!("expected an ephemeral message, got {:?}", other),
+        }
+
+        // Stretching the TTL in transit breaks the AEAD tag
+        let mut msg = Message::ephemeral([0u8; 24], Vec::new(), 0, client_session.ratchet.epoch() as u16, 30);
+        client_session.seal_next(&mut msg, b"tampered").unwrap();
+        if let MessagePayload::EphemeralData { ttl_secs, .. } = &mut msg.payload {
+            *ttl_secs = u32::MAX;
+        }
+        assert!(server_session.process_message(msg).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_statistics_interval() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        let mut stats = client_session.statistics_interval(Duration::from_millis(10));
+        let before = stats.next().await.unwrap();
+        assert_eq!(before, client_session.statistics());
+        assert_eq!(before.key_rotations, 0);
+
+        client_session.send(b"hello").await.unwrap();
+        client_session.rotate_keys().await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"hello");
+
+        let after = stats.next().await.unwrap();
+        assert_eq!(after.messages_sent, before.messages_sent + 2);
+        assert!(after.bytes_sent > before.bytes_sent);
+        assert_eq!(after.key_rotations, 1);
+        assert_eq!(after.epoch, client_session.ratchet.epoch());
+        assert_eq!(after.health_score, 1.0);
+
+        // With no stream left, sends don't pay for a snapshot; a new stream starts current
+        drop(stats);
+        client_session.send(b"unwatched").await.unwrap();
+        assert_ne!(*client_session.published_stats.lock().unwrap(), client_session.statistics());
+        let mut stats = client_session.statistics_interval(Duration::from_millis(10));
+        assert_eq!(stats.next().await.unwrap(), client_session.statistics());
+    }
+
+    #[tokio::test]
+    async fn test_contacts_share_one_connection() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        for (session, role) in [(&mut client_session, SessionRole::Initiator), (&mut server_session, SessionRole::Responder)] {
+            session.add_contact(1, [1u8; 32], role).unwrap();
+            session.add_contact(2, [2u8; 32], role).unwrap();
+        }
+        assert!(client_session.add_contact(1, [3u8; 32], SessionRole::Initiator).is_err());
+        assert!(client_session.send_to(3, b"nobody").await.is_err());
+
+        client_session.send_to(1, b"for alice").await.unwrap();
+        client_session.rotate_contact_keys(2).await.unwrap();
+        client_session.send_to(2, b"for bob").await.unwrap();
+        client_session.send(b"for the relay").await.unwrap();
+        assert_eq!(server_session.recv_from().await.unwrap(), (1, b"for alice".to_vec()));
+        assert_eq!(server_session.recv_from().await.unwrap(), (2, b"for bob".to_vec()));
+        assert_eq!(server_session.recv().await.unwrap(), b"for the relay");
+
+        // Each contact's chain advanced on its own
+        assert_eq!(server_session.contacts[&1].recv_counter(), 1);
+        assert_eq!(server_session.contacts[&2].recv_epoch(), 1);
+        assert_eq!(server_session.ratchet.recv_counter(), 1);
+
+        // A message can't be redirected to another contact
+        let mut msg = Message::contact(1, [0u8; 24], Vec::new(), 0, 0);
+        client_session.seal_next(&mut msg, b"redirected").unwrap();
+        if let MessagePayload::ContactData { contact_id, .. } = &mut msg.payload {
+            *contact_id = 2;
+        }
+        assert!(server_session.process_message(msg).await.is_err());
+
+        assert!(server_session.remove_contact(1));
+        assert_eq!(server_session.contact_ids().collect::<Vec<_>>(), [2]);
+    }
+
+    #[tokio::test]
+    async fn test_contacts_through_a_relay() {
+        async fn relayed_client() -> (Session, Session) {
+            let (client_conn, relay_conn) = crate::network::connection::memory_pair();
+            let relay_handle = tokio::spawn(Session::accept(relay_conn));
+            let client = Session::connect(client_conn).await.unwrap();
+            (client, relay_handle.await.unwrap().unwrap())
+        }
+
+        // Alice and Bob both dial the relay, so both are transport initiators
+        let (mut alice, mut relay_alice) = relayed_client().await;
+        let (mut bob, mut relay_bob) = relayed_client().await;
+        alice.add_contact(7, [7u8; 32], SessionRole::Initiator).unwrap();
+        bob.add_contact(7, [7u8; 32], SessionRole::Responder).unwrap();
+
+        // The relay holds no contact keys and forwards frames untouched
+        alice.send_to(7, b"hi bob").await.unwrap();
+        alice.rotate_contact_keys(7).await.unwrap();
+        alice.send_to(7, b"after rotating").await.unwrap();
+        for _ in 0..3 {
+            let frame = relay_alice.connection.recv_message().await.unwrap();
+            relay_bob.connection.send_message(&frame).await.unwrap();
+        }
+        assert_eq!(bob.recv_from().await.unwrap(), (7, b"hi bob".to_vec()));
+        assert_eq!(bob.recv_from().await.unwrap(), (7, b"after rotating".to_vec()));
+
+        bob.send_to(7, b"hi alice").await.unwrap();
+        let frame = relay_bob.connection.recv_message().await.unwrap();
+        relay_alice.connection.send_message(&frame).await.unwrap();
+        assert_eq!(alice.recv_from().await.unwrap(), (7, b"hi alice".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_tracked_messages_report_acks() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+
+        let mut ids = Vec::new();
+        for text in [&b"one"[..], b"two", b"three"] {
+            ids.push(client_session.send_tracked(text).await.unwrap());
+        }
+        client_session.send(b"untracked").await.unwrap();
+
+        // The consumer handles every message but only acks the odd ones
+        for (i, expected) in [&b"one"[..], b"two", b"three"].into_iter().enumerate() {
+            let (data, token) = server_session.recv_with_token().await.unwrap();
+            assert_eq!(data, expected);
+            let token = token.unwrap();
+            assert_eq!(token.message_id(), ids[i]);
+            if i != 1 {
+                token.ack(&mut server_session).await.unwrap();
+            }
+        }
+        assert_eq!(server_session.recv_with_token().await.unwrap(), (b"untracked".to_vec(), None));
+
+        assert_eq!(client_session.recv_event().await.unwrap(), ReceivedEvent::Delivered { message_id: ids[0] });
+        assert_eq!(client_session.recv_event().await.unwrap(), ReceivedEvent::Delivered { message_id: ids[2] });
+        // A plaintext ack injected on the path is not a delivery
+        server_session.connection.send_message(&Message::ack(ids[1])).await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(100), client_session.recv_event()).await.is_err());
+        assert_eq!(client_session.awaiting_ack_count(), 1);
+        assert!(client_session.overdue_deliveries(Duration::from_secs(60)).is_empty());
+        assert_eq!(client_session.overdue_deliveries(Duration::ZERO), [ids[1]]);
+        assert_eq!(client_session.awaiting_ack_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_gated_frames_need_negotiated_features() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        assert!(client_session.suite.supports(WireVersion::FEATURE_DELIVERY_ACKS));
+        assert_eq!(client_session.suite.features, server_session.suite.features);
+
+        // Pretend the server predates delivery acks
+        server_session.suite.features &= !WireVersion::FEATURE_DELIVERY_ACKS;
+        assert!(server_session.send_tracked(b"nope").await.is_err());
+        client_session.send_tracked(b"tracked").await.unwrap();
+        assert!(server_session.recv().await.is_err());
+
+        server_session.suite.features = 0;
+        assert!(server_session.discover_mtu().await.is_err());
+        assert!(server_session.send_peer_info(&PeerInfo::new("alice".to_string(), Vec::new())).await.is_err());
+        assert!(server_session.send_ephemeral(b"nope", Duration::from_secs(5)).await.is_err());
+        assert!(server_session.add_contact(1, [0u8; 32], SessionRole::Initiator).is_err());
+        assert_eq!(server_session.close().await.unwrap(), CloseOutcome::AckUnsupported);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_echo_without_response_feature() {
+        let (client_conn, server_conn) = crate::network::connection::memory_pair();
+        let server_handle = tokio::spawn(Session::accept(server_conn));
+        let mut client_session = Session::connect(client_conn).await.unwrap();
+        let mut server_session = server_handle.await.unwrap().unwrap();
+        assert!(client_session.suite.supports(WireVersion::FEATURE_HEARTBEAT_RESPONSE));
+
+        // Pretend the peers predate heartbeat responses
+        client_session.suite.features &= !WireVersion::FEATURE_HEARTBEAT_RESPONSE;
+        server_session.suite.features &= !WireVersion::FEATURE_HEARTBEAT_RESPONSE;
+
+        client_session.send_heartbeat().await.unwrap();
+        assert_eq!(server_session.recv_event().await.unwrap(), ReceivedEvent::Heartbeat);
+        let reply = client_session.connection.recv_message().await.unwrap();
+        assert_eq!(reply.message_type, MessageType::Heartbeat);
+
+        // The echo answers our heartbeat without an RTT sample or another echo
+        assert_eq!(client_session.handle_message(reply).await.unwrap(), None);
+        assert!(client_session.heartbeat_sent_at.is_none());
+        assert_eq!(client_session.ping_rtt_histogram().len(), 0);
+        client_session.send(b"done").await.unwrap();
+        assert_eq!(server_session.recv().await.unwrap(), b"done");
+    }
 }
@@ -1,62 +1,177 @@
 // Ephemeral secure memory storage
 // Memory is locked, zeroized, and protected against swapping
 
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
+use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SecureMemoryError {
+    #[error("Failed to lock {len} bytes in memory: {source}")]
+    LockFailed {
+        len: usize,
+        source: std::io::Error,
+    },
+}
 
 /// Secure buffer that locks memory and zeroizes on drop
+///
+/// Derefs to `[u8]`, never to the `Vec`, so the allocation can only change
+/// through `extend_from_slice`/`push`, which keep it locked and wiped.
 pub struct SecureBuffer {
     data: Vec<u8>,
     locked: bool,
+    /// Bytes covered by the `mlock` call (the allocation, not just `len`)
+    locked_len: usize,
+    /// Created with `new_strict`/`from_vec_strict`: growing must stay locked
+    strict: bool,
+    /// Reads through `as_protected_slice`
+    access: AccessCounter,
+}
+
+/// How often a buffer's contents were read through `as_protected_slice`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessReport {
+    pub count: u64,
+    pub last_access: Option<Instant>,
+}
+
+/// Access count and last access time, shared with outstanding `ProtectedSlice`s
+///
+/// Also used on its own by keys too small to be worth a locked page each
+/// (`crypto::symmetric::SymmetricKey`).
+pub(crate) struct AccessCounter {
+    count: AtomicU64,
+    created_at: Instant,
+    /// Nanoseconds after `created_at` of the last access, plus one (0 = never)
+    last_access_nanos: AtomicU64,
+}
+
+impl AccessCounter {
+    pub(crate) fn new() -> Self {
+        Self { count: AtomicU64::new(0), created_at: Instant::now(), last_access_nanos: AtomicU64::new(0) }
+    }
+
+    pub(crate) fn record(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let nanos = self.created_at.elapsed().as_nanos().min(u64::MAX as u128 - 1) as u64;
+        self.last_access_nanos.fetch_max(nanos + 1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn report(&self) -> AccessReport {
+        let last_access = match self.last_access_nanos.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(self.created_at + Duration::from_nanos(nanos - 1)),
+        };
+        AccessReport { count: self.count.load(Ordering::Relaxed), last_access }
+    }
+}
+
+/// Read-only view of a `SecureBuffer` that counts each dereference
+pub struct ProtectedSlice<'a> {
+    data: &'a [u8],
+    access: &'a AccessCounter,
+}
+
+impl<'a> ProtectedSlice<'a> {
+    /// Count one access and keep the bytes for the rest of the borrow
+    pub fn into_slice(self) -> &'a [u8] {
+        self.access.record();
+        self.data
+    }
+}
+
+impl Deref for ProtectedSlice<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.access.record();
+        self.data
+    }
 }
 
 impl SecureBuffer {
     /// Create a new secure buffer with the given capacity
+    ///
+    /// If the memory can't be locked (e.g. `RLIMIT_MEMLOCK` is exhausted) a
+    /// warning is logged and the buffer stays swappable; check `is_locked`,
+    /// or use `new_strict` to fail instead.
     pub fn new(capacity: usize) -> Self {
-        let mut buffer = Self {
-            data: Vec::with_capacity(capacity),
-            locked: false,
-        };
+        Self::lenient(Vec::with_capacity(capacity))
+    }
 
-        // Try to lock memory (may fail on some systems without proper permissions)
-        #[cfg(unix)]
-        {
-            buffer.try_lock_memory();
-        }
+    /// Create a secure buffer from existing data (locking as in `new`)
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        Self::lenient(data)
+    }
 
-        buffer
+    /// Like `new`, but fails if the memory can't be locked
+    pub fn new_strict(capacity: usize) -> Result<Self, SecureMemoryError> {
+        Self::strict(Vec::with_capacity(capacity))
     }
 
-    /// Create a secure buffer from existing data
-    pub fn from_vec(data: Vec<u8>) -> Self {
-        let mut buffer = Self {
-            data,
-            locked: false,
-        };
+    /// Like `from_vec`, but fails if the memory can't be locked
+    pub fn from_vec_strict(data: Vec<u8>) -> Result<Self, SecureMemoryError> {
+        Self::strict(data)
+    }
 
-        #[cfg(unix)]
-        {
-            buffer.try_lock_memory();
+    /// Whether the buffer's allocation is locked in RAM
+    ///
+    /// Always false for a buffer with no allocation, which has nothing to lock.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    fn lenient(data: Vec<u8>) -> Self {
+        let mut buffer = Self { data, locked: false, locked_len: 0, strict: false, access: AccessCounter::new() };
+
+        if let Err(e) = buffer.try_lock_memory() {
+            tracing::warn!("Secure buffer is not locked and may be swapped to disk: {}", e);
         }
 
         buffer
     }
 
+    fn strict(data: Vec<u8>) -> Result<Self, SecureMemoryError> {
+        let mut buffer = Self { data, locked: false, locked_len: 0, strict: true, access: AccessCounter::new() };
+        buffer.try_lock_memory()?;
+        Ok(buffer)
+    }
+
     /// Try to lock memory to prevent swapping to disk
     #[cfg(unix)]
-    fn try_lock_memory(&mut self) {
+    fn try_lock_memory(&mut self) -> Result<(), SecureMemoryError> {
         use libc::{mlock, c_void};
 
-        if !self.data.is_empty() {
-            let ptr = self.data.as_ptr() as *const c_void;
-            let len = self.data.len();
+        let len = self.data.capacity();
+        if len == 0 {
+            return Ok(());
+        }
 
-            unsafe {
-                if mlock(ptr, len) == 0 {
-                    self.locked = true;
-                }
-            }
+        let ptr = self.data.as_ptr() as *const c_void;
+        if unsafe { mlock(ptr, len) } != 0 {
+            return Err(SecureMemoryError::LockFailed { len, source: std::io::Error::last_os_error() });
         }
+
+        self.locked = true;
+        self.locked_len = len;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn try_lock_memory(&mut self) -> Result<(), SecureMemoryError> {
+        let len = self.data.capacity();
+        if len == 0 {
+            return Ok(());
+        }
+
+        Err(SecureMemoryError::LockFailed {
+            len,
+            source: std::io::Error::new(std::io::ErrorKind::Unsupported, "memory locking is not supported on this platform"),
+        })
     }
 
     /// Unlock memory (called automatically on drop)
@@ -64,22 +179,75 @@ impl SecureBuffer {
     fn unlock_memory(&mut self) {
         use libc::{munlock, c_void};
 
-        if self.locked && !self.data.is_empty() {
+        if self.locked {
             let ptr = self.data.as_ptr() as *const c_void;
-            let len = self.data.len();
 
             unsafe {
-                munlock(ptr, len);
+                munlock(ptr, self.locked_len);
             }
             self.locked = false;
         }
     }
 
+    /// Append `bytes`, moving to a larger locked allocation if they don't fit
+    ///
+    /// The old allocation is zeroized and unlocked after the copy, so growing
+    /// leaves nothing behind. A strict buffer whose new allocation can't be
+    /// locked fails and stays as it was; a lenient one logs a warning.
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), SecureMemoryError> {
+        let needed = self.data.len() + bytes.len();
+        if needed > self.data.capacity() {
+            let mut grown = Vec::with_capacity(needed.max(self.data.capacity() * 2));
+            grown.extend_from_slice(&self.data);
+            self.move_to(grown)?;
+        }
+        self.data.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Append one byte; see `extend_from_slice`
+    pub fn push(&mut self, byte: u8) -> Result<(), SecureMemoryError> {
+        self.extend_from_slice(&[byte])
+    }
+
+    /// Swap in `data` as the allocation, locking it and wiping the old one
+    fn move_to(&mut self, data: Vec<u8>) -> Result<(), SecureMemoryError> {
+        let mut old = std::mem::replace(&mut self.data, data);
+        let (old_locked, old_locked_len) = (self.locked, self.locked_len);
+        self.locked = false;
+        self.locked_len = 0;
+
+        if let Err(e) = self.try_lock_memory() {
+            if self.strict {
+                self.data.zeroize();
+                self.data = old;
+                self.locked = old_locked;
+                self.locked_len = old_locked_len;
+                return Err(e);
+            }
+            tracing::warn!("Secure buffer is not locked and may be swapped to disk: {}", e);
+        }
+
+        #[cfg(unix)]
+        if old_locked {
+            unsafe {
+                libc::munlock(old.as_ptr() as *const libc::c_void, old_locked_len);
+            }
+        }
+        old.zeroize();
+        Ok(())
+    }
+
     /// Get the length of the buffer
     pub fn len(&self) -> usize {
         self.data.len()
     }
 
+    /// Bytes the locked allocation can hold before `extend_from_slice` moves it
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
     /// Check if the buffer is empty
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
@@ -94,10 +262,30 @@ impl SecureBuffer {
     pub fn as_mut_slice(&mut self) -> &mut [u8] {
         &mut self.data
     }
+
+    /// Read-only view whose dereferences count towards `access_report`
+    ///
+    /// Key material should be read through this so overuse shows up in the
+    /// audit log (see `AuditLog::check_key_access`).
+    pub fn as_protected_slice(&self) -> ProtectedSlice<'_> {
+        ProtectedSlice { data: &self.data, access: &self.access }
+    }
+
+    /// Reads through `as_protected_slice` so far
+    pub fn access_report(&self) -> AccessReport {
+        self.access.report()
+    }
+}
+
+/// The copy is locked like `from_vec` and starts with no recorded accesses
+impl Clone for SecureBuffer {
+    fn clone(&self) -> Self {
+        Self::lenient(self.data.clone())
+    }
 }
 
 impl Deref for SecureBuffer {
-    type Target = Vec<u8>;
+    type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
         &self.data
@@ -122,9 +310,43 @@ impl Drop for SecureBuffer {
     }
 }
 
+/// Decrypted data that is zeroized when dropped
+///
+/// Returned by hardened receive paths so callers don't leave plaintext
+/// behind in freed memory by default.
+pub struct SecretBytes(Zeroizing<Vec<u8>>);
+
+impl SecretBytes {
+    /// Take ownership of `data` without copying it
+    pub fn new(data: Vec<u8>) -> Self {
+        Self(Zeroizing::new(data))
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(data: Vec<u8>) -> Self {
+        Self::new(data)
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {}])", self.0.len())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::random::fill_with_pattern;
 
     #[test]
     fn test_secure_buffer_creation() {
@@ -152,7 +374,89 @@ mod tests {
     #[test]
     fn test_secure_buffer_deref() {
         let mut buffer = SecureBuffer::from_vec(vec![1, 2, 3]);
-        buffer.push(4);
+        buffer.push(4).unwrap();
         assert_eq!(buffer.as_slice(), &[1, 2, 3, 4]);
+        buffer[0] = 9;
+        assert_eq!(&buffer[..2], &[9, 2]);
+    }
+
+    #[test]
+    fn test_secure_buffer_growth_stays_locked() {
+        let mut buffer = SecureBuffer::new(4);
+        let locked = buffer.is_locked();
+        buffer.extend_from_slice(&[1, 2, 3, 4]).unwrap();
+        let first_allocation = buffer.as_ptr();
+
+        // Growing moves to a new allocation with the same lock state
+        buffer.extend_from_slice(&[5; 64]).unwrap();
+        assert_ne!(buffer.as_ptr(), first_allocation);
+        assert_eq!(buffer.len(), 68);
+        assert_eq!(&buffer[..5], &[1, 2, 3, 4, 5]);
+        assert_eq!(buffer.is_locked(), locked);
+        if locked {
+            assert!(buffer.locked_len >= 68);
+        }
+    }
+
+    #[test]
+    fn test_lenient_lock_status() {
+        assert!(!SecureBuffer::new(0).is_locked());
+
+        // Whatever the environment allows, lenient and strict must agree
+        let lenient = SecureBuffer::new(4096);
+        let strict = SecureBuffer::new_strict(4096);
+        assert_eq!(lenient.is_locked(), strict.is_ok());
+        if let Ok(strict) = strict {
+            assert!(strict.is_locked());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "lowers RLIMIT_MEMLOCK for the whole process; run alone with --ignored"]
+    fn test_strict_lock_fails_under_lowered_rlimit() {
+        let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) }, 0);
+
+        // CAP_IPC_LOCK (e.g. running as root) ignores the limit
+        if SecureBuffer::new(64 * 1024).is_locked() {
+            return;
+        }
+
+        assert!(matches!(
+            SecureBuffer::new_strict(64 * 1024),
+            Err(SecureMemoryError::LockFailed { len: 65536, .. })
+        ));
+        let mut data = vec![0u8; 4096];
+        fill_with_pattern(&mut data, b"secret");
+        assert!(SecureBuffer::from_vec_strict(data).is_err());
+    }
+
+    #[test]
+    fn test_protected_slice_access_report() {
+        let buffer = SecureBuffer::from_vec(vec![7u8; 32]);
+        assert_eq!(buffer.access_report(), AccessReport { count: 0, last_access: None });
+
+        let slice = buffer.as_protected_slice();
+        assert_eq!(slice.len(), 32);
+        assert_eq!(slice[0], 7);
+        assert_eq!(buffer.as_protected_slice().into_slice(), &[7u8; 32]);
+
+        let report = buffer.access_report();
+        assert_eq!(report.count, 3);
+        assert!(report.last_access.unwrap() <= Instant::now());
+
+        // Plain reads aren't counted, and a clone starts over
+        assert_eq!(buffer.as_slice().len(), 32);
+        assert_eq!(buffer.access_report().count, 3);
+        assert_eq!(buffer.clone().access_report().count, 0);
+    }
+
+    #[test]
+    fn test_secret_bytes() {
+        let secret = SecretBytes::from(b"plaintext".to_vec());
+        assert_eq!(&*secret, b"plaintext");
+        assert_eq!(secret.len(), 9);
+        assert_eq!(format!("{:?}", secret), "SecretBytes([REDACTED; 9])");
     }
 }
@@ -0,0 +1,214 @@
+// Locked key store: one mlock'd allocation carved into named key slots
+// Avoids a separate mlock per key, which runs into per-process lock limits
+
+use std::collections::HashMap;
+use thiserror::Error;
+use zeroize::Zeroize;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KeyStoreError {
+    #[error("Slot already exists: {0}")]
+    SlotExists(String),
+
+    #[error("Not enough space for {requested} bytes ({available} free in largest region)")]
+    OutOfSpace { requested: usize, available: usize },
+}
+
+/// Fixed-size locked memory region holding named key slots
+pub struct LockedKeyStore {
+    data: Box<[u8]>,
+    locked: bool,
+    /// Slot name -> (offset, length)
+    slots: HashMap<String, (usize, usize)>,
+    /// Unused regions as (offset, length), sorted by offset and coalesced
+    free: Vec<(usize, usize)>,
+}
+
+impl LockedKeyStore {
+    /// Allocate `total_bytes` and lock them in memory with a single `mlock`
+    pub fn new(total_bytes: usize) -> Self {
+        let mut store = Self {
+            data: vec![0u8; total_bytes].into_boxed_slice(),
+            locked: false,
+            slots: HashMap::new(),
+            free: if total_bytes > 0 { vec![(0, total_bytes)] } else { Vec::new() },
+        };
+
+        // Try to lock memory (may fail on some systems without proper permissions)
+        #[cfg(unix)]
+        {
+            store.try_lock_memory();
+        }
+
+        store
+    }
+
+    /// Reserve a zeroed slot of `size` bytes under `name`
+    pub fn alloc_slot(&mut self, name: &str, size: usize) -> Result<&mut [u8], KeyStoreError> {
+        if self.slots.contains_key(name) {
+            return Err(KeyStoreError::SlotExists(name.to_string()));
+        }
+
+        // First fit
+        let index = self.free.iter().position(|&(_, len)| len >= size).ok_or_else(|| {
+            KeyStoreError::OutOfSpace {
+                requested: size,
+                available: self.free.iter().map(|&(_, len)| len).max().unwrap_or(0),
+            }
+        })?;
+
+        let (offset, len) = self.free[index];
+        if len == size {
+            self.free.remove(index);
+        } else {
+            self.free[index] = (offset + size, len - size);
+        }
+
+        self.slots.insert(name.to_string(), (offset, size));
+        Ok(&mut self.data[offset..offset + size])
+    }
+
+    /// Zeroize a slot and return its space to the store; false if it didn't exist
+    pub fn free_slot(&mut self, name: &str) -> bool {
+        let Some((offset, size)) = self.slots.remove(name) else {
+            return false;
+        };
+
+        self.data[offset..offset + size].zeroize();
+
+        let index = self.free.partition_point(|&(free_offset, _)| free_offset < offset);
+        self.free.insert(index, (offset, size));
+        self.coalesce();
+        true
+    }
+
+    /// Read a slot
+    pub fn slot(&self, name: &str) -> Option<&[u8]> {
+        self.slots.get(name).map(|&(offset, size)| &self.data[offset..offset + size])
+    }
+
+    /// Write to a slot
+    pub fn slot_mut(&mut self, name: &str) -> Option<&mut [u8]> {
+        self.slots.get(name).map(|&(offset, size)| &mut self.data[offset..offset + size])
+    }
+
+    /// Total size of the locked region
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Bytes not assigned to any slot
+    pub fn available(&self) -> usize {
+        self.free.iter().map(|&(_, len)| len).sum()
+    }
+
+    /// Whether the `mlock` call succeeded
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Merge adjacent free regions
+    fn coalesce(&mut self) {
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.free.len());
+        for &(offset, len) in &self.free {
+            match merged.last_mut() {
+                Some((last_offset, last_len)) if *last_offset + *last_len == offset => *last_len += len,
+                _ => merged.push((offset, len)),
+            }
+        }
+        self.free = merged;
+    }
+
+    /// Try to lock memory to prevent swapping to disk
+    #[cfg(unix)]
+    fn try_lock_memory(&mut self) {
+        use libc::{mlock, c_void};
+
+        if !self.data.is_empty() {
+            let ptr = self.data.as_ptr() as *const c_void;
+
+            unsafe {
+                if mlock(ptr, self.data.len()) == 0 {
+                    self.locked = true;
+                }
+            }
+        }
+    }
+
+    /// Unlock memory (called automatically on drop)
+    #[cfg(unix)]
+    fn unlock_memory(&mut self) {
+        use libc::{munlock, c_void};
+
+        if self.locked {
+            let ptr = self.data.as_ptr() as *const c_void;
+
+            unsafe {
+                munlock(ptr, self.data.len());
+            }
+            self.locked = false;
+        }
+    }
+}
+
+impl Drop for LockedKeyStore {
+    fn drop(&mut self) {
+        // Zeroize while the pages are still locked
+        self.data.zeroize();
+
+        #[cfg(unix)]
+        {
+            self.unlock_memory();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_and_free_slots() {
+        let mut store = LockedKeyStore::new(96);
+
+        store.alloc_slot("identity", 32).unwrap().copy_from_slice(&[1u8; 32]);
+        store.alloc_slot("master", 32).unwrap().copy_from_slice(&[2u8; 32]);
+        assert_eq!(store.slot("identity").unwrap(), &[1u8; 32]);
+        assert_eq!(store.available(), 32);
+
+        assert!(store.free_slot("identity"));
+        assert!(!store.free_slot("identity"));
+        assert_eq!(store.available(), 64);
+
+        // Freed space is reused and handed out zeroed
+        assert_eq!(store.alloc_slot("chain", 32).unwrap(), &[0u8; 32]);
+        assert_eq!(store.slot("master").unwrap(), &[2u8; 32]);
+    }
+
+    #[test]
+    fn test_slot_errors() {
+        let mut store = LockedKeyStore::new(64);
+
+        store.alloc_slot("a", 16).unwrap();
+        assert_eq!(store.alloc_slot("a", 16).unwrap_err(), KeyStoreError::SlotExists("a".to_string()));
+        assert_eq!(
+            store.alloc_slot("b", 64).unwrap_err(),
+            KeyStoreError::OutOfSpace { requested: 64, available: 48 }
+        );
+    }
+
+    #[test]
+    fn test_free_regions_coalesce() {
+        let mut store = LockedKeyStore::new(64);
+
+        for name in ["a", "b", "c", "d"] {
+            store.alloc_slot(name, 16).unwrap();
+        }
+        store.free_slot("b");
+        store.free_slot("c");
+
+        // Only fits if the two freed neighbours merged
+        store.alloc_slot("wide", 32).unwrap();
+        assert_eq!(store.available(), 0);
+    }
+}
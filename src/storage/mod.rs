@@ -3,3 +3,4 @@
 
 pub mod ephemeral;
 
+pub mod keystore;
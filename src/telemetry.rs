@@ -0,0 +1,118 @@
+// Session telemetry hooks for embedding applications
+// Lets callers instrument message events without forking the session code
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::network::NetworkError;
+use crate::network::protocol::MessageType;
+use crate::session::SessionRole;
+
+/// Callbacks for session events; every method defaults to a no-op
+pub trait SessionTelemetry: Send + Sync {
+    /// An application message was sent with the given ratchet counter
+    fn on_send(&self, _counter: u64, _byte_count: usize) {}
+
+    /// An application message was received and decrypted
+    fn on_recv(&self, _counter: u64, _byte_count: usize) {}
+
+    /// The local sending chain was rotated (`rotation_count` rotations so far)
+    fn on_rotate(&self, _rotation_count: u32) {}
+
+    /// A send or receive failed
+    fn on_error(&self, _error: &NetworkError) {}
+
+    /// The key exchange finished
+    fn on_handshake_complete(&self, _role: SessionRole) {}
+
+    /// A frame of any type arrived, before it was processed
+    fn on_frame_received(&self, _message_type: MessageType, _byte_count: usize) {}
+}
+
+/// Telemetry hook that ignores every event (the default)
+pub struct NoopTelemetry;
+
+impl SessionTelemetry for NoopTelemetry {}
+
+/// Counters in Prometheus text exposition format
+#[derive(Default)]
+pub struct PrometheusSessionTelemetry {
+    messages_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_received: AtomicU64,
+    rotations: AtomicU64,
+    errors: AtomicU64,
+    handshakes: AtomicU64,
+}
+
+impl PrometheusSessionTelemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render all counters for a `/metrics` endpoint
+    pub fn render(&self) -> String {
+        let counters = [
+            ("aegis_messages_sent_total", "Application messages sent", &self.messages_sent),
+            ("aegis_bytes_sent_total", "Plaintext bytes sent", &self.bytes_sent),
+            ("aegis_messages_received_total", "Application messages received", &self.messages_received),
+            ("aegis_bytes_received_total", "Plaintext bytes received", &self.bytes_received),
+            ("aegis_key_rotations_total", "Local sending chain rotations", &self.rotations),
+            ("aegis_session_errors_total", "Failed sends and receives", &self.errors),
+            ("aegis_handshakes_total", "Completed handshakes", &self.handshakes),
+        ];
+
+        let mut out = String::new();
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        out
+    }
+}
+
+impl SessionTelemetry for PrometheusSessionTelemetry {
+    fn on_send(&self, _counter: u64, byte_count: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(byte_count as u64, Ordering::Relaxed);
+    }
+
+    fn on_recv(&self, _counter: u64, byte_count: usize) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(byte_count as u64, Ordering::Relaxed);
+    }
+
+    fn on_rotate(&self, _rotation_count: u32) {
+        self.rotations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_error(&self, _error: &NetworkError) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_handshake_complete(&self, _role: SessionRole) {
+        self.handshakes.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_render() {
+        let telemetry = PrometheusSessionTelemetry::new();
+        telemetry.on_send(0, 10);
+        telemetry.on_send(1, 5);
+        telemetry.on_error(&NetworkError::Timeout);
+
+        let text = telemetry.render();
+        assert!(text.contains("# TYPE aegis_messages_sent_total counter"));
+        assert!(text.contains("aegis_messages_sent_total 2\n"));
+        assert!(text.contains("aegis_bytes_sent_total 15\n"));
+        assert!(text.contains("aegis_session_errors_total 1\n"));
+        assert!(text.contains("aegis_handshakes_total 0\n"));
+    }
+}
@@ -1,12 +1,87 @@
-// Status bar implementation (placeholder)
+// Status bar security indicator
+// Summarizes a session's negotiated parameters for the status bar and `/security`
+
+use crate::session::SecurityParams;
+
+/// How the security summary is colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityLevel {
+    /// No session parameters yet
+    Unknown,
+    /// Post-quantum keys and an authenticated peer
+    Strong,
+    /// Connected, but the peer is unverified or the keys are not post-quantum
+    Weak,
+}
 
 pub struct StatusBar {
-    // TODO: Add status state
+    security: Option<SecurityParams>,
 }
 
 impl StatusBar {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            security: None,
+        }
+    }
+
+    /// Show the parameters from `Session::negotiated_params`
+    pub fn set_security(&mut self, params: Option<SecurityParams>) {
+        self.security = params;
+    }
+
+    pub fn security(&self) -> Option<SecurityParams> {
+        self.security
+    }
+
+    /// Whether the session authenticates the peer
+    ///
+    /// A Kyber handshake alone doesn't; only a pre-shared key mixed into it
+    /// proves the peer is who we set up the session with.
+    pub fn peer_verified(&self) -> bool {
+        self.security.is_some_and(|params| params.psk)
+    }
+
+    pub fn level(&self) -> SecurityLevel {
+        match self.security {
+            None => SecurityLevel::Unknown,
+            Some(params) if params.post_quantum && self.peer_verified() => SecurityLevel::Strong,
+            Some(_) => SecurityLevel::Weak,
+        }
+    }
+
+    /// One-line summary for the status bar, empty before a session exists
+    pub fn summary(&self) -> String {
+        let Some(params) = self.security else {
+            return String::new();
+        };
+        let keys = if params.post_quantum {
+            format!("{:?}", params.suite.kem)
+        } else {
+            "TLS only, not post-quantum".to_string()
+        };
+        let verified = if self.peer_verified() { "verified by PSK" } else { "unverified" };
+        format!("{} + {:?}, {}", keys, params.aead, verified)
+    }
+
+    /// Every parameter, one per line, for the `/security` command
+    pub fn details(&self) -> Vec<String> {
+        let Some(params) = self.security else {
+            return vec!["No session yet".to_string()];
+        };
+        let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+        vec![
+            format!("Key exchange: {:?}{}", params.suite.kem, if params.post_quantum { "" } else { " (not used: keyed from TLS)" }),
+            format!("Cipher: {:?}", params.aead),
+            format!("Header binding: {:?}", params.suite.aad_schema),
+            format!("Post-quantum: {}", yes_no(params.post_quantum)),
+            format!("Kyber rekey since handshake: {}", yes_no(params.pfs_confirmed)),
+            format!("Pre-shared key: {}", yes_no(params.psk)),
+            format!("Transcript bound: {}", yes_no(params.transcript_bound)),
+            format!("TLS transport: {}", yes_no(params.tls)),
+            format!("Peer verified: {}", if self.peer_verified() { "yes (pre-shared key)" } else { "no" }),
+            "Commands: /rekey (Kyber rekey now), /rotate <seconds> (rotation interval)".to_string(),
+        ]
     }
 }
 
@@ -15,14 +15,91 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use std::io;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
+use crate::network::protocol::PeerInfo;
+use crate::session::SecurityParams;
+use super::status::{SecurityLevel, StatusBar};
+
 pub struct TerminalUI {
     messages: Vec<ChatMessage>,
     input: String,
     scroll_offset: usize,
     connection_status: ConnectionStatus,
     key_rotation_countdown: u64,
+    theme: ColorTheme,
+    /// Peer's display name, or its address until `PeerInfo` arrives
+    peer_label: Option<String>,
+    /// Negotiated security parameters and verification state
+    status_bar: StatusBar,
+}
+
+/// Colors used by the chat view and status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorTheme {
+    pub sent: Color,
+    pub received: Color,
+    pub system: Color,
+    pub error: Color,
+    pub status_ok: Color,
+    pub status_warn: Color,
+    pub status_err: Color,
+}
+
+impl ColorTheme {
+    /// Names accepted by `by_name` (and the `/theme` command)
+    pub const NAMES: [&'static str; 3] = ["default", "high-contrast", "monochrome"];
+
+    /// Maximum-contrast palette that avoids red/green pairs
+    pub fn high_contrast() -> Self {
+        Self {
+            sent: Color::LightCyan,
+            received: Color::LightYellow,
+            system: Color::White,
+            error: Color::LightMagenta,
+            status_ok: Color::LightCyan,
+            status_warn: Color::LightYellow,
+            status_err: Color::LightMagenta,
+        }
+    }
+
+    /// No color at all; sources stay distinguishable by prefix and style
+    pub fn monochrome() -> Self {
+        Self {
+            sent: Color::Reset,
+            received: Color::Reset,
+            system: Color::Reset,
+            error: Color::Reset,
+            status_ok: Color::Reset,
+            status_warn: Color::Reset,
+            status_err: Color::Reset,
+        }
+    }
+
+    /// Look up a predefined theme by name
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::default()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ColorTheme {
+    fn default() -> Self {
+        Self {
+            sent: Color::Blue,
+            received: Color::Green,
+            system: Color::Yellow,
+            error: Color::Red,
+            status_ok: Color::Green,
+            status_warn: Color::Yellow,
+            status_err: Color::Red,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -30,6 +107,8 @@ pub struct ChatMessage {
     pub from: MessageSource,
     pub content: String,
     pub timestamp: String,
+    /// Unix time after which an ephemeral message is dropped from history
+    pub expires_at: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -48,8 +127,21 @@ pub enum ConnectionStatus {
     Error(String),
 }
 
+/// What the session side pushes into `run_ui_loop`
+pub enum UIUpdate {
+    Message(ChatMessage),
+    /// The peer introduced itself (`ReceivedEvent::PeerInfo`)
+    PeerInfo(PeerInfo),
+}
+
 pub enum UIEvent {
     SendMessage(String),
+    /// A theme was applied with `/theme`; carries its name so it can be persisted
+    ThemeChanged(String),
+    /// `/rekey`: start a Kyber rekey now
+    Rekey,
+    /// `/rotate <seconds>`: change the key rotation interval
+    SetRotationInterval(Duration),
     Quit,
 }
 
@@ -61,15 +153,42 @@ impl TerminalUI {
             scroll_offset: 0,
             connection_status: ConnectionStatus::Disconnected,
             key_rotation_countdown: 60,
+            theme: ColorTheme::default(),
+            peer_label: None,
+            status_bar: StatusBar::new(),
         }
     }
 
+    pub fn set_theme(&mut self, theme: ColorTheme) {
+        self.theme = theme;
+    }
+
+    pub fn theme(&self) -> ColorTheme {
+        self.theme
+    }
+
     pub fn add_message(&mut self, from: MessageSource, content: String) {
+        self.push_message(from, content, None);
+    }
+
+    /// Add a message that `remove_expired` drops once `expires_at` passes
+    pub fn add_ephemeral_message(&mut self, from: MessageSource, content: String, expires_at: u64) {
+        self.push_message(from, content, Some(expires_at));
+    }
+
+    /// Drop ephemeral messages whose expiry is at or before `now` (Unix seconds)
+    pub fn remove_expired(&mut self, now: u64) {
+        self.messages.retain(|m| m.expires_at.is_none_or(|at| at > now));
+        self.scroll_offset = self.scroll_offset.min(self.messages.len().saturating_sub(20));
+    }
+
+    fn push_message(&mut self, from: MessageSource, content: String, expires_at: Option<u64>) {
         let timestamp = chrono::Local::now().format("%H:%M:%S").to_string();
         self.messages.push(ChatMessage {
             from,
             content,
             timestamp,
+            expires_at,
         });
 
         // Auto-scroll to bottom
@@ -82,10 +201,20 @@ impl TerminalUI {
         self.connection_status = status;
     }
 
+    /// Name the peer in the status bar instead of its address
+    pub fn set_peer_label(&mut self, label: impl Into<String>) {
+        self.peer_label = Some(label.into());
+    }
+
     pub fn set_key_rotation_countdown(&mut self, seconds: u64) {
         self.key_rotation_countdown = seconds;
     }
 
+    /// Show the session's parameters (see `Session::negotiated_params`)
+    pub fn set_security_params(&mut self, params: SecurityParams) {
+        self.status_bar.set_security(Some(params));
+    }
+
     pub fn draw(&self, frame: &mut Frame, area: Rect) {
         // Create main layout
         let chunks = Layout::default()
@@ -110,35 +239,54 @@ impl TerminalUI {
     fn draw_status_bar(&self, frame: &mut Frame, area: Rect) {
         let status_text = match &self.connection_status {
             ConnectionStatus::Disconnected => {
-                Span::styled("Disconnected", Style::default().fg(Color::Red))
+                Span::styled("Disconnected", Style::default().fg(self.theme.status_err))
             }
             ConnectionStatus::Connecting => {
-                Span::styled("Connecting...", Style::default().fg(Color::Yellow))
+                Span::styled("Connecting...", Style::default().fg(self.theme.status_warn))
             }
             ConnectionStatus::Handshaking => {
-                Span::styled("Performing key exchange...", Style::default().fg(Color::Yellow))
+                Span::styled("Performing key exchange...", Style::default().fg(self.theme.status_warn))
             }
             ConnectionStatus::Connected => {
-                Span::styled("Connected (Quantum-Safe)", Style::default().fg(Color::Green))
+                let safety = match self.status_bar.security() {
+                    Some(params) if !params.post_quantum => "Not Quantum-Safe",
+                    _ => "Quantum-Safe",
+                };
+                match &self.peer_label {
+                    Some(peer) => Span::styled(
+                        format!("Connected to {} ({})", peer, safety),
+                        Style::default().fg(self.theme.status_ok),
+                    ),
+                    None => Span::styled(format!("Connected ({})", safety), Style::default().fg(self.theme.status_ok)),
+                }
             }
             ConnectionStatus::Error(msg) => {
-                Span::styled(format!("Error: {}", msg), Style::default().fg(Color::Red))
+                Span::styled(format!("Error: {}", msg), Style::default().fg(self.theme.error))
             }
         };
 
         let rotation_text = if matches!(self.connection_status, ConnectionStatus::Connected) {
             Span::styled(
                 format!(" | Key rotation: {}s", self.key_rotation_countdown),
-                Style::default().fg(Color::Cyan),
+                Style::default().fg(self.theme.system),
             )
         } else {
             Span::raw("")
         };
 
+        let security_text = match self.status_bar.level() {
+            SecurityLevel::Unknown => Span::raw(""),
+            level => {
+                let color = if level == SecurityLevel::Strong { self.theme.status_ok } else { self.theme.status_warn };
+                Span::styled(format!(" | {}", self.status_bar.summary()), Style::default().fg(color))
+            }
+        };
+
         let status_line = Line::from(vec![
             Span::raw(" "),
             status_text,
             rotation_text,
+            security_text,
         ]);
 
         let status_block = Paragraph::new(status_line)
@@ -156,20 +304,20 @@ impl TerminalUI {
                 let (prefix, style) = match msg.from {
                     MessageSource::Sent => (
                         "> ",
-                        Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+                        Style::default().fg(self.theme.sent).add_modifier(Modifier::BOLD),
                     ),
                     MessageSource::Received => (
                         "< ",
-                        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+                        Style::default().fg(self.theme.received).add_modifier(Modifier::BOLD),
                     ),
                     MessageSource::System => (
                         "* ",
-                        Style::default().fg(Color::Yellow).add_modifier(Modifier::ITALIC),
+                        Style::default().fg(self.theme.system).add_modifier(Modifier::ITALIC),
                     ),
                 };
 
                 let content = Line::from(vec![
-                    Span::styled(&msg.timestamp, Style::default().fg(Color::DarkGray)),
+                    Span::styled(&msg.timestamp, Style::default().add_modifier(Modifier::DIM)),
                     Span::raw(" "),
                     Span::styled(prefix, style),
                     Span::styled(&msg.content, style),
@@ -204,8 +352,28 @@ impl TerminalUI {
                 None
             }
             KeyCode::Enter => {
-                if !self.input.trim().is_empty() {
-                    let message = self.input.clone();
+                // Only an exact command word is a command; anything else is chat
+                let input = self.input.trim().to_string();
+                let (command, args) = input.split_once(char::is_whitespace)
+                    .map_or((input.as_str(), ""), |(command, args)| (command, args.trim()));
+                match command {
+                    "/theme" => {
+                        self.input.clear();
+                        return self.apply_theme_command(args);
+                    }
+                    "/security" | "/rekey" | "/rotate" => {
+                        self.input.clear();
+                        return self.apply_security_command(command, args);
+                    }
+                    _ => {}
+                }
+
+                if !input.is_empty() {
+                    // `//` sends a message that starts with a slash
+                    let message = match self.input.strip_prefix("//") {
+                        Some(rest) => format!("/{}", rest),
+                        None => self.input.clone(),
+                    };
                     self.input.clear();
                     Some(UIEvent::SendMessage(message))
                 } else {
@@ -216,6 +384,50 @@ impl TerminalUI {
             _ => None,
         }
     }
+
+    /// Handle `/security`, `/rekey` and `/rotate <seconds>`
+    fn apply_security_command(&mut self, command: &str, args: &str) -> Option<UIEvent> {
+        match command {
+            "/security" => {
+                for line in self.status_bar.details() {
+                    self.add_message(MessageSource::System, line);
+                }
+                None
+            }
+            "/rekey" => {
+                self.add_message(MessageSource::System, "Starting a Kyber rekey".to_string());
+                Some(UIEvent::Rekey)
+            }
+            _ => match args.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => {
+                    self.add_message(MessageSource::System, format!("Key rotation interval set to {}s", seconds));
+                    Some(UIEvent::SetRotationInterval(Duration::from_secs(seconds)))
+                }
+                _ => {
+                    self.add_message(MessageSource::System, "Usage: /rotate <seconds>".to_string());
+                    None
+                }
+            },
+        }
+    }
+
+    /// Handle `/theme <name>`
+    fn apply_theme_command(&mut self, name: &str) -> Option<UIEvent> {
+        match ColorTheme::by_name(name) {
+            Some(theme) => {
+                self.set_theme(theme);
+                self.add_message(MessageSource::System, format!("Theme set to {}", name));
+                Some(UIEvent::ThemeChanged(name.to_string()))
+            }
+            None => {
+                self.add_message(
+                    MessageSource::System,
+                    format!("Unknown theme '{}' (available: {})", name, ColorTheme::NAMES.join(", ")),
+                );
+                None
+            }
+        }
+    }
 }
 
 impl Default for TerminalUI {
@@ -227,7 +439,7 @@ impl Default for TerminalUI {
 /// Run the terminal UI event loop
 pub async fn run_ui_loop(
     mut ui: TerminalUI,
-    mut rx: mpsc::Receiver<ChatMessage>,
+    mut rx: mpsc::Receiver<UIUpdate>,
     tx: mpsc::Sender<UIEvent>,
 ) -> io::Result<()> {
     // Setup terminal
@@ -268,10 +480,14 @@ pub async fn run_ui_loop(
             }
         }
 
-        // Check for incoming messages
-        while let Ok(msg) = rx.try_recv() {
-            ui.messages.push(msg);
+        // Check for incoming messages and peer details
+        while let Ok(update) = rx.try_recv() {
+            match update {
+                UIUpdate::Message(msg) => ui.messages.push(msg),
+                UIUpdate::PeerInfo(info) => ui.set_peer_label(info.display_name),
+            }
         }
+        ui.remove_expired(chrono::Utc::now().timestamp().max(0) as u64);
     }
 
     // Restore terminal
@@ -301,6 +517,18 @@ mod tests {
         assert_eq!(ui.messages[0].content, "Test message");
     }
 
+    #[test]
+    fn test_remove_expired() {
+        let mut ui = TerminalUI::new();
+        ui.add_message(MessageSource::Received, "kept".to_string());
+        ui.add_ephemeral_message(MessageSource::Received, "gone".to_string(), 100);
+        ui.add_ephemeral_message(MessageSource::Received, "later".to_string(), 200);
+
+        ui.remove_expired(100);
+        let contents: Vec<_> = ui.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["kept", "later"]);
+    }
+
     #[test]
     fn test_input_handling() {
         let mut ui = TerminalUI::new();
@@ -315,6 +543,70 @@ mod tests {
         assert_eq!(ui.input, "h");
     }
 
+    #[test]
+    fn test_theme_command() {
+        let mut ui = TerminalUI::new();
+
+        for c in "/theme high-contrast".chars() {
+            ui.handle_input(KeyEvent::from(KeyCode::Char(c)));
+        }
+        let event = ui.handle_input(KeyEvent::from(KeyCode::Enter));
+
+        assert!(matches!(event, Some(UIEvent::ThemeChanged(ref name)) if name == "high-contrast"));
+        assert_eq!(ui.theme(), ColorTheme::high_contrast());
+        assert_eq!(ui.input, "");
+
+        for c in "/theme neon".chars() {
+            ui.handle_input(KeyEvent::from(KeyCode::Char(c)));
+        }
+        assert!(ui.handle_input(KeyEvent::from(KeyCode::Enter)).is_none());
+        assert_eq!(ui.theme(), ColorTheme::high_contrast());
+    }
+
+    #[test]
+    fn test_security_commands() {
+        use crate::crypto::kyber::KyberLevel;
+        use crate::crypto::symmetric::AEAD;
+        use crate::network::protocol::AadSchema;
+        use crate::session::NegotiatedSuite;
+
+        let mut ui = TerminalUI::new();
+        let enter = |ui: &mut TerminalUI, text: &str| {
+            for c in text.chars() {
+                ui.handle_input(KeyEvent::from(KeyCode::Char(c)));
+            }
+            ui.handle_input(KeyEvent::from(KeyCode::Enter))
+        };
+
+        assert_eq!(ui.status_bar.level(), SecurityLevel::Unknown);
+        let params = SecurityParams {
+            suite: NegotiatedSuite { kem: KyberLevel::Kyber1024, aad_schema: AadSchema::LATEST, features: 0 },
+            aead: AEAD::XChaCha20Poly1305,
+            post_quantum: true,
+            pfs_confirmed: false,
+            psk: false,
+            transcript_bound: true,
+            tls: false,
+        };
+        ui.set_security_params(params);
+        assert_eq!(ui.status_bar.level(), SecurityLevel::Weak);
+        ui.set_security_params(SecurityParams { psk: true, ..params });
+        assert_eq!(ui.status_bar.level(), SecurityLevel::Strong);
+
+        assert!(enter(&mut ui, "/security").is_none());
+        assert!(ui.messages.iter().any(|m| m.content == "Key exchange: Kyber1024"));
+
+        assert!(matches!(enter(&mut ui, "/rekey"), Some(UIEvent::Rekey)));
+        assert!(matches!(enter(&mut ui, "/rotate 30"), Some(UIEvent::SetRotationInterval(d)) if d == Duration::from_secs(30)));
+        assert!(enter(&mut ui, "/rotate soon").is_none());
+        assert_eq!(ui.input, "");
+
+        // Words that merely start like a command are chat, and `//` escapes a slash
+        assert!(matches!(enter(&mut ui, "/rekeying is fun"), Some(UIEvent::SendMessage(m)) if m == "/rekeying is fun"));
+        assert!(matches!(enter(&mut ui, "/themes"), Some(UIEvent::SendMessage(m)) if m == "/themes"));
+        assert!(matches!(enter(&mut ui, "//rekey"), Some(UIEvent::SendMessage(m)) if m == "/rekey"));
+    }
+
     #[test]
     fn test_status_changes() {
         let mut ui = TerminalUI::new();
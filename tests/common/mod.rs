@@ -0,0 +1,126 @@
+// Shared helpers for integration tests
+// LossyTransport simulates an unreliable link on top of the in-memory transport
+
+use std::sync::{Arc, Mutex};
+
+use aegis::network::connection::{memory_pair, Connection};
+use aegis::network::protocol::{Message, MessageType};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Per-message fault probabilities (each in `0.0..=1.0`)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LossyConfig {
+    pub drop_rate: f64,
+    pub reorder_rate: f64,
+    pub duplicate_rate: f64,
+}
+
+/// What the relay did to the encrypted traffic it saw
+#[derive(Debug, Clone, Default)]
+pub struct LossyStats {
+    /// Distinct encrypted messages forwarded at least once
+    pub delivered: usize,
+    pub dropped: usize,
+    pub reordered: usize,
+    pub duplicated: usize,
+}
+
+/// Relay between two in-memory connections that drops, reorders and
+/// duplicates encrypted messages
+///
+/// Control messages (handshake, heartbeats, ...) always pass through
+/// untouched so sessions can be established over it.
+pub struct LossyTransport {
+    stats: Arc<Mutex<LossyStats>>,
+}
+
+/// One direction of the relay
+struct Lane {
+    /// Message held back to be delivered after the next one
+    held: Option<Message>,
+}
+
+impl LossyTransport {
+    /// Connected pair whose traffic (both directions) passes through the relay
+    ///
+    /// `seed` makes the fault pattern reproducible.
+    pub fn pair(config: LossyConfig, seed: u64) -> (Connection, Connection, Self) {
+        let (client, mut relay_client) = memory_pair();
+        let (mut relay_server, server) = memory_pair();
+        let stats = Arc::new(Mutex::new(LossyStats::default()));
+        let relay_stats = stats.clone();
+
+        tokio::spawn(async move {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut to_server = Lane { held: None };
+            let mut to_client = Lane { held: None };
+
+            loop {
+                // recv_message is cancel-safe, so racing both sides loses nothing
+                let (msg, lane, out) = tokio::select! {
+                    msg = relay_client.recv_message() => (msg, &mut to_server, &mut relay_server),
+                    msg = relay_server.recv_message() => (msg, &mut to_client, &mut relay_client),
+                };
+                let Ok(msg) = msg else { break };
+
+                let forwarded = lane.forward(msg, &config, &mut rng, &relay_stats);
+                let mut failed = false;
+                for msg in forwarded {
+                    failed |= out.send_message(&msg).await.is_err();
+                }
+                if failed || out.flush().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        (client, server, Self { stats })
+    }
+
+    /// Snapshot of the faults injected so far
+    pub fn stats(&self) -> LossyStats {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+impl Lane {
+    /// Decide the fate of one message, returning what to send on now
+    fn forward(
+        &mut self,
+        msg: Message,
+        config: &LossyConfig,
+        rng: &mut StdRng,
+        stats: &Mutex<LossyStats>,
+    ) -> Vec<Message> {
+        if msg.message_type != MessageType::EncryptedMessage {
+            return vec![msg];
+        }
+
+        let mut stats = stats.lock().unwrap();
+        let mut out = Vec::new();
+
+        if rng.gen_bool(config.drop_rate) {
+            stats.dropped += 1;
+        } else if self.held.is_none() && rng.gen_bool(config.reorder_rate) {
+            stats.reordered += 1;
+            self.held = Some(msg);
+            return out;
+        } else {
+            stats.delivered += 1;
+            if rng.gen_bool(config.duplicate_rate) {
+                stats.duplicated += 1;
+                out.push(msg.clone());
+            }
+            out.push(msg);
+        }
+
+        // A held message goes out right after the one that overtook it
+        if let Some(held) = self.held.take() {
+            stats.delivered += 1;
+            out.push(held);
+        }
+
+        out
+    }
+}
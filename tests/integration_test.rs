@@ -1,6 +1,10 @@
 // Integration tests for Aegis end-to-end encrypted messaging
 
+mod common;
+
 use aegis::network::connection::{Listener, connect};
+use common::{LossyConfig, LossyTransport};
+use aegis::crypto::random::fill_with_pattern;
 use aegis::session::Session;
 use tokio::time::{timeout, Duration};
 
@@ -173,7 +177,8 @@ async fn test_large_message_transfer() {
     let addr = listener.local_addr().unwrap();
 
     // Create a large message (100 KB - reduced from 1 MB for faster tests)
-    let large_data = vec![0x42u8; 100 * 1024];
+    let mut large_data = vec![0u8; 100 * 1024];
+    fill_with_pattern(&mut large_data, b"aegis large message ");
 
     let large_data_clone = large_data.clone();
 
@@ -312,3 +317,100 @@ async fn test_utf8_message_encoding() {
     // Close sessions
     let _ = client_session.close().await;
 }
+
+#[cfg(feature = "proto-wire")]
+#[test]
+fn test_proto_wire_framed_handshake_roundtrip() {
+    use aegis::crypto::kyber::KeyPair;
+    use aegis::network::proto_bridge::ProtoTranscoder;
+    use aegis::network::protocol::{Message, MessagePayload, MessageType};
+
+    let keypair = KeyPair::generate().unwrap();
+    let msg = Message::handshake(keypair.public_key().clone());
+
+    let framed = ProtoTranscoder::frame(&msg).unwrap();
+    let (parsed, consumed) = ProtoTranscoder::parse_frame(&framed).unwrap();
+
+    assert_eq!(consumed, framed.len());
+    assert_eq!(parsed.message_type, MessageType::Handshake);
+    assert!(parsed.validate().is_ok());
+    match parsed.payload {
+        MessagePayload::Handshake { public_key } => {
+            assert_eq!(public_key, keypair.public_key().as_bytes());
+        }
+        other => panic!("unexpected payload: {:?}", other),
+    }
+}
+
+/// Receive until the link goes quiet, returning plaintexts and the number of rejected messages
+async fn collect_until_idle(session: &mut Session) -> (Vec<Vec<u8>>, usize) {
+    let mut received = Vec::new();
+    let mut rejected = 0;
+
+    while let Ok(result) = timeout(Duration::from_millis(300), session.recv()).await {
+        match result {
+            Ok(plaintext) => received.push(plaintext),
+            Err(_) => rejected += 1,
+        }
+    }
+
+    (received, rejected)
+}
+
+async fn lossy_session_pair(config: LossyConfig, seed: u64) -> (Session, Session, LossyTransport) {
+    let (client_conn, server_conn, transport) = LossyTransport::pair(config, seed);
+
+    let server_task = tokio::spawn(Session::accept(server_conn));
+    let client_session = Session::connect(client_conn).await.unwrap();
+    let server_session = server_task.await.unwrap().unwrap();
+
+    (client_session, server_session, transport)
+}
+
+#[tokio::test]
+async fn test_session_survives_loss_and_reordering() {
+    let config = LossyConfig { drop_rate: 0.1, reorder_rate: 0.2, duplicate_rate: 0.0 };
+    let (mut client_session, mut server_session, transport) = lossy_session_pair(config, 7).await;
+
+    let sent: Vec<Vec<u8>> = (0..200).map(|i| format!("message {}", i).into_bytes()).collect();
+    for msg in &sent {
+        client_session.send(msg).await.unwrap();
+    }
+
+    let (received, rejected) = collect_until_idle(&mut server_session).await;
+    let stats = transport.stats();
+
+    assert!(stats.dropped > 0 && stats.reordered > 0);
+    assert_eq!(rejected, 0);
+    assert_eq!(received.len(), stats.delivered);
+    assert!(received.iter().all(|msg| sent.contains(msg)));
+
+    // Skipped keys let out-of-order messages through
+    let mut in_order = received.clone();
+    in_order.sort_by_key(|msg| sent.iter().position(|s| s == msg));
+    assert_ne!(received, in_order);
+}
+
+#[tokio::test]
+async fn test_session_rejects_duplicates() {
+    let config = LossyConfig { drop_rate: 0.0, reorder_rate: 0.1, duplicate_rate: 0.3 };
+    let (mut client_session, mut server_session, transport) = lossy_session_pair(config, 11).await;
+
+    let sent: Vec<Vec<u8>> = (0..100).map(|i| format!("message {}", i).into_bytes()).collect();
+    for msg in &sent {
+        client_session.send(msg).await.unwrap();
+    }
+
+    let (received, rejected) = collect_until_idle(&mut server_session).await;
+    let stats = transport.stats();
+
+    assert!(stats.duplicated > 0);
+    assert_eq!(rejected, stats.duplicated);
+    assert_eq!(received.len(), stats.delivered);
+
+    // Every delivered message was accepted exactly once
+    let mut unique = received.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), received.len());
+}